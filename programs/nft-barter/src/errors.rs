@@ -46,4 +46,147 @@ pub enum MyError {
     NotRentExempt,
     #[msg("NotFoundRemainingAccounts")]
     NotFoundRemainingAccounts,
+    // 6020
+    #[msg("NotNftMint")]
+    NotNftMint,
+    #[msg("MetadataPublicKeyMismatch")]
+    MetadataPublicKeyMismatch,
+    #[msg("UnverifiedCollection")]
+    UnverifiedCollection,
+    #[msg("CollectionPublicKeyMismatch")]
+    CollectionPublicKeyMismatch,
+    #[msg("EscrowExpired")]
+    EscrowExpired,
+    // 6025
+    #[msg("EscrowNotExpired")]
+    EscrowNotExpired,
+    #[msg("EscrowAlreadyAccepted")]
+    EscrowAlreadyAccepted,
+    #[msg("EscrowNotAccepted")]
+    EscrowNotAccepted,
+    #[msg("UnsupportedCollectionOffer")]
+    UnsupportedCollectionOffer,
+    #[msg("InvalidRingSize")]
+    InvalidRingSize,
+    // 6030
+    #[msg("DuplicateRingParticipant")]
+    DuplicateRingParticipant,
+    #[msg("NotRingParticipant")]
+    NotRingParticipant,
+    #[msg("RingLegAlreadyDeposited")]
+    RingLegAlreadyDeposited,
+    #[msg("RingNotFullyDeposited")]
+    RingNotFullyDeposited,
+    #[msg("InvalidFungibleAmount")]
+    InvalidFungibleAmount,
+    // 6035
+    #[msg("InsufficientTokenBalance")]
+    InsufficientTokenBalance,
+    #[msg("UnsupportedFungibleOffer")]
+    UnsupportedFungibleOffer,
+    #[msg("InvalidNftQuantity")]
+    InvalidNftQuantity,
+    #[msg("NonTransferableMint")]
+    NonTransferableMint,
+    #[msg("PermanentDelegateMint")]
+    PermanentDelegateMint,
+    // 6040
+    #[msg("UnsupportedTransferFee")]
+    UnsupportedTransferFee,
+    #[msg("TransferHookAccountsNotFound")]
+    TransferHookAccountsNotFound,
+    #[msg("UnsupportedPnftOffer")]
+    UnsupportedPnftOffer,
+    #[msg("PnftProgramMismatch")]
+    PnftProgramMismatch,
+    #[msg("UnsupportedCnftOffer")]
+    UnsupportedCnftOffer,
+    // 6045
+    #[msg("CnftProgramMismatch")]
+    CnftProgramMismatch,
+    #[msg("CnftAssetMismatch")]
+    CnftAssetMismatch,
+    #[msg("UnsupportedCoreOffer")]
+    UnsupportedCoreOffer,
+    #[msg("CoreProgramMismatch")]
+    CoreProgramMismatch,
+    #[msg("InvalidCoreAsset")]
+    InvalidCoreAsset,
+    // 6050
+    #[msg("CreatorPublicKeyMismatch")]
+    CreatorPublicKeyMismatch,
+    #[msg("InvalidFeeBasisPoints")]
+    InvalidFeeBasisPoints,
+    #[msg("AdminPublicKeyMismatch")]
+    AdminPublicKeyMismatch,
+    #[msg("TreasuryPublicKeyMismatch")]
+    TreasuryPublicKeyMismatch,
+    #[msg("FeeExceedsSolAmount")]
+    FeeExceedsSolAmount,
+    // 6055
+    #[msg("InvalidReferrerBasisPoints")]
+    InvalidReferrerBasisPoints,
+    #[msg("ReferrerPublicKeyMismatch")]
+    ReferrerPublicKeyMismatch,
+    #[msg("InvalidAuctionEndTime")]
+    InvalidAuctionEndTime,
+    #[msg("AuctionEnded")]
+    AuctionEnded,
+    #[msg("AuctionNotEnded")]
+    AuctionNotEnded,
+    // 6060
+    #[msg("BidBelowReservePrice")]
+    BidBelowReservePrice,
+    #[msg("BidIncrementTooSmall")]
+    BidIncrementTooSmall,
+    #[msg("InvalidBidIncrement")]
+    InvalidBidIncrement,
+    #[msg("BidderPublicKeyMismatch")]
+    BidderPublicKeyMismatch,
+    #[msg("InvalidDutchPricing")]
+    InvalidDutchPricing,
+    // 6065
+    #[msg("PriceExceedsMaximum")]
+    PriceExceedsMaximum,
+    #[msg("UnsupportedDutchOffer")]
+    UnsupportedDutchOffer,
+    #[msg("InvalidSealedAuctionSchedule")]
+    InvalidSealedAuctionSchedule,
+    #[msg("SealedBidLimitReached")]
+    SealedBidLimitReached,
+    #[msg("NotInRevealWindow")]
+    NotInRevealWindow,
+    // 6070
+    #[msg("SealedBidCommitmentMismatch")]
+    SealedBidCommitmentMismatch,
+    #[msg("BidExceedsDeposit")]
+    BidExceedsDeposit,
+    #[msg("SealedBidAlreadyRevealed")]
+    SealedBidAlreadyRevealed,
+    #[msg("SealedBidAccountsMismatch")]
+    SealedBidAccountsMismatch,
+    #[msg("InvalidPoolRatio")]
+    InvalidPoolRatio,
+    // 6075
+    #[msg("PoolEmpty")]
+    PoolEmpty,
+    #[msg("InvalidStandingOffer")]
+    InvalidStandingOffer,
+    #[msg("StandingOfferFilled")]
+    StandingOfferFilled,
+    #[msg("UnmatchableOffer")]
+    UnmatchableOffer,
+    #[msg("OffersNotCompatible")]
+    OffersNotCompatible,
+    // 6080
+    #[msg("InvalidRentalDays")]
+    InvalidRentalDays,
+    #[msg("RentalAlreadyRented")]
+    RentalAlreadyRented,
+    #[msg("RentalNotEnded")]
+    RentalNotEnded,
+    #[msg("InvalidKeeperTipBasisPoints")]
+    InvalidKeeperTipBasisPoints,
+    #[msg("SealedBidDepositTooSmall")]
+    SealedBidDepositTooSmall,
 }
@@ -0,0 +1,15 @@
+use anchor_lang::prelude::*;
+
+// 提携marketplaceがreferrerとしての受け取りを照合できるように、exchangeとsettleで動いたSOLの内訳を残す
+#[event]
+pub struct ExchangeSettled {
+    pub escrow_key: Pubkey,
+    pub initializer_key: Pubkey,
+    pub taker_key: Pubkey,
+    pub initializer_additional_sol_amount: u64,
+    pub taker_additional_sol_amount: u64,
+    pub referrer_key: Pubkey, // referrerがいない場合はPubkey::default()
+    pub referrer_amount: u64,
+    pub fee_amount: u64, // treasuryに払った手数料
+    pub royalty_amount: u64,
+}
@@ -0,0 +1,142 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Token;
+
+use crate::{
+    errors::MyError,
+    state::{EscrowAccount, ESCROW_PDA_SEED, VAULT_AUTHORITY_PDA_SEED, VaultAuthority},
+    traits::*,
+    utils::assert_keys_equal,
+};
+
+// 高額な取引向けに、takerが先に資産をvaultに預けてsettleで両者に払い出す
+#[derive(Accounts)]
+#[instruction(taker_additional_sol_amount: u64, vault_account_bumps: Vec<u8>)]
+pub struct Accept<'info> {
+    #[account(
+        mut,
+        constraint = taker.to_account_info().try_lamports().unwrap() >= taker_additional_sol_amount @ MyError::TakerInsufficientFunds,
+        constraint = taker_additional_sol_amount == escrow_account.taker_additional_sol_amount @ MyError::TakerAdditionalSolAmountMismatch,
+        constraint = vault_account_bumps.len() == escrow_account.taker_nft_mints.len() @ MyError::VaultAccountBumpsMismatch
+    )]
+    pub taker: Signer<'info>,
+    pub initializer: SystemAccount<'info>,
+    #[account(
+        mut,
+        seeds = [
+            ESCROW_PDA_SEED,
+            initializer.key().as_ref(),
+            escrow_account.taker_key.as_ref(),
+            escrow_account.nonce.to_le_bytes().as_ref()
+        ],
+        bump = escrow_account.bump,
+        constraint = escrow_account.initializer_key == *initializer.key @ MyError::InitializerPublicKeyMismatch,
+        constraint = escrow_account.is_open_offer() || escrow_account.taker_key == *taker.key @ MyError::TakerPublicKeyMismatch,
+        constraint = !escrow_account.is_accepted() @ MyError::EscrowAlreadyAccepted,
+        // collectionのNFTはtakerが選んだtoken accountを保存する領域がないのでexchangeだけで扱う
+        constraint = escrow_account.taker_collection_nft_amount == 0 @ MyError::UnsupportedCollectionOffer,
+        // fungible tokenのvaultはsettleで扱わないのでexchangeだけで扱う
+        constraint = escrow_account.initializer_fungible_legs.is_empty() && escrow_account.taker_fungible_legs.is_empty() @ MyError::UnsupportedFungibleOffer,
+        // pNFTはvault authorityのATAに預けるのでsettleで扱わずexchangeだけで扱う
+        constraint = !escrow_account.has_pnft() @ MyError::UnsupportedPnftOffer,
+        // cNFTはsettleでleafとproofを受け取れないのでexchangeだけで扱う
+        constraint = !escrow_account.has_cnft() @ MyError::UnsupportedCnftOffer,
+        // Core assetはvault authorityがownerになるのでsettleで扱わずexchangeだけで扱う
+        constraint = !escrow_account.has_core_asset() @ MyError::UnsupportedCoreOffer,
+        // settleの時点の価格を決められないので固定価格だけ
+        constraint = !escrow_account.has_dutch_pricing() @ MyError::UnsupportedDutchOffer,
+    )]
+    pub escrow_account: Box<Account<'info, EscrowAccount>>,
+    #[account(
+        seeds = [
+            VAULT_AUTHORITY_PDA_SEED,
+            escrow_account.key().as_ref()
+        ],
+        bump = vault_authority.bump,
+    )]
+    pub vault_authority: Box<Account<'info, VaultAuthority>>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+    pub token_program: Program<'info, Token>,
+    pub token_2022_program: Program<'info, Token2022>,
+}
+
+pub fn handler<'info>(
+    ctx: Context<'_, '_, '_, 'info, Accept<'info>>,
+    taker_additional_sol_amount: u64,
+    vault_account_bumps: Vec<u8>,
+) -> Result<()> {
+    msg!("start accept");
+
+    require!(
+        !ctx.accounts
+            .escrow_account
+            .is_expired(Clock::get()?.unix_timestamp),
+        MyError::EscrowExpired
+    );
+
+    // remaining_accountsの数の検証 transfer hookのあるmintのaccountは最後に渡す
+    let taker_nft_amount_count = ctx.accounts.escrow_account.taker_nft_mints.len();
+    let transfer_hook_offset = taker_nft_amount_count * 3;
+    require_gte!(
+        ctx.remaining_accounts.len(),
+        transfer_hook_offset,
+        MyError::NftAmountMismatch
+    );
+
+    // escrow accountの中身と検証 open offerの場合はtoken accountが未定なのでmintだけ
+    for index in 0..taker_nft_amount_count {
+        let token_account = &ctx.remaining_accounts[index * 3];
+        let mint_account = &ctx.remaining_accounts[index * 3 + 2];
+
+        assert_keys_equal(
+            &mint_account.key(),
+            &ctx.accounts.escrow_account.taker_nft_mints[index],
+            MyError::MintPublicKeyMismatch,
+        )?;
+        if !ctx.accounts.escrow_account.is_open_offer() {
+            assert_keys_equal(
+                &token_account.key(),
+                &ctx.accounts.escrow_account.taker_nft_token_accounts[index],
+                MyError::AssociatedTokenPublicKeyMismatch,
+            )?;
+        }
+    }
+
+    // takerのNFTをvaultに預ける
+    let deposit_context = &DepositContext {
+        accounts: &DepositContextAccounts {
+            initializer: ctx.accounts.taker.to_account_info().clone(),
+            vault_authority: ctx.accounts.vault_authority.to_account_info().clone(),
+            token_program: ctx.accounts.token_program.to_account_info().clone(),
+            token_2022_program: ctx.accounts.token_2022_program.to_account_info().clone(),
+            rent: ctx.accounts.rent.clone(),
+        },
+        remaining_accounts: &ctx.remaining_accounts[..transfer_hook_offset],
+        transfer_hook_accounts: &ctx.remaining_accounts[transfer_hook_offset..],
+        program_id: ctx.program_id,
+    };
+    // open offerの場合もspaceはtaker_nft_amount分確保してあるのでreallocは不要
+    let taker_nft_quantities = ctx.accounts.escrow_account.taker_nft_quantities.clone();
+    ctx.accounts.escrow_account.taker_nft_token_accounts =
+        deposit(deposit_context, &ctx.accounts.escrow_account.key(), &vault_account_bumps, &taker_nft_quantities)?;
+    ctx.accounts.escrow_account.taker_vault_account_bumps = vault_account_bumps;
+    ctx.accounts.escrow_account.accepted_taker_key = ctx.accounts.taker.key();
+
+    if taker_additional_sol_amount > 0 {
+        let ix = anchor_lang::solana_program::system_instruction::transfer(
+            &ctx.accounts.taker.key(),
+            &ctx.accounts.escrow_account.key(),
+            taker_additional_sol_amount,
+        );
+        anchor_lang::solana_program::program::invoke(
+            &ix,
+            &[
+                ctx.accounts.taker.to_account_info().clone(),
+                ctx.accounts.escrow_account.to_account_info().clone(),
+            ],
+        )?;
+    }
+
+    msg!("end accept");
+    Ok(())
+}
@@ -0,0 +1,324 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Token;
+
+use crate::{
+    errors::MyError,
+    state::{EscrowAccount, ESCROW_PDA_SEED, VAULT_AUTHORITY_PDA_SEED, VaultAuthority},
+    traits::*,
+    utils::{assert_is_ata, assert_is_pda, assert_is_sft_mint},
+};
+
+// amendの引数 預けたNFTの一部を引き出し、追加で預け、takerに要求するNFTを置き換える
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct AmendArgs {
+    pub initializer_additional_sol_amount: u64,
+    pub taker_additional_sol_amount: u64,
+    pub withdraw_nft_amount: u8,
+    pub deposit_nft_amount: u8,
+    pub taker_nft_amount: u8,
+    pub vault_account_bumps: Vec<u8>,
+    pub deposit_nft_quantities: Vec<u64>,
+    pub taker_nft_quantities: Vec<u64>,
+}
+
+// cancelしてinitializeし直すとvaultごとにrentが往復するので、escrowを閉じずに条件を変更する
+#[derive(Accounts)]
+#[instruction(args: AmendArgs)]
+pub struct Amend<'info> {
+    #[account(
+        mut,
+        constraint = args.deposit_nft_amount as usize == args.vault_account_bumps.len() @ MyError::VaultAccountBumpsMismatch,
+        constraint = args.deposit_nft_amount as usize == args.deposit_nft_quantities.len() @ MyError::NftAmountMismatch,
+        constraint = args.taker_nft_amount as usize == args.taker_nft_quantities.len() @ MyError::NftAmountMismatch
+    )]
+    pub initializer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [
+            ESCROW_PDA_SEED,
+            initializer.key().as_ref(),
+            escrow_account.taker_key.as_ref(),
+            escrow_account.nonce.to_le_bytes().as_ref()
+        ],
+        bump = escrow_account.bump,
+        constraint = escrow_account.initializer_key == *initializer.key @ MyError::InitializerPublicKeyMismatch,
+        constraint = !escrow_account.is_accepted() @ MyError::EscrowAlreadyAccepted,
+    )]
+    pub escrow_account: Box<Account<'info, EscrowAccount>>,
+    #[account(
+        seeds = [
+            VAULT_AUTHORITY_PDA_SEED,
+            escrow_account.key().as_ref()
+        ],
+        bump = vault_authority.bump,
+    )]
+    pub vault_authority: Box<Account<'info, VaultAuthority>>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+    pub token_program: Program<'info, Token>,
+    pub token_2022_program: Program<'info, Token2022>,
+}
+
+pub fn handler<'info>(
+    ctx: Context<'_, '_, '_, 'info, Amend<'info>>,
+    args: AmendArgs,
+) -> Result<()> {
+    msg!("start amend");
+
+    let AmendArgs {
+        initializer_additional_sol_amount,
+        taker_additional_sol_amount,
+        withdraw_nft_amount,
+        deposit_nft_amount,
+        taker_nft_amount,
+        vault_account_bumps,
+        deposit_nft_quantities,
+        taker_nft_quantities,
+    } = args;
+
+    // remaining_accountsの数の検証
+    // 引き出すNFTの[token account, vault account, mint]、追加で預けるNFTの[token account, vault account, mint]、takerに要求するNFTの順
+    let is_open_offer = ctx.accounts.escrow_account.is_open_offer();
+    let taker_accounts_per_nft = if is_open_offer { 1 } else { 2 };
+    let withdraw_offset = withdraw_nft_amount as usize * 3;
+    let deposit_offset = withdraw_offset + deposit_nft_amount as usize * 3;
+    // transfer hookのあるmintのaccountは最後に渡す
+    let transfer_hook_offset = deposit_offset + taker_nft_amount as usize * taker_accounts_per_nft;
+    require_gte!(
+        ctx.remaining_accounts.len(),
+        transfer_hook_offset,
+        MyError::NftAmountMismatch
+    );
+    let transfer_hook_accounts = &ctx.remaining_accounts[transfer_hook_offset..];
+
+    let escrow_key = ctx.accounts.escrow_account.key();
+    let vault_authority_bump = ctx.accounts.vault_authority.bump;
+
+    // 引き出すNFTをinitializerに戻してvaultをcloseする
+    for index in 0..withdraw_nft_amount as usize {
+        let initializer_nft_token_account = &ctx.remaining_accounts[index * 3];
+        let vault_account = &ctx.remaining_accounts[index * 3 + 1];
+        let mint_account = &ctx.remaining_accounts[index * 3 + 2];
+
+        // escrow accountに預けられているNFTであること
+        let position = ctx
+            .accounts
+            .escrow_account
+            .initializer_nft_token_accounts
+            .iter()
+            .position(|key| key == initializer_nft_token_account.key)
+            .ok_or(MyError::AssociatedTokenPublicKeyMismatch)?;
+
+        assert_is_ata(
+            initializer_nft_token_account,
+            ctx.accounts.initializer.key,
+            mint_account,
+            0,
+        )?;
+
+        let quantity = ctx.accounts.escrow_account.initializer_nft_quantities[position];
+        assert_is_pda(
+            initializer_nft_token_account,
+            &ctx.accounts.escrow_account.key(),
+            ctx.accounts.escrow_account.vault_account_bumps[position],
+            vault_account,
+            &ctx.accounts.vault_authority.key(),
+            ctx.program_id,
+            quantity,
+        )?;
+
+        transfer_checked(
+            ctx.accounts
+                .into_transfer_to_initializer_context(
+                    vault_account,
+                    initializer_nft_token_account,
+                    mint_account,
+                )
+                .with_signer(&[&[
+                    VAULT_AUTHORITY_PDA_SEED,
+                    escrow_key.as_ref(),
+                    &[vault_authority_bump],
+                ]])
+                .with_remaining_accounts(transfer_hook_accounts.to_vec()),
+            quantity,
+            0,
+        )?;
+
+        close_account(
+            ctx.accounts
+                .into_close_context(vault_account)
+                .with_signer(&[&[
+                    VAULT_AUTHORITY_PDA_SEED,
+                    escrow_key.as_ref(),
+                    &[vault_authority_bump],
+                ]]),
+        )?;
+
+        ctx.accounts
+            .escrow_account
+            .initializer_nft_token_accounts
+            .remove(position);
+        ctx.accounts
+            .escrow_account
+            .vault_account_bumps
+            .remove(position);
+        ctx.accounts
+            .escrow_account
+            .initializer_nft_quantities
+            .remove(position);
+    }
+
+    // 追加のNFTを預ける
+    let deposit_context = &DepositContext {
+        accounts: &DepositContextAccounts {
+            initializer: ctx.accounts.initializer.to_account_info().clone(),
+            vault_authority: ctx.accounts.vault_authority.to_account_info().clone(),
+            token_program: ctx.accounts.token_program.to_account_info().clone(),
+            token_2022_program: ctx.accounts.token_2022_program.to_account_info().clone(),
+            rent: ctx.accounts.rent.clone(),
+        },
+        remaining_accounts: &ctx.remaining_accounts[withdraw_offset..deposit_offset],
+        transfer_hook_accounts,
+        program_id: ctx.program_id,
+    };
+    let deposited_token_accounts = deposit(deposit_context, &ctx.accounts.escrow_account.key(), &vault_account_bumps, &deposit_nft_quantities)?;
+    ctx.accounts
+        .escrow_account
+        .initializer_nft_quantities
+        .extend(deposit_nft_quantities);
+    ctx.accounts
+        .escrow_account
+        .initializer_nft_token_accounts
+        .extend(deposited_token_accounts);
+    ctx.accounts
+        .escrow_account
+        .vault_account_bumps
+        .extend(vault_account_bumps);
+
+    // takerに要求するNFTは丸ごと置き換える
+    let taker_key = ctx.accounts.escrow_account.taker_key;
+    ctx.accounts.escrow_account.taker_nft_token_accounts.clear();
+    ctx.accounts.escrow_account.taker_nft_mints.clear();
+    for (index, quantity) in taker_nft_quantities.iter().copied().enumerate() {
+        require_gt!(quantity, 0, MyError::InvalidNftQuantity);
+
+        if is_open_offer {
+            let mint_account = &ctx.remaining_accounts[deposit_offset + index];
+
+            assert_is_sft_mint(mint_account, quantity)?;
+
+            ctx.accounts
+                .escrow_account
+                .taker_nft_mints
+                .push(mint_account.key());
+        } else {
+            let token_account = &ctx.remaining_accounts[deposit_offset + index * 2];
+            let mint_account = &ctx.remaining_accounts[deposit_offset + index * 2 + 1];
+
+            assert_is_ata(token_account, &taker_key, mint_account, quantity)?;
+            // exchangeでdecimals 0としてtransfer_checkedするので、NFTとSFTだけを受け付ける
+            assert_is_sft_mint(mint_account, quantity)?;
+
+            ctx.accounts
+                .escrow_account
+                .taker_nft_token_accounts
+                .push(token_account.key());
+            ctx.accounts
+                .escrow_account
+                .taker_nft_mints
+                .push(mint_account.key());
+        }
+    }
+
+    ctx.accounts.escrow_account.taker_nft_quantities = taker_nft_quantities;
+
+    // 変更後もinitializeと同じ条件を満たしていること
+    let escrow_account = &mut ctx.accounts.escrow_account;
+    require_gt!(
+        initializer_additional_sol_amount as usize
+            + escrow_account.initializer_nft_token_accounts.len()
+            + escrow_account.initializer_fungible_legs.len()
+            + escrow_account.initializer_pnft_mints.len()
+            + escrow_account.initializer_cnft_assets.len()
+            + escrow_account.initializer_core_assets.len(),
+        0,
+        MyError::NotProvidedInitializerAssets
+    );
+    require_gt!(
+        taker_additional_sol_amount as usize
+            + escrow_account.taker_nft_mints.len()
+            + escrow_account.taker_collection_nft_amount as usize
+            + escrow_account.taker_fungible_legs.len()
+            + escrow_account.taker_pnft_mints.len()
+            + escrow_account.taker_cnft_assets.len()
+            + escrow_account.taker_core_assets.len(),
+        0,
+        MyError::NotProvidedTakerAssets
+    );
+    escrow_account.initializer_additional_sol_amount = initializer_additional_sol_amount;
+    escrow_account.taker_additional_sol_amount = taker_additional_sol_amount;
+    // dutch auctionの場合は開始価格が変わるので終了価格を下回っていないこと
+    require!(
+        escrow_account.is_valid_dutch_pricing(),
+        MyError::InvalidDutchPricing
+    );
+
+    // escrow accountはrentとinitializerの追加のsolを持っているので、変更後のsizeとsolに合わせて過不足を精算する
+    let space = escrow_account.current_space();
+    let escrow_account_info = escrow_account.to_account_info();
+    let required_lamports = ctx.accounts.rent.minimum_balance(space) + initializer_additional_sol_amount;
+    let current_lamports = escrow_account_info.lamports();
+
+    if required_lamports > current_lamports {
+        let ix = anchor_lang::solana_program::system_instruction::transfer(
+            &ctx.accounts.initializer.key(),
+            &escrow_account_info.key(),
+            required_lamports - current_lamports,
+        );
+        anchor_lang::solana_program::program::invoke(
+            &ix,
+            &[
+                ctx.accounts.initializer.to_account_info().clone(),
+                escrow_account_info.clone(),
+            ],
+        )?;
+    }
+
+    // accountのserializeはhandlerの後なので、ここでsizeを変えておく
+    escrow_account_info.realloc(space, false)?;
+
+    // 最難関： solanaのbugで金額を動かすのはCPIの後にする必要がある
+    if current_lamports > required_lamports {
+        **escrow_account_info.try_borrow_mut_lamports()? -= current_lamports - required_lamports;
+        **ctx.accounts.initializer.try_borrow_mut_lamports()? += current_lamports - required_lamports;
+    }
+
+    msg!("end amend");
+    Ok(())
+}
+
+impl<'info> TokenPrograms<'info> for Amend<'info> {
+    fn token_program(&self) -> &AccountInfo<'info> {
+        &self.token_program
+    }
+
+    fn token_2022_program(&self) -> &AccountInfo<'info> {
+        &self.token_2022_program
+    }
+}
+
+impl<'info> Cancel<'info> for Amend<'info> {
+    fn vault_authority(&self) -> &Box<Account<'info, VaultAuthority>> {
+        &self.vault_authority
+    }
+}
+
+impl<'info> Common<'info> for Amend<'info> {
+    fn vault_authority(&self) -> &Box<Account<'info, VaultAuthority>> {
+        &self.vault_authority
+    }
+
+    fn initializer(&self) -> &AccountInfo<'info> {
+        &self.initializer
+    }
+}
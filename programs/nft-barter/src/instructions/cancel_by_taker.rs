@@ -3,7 +3,7 @@ use anchor_spl::token::Token;
 
 use crate::{
     errors::MyError,
-    state::{EscrowAccount, VAULT_AUTHORITY_PDA_SEED, VaultAuthority},
+    state::{CnftLeaf, EscrowAccount, ESCROW_PDA_SEED, VAULT_AUTHORITY_PDA_SEED, VaultAuthority},
     traits::*,
 };
 
@@ -11,14 +11,13 @@ use crate::{
 pub struct CancelByTaker<'info> {
     #[account(mut)]
     pub initializer: SystemAccount<'info>,
-    #[account()]
+    #[account(mut)] // pNFTとCore assetのCPIのpayerになる
     pub taker: Signer<'info>,
     #[account(
         mut, 
         seeds = [
             VAULT_AUTHORITY_PDA_SEED,
-            initializer.key().as_ref(),
-            taker.key().as_ref()
+            escrow_account.key().as_ref()
         ], 
         bump = vault_authority.bump,
         close = initializer
@@ -26,18 +25,35 @@ pub struct CancelByTaker<'info> {
     pub vault_authority: Box<Account<'info, VaultAuthority>>,
     #[account(
         mut,
+        seeds = [
+            ESCROW_PDA_SEED,
+            initializer.key().as_ref(),
+            taker.key().as_ref(),
+            escrow_account.nonce.to_le_bytes().as_ref()
+        ],
+        bump = escrow_account.bump,
         constraint = escrow_account.initializer_key == *initializer.key @ MyError::InitializerPublicKeyMismatch,
         constraint = escrow_account.taker_key == *taker.key @ MyError::TakerPublicKeyMismatch,
         close = initializer // accountを実行後にcloseし、initializerにrentをreturnする　
     )]
     pub escrow_account: Box<Account<'info, EscrowAccount>>,
     pub token_program: Program<'info, Token>,
+    pub token_2022_program: Program<'info, Token2022>,
     pub rent: Sysvar<'info, Rent>,
 }
 
 pub fn handler<'info>(
     ctx: Context<'_, '_, '_, 'info, CancelByTaker<'info>>,
+    cnft_leaves: Vec<CnftLeaf>,
 ) -> Result<()> {
+    // transfer hookのあるmintのaccountはcancelで使うaccountの後ろに渡す
+    let cancel_accounts_count = ctx.accounts.escrow_account.cancel_accounts_count(&cnft_leaves);
+    require_gte!(
+        ctx.remaining_accounts.len(),
+        cancel_accounts_count,
+        MyError::NftAmountMismatch
+    );
+
     let cancel_context = &CancelContext {
         accounts: &CancelContextAccounts {
             initializer: ctx.accounts.initializer.to_account_info().clone(),
@@ -45,10 +61,14 @@ pub fn handler<'info>(
             vault_authority: ctx.accounts.vault_authority.clone(),
             escrow_account: ctx.accounts.escrow_account.clone(),
             token_program: ctx.accounts.token_program.clone(),
+            token_2022_program: ctx.accounts.token_2022_program.clone(),
             rent: ctx.accounts.rent.clone(),
+            payer: Some(ctx.accounts.taker.to_account_info()),
         },
-        remaining_accounts: ctx.remaining_accounts,
+        remaining_accounts: &ctx.remaining_accounts[..cancel_accounts_count],
+        transfer_hook_accounts: &ctx.remaining_accounts[cancel_accounts_count..],
         program_id: &ctx.program_id,
+        cnft_leaves: &cnft_leaves,
     };
     cancel(cancel_context)?;
     Ok(())
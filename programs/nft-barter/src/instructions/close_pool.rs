@@ -0,0 +1,126 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Token;
+
+use crate::{
+    errors::MyError,
+    state::{PoolAccount, VaultAuthority, POOL_PDA_SEED, VAULT_AUTHORITY_PDA_SEED},
+    traits::{close_account, transfer_checked, Release, Token2022, TokenPrograms},
+    utils::{assert_is_ata, assert_is_pda, assert_keys_equal},
+};
+
+// ownerが残っているNFTを引き出してpoolを閉じる 空になったpoolのrentもこれで回収する
+#[derive(Accounts)]
+pub struct ClosePool<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [
+            POOL_PDA_SEED,
+            owner.key().as_ref(),
+            pool_account.nonce.to_le_bytes().as_ref()
+        ],
+        bump = pool_account.bump,
+        constraint = pool_account.owner_key == *owner.key @ MyError::InitializerPublicKeyMismatch,
+        close = owner
+    )]
+    pub pool_account: Box<Account<'info, PoolAccount>>,
+    #[account(
+        mut,
+        seeds = [
+            VAULT_AUTHORITY_PDA_SEED,
+            pool_account.key().as_ref()
+        ],
+        bump = vault_authority.bump,
+        close = owner
+    )]
+    pub vault_authority: Box<Account<'info, VaultAuthority>>,
+    pub token_program: Program<'info, Token>,
+    pub token_2022_program: Program<'info, Token2022>,
+}
+
+pub fn handler<'info>(ctx: Context<'_, '_, '_, 'info, ClosePool<'info>>) -> Result<()> {
+    msg!("start close_pool");
+
+    // remaining accountsの数の検証
+    // 残っているNFTごとにtoken account, vault account, mintの順
+    let nft_amount_count = ctx.accounts.pool_account.nft_token_accounts.len();
+    // transfer hookのあるmintのaccountは最後に渡す
+    let transfer_hook_offset = nft_amount_count * 3;
+    require_gte!(
+        ctx.remaining_accounts.len(),
+        transfer_hook_offset,
+        MyError::NftAmountMismatch
+    );
+    let transfer_hook_accounts = &ctx.remaining_accounts[transfer_hook_offset..];
+
+    let pool_key = ctx.accounts.pool_account.key();
+    let vault_authority_bump = ctx.accounts.vault_authority.bump;
+
+    for index in 0..nft_amount_count {
+        let token_account = &ctx.remaining_accounts[index * 3];
+        let vault_account = &ctx.remaining_accounts[index * 3 + 1];
+        let mint_account = &ctx.remaining_accounts[index * 3 + 2];
+
+        // pool accountの中身と検証
+        assert_keys_equal(
+            &token_account.key(),
+            &ctx.accounts.pool_account.nft_token_accounts[index],
+            MyError::AssociatedTokenPublicKeyMismatch,
+        )?;
+
+        assert_is_ata(token_account, ctx.accounts.owner.key, mint_account, 0)?;
+
+        assert_is_pda(
+            token_account,
+            &ctx.accounts.pool_account.key(),
+            ctx.accounts.pool_account.vault_account_bumps[index],
+            vault_account,
+            &ctx.accounts.vault_authority.key(),
+            ctx.program_id,
+            1, // poolはNFTだけ
+        )?;
+
+        transfer_checked(
+            ctx.accounts
+                .into_transfer_to_receiver_context(vault_account, token_account, mint_account)
+                .with_signer(&[&[
+                    VAULT_AUTHORITY_PDA_SEED,
+                    pool_key.as_ref(),
+                    &[vault_authority_bump],
+                ]])
+                .with_remaining_accounts(transfer_hook_accounts.to_vec()),
+            1,
+            0,
+        )?;
+
+        close_account(
+            ctx.accounts
+                .into_close_to_context(vault_account, &ctx.accounts.owner)
+                .with_signer(&[&[
+                    VAULT_AUTHORITY_PDA_SEED,
+                    pool_key.as_ref(),
+                    &[vault_authority_bump],
+                ]]),
+        )?;
+    }
+
+    msg!("end close_pool");
+    Ok(())
+}
+
+impl<'info> TokenPrograms<'info> for ClosePool<'info> {
+    fn token_program(&self) -> &AccountInfo<'info> {
+        &self.token_program
+    }
+
+    fn token_2022_program(&self) -> &AccountInfo<'info> {
+        &self.token_2022_program
+    }
+}
+
+impl<'info> Release<'info> for ClosePool<'info> {
+    fn vault_authority(&self) -> &Box<Account<'info, VaultAuthority>> {
+        &self.vault_authority
+    }
+}
@@ -0,0 +1,95 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    errors::MyError,
+    state::{
+        SealedAuctionAccount, SealedBidAccount, MAX_SEALED_BIDS, SEALED_AUCTION_PDA_SEED,
+        SEALED_BID_PDA_SEED,
+    },
+};
+
+#[derive(Accounts)]
+#[instruction(commitment: [u8; 32], deposit_amount: u64)]
+pub struct CommitSealedBid<'info> {
+    #[account(
+        mut,
+        constraint = bidder.to_account_info().try_lamports().unwrap() >= deposit_amount @ MyError::TakerInsufficientFunds
+    )]
+    pub bidder: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [
+            SEALED_AUCTION_PDA_SEED,
+            sealed_auction_account.seller_key.as_ref(),
+            sealed_auction_account.nonce.to_le_bytes().as_ref()
+        ],
+        bump = sealed_auction_account.bump,
+        constraint = sealed_auction_account.bid_count < MAX_SEALED_BIDS @ MyError::SealedBidLimitReached,
+    )]
+    pub sealed_auction_account: Box<Account<'info, SealedAuctionAccount>>,
+    // 1つのauctionにbidderごとに1つだけ
+    #[account(
+        init,
+        payer = bidder,
+        space = SealedBidAccount::SPACE,
+        seeds = [
+            SEALED_BID_PDA_SEED,
+            sealed_auction_account.key().as_ref(),
+            bidder.key().as_ref()
+        ],
+        bump,
+    )]
+    pub sealed_bid_account: Box<Account<'info, SealedBidAccount>>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler(
+    ctx: Context<CommitSealedBid>,
+    commitment: [u8; 32],
+    deposit_amount: u64,
+) -> Result<()> {
+    msg!("start commit_sealed_bid");
+
+    require!(
+        ctx.accounts
+            .sealed_auction_account
+            .is_commit_window(Clock::get()?.unix_timestamp),
+        MyError::AuctionEnded
+    );
+
+    // bidの枠はMAX_SEALED_BIDSまでなので、sellerが決めた下限とreserve priceに届かないdepositで枠を埋められないようにする
+    require_gte!(
+        deposit_amount,
+        ctx.accounts.sealed_auction_account.min_deposit,
+        MyError::SealedBidDepositTooSmall
+    );
+    require_gte!(
+        deposit_amount,
+        ctx.accounts.sealed_auction_account.reserve_price,
+        MyError::BidBelowReservePrice
+    );
+
+    let ix = anchor_lang::solana_program::system_instruction::transfer(
+        &ctx.accounts.bidder.key(),
+        &ctx.accounts.sealed_bid_account.key(),
+        deposit_amount,
+    );
+    anchor_lang::solana_program::program::invoke(
+        &ix,
+        &[
+            ctx.accounts.bidder.to_account_info().clone(),
+            ctx.accounts.sealed_bid_account.to_account_info().clone(),
+        ],
+    )?;
+
+    let sealed_bid_account = &mut ctx.accounts.sealed_bid_account;
+    sealed_bid_account.auction_key = ctx.accounts.sealed_auction_account.key();
+    sealed_bid_account.bidder_key = ctx.accounts.bidder.key();
+    sealed_bid_account.commitment = commitment;
+    sealed_bid_account.deposit_amount = deposit_amount;
+    sealed_bid_account.bump = *ctx.bumps.get("sealed_bid_account").unwrap();
+    ctx.accounts.sealed_auction_account.bid_count += 1;
+
+    msg!("end commit_sealed_bid");
+    Ok(())
+}
@@ -0,0 +1,238 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Token;
+
+use crate::{
+    errors::MyError,
+    state::{EscrowAccount, EscrowLegCounts, ESCROW_PDA_SEED, VAULT_AUTHORITY_PDA_SEED, VaultAuthority},
+    traits::*,
+    utils::{assert_is_ata, assert_is_sft_mint},
+};
+
+// counter_offerの引数 新しいescrowではtakerがinitializer、initializerがtakerになる
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct CounterOfferArgs {
+    pub initializer_additional_sol_amount: u64,
+    pub taker_additional_sol_amount: u64,
+    pub initializer_nft_amount: u8,
+    pub taker_nft_amount: u8,
+    pub vault_account_bumps: Vec<u8>,
+    pub nonce: u64,
+    pub expires_at: i64, // 0の場合は期限なし
+    pub initializer_nft_quantities: Vec<u64>,
+    pub taker_nft_quantities: Vec<u64>,
+}
+
+// takerが条件を変えたい場合に、元のescrowをcancelして逆向きのescrowを1つのトランザクションで作る
+// counter offerではtakerがinitializer、initializerがtakerになる
+#[derive(Accounts)]
+#[instruction(args: CounterOfferArgs)]
+pub struct CounterOffer<'info> {
+    #[account(
+        mut,
+        constraint = args.initializer_additional_sol_amount as usize + args.initializer_nft_amount as usize > 0 @ MyError::NotProvidedInitializerAssets,
+        constraint = taker.to_account_info().try_lamports().unwrap() >= args.initializer_additional_sol_amount @ MyError::TakerInsufficientFunds,
+        constraint = args.initializer_nft_amount as usize == args.vault_account_bumps.len() @ MyError::VaultAccountBumpsMismatch,
+        constraint = args.initializer_nft_amount as usize == args.initializer_nft_quantities.len() @ MyError::NftAmountMismatch,
+        constraint = args.taker_nft_amount as usize == args.taker_nft_quantities.len() @ MyError::NftAmountMismatch
+    )]
+    pub taker: Signer<'info>,
+    #[account(
+        mut,
+        constraint = args.taker_additional_sol_amount as usize + args.taker_nft_amount as usize > 0 @ MyError::NotProvidedTakerAssets,
+    )]
+    pub initializer: SystemAccount<'info>,
+    #[account(
+        mut,
+        seeds = [
+            VAULT_AUTHORITY_PDA_SEED,
+            escrow_account.key().as_ref()
+        ],
+        bump = vault_authority.bump,
+        close = initializer
+    )]
+    pub vault_authority: Box<Account<'info, VaultAuthority>>,
+    // open offerはtaker_keyがPubkey::default()なのでcounter offerできない
+    #[account(
+        mut,
+        seeds = [
+            ESCROW_PDA_SEED,
+            initializer.key().as_ref(),
+            taker.key().as_ref(),
+            escrow_account.nonce.to_le_bytes().as_ref()
+        ],
+        bump = escrow_account.bump,
+        constraint = escrow_account.initializer_key == *initializer.key @ MyError::InitializerPublicKeyMismatch,
+        constraint = escrow_account.taker_key == *taker.key @ MyError::TakerPublicKeyMismatch,
+        // cNFTの返却にはleafとproofが必要なので、cancel_by_takerしてからinitializeする
+        constraint = !escrow_account.has_cnft() @ MyError::UnsupportedCnftOffer,
+        close = initializer // 元のescrowのrentはinitializerに返す
+    )]
+    pub escrow_account: Box<Account<'info, EscrowAccount>>,
+    #[account(
+        init,
+        payer = taker,
+        seeds = [
+            ESCROW_PDA_SEED,
+            taker.key().as_ref(),
+            initializer.key().as_ref(),
+            args.nonce.to_le_bytes().as_ref()
+        ],
+        bump,
+        space = EscrowAccount::space(&EscrowLegCounts { nft: args.initializer_nft_amount as usize, ..Default::default() }, &EscrowLegCounts { nft: args.taker_nft_amount as usize, ..Default::default() })
+    )]
+    pub counter_escrow_account: Box<Account<'info, EscrowAccount>>,
+    #[account(
+        init,
+        payer = taker,
+        space = 8 // internal anchor discriminator
+            + 1, // bump
+        seeds = [
+            VAULT_AUTHORITY_PDA_SEED,
+            counter_escrow_account.key().as_ref()
+        ],
+        bump,
+    )]
+    pub counter_vault_authority: Box<Account<'info, VaultAuthority>>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+    pub token_program: Program<'info, Token>,
+    pub token_2022_program: Program<'info, Token2022>,
+}
+
+pub fn handler<'info>(
+    ctx: Context<'_, '_, '_, 'info, CounterOffer<'info>>,
+    args: CounterOfferArgs,
+) -> Result<()> {
+    msg!("start counter_offer");
+
+    let CounterOfferArgs {
+        initializer_additional_sol_amount,
+        taker_additional_sol_amount,
+        initializer_nft_amount,
+        taker_nft_amount,
+        vault_account_bumps,
+        nonce,
+        expires_at,
+        initializer_nft_quantities,
+        taker_nft_quantities,
+    } = args;
+
+    if expires_at != 0 {
+        require_gt!(expires_at, Clock::get()?.unix_timestamp, MyError::EscrowExpired);
+    }
+
+    // remaining_accountsの数の検証
+    // 元のescrowのinitializerのNFTとfungible token、takerが預けるNFT、takerが要求するinitializerのNFTの順
+    // transfer hookのあるmintのaccountは最後に渡す
+    let cancel_offset = ctx.accounts.escrow_account.cancel_accounts_count(&[]);
+    let deposit_offset = cancel_offset + initializer_nft_amount as usize * 3;
+    let transfer_hook_offset = deposit_offset + taker_nft_amount as usize * 2;
+    require_gte!(
+        ctx.remaining_accounts.len(),
+        transfer_hook_offset,
+        MyError::NftAmountMismatch
+    );
+
+    // 元のescrowのNFTとSOLをinitializerに戻す
+    let cancel_context = &CancelContext {
+        accounts: &CancelContextAccounts {
+            initializer: ctx.accounts.initializer.to_account_info().clone(),
+            taker: ctx.accounts.taker.to_account_info().clone(),
+            vault_authority: ctx.accounts.vault_authority.clone(),
+            escrow_account: ctx.accounts.escrow_account.clone(),
+            token_program: ctx.accounts.token_program.clone(),
+            token_2022_program: ctx.accounts.token_2022_program.clone(),
+            rent: ctx.accounts.rent.clone(),
+            payer: Some(ctx.accounts.taker.to_account_info()),
+        },
+        remaining_accounts: &ctx.remaining_accounts[..cancel_offset],
+        transfer_hook_accounts: &ctx.remaining_accounts[transfer_hook_offset..],
+        program_id: ctx.program_id,
+        cnft_leaves: &[],
+    };
+    cancel(cancel_context)?;
+
+    // takerのNFTを新しいvaultに預ける
+    let deposit_context = &DepositContext {
+        accounts: &DepositContextAccounts {
+            initializer: ctx.accounts.taker.to_account_info().clone(),
+            vault_authority: ctx.accounts.counter_vault_authority.to_account_info().clone(),
+            token_program: ctx.accounts.token_program.to_account_info().clone(),
+            token_2022_program: ctx.accounts.token_2022_program.to_account_info().clone(),
+            rent: ctx.accounts.rent.clone(),
+        },
+        remaining_accounts: &ctx.remaining_accounts[cancel_offset..deposit_offset],
+        transfer_hook_accounts: &ctx.remaining_accounts[transfer_hook_offset..],
+        program_id: ctx.program_id,
+    };
+    ctx.accounts
+        .counter_escrow_account
+        .initializer_nft_token_accounts =
+        deposit(deposit_context, &ctx.accounts.counter_escrow_account.key(), &vault_account_bumps, &initializer_nft_quantities)?;
+
+    // cancelで戻したNFTも要求できるように、initializerのToken Accountの検証はcancelの後に行う
+    for (index, quantity) in taker_nft_quantities.iter().copied().enumerate() {
+        let token_account = &ctx.remaining_accounts[deposit_offset + index * 2];
+        let mint_account = &ctx.remaining_accounts[deposit_offset + index * 2 + 1];
+
+        require_gt!(quantity, 0, MyError::InvalidNftQuantity);
+        assert_is_ata(
+            token_account,
+            ctx.accounts.initializer.key,
+            mint_account,
+            quantity,
+        )?;
+        // exchangeでdecimals 0としてtransfer_checkedするので、NFTとSFTだけを受け付ける
+        assert_is_sft_mint(mint_account, quantity)?;
+
+        ctx.accounts
+            .counter_escrow_account
+            .taker_nft_token_accounts
+            .push(token_account.key());
+        ctx.accounts
+            .counter_escrow_account
+            .taker_nft_mints
+            .push(mint_account.key());
+    }
+
+    let previous_escrow_key = ctx.accounts.escrow_account.key();
+    // 交渉中のofferも同じmarketplaceが持ち込んだものとして扱う
+    let referrer_key = ctx.accounts.escrow_account.referrer_key;
+    let referrer_basis_points = ctx.accounts.escrow_account.referrer_basis_points;
+    let counter_escrow_account = &mut ctx.accounts.counter_escrow_account;
+    counter_escrow_account.initializer_key = ctx.accounts.taker.key();
+    counter_escrow_account.initializer_additional_sol_amount = initializer_additional_sol_amount;
+    counter_escrow_account.taker_key = ctx.accounts.initializer.key();
+    counter_escrow_account.taker_additional_sol_amount = taker_additional_sol_amount;
+    counter_escrow_account.taker_collection_key = Pubkey::default();
+    counter_escrow_account.taker_collection_nft_amount = 0;
+    counter_escrow_account.vault_account_bumps = vault_account_bumps;
+    counter_escrow_account.initializer_nft_quantities = initializer_nft_quantities;
+    counter_escrow_account.taker_nft_quantities = taker_nft_quantities;
+    counter_escrow_account.nonce = nonce;
+    counter_escrow_account.bump = *ctx.bumps.get("counter_escrow_account").unwrap();
+    counter_escrow_account.expires_at = expires_at;
+    counter_escrow_account.previous_escrow_key = previous_escrow_key; // 交渉の履歴をたどれるようにする
+    counter_escrow_account.referrer_key = referrer_key;
+    counter_escrow_account.referrer_basis_points = referrer_basis_points;
+    ctx.accounts.counter_vault_authority.bump =
+        *ctx.bumps.get("counter_vault_authority").unwrap();
+
+    if initializer_additional_sol_amount > 0 {
+        let ix = anchor_lang::solana_program::system_instruction::transfer(
+            &ctx.accounts.taker.key(),
+            &ctx.accounts.counter_escrow_account.key(),
+            initializer_additional_sol_amount,
+        );
+        anchor_lang::solana_program::program::invoke(
+            &ix,
+            &[
+                ctx.accounts.taker.to_account_info().clone(),
+                ctx.accounts.counter_escrow_account.to_account_info().clone(),
+            ],
+        )?;
+    }
+
+    msg!("end counter_offer");
+    Ok(())
+}
@@ -0,0 +1,117 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Token;
+
+use crate::{
+    errors::MyError,
+    state::{
+        AuctionAccount, BidAccount, VaultAuthority, AUCTION_PDA_SEED, BID_PDA_SEED,
+        VAULT_AUTHORITY_PDA_SEED,
+    },
+    traits::*,
+};
+
+// sellerがNFTをvaultに預けてauctionを始める vaultの作り方はinitializeと同じ
+#[derive(Accounts)]
+#[instruction(vault_account_bumps: Vec<u8>, nft_quantities: Vec<u64>, reserve_price: u64, min_bid_increment: u64, end_at: i64, nonce: u64)]
+pub struct CreateAuction<'info> {
+    #[account(
+        mut,
+        constraint = !vault_account_bumps.is_empty() @ MyError::NotProvidedInitializerAssets,
+        constraint = vault_account_bumps.len() == nft_quantities.len() @ MyError::NftAmountMismatch,
+        constraint = min_bid_increment > 0 @ MyError::InvalidBidIncrement
+    )]
+    pub seller: Signer<'info>,
+    #[account(
+        init,
+        payer = seller,
+        seeds = [
+            AUCTION_PDA_SEED,
+            seller.key().as_ref(),
+            nonce.to_le_bytes().as_ref()
+        ],
+        bump,
+        space = AuctionAccount::space(vault_account_bumps.len())
+    )]
+    pub auction_account: Box<Account<'info, AuctionAccount>>,
+    #[account(
+        init,
+        payer = seller,
+        space = 8 // internal anchor discriminator
+            + 1, // bump
+        seeds = [
+            VAULT_AUTHORITY_PDA_SEED,
+            auction_account.key().as_ref() // auctionごとにvaultの権限を分ける
+        ],
+        bump,
+    )]
+    pub vault_authority: Box<Account<'info, VaultAuthority>>,
+    #[account(
+        init,
+        payer = seller,
+        space = BidAccount::SPACE,
+        seeds = [
+            BID_PDA_SEED,
+            auction_account.key().as_ref()
+        ],
+        bump,
+    )]
+    pub bid_account: Box<Account<'info, BidAccount>>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+    pub token_program: Program<'info, Token>,
+    pub token_2022_program: Program<'info, Token2022>,
+}
+
+pub fn handler<'info>(
+    ctx: Context<'_, '_, '_, 'info, CreateAuction<'info>>,
+    vault_account_bumps: Vec<u8>,
+    nft_quantities: Vec<u64>,
+    reserve_price: u64,
+    min_bid_increment: u64,
+    end_at: i64,
+    nonce: u64,
+) -> Result<()> {
+    msg!("start create_auction");
+
+    // 過去の終了時刻ではbidできないauctionになってしまう
+    require_gt!(end_at, Clock::get()?.unix_timestamp, MyError::InvalidAuctionEndTime);
+
+    // transfer hookのあるmintのaccountは最後に渡す
+    let transfer_hook_offset = vault_account_bumps.len() * 3;
+    require_gte!(
+        ctx.remaining_accounts.len(),
+        transfer_hook_offset,
+        MyError::NftAmountMismatch
+    );
+
+    // 3で割ってあまり0にtoken account 1にvault account 2にmint account
+    let deposit_context = &DepositContext {
+        accounts: &DepositContextAccounts {
+            initializer: ctx.accounts.seller.to_account_info().clone(),
+            vault_authority: ctx.accounts.vault_authority.to_account_info().clone(),
+            token_program: ctx.accounts.token_program.to_account_info().clone(),
+            token_2022_program: ctx.accounts.token_2022_program.to_account_info().clone(),
+            rent: ctx.accounts.rent.clone(),
+        },
+        remaining_accounts: &ctx.remaining_accounts[..transfer_hook_offset],
+        transfer_hook_accounts: &ctx.remaining_accounts[transfer_hook_offset..],
+        program_id: ctx.program_id,
+    };
+    let nft_token_accounts = deposit(deposit_context, &ctx.accounts.auction_account.key(), &vault_account_bumps, &nft_quantities)?;
+
+    let auction_account = &mut ctx.accounts.auction_account;
+    auction_account.seller_key = ctx.accounts.seller.key();
+    auction_account.nft_token_accounts = nft_token_accounts;
+    auction_account.vault_account_bumps = vault_account_bumps;
+    auction_account.nft_quantities = nft_quantities;
+    auction_account.reserve_price = reserve_price;
+    auction_account.min_bid_increment = min_bid_increment;
+    auction_account.end_at = end_at;
+    auction_account.nonce = nonce;
+    auction_account.bump = *ctx.bumps.get("auction_account").unwrap();
+    ctx.accounts.vault_authority.bump = *ctx.bumps.get("vault_authority").unwrap();
+    ctx.accounts.bid_account.bump = *ctx.bumps.get("bid_account").unwrap();
+
+    msg!("end create_auction");
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Token;
+
+use crate::{
+    errors::MyError,
+    state::{PoolAccount, VaultAuthority, POOL_PDA_SEED, VAULT_AUTHORITY_PDA_SEED},
+    traits::*,
+    utils::assert_is_collection_nft,
+};
+
+// ownerがcollectionのNFTをvaultに預けてpoolを作る vaultの作り方はinitializeと同じ
+#[derive(Accounts)]
+#[instruction(vault_account_bumps: Vec<u8>, offered_collection_key: Pubkey, accepted_collection_key: Pubkey, accepted_nft_ratio: u8, swap_fee: u64, nonce: u64)]
+pub struct CreatePool<'info> {
+    #[account(
+        mut,
+        constraint = !vault_account_bumps.is_empty() @ MyError::NotProvidedInitializerAssets,
+        constraint = accepted_nft_ratio > 0 @ MyError::InvalidPoolRatio,
+        constraint = accepted_collection_key != Pubkey::default() @ MyError::CollectionPublicKeyMismatch
+    )]
+    pub owner: Signer<'info>,
+    #[account(
+        init,
+        payer = owner,
+        seeds = [
+            POOL_PDA_SEED,
+            owner.key().as_ref(),
+            nonce.to_le_bytes().as_ref()
+        ],
+        bump,
+        space = PoolAccount::space(vault_account_bumps.len())
+    )]
+    pub pool_account: Box<Account<'info, PoolAccount>>,
+    #[account(
+        init,
+        payer = owner,
+        space = 8 // internal anchor discriminator
+            + 1, // bump
+        seeds = [
+            VAULT_AUTHORITY_PDA_SEED,
+            pool_account.key().as_ref() // poolごとにvaultの権限を分ける
+        ],
+        bump,
+    )]
+    pub vault_authority: Box<Account<'info, VaultAuthority>>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+    pub token_program: Program<'info, Token>,
+    pub token_2022_program: Program<'info, Token2022>,
+}
+
+pub fn handler<'info>(
+    ctx: Context<'_, '_, '_, 'info, CreatePool<'info>>,
+    vault_account_bumps: Vec<u8>,
+    offered_collection_key: Pubkey,
+    accepted_collection_key: Pubkey,
+    accepted_nft_ratio: u8,
+    swap_fee: u64,
+    nonce: u64,
+) -> Result<()> {
+    msg!("start create_pool");
+
+    // remaining accountsの数の検証
+    // NFTごとの[token account, vault account, mint]の後にNFTごとのmetadata
+    let nft_amount_count = vault_account_bumps.len();
+    let metadata_offset = nft_amount_count * 3;
+    // transfer hookのあるmintのaccountは最後に渡す
+    let transfer_hook_offset = metadata_offset + nft_amount_count;
+    require_gte!(
+        ctx.remaining_accounts.len(),
+        transfer_hook_offset,
+        MyError::NftAmountMismatch
+    );
+
+    // poolが出すNFTも同じcollectionであること
+    for index in 0..nft_amount_count {
+        assert_is_collection_nft(
+            &ctx.remaining_accounts[index * 3 + 2],
+            &ctx.remaining_accounts[metadata_offset + index],
+            &offered_collection_key,
+        )?;
+    }
+
+    // 3で割ってあまり0にtoken account 1にvault account 2にmint account
+    let deposit_context = &DepositContext {
+        accounts: &DepositContextAccounts {
+            initializer: ctx.accounts.owner.to_account_info().clone(),
+            vault_authority: ctx.accounts.vault_authority.to_account_info().clone(),
+            token_program: ctx.accounts.token_program.to_account_info().clone(),
+            token_2022_program: ctx.accounts.token_2022_program.to_account_info().clone(),
+            rent: ctx.accounts.rent.clone(),
+        },
+        remaining_accounts: &ctx.remaining_accounts[..metadata_offset],
+        transfer_hook_accounts: &ctx.remaining_accounts[transfer_hook_offset..],
+        program_id: ctx.program_id,
+    };
+    let nft_token_accounts = deposit(
+        deposit_context,
+        &ctx.accounts.pool_account.key(),
+        &vault_account_bumps,
+        &vec![1; nft_amount_count], // poolはcollectionのNFTだけ
+    )?;
+
+    let pool_account = &mut ctx.accounts.pool_account;
+    pool_account.owner_key = ctx.accounts.owner.key();
+    pool_account.offered_collection_key = offered_collection_key;
+    pool_account.accepted_collection_key = accepted_collection_key;
+    pool_account.accepted_nft_ratio = accepted_nft_ratio;
+    pool_account.swap_fee = swap_fee;
+    pool_account.nft_token_accounts = nft_token_accounts;
+    pool_account.vault_account_bumps = vault_account_bumps;
+    pool_account.nonce = nonce;
+    pool_account.bump = *ctx.bumps.get("pool_account").unwrap();
+    ctx.accounts.vault_authority.bump = *ctx.bumps.get("vault_authority").unwrap();
+
+    msg!("end create_pool");
+    Ok(())
+}
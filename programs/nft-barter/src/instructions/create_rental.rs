@@ -0,0 +1,98 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Token;
+
+use crate::{
+    errors::MyError,
+    state::{RentalAccount, VaultAuthority, RENTAL_PDA_SEED, VAULT_AUTHORITY_PDA_SEED},
+    traits::*,
+    utils::assert_owned_by,
+};
+
+// lenderがNFTをvaultに預けて貸し出す vaultの作り方はinitializeと同じ
+#[derive(Accounts)]
+#[instruction(vault_account_bump: u8, daily_price: u64, max_days: u16, nonce: u64)]
+pub struct CreateRental<'info> {
+    #[account(
+        mut,
+        constraint = max_days > 0 @ MyError::InvalidRentalDays
+    )]
+    pub lender: Signer<'info>,
+    #[account(
+        init,
+        payer = lender,
+        seeds = [
+            RENTAL_PDA_SEED,
+            lender.key().as_ref(),
+            nonce.to_le_bytes().as_ref()
+        ],
+        bump,
+        space = RentalAccount::SPACE
+    )]
+    pub rental_account: Box<Account<'info, RentalAccount>>,
+    #[account(
+        init,
+        payer = lender,
+        space = 8 // internal anchor discriminator
+            + 1, // bump
+        seeds = [
+            VAULT_AUTHORITY_PDA_SEED,
+            rental_account.key().as_ref() // rentalごとにvaultの権限を分ける
+        ],
+        bump,
+    )]
+    pub vault_authority: Box<Account<'info, VaultAuthority>>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+    pub token_program: Program<'info, Token>,
+    pub token_2022_program: Program<'info, Token2022>,
+}
+
+pub fn handler<'info>(
+    ctx: Context<'_, '_, '_, 'info, CreateRental<'info>>,
+    vault_account_bump: u8,
+    daily_price: u64,
+    max_days: u16,
+    nonce: u64,
+) -> Result<()> {
+    msg!("start create_rental");
+
+    // token account, vault account, mintの順 transfer hookのあるmintのaccountは最後に渡す
+    let transfer_hook_offset = 3;
+    require_gte!(
+        ctx.remaining_accounts.len(),
+        transfer_hook_offset,
+        MyError::NftAmountMismatch
+    );
+
+    // 借りられている間はToken Metadataでfreezeするので、Token ProgramのNFTだけ貸せる
+    assert_owned_by(&ctx.remaining_accounts[2], &Token::id())?;
+
+    let deposit_context = &DepositContext {
+        accounts: &DepositContextAccounts {
+            initializer: ctx.accounts.lender.to_account_info().clone(),
+            vault_authority: ctx.accounts.vault_authority.to_account_info().clone(),
+            token_program: ctx.accounts.token_program.to_account_info().clone(),
+            token_2022_program: ctx.accounts.token_2022_program.to_account_info().clone(),
+            rent: ctx.accounts.rent.clone(),
+        },
+        remaining_accounts: &ctx.remaining_accounts[..transfer_hook_offset],
+        transfer_hook_accounts: &ctx.remaining_accounts[transfer_hook_offset..],
+        program_id: ctx.program_id,
+    };
+    let nft_token_accounts = deposit(deposit_context, &ctx.accounts.rental_account.key(), &[vault_account_bump], &[1])?; // 貸すのはNFTだけ
+
+    let rental_account = &mut ctx.accounts.rental_account;
+    rental_account.lender_key = ctx.accounts.lender.key();
+    rental_account.nft_token_account = nft_token_accounts[0];
+    rental_account.vault_account_bump = vault_account_bump;
+    rental_account.daily_price = daily_price;
+    rental_account.max_days = max_days;
+    rental_account.renter_key = Pubkey::default();
+    rental_account.rented_until = 0;
+    rental_account.nonce = nonce;
+    rental_account.bump = *ctx.bumps.get("rental_account").unwrap();
+    ctx.accounts.vault_authority.bump = *ctx.bumps.get("vault_authority").unwrap();
+
+    msg!("end create_rental");
+    Ok(())
+}
@@ -0,0 +1,131 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Token;
+
+use crate::{
+    errors::MyError,
+    state::{
+        SealedAuctionAccount, VaultAuthority, MAX_FEE_BASIS_POINTS, SEALED_AUCTION_PDA_SEED,
+        VAULT_AUTHORITY_PDA_SEED,
+    },
+    traits::*,
+};
+
+// create_sealed_auctionの引数
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct CreateSealedAuctionArgs {
+    pub vault_account_bumps: Vec<u8>,
+    pub nft_quantities: Vec<u64>,
+    pub reserve_price: u64,
+    pub min_deposit: u64, // 0より大きいこと
+    pub commit_end_at: i64,
+    pub reveal_end_at: i64,
+    pub unrevealed_forfeit_basis_points: u16,
+    pub nonce: u64,
+}
+
+// sellerがNFTをvaultに預けてsealed bidのauctionを始める vaultの作り方はinitializeと同じ
+#[derive(Accounts)]
+#[instruction(args: CreateSealedAuctionArgs)]
+pub struct CreateSealedAuction<'info> {
+    #[account(
+        mut,
+        constraint = !args.vault_account_bumps.is_empty() @ MyError::NotProvidedInitializerAssets,
+        constraint = args.vault_account_bumps.len() == args.nft_quantities.len() @ MyError::NftAmountMismatch,
+        constraint = args.unrevealed_forfeit_basis_points <= MAX_FEE_BASIS_POINTS @ MyError::InvalidSealedAuctionSchedule
+    )]
+    pub seller: Signer<'info>,
+    #[account(
+        init,
+        payer = seller,
+        seeds = [
+            SEALED_AUCTION_PDA_SEED,
+            seller.key().as_ref(),
+            args.nonce.to_le_bytes().as_ref()
+        ],
+        bump,
+        space = SealedAuctionAccount::space(args.vault_account_bumps.len())
+    )]
+    pub sealed_auction_account: Box<Account<'info, SealedAuctionAccount>>,
+    #[account(
+        init,
+        payer = seller,
+        space = 8 // internal anchor discriminator
+            + 1, // bump
+        seeds = [
+            VAULT_AUTHORITY_PDA_SEED,
+            sealed_auction_account.key().as_ref() // auctionごとにvaultの権限を分ける
+        ],
+        bump,
+    )]
+    pub vault_authority: Box<Account<'info, VaultAuthority>>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+    pub token_program: Program<'info, Token>,
+    pub token_2022_program: Program<'info, Token2022>,
+}
+
+pub fn handler<'info>(
+    ctx: Context<'_, '_, '_, 'info, CreateSealedAuction<'info>>,
+    args: CreateSealedAuctionArgs,
+) -> Result<()> {
+    msg!("start create_sealed_auction");
+
+    let CreateSealedAuctionArgs {
+        vault_account_bumps,
+        nft_quantities,
+        reserve_price,
+        min_deposit,
+        commit_end_at,
+        reveal_end_at,
+        unrevealed_forfeit_basis_points,
+        nonce,
+    } = args;
+
+    // commitの後にrevealの期間がないとbidを公開できない
+    require!(
+        Clock::get()?.unix_timestamp < commit_end_at && commit_end_at < reveal_end_at,
+        MyError::InvalidSealedAuctionSchedule
+    );
+    // bidの枠はMAX_SEALED_BIDSまでなので、1 lamportのcommitで枠を埋められないようにdepositの下限を決める
+    require_gt!(min_deposit, 0, MyError::SealedBidDepositTooSmall);
+
+    // transfer hookのあるmintのaccountは最後に渡す
+    let transfer_hook_offset = vault_account_bumps.len() * 3;
+    require_gte!(
+        ctx.remaining_accounts.len(),
+        transfer_hook_offset,
+        MyError::NftAmountMismatch
+    );
+
+    // 3で割ってあまり0にtoken account 1にvault account 2にmint account
+    let deposit_context = &DepositContext {
+        accounts: &DepositContextAccounts {
+            initializer: ctx.accounts.seller.to_account_info().clone(),
+            vault_authority: ctx.accounts.vault_authority.to_account_info().clone(),
+            token_program: ctx.accounts.token_program.to_account_info().clone(),
+            token_2022_program: ctx.accounts.token_2022_program.to_account_info().clone(),
+            rent: ctx.accounts.rent.clone(),
+        },
+        remaining_accounts: &ctx.remaining_accounts[..transfer_hook_offset],
+        transfer_hook_accounts: &ctx.remaining_accounts[transfer_hook_offset..],
+        program_id: ctx.program_id,
+    };
+    let nft_token_accounts = deposit(deposit_context, &ctx.accounts.sealed_auction_account.key(), &vault_account_bumps, &nft_quantities)?;
+
+    let sealed_auction_account = &mut ctx.accounts.sealed_auction_account;
+    sealed_auction_account.seller_key = ctx.accounts.seller.key();
+    sealed_auction_account.nft_token_accounts = nft_token_accounts;
+    sealed_auction_account.vault_account_bumps = vault_account_bumps;
+    sealed_auction_account.nft_quantities = nft_quantities;
+    sealed_auction_account.reserve_price = reserve_price;
+    sealed_auction_account.min_deposit = min_deposit;
+    sealed_auction_account.commit_end_at = commit_end_at;
+    sealed_auction_account.reveal_end_at = reveal_end_at;
+    sealed_auction_account.unrevealed_forfeit_basis_points = unrevealed_forfeit_basis_points;
+    sealed_auction_account.nonce = nonce;
+    sealed_auction_account.bump = *ctx.bumps.get("sealed_auction_account").unwrap();
+    ctx.accounts.vault_authority.bump = *ctx.bumps.get("vault_authority").unwrap();
+
+    msg!("end create_sealed_auction");
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    errors::MyError,
+    state::{StandingOfferAccount, STANDING_OFFER_PDA_SEED},
+};
+
+// buyerがcollectionのNFTを何個までいくらで買うかを決めて、予算のSOLをまとめて預ける
+#[derive(Accounts)]
+#[instruction(collection_key: Pubkey, price_per_nft: u64, count: u8, nonce: u64)]
+pub struct CreateStandingOffer<'info> {
+    #[account(
+        mut,
+        constraint = collection_key != Pubkey::default() @ MyError::CollectionPublicKeyMismatch,
+        constraint = price_per_nft > 0 && count > 0 @ MyError::InvalidStandingOffer
+    )]
+    pub buyer: Signer<'info>,
+    #[account(
+        init,
+        payer = buyer,
+        seeds = [
+            STANDING_OFFER_PDA_SEED,
+            buyer.key().as_ref(),
+            nonce.to_le_bytes().as_ref()
+        ],
+        bump,
+        space = StandingOfferAccount::SPACE
+    )]
+    pub standing_offer_account: Box<Account<'info, StandingOfferAccount>>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler(
+    ctx: Context<CreateStandingOffer>,
+    collection_key: Pubkey,
+    price_per_nft: u64,
+    count: u8,
+    nonce: u64,
+) -> Result<()> {
+    msg!("start create_standing_offer");
+
+    let standing_offer_account = &mut ctx.accounts.standing_offer_account;
+    standing_offer_account.buyer_key = ctx.accounts.buyer.key();
+    standing_offer_account.collection_key = collection_key;
+    standing_offer_account.price_per_nft = price_per_nft;
+    standing_offer_account.remaining_count = count;
+    standing_offer_account.nonce = nonce;
+    standing_offer_account.bump = *ctx.bumps.get("standing_offer_account").unwrap();
+
+    let budget = standing_offer_account
+        .remaining_budget()
+        .ok_or(MyError::InvalidStandingOffer)?;
+    require_gte!(
+        ctx.accounts.buyer.to_account_info().try_lamports()?,
+        budget,
+        MyError::InitializerInsufficientFunds
+    );
+
+    let ix = anchor_lang::solana_program::system_instruction::transfer(
+        &ctx.accounts.buyer.key(),
+        &ctx.accounts.standing_offer_account.key(),
+        budget,
+    );
+    anchor_lang::solana_program::program::invoke(
+        &ix,
+        &[
+            ctx.accounts.buyer.to_account_info().clone(),
+            ctx.accounts
+                .standing_offer_account
+                .to_account_info()
+                .clone(),
+        ],
+    )?;
+
+    msg!("end create_standing_offer");
+    Ok(())
+}
@@ -0,0 +1,122 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Token;
+
+use crate::{
+    errors::MyError,
+    state::{RingAccount, VaultAuthority, RING_PDA_SEED, VAULT_AUTHORITY_PDA_SEED},
+    traits::*,
+    utils::assert_keys_equal,
+};
+
+#[derive(Accounts)]
+pub struct DepositRing<'info> {
+    #[account(mut)]
+    pub participant: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [
+            RING_PDA_SEED,
+            ring_account.creator_key.as_ref(),
+            ring_account.nonce.to_le_bytes().as_ref()
+        ],
+        bump = ring_account.bump,
+    )]
+    pub ring_account: Box<Account<'info, RingAccount>>,
+    #[account(
+        seeds = [
+            VAULT_AUTHORITY_PDA_SEED,
+            ring_account.key().as_ref()
+        ],
+        bump = vault_authority.bump,
+    )]
+    pub vault_authority: Box<Account<'info, VaultAuthority>>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+    pub token_program: Program<'info, Token>,
+    pub token_2022_program: Program<'info, Token2022>,
+}
+
+pub fn handler<'info>(
+    ctx: Context<'_, '_, '_, 'info, DepositRing<'info>>,
+    vault_account_bumps: Vec<u8>,
+) -> Result<()> {
+    msg!("start deposit_ring");
+
+    let leg_index = ctx
+        .accounts
+        .ring_account
+        .legs
+        .iter()
+        .position(|leg| leg.participant_key == *ctx.accounts.participant.key)
+        .ok_or(MyError::NotRingParticipant)?;
+    let leg = &ctx.accounts.ring_account.legs[leg_index];
+    require!(!leg.deposited, MyError::RingLegAlreadyDeposited);
+    require_eq!(
+        vault_account_bumps.len(),
+        leg.nft_mints.len(),
+        MyError::VaultAccountBumpsMismatch
+    );
+    require!(
+        ctx.accounts.participant.lamports() >= leg.additional_sol_amount,
+        MyError::InitializerInsufficientFunds
+    );
+
+    // initialize_ringで決めたmintと同じものを預けること transfer hookのあるmintのaccountは最後に渡す
+    let transfer_hook_offset = leg.nft_mints.len() * 3;
+    require_gte!(
+        ctx.remaining_accounts.len(),
+        transfer_hook_offset,
+        MyError::NftAmountMismatch
+    );
+    for (index, nft_mint) in leg.nft_mints.iter().enumerate() {
+        assert_keys_equal(
+            &ctx.remaining_accounts[index * 3 + 2].key(),
+            nft_mint,
+            MyError::MintPublicKeyMismatch,
+        )?;
+    }
+    let additional_sol_amount = leg.additional_sol_amount;
+
+    // 3で割ってあまり0にtoken account 1にvault account 2にmint account
+    let deposit_context = &DepositContext {
+        accounts: &DepositContextAccounts {
+            initializer: ctx.accounts.participant.to_account_info().clone(),
+            vault_authority: ctx.accounts.vault_authority.to_account_info().clone(),
+            token_program: ctx.accounts.token_program.to_account_info().clone(),
+            token_2022_program: ctx.accounts.token_2022_program.to_account_info().clone(),
+            rent: ctx.accounts.rent.clone(),
+        },
+        remaining_accounts: &ctx.remaining_accounts[..transfer_hook_offset],
+        transfer_hook_accounts: &ctx.remaining_accounts[transfer_hook_offset..],
+        program_id: ctx.program_id,
+    };
+    let nft_token_accounts = deposit(
+        deposit_context,
+        &ctx.accounts.ring_account.key(),
+        &vault_account_bumps,
+        &vec![1; vault_account_bumps.len()], // ringはNFTだけ
+    )?;
+
+    let leg = &mut ctx.accounts.ring_account.legs[leg_index];
+    leg.nft_token_accounts = nft_token_accounts;
+    leg.vault_account_bumps = vault_account_bumps;
+    leg.deposited = true;
+
+    if additional_sol_amount > 0 {
+        let ix = anchor_lang::solana_program::system_instruction::transfer(
+            &ctx.accounts.participant.key(),
+            &ctx.accounts.ring_account.key(),
+            additional_sol_amount,
+        );
+        anchor_lang::solana_program::program::invoke(
+            &ix,
+            &[
+                ctx.accounts.participant.to_account_info().clone(),
+                ctx.accounts.ring_account.to_account_info().clone(),
+            ],
+        )?;
+    }
+
+    msg!("end deposit_ring");
+    Ok(())
+}
@@ -0,0 +1,189 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Token;
+use mpl_token_metadata::pda::find_master_edition_account;
+
+use crate::{
+    errors::MyError,
+    state::{RentalAccount, VaultAuthority, RENTAL_PDA_SEED, VAULT_AUTHORITY_PDA_SEED},
+    traits::{
+        close_account, thaw_delegated_nft, transfer_checked, DelegatedNftAccounts, Release,
+        Token2022, TokenMetadata, TokenPrograms,
+    },
+    utils::{assert_is_ata, assert_is_pda, assert_keys_equal},
+};
+
+// 期間が終わった後は誰が呼んでもlenderに返すだけなので誰でも呼べる まだ借りられていない場合はlenderだけが取り下げられる
+// 借りられている場合はrenterのATAをthawし、delegateのvault authorityがlenderに戻す
+#[derive(Accounts)]
+pub struct EndRental<'info> {
+    pub authority: Signer<'info>,
+    #[account(mut)]
+    pub lender: SystemAccount<'info>,
+    #[account(
+        mut,
+        seeds = [
+            RENTAL_PDA_SEED,
+            lender.key().as_ref(),
+            rental_account.nonce.to_le_bytes().as_ref()
+        ],
+        bump = rental_account.bump,
+        constraint = rental_account.lender_key == *lender.key @ MyError::InitializerPublicKeyMismatch,
+        close = lender
+    )]
+    pub rental_account: Box<Account<'info, RentalAccount>>,
+    #[account(
+        mut,
+        seeds = [
+            VAULT_AUTHORITY_PDA_SEED,
+            rental_account.key().as_ref()
+        ],
+        bump = vault_authority.bump,
+        close = lender
+    )]
+    pub vault_authority: Box<Account<'info, VaultAuthority>>,
+    pub token_program: Program<'info, Token>,
+    pub token_2022_program: Program<'info, Token2022>,
+    pub token_metadata_program: Program<'info, TokenMetadata>,
+}
+
+pub fn handler<'info>(ctx: Context<'_, '_, '_, 'info, EndRental<'info>>) -> Result<()> {
+    msg!("start end_rental");
+
+    let rental_account = &ctx.accounts.rental_account;
+    let rental_key = rental_account.key();
+    let vault_authority_bump = ctx.accounts.vault_authority.bump;
+    let signer_seeds: &[&[&[u8]]] = &[&[
+        VAULT_AUTHORITY_PDA_SEED,
+        rental_key.as_ref(),
+        &[vault_authority_bump],
+    ]];
+
+    if rental_account.is_rented() {
+        require!(
+            !rental_account.is_active(Clock::get()?.unix_timestamp),
+            MyError::RentalNotEnded
+        );
+
+        // renterのtoken account, lenderのtoken account, mint, master editionの順
+        require_gte!(
+            ctx.remaining_accounts.len(),
+            4,
+            MyError::NotFoundRemainingAccounts
+        );
+        let renter_token_account = &ctx.remaining_accounts[0];
+        let token_account = &ctx.remaining_accounts[1];
+        let mint_account = &ctx.remaining_accounts[2];
+        let edition_account = &ctx.remaining_accounts[3];
+
+        // rental accountの中身と検証
+        assert_keys_equal(
+            &renter_token_account.key(),
+            &rental_account.renter_token_account,
+            MyError::AssociatedTokenPublicKeyMismatch,
+        )?;
+        assert_keys_equal(
+            &token_account.key(),
+            &rental_account.nft_token_account,
+            MyError::AssociatedTokenPublicKeyMismatch,
+        )?;
+        assert_is_ata(token_account, ctx.accounts.lender.key, mint_account, 0)?;
+        assert_keys_equal(
+            &find_master_edition_account(mint_account.key).0,
+            edition_account.key,
+            MyError::MetadataPublicKeyMismatch,
+        )?;
+
+        thaw_delegated_nft(
+            &DelegatedNftAccounts {
+                token_metadata_program: ctx.accounts.token_metadata_program.to_account_info(),
+                delegate: ctx.accounts.vault_authority.to_account_info(),
+                token_account: renter_token_account.clone(),
+                edition: edition_account.clone(),
+                mint: mint_account.clone(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+            },
+            signer_seeds,
+        )?;
+
+        // delegateの分を全部動かすのでrenterのtoken accountのdelegateも外れる
+        transfer_checked(
+            ctx.accounts
+                .into_transfer_to_receiver_context(renter_token_account, token_account, mint_account)
+                .with_signer(signer_seeds),
+            1,
+            0,
+        )?;
+    } else {
+        assert_keys_equal(
+            ctx.accounts.authority.key,
+            ctx.accounts.lender.key,
+            MyError::AssociatedAuthorityMismatch,
+        )?;
+
+        // token account, vault account, mintの順 transfer hookのあるmintのaccountは最後に渡す
+        let transfer_hook_offset = 3;
+        require_gte!(
+            ctx.remaining_accounts.len(),
+            transfer_hook_offset,
+            MyError::NftAmountMismatch
+        );
+        let transfer_hook_accounts = &ctx.remaining_accounts[transfer_hook_offset..];
+
+        let token_account = &ctx.remaining_accounts[0];
+        let vault_account = &ctx.remaining_accounts[1];
+        let mint_account = &ctx.remaining_accounts[2];
+
+        // rental accountの中身と検証
+        assert_keys_equal(
+            &token_account.key(),
+            &rental_account.nft_token_account,
+            MyError::AssociatedTokenPublicKeyMismatch,
+        )?;
+
+        assert_is_ata(token_account, ctx.accounts.lender.key, mint_account, 0)?;
+
+        assert_is_pda(
+            token_account,
+            &rental_key,
+            rental_account.vault_account_bump,
+            vault_account,
+            &ctx.accounts.vault_authority.key(),
+            ctx.program_id,
+            1, // 貸すのはNFTだけ
+        )?;
+
+        transfer_checked(
+            ctx.accounts
+                .into_transfer_to_receiver_context(vault_account, token_account, mint_account)
+                .with_signer(signer_seeds)
+                .with_remaining_accounts(transfer_hook_accounts.to_vec()),
+            1,
+            0,
+        )?;
+
+        close_account(
+            ctx.accounts
+                .into_close_to_context(vault_account, &ctx.accounts.lender)
+                .with_signer(signer_seeds),
+        )?;
+    }
+
+    msg!("end end_rental");
+    Ok(())
+}
+
+impl<'info> TokenPrograms<'info> for EndRental<'info> {
+    fn token_program(&self) -> &AccountInfo<'info> {
+        &self.token_program
+    }
+
+    fn token_2022_program(&self) -> &AccountInfo<'info> {
+        &self.token_2022_program
+    }
+}
+
+impl<'info> Release<'info> for EndRental<'info> {
+    fn vault_authority(&self) -> &Box<Account<'info, VaultAuthority>> {
+        &self.vault_authority
+    }
+}
@@ -1,31 +1,40 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{Transfer, self, Token};
+use anchor_spl::token::Token;
 
-use crate::{utils::{assert_is_ata, assert_is_pda, assert_keys_equal}, errors::MyError, state::{EscrowAccount, VaultAuthority}, traits::Common};
+use crate::{events::ExchangeSettled, utils::{assert_is_ata, assert_is_collection_nft, assert_is_fungible_vault, assert_is_pda, assert_keys_equal, transfer_amount_with_fee, unpack_token_account}, errors::MyError, state::{CnftLeaf, Config, EscrowAccount, VaultAuthority}, traits::{close_account, collect_royalties, harvest_withheld_fees, pay_sol_split, transfer_checked, transfer_cnft, transfer_core, transfer_pnft, CnftContextAccounts, Common, CoreContextAccounts, PnftContextAccounts, SolSplit, SolSplitAccounts, Token2022, TokenPrograms, TransferChecked}};
 
-use crate::state::VAULT_AUTHORITY_PDA_SEED;
+use crate::state::{cnft_accounts_count, core_accounts_count, pnft_accounts_count, CNFT_ACCOUNTS_PER_ITEM, CNFT_PROGRAM_ACCOUNTS, CORE_ACCOUNTS_PER_ITEM, CONFIG_PDA_SEED, CORE_PROGRAM_ACCOUNTS, ESCROW_PDA_SEED, PNFT_ACCOUNTS_PER_ITEM, PNFT_PROGRAM_ACCOUNTS, VAULT_AUTHORITY_PDA_SEED};
 
 #[derive(Accounts)]
 #[instruction(initializer_additional_sol_amount: u64, taker_additional_sol_amount: u64)]
 pub struct Exchange<'info> {
     #[account(
         mut, 
-        constraint = taker_additional_sol_amount as usize + escrow_account.taker_nft_token_accounts.len() > 0 @ MyError::NotProvidedTakerAssets,
+        constraint = taker_additional_sol_amount as usize + escrow_account.taker_nft_mints.len() + escrow_account.taker_collection_nft_amount as usize + escrow_account.taker_fungible_legs.len() + escrow_account.taker_pnft_mints.len() + escrow_account.taker_cnft_assets.len() + escrow_account.taker_core_assets.len() > 0 @ MyError::NotProvidedTakerAssets,
         constraint = taker.to_account_info().try_lamports().unwrap() >= taker_additional_sol_amount @ MyError::TakerInsufficientFunds,
-        constraint = taker_additional_sol_amount == escrow_account.taker_additional_sol_amount @ MyError::TakerAdditionalSolAmountMismatch
+        // dutch auctionの場合はtakerが払ってもよい上限 価格はhandlerで検証
+        constraint = escrow_account.has_dutch_pricing() || taker_additional_sol_amount == escrow_account.taker_additional_sol_amount @ MyError::TakerAdditionalSolAmountMismatch
     )]
     pub taker: Signer<'info>,
     #[account(
         mut, // mutであることが必須
-        constraint = initializer_additional_sol_amount as usize + escrow_account.initializer_nft_token_accounts.len() > 0 @ MyError::NotProvidedInitializerAssets,
+        constraint = initializer_additional_sol_amount as usize + escrow_account.initializer_nft_token_accounts.len() + escrow_account.initializer_fungible_legs.len() + escrow_account.initializer_pnft_mints.len() + escrow_account.initializer_cnft_assets.len() + escrow_account.initializer_core_assets.len() > 0 @ MyError::NotProvidedInitializerAssets,
         // constraint = initializer.to_account_info().try_lamports().unwrap() >= initializer_additional_sol_amount @ MyError::InitializerInsufficientFunds, initialze時点でescrowにお金が移動しているので不要
         constraint = initializer_additional_sol_amount == escrow_account.initializer_additional_sol_amount @ MyError::InitializerAdditionalSolAmountMismatch
     )]
     pub initializer: SystemAccount<'info>,
     #[account(
         mut,
+        seeds = [
+            ESCROW_PDA_SEED,
+            initializer.key().as_ref(),
+            escrow_account.taker_key.as_ref(),
+            escrow_account.nonce.to_le_bytes().as_ref()
+        ],
+        bump = escrow_account.bump,
         constraint = escrow_account.initializer_key == *initializer.key @ MyError::InitializerPublicKeyMismatch,
-        constraint = escrow_account.taker_key == *taker.key @ MyError::TakerPublicKeyMismatch,
+        constraint = !escrow_account.is_accepted() @ MyError::EscrowAlreadyAccepted,
+        constraint = escrow_account.is_open_offer() || escrow_account.taker_key == *taker.key @ MyError::TakerPublicKeyMismatch, // open offerなら誰でもtakerになれる
         close = initializer // 関係なし Error: failed to send transaction: Transaction simulation failed: Error processing Instruction 0: instruction spent from the balance of an account it does not own
     )]
     pub escrow_account: Box<Account<'info, EscrowAccount>>,
@@ -33,14 +42,30 @@ pub struct Exchange<'info> {
         mut, 
         seeds = [
             VAULT_AUTHORITY_PDA_SEED,
-            initializer.key().as_ref(),
-            taker.key().as_ref()
+            escrow_account.key().as_ref()
         ], 
         bump = vault_authority.bump,
         close = initializer
     )]
     pub vault_authority: Box<Account<'info, VaultAuthority>>,
+    #[account(
+        seeds = [CONFIG_PDA_SEED],
+        bump = config.bump,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        mut,
+        constraint = treasury.key() == config.treasury_key @ MyError::TreasuryPublicKeyMismatch
+    )]
+    pub treasury: SystemAccount<'info>,
+    /// CHECK: referrerがいない場合は使わないので何を渡してもよい
+    #[account(
+        mut,
+        constraint = escrow_account.referrer_key == Pubkey::default() || referrer.key() == escrow_account.referrer_key @ MyError::ReferrerPublicKeyMismatch
+    )]
+    pub referrer: UncheckedAccount<'info>,
     pub token_program: Program<'info, Token>,
+    pub token_2022_program: Program<'info, Token2022>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
 }
@@ -49,10 +74,26 @@ pub struct Exchange<'info> {
 pub fn handler<'info>(
     ctx: Context<'_, '_, '_, 'info, Exchange<'info>>,
     initializer_additional_sol_amount: u64,
-    _taker_additional_sol_amount: u64,
+    max_taker_additional_sol_amount: u64,
+    cnft_leaves: Vec<CnftLeaf>, // initializer_cnft_assets, taker_cnft_assetsの順
 ) -> Result<()> {
     msg!("start exchange");
 
+    // 期限切れのofferはexchangeできない expireでinitializerに返却する
+    let now = Clock::get()?.unix_timestamp;
+    require!(
+        !ctx.accounts.escrow_account.is_expired(now),
+        MyError::EscrowExpired
+    );
+
+    // dutch auctionは時間とともに価格が下がるので、takerが渡した上限を超えていないこと
+    let taker_additional_sol_amount = ctx.accounts.escrow_account.current_taker_sol_amount(now);
+    require_gte!(
+        max_taker_additional_sol_amount,
+        taker_additional_sol_amount,
+        MyError::PriceExceedsMaximum
+    );
+
     // remaining accountsの数の検証
     let initializer_nft_amount = ctx
         .accounts
@@ -60,13 +101,33 @@ pub fn handler<'info>(
         .initializer_nft_token_accounts
         .len();
 
-    let taker_nft_amount = ctx.accounts.escrow_account.taker_nft_token_accounts.len();
+    let taker_nft_amount = ctx.accounts.escrow_account.taker_nft_mints.len();
     let initializer_nft_amount_count = initializer_nft_amount;
     let taker_nft_amount_count = taker_nft_amount;
+    let taker_collection_nft_amount_count =
+        ctx.accounts.escrow_account.taker_collection_nft_amount as usize;
+    let initializer_fungible_amount_count = ctx.accounts.escrow_account.initializer_fungible_legs.len();
+    let taker_fungible_amount_count = ctx.accounts.escrow_account.taker_fungible_legs.len();
+    let initializer_pnft_amount_count = ctx.accounts.escrow_account.initializer_pnft_mints.len();
+    let taker_pnft_amount_count = ctx.accounts.escrow_account.taker_pnft_mints.len();
+    let initializer_core_amount_count = ctx.accounts.escrow_account.initializer_core_assets.len();
+    let taker_core_amount_count = ctx.accounts.escrow_account.taker_core_assets.len();
     let remaining_accounts_count = (initializer_nft_amount_count * 3 + taker_nft_amount_count * 2)
         as usize
-        + (initializer_nft_amount_count + taker_nft_amount_count) * 2 as usize;
+        + (initializer_nft_amount_count + taker_nft_amount_count) * 2 as usize
+        + taker_collection_nft_amount_count * 4 // collectionのNFTはtakerのtoken account, initializerのtoken account, mint, metadata
+        + (initializer_fungible_amount_count + taker_fungible_amount_count) * 3 // fungible tokenは払い出し元, 受け取るtoken account, mint
+        + pnft_accounts_count(initializer_pnft_amount_count + taker_pnft_amount_count) // pNFTはprogramのaccountとitemごとのaccount
+        + cnft_accounts_count(&cnft_leaves) // cNFTはprogramのaccountとleafごとの[merkle tree, tree authority, proof...]
+        + core_accounts_count(initializer_core_amount_count + taker_core_amount_count); // Core assetはprogramのaccountとassetごとの[asset, collection]
+    let initializer_cnft_amount_count = ctx.accounts.escrow_account.initializer_cnft_assets.len();
     require_eq!(
+        cnft_leaves.len(),
+        initializer_cnft_amount_count + ctx.accounts.escrow_account.taker_cnft_assets.len(),
+        MyError::CnftAssetMismatch
+    );
+    // transfer hookのあるmintのaccountは最後に渡す
+    require_gte!(
         ctx.remaining_accounts.len(),
         remaining_accounts_count,
         MyError::NftAmountMismatch
@@ -89,16 +150,18 @@ pub fn handler<'info>(
             token_account,
             ctx.accounts.initializer.key,
             mint_account,
-            false,
+            0,
         )?;
 
         // Vaultの検証
         assert_is_pda(
             &token_account,
+            &ctx.accounts.escrow_account.key(),
             ctx.accounts.escrow_account.vault_account_bumps[index],
             vault_account,
             &ctx.accounts.vault_authority.key(),
             ctx.program_id,
+            ctx.accounts.escrow_account.initializer_nft_quantities[index],
         )?;
     }
 
@@ -106,12 +169,19 @@ pub fn handler<'info>(
         let token_account = &ctx.remaining_accounts[initializer_nft_amount_count * 3 + index * 2];
         let mint_account = &ctx.remaining_accounts[initializer_nft_amount_count * 3 + index * 2 + 1];
 
+        // escrow accountの中身と検証
+        assert_keys_equal(
+            &mint_account.key(),
+            &ctx.accounts.escrow_account.taker_nft_mints[index],
+            MyError::MintPublicKeyMismatch,
+        )?;
+
         // initializerのvaultがないToken Accountの検証
         assert_is_ata(
             token_account,
             ctx.accounts.initializer.key,
             mint_account,
-            false,
+            0,
         )?;
     }
 
@@ -123,45 +193,210 @@ pub fn handler<'info>(
 
         // takerのToken Accountの検証
         if index >= initializer_nft_amount_count {
-            // escrow accountの中身と検証
+            // escrow accountの中身と検証 open offerの場合はtoken accountが未定なのでmintだけ
             assert_keys_equal(
-                &token_account.key(),
-                &ctx.accounts.escrow_account.taker_nft_token_accounts[index-initializer_nft_amount_count],
-                MyError::AssociatedTokenPublicKeyMismatch,
+                &mint_account.key(),
+                &ctx.accounts.escrow_account.taker_nft_mints[index-initializer_nft_amount_count],
+                MyError::MintPublicKeyMismatch,
             )?;
+            if !ctx.accounts.escrow_account.is_open_offer() {
+                assert_keys_equal(
+                    &token_account.key(),
+                    &ctx.accounts.escrow_account.taker_nft_token_accounts[index-initializer_nft_amount_count],
+                    MyError::AssociatedTokenPublicKeyMismatch,
+                )?;
+            }
 
-            assert_is_ata(token_account, ctx.accounts.taker.key, mint_account, true)?;
+            assert_is_ata(
+                token_account,
+                ctx.accounts.taker.key,
+                mint_account,
+                ctx.accounts.escrow_account.taker_nft_quantities[index-initializer_nft_amount_count],
+            )?;
         } else {
-            assert_is_ata(token_account, ctx.accounts.taker.key, mint_account, false)?;
+            assert_is_ata(token_account, ctx.accounts.taker.key, mint_account, 0)?;
         }
     }
 
+    // collectionのNFTはtakerが選んだものをmetadataで検証
+    let collection_offset = offset + (initializer_nft_amount_count + taker_nft_amount_count) * 2;
+    for index in 0..taker_collection_nft_amount_count {
+        let taker_nft_token_account = &ctx.remaining_accounts[collection_offset + index * 4];
+        let initializer_nft_token_account = &ctx.remaining_accounts[collection_offset + index * 4 + 1];
+        let mint_account = &ctx.remaining_accounts[collection_offset + index * 4 + 2];
+        let metadata_account = &ctx.remaining_accounts[collection_offset + index * 4 + 3];
+
+        assert_is_collection_nft(
+            mint_account,
+            metadata_account,
+            &ctx.accounts.escrow_account.taker_collection_key,
+        )?;
+
+        assert_is_ata(taker_nft_token_account, ctx.accounts.taker.key, mint_account, 1)?;
+        assert_is_ata(
+            initializer_nft_token_account,
+            ctx.accounts.initializer.key,
+            mint_account,
+            0,
+        )?;
+    }
+
+    // fungible tokenはinitializerの分がvault, takerのtoken account, mint takerの分がtakerのtoken account, initializerのtoken account, mint
+    let fungible_offset = collection_offset + taker_collection_nft_amount_count * 4;
+    for (index, fungible_leg) in ctx.accounts.escrow_account.initializer_fungible_legs.iter().enumerate() {
+        let vault_account = &ctx.remaining_accounts[fungible_offset + index * 3];
+        let taker_token_account = &ctx.remaining_accounts[fungible_offset + index * 3 + 1];
+        let mint_account = &ctx.remaining_accounts[fungible_offset + index * 3 + 2];
+
+        assert_keys_equal(&mint_account.key(), &fungible_leg.mint, MyError::MintPublicKeyMismatch)?;
+        // transfer feeのあるmintは、今のepochのfeeが引かれてもlegの量がtakerに届くだけvaultにあること
+        let vault_account_data = assert_is_fungible_vault(
+            vault_account,
+            &ctx.accounts.escrow_account.key(),
+            mint_account,
+            ctx.accounts.escrow_account.initializer_fungible_vault_account_bumps[index],
+            &ctx.accounts.vault_authority.key(),
+            ctx.program_id,
+        )?;
+        require_gte!(
+            vault_account_data.amount,
+            transfer_amount_with_fee(mint_account, fungible_leg.amount)?,
+            MyError::InsufficientTokenBalance
+        );
+        assert_is_ata(taker_token_account, ctx.accounts.taker.key, mint_account, 0)?;
+    }
+
+    let taker_fungible_offset = fungible_offset + initializer_fungible_amount_count * 3;
+    for (index, fungible_leg) in ctx.accounts.escrow_account.taker_fungible_legs.iter().enumerate() {
+        let taker_token_account = &ctx.remaining_accounts[taker_fungible_offset + index * 3];
+        let initializer_token_account = &ctx.remaining_accounts[taker_fungible_offset + index * 3 + 1];
+        let mint_account = &ctx.remaining_accounts[taker_fungible_offset + index * 3 + 2];
+
+        assert_keys_equal(&mint_account.key(), &fungible_leg.mint, MyError::MintPublicKeyMismatch)?;
+        let taker_token_account_data = assert_is_ata(taker_token_account, ctx.accounts.taker.key, mint_account, 0)?;
+        // transfer feeのあるmintはfeeの分も上乗せして送るので、その分も持っていること
+        require_gte!(
+            taker_token_account_data.amount,
+            transfer_amount_with_fee(mint_account, fungible_leg.amount)?,
+            MyError::InsufficientTokenBalance
+        );
+        assert_is_ata(initializer_token_account, ctx.accounts.initializer.key, mint_account, 0)?;
+    }
+
+    // SOLが動く場合はNFTが売られたのと同じなので、SOLと逆向きに動くNFTのcreatorにroyaltyを払う
+    // initializerのNFTのmetadataとcreator、takerのNFTのmetadataとcreatorの順で渡す SOLが0の向きは不要
+    // cNFTとCore assetはroyaltyの対象外なのでmintsに含めない
+    let initializer_royalty_mints = (0..initializer_nft_amount_count)
+        .map(|index| ctx.remaining_accounts[index * 3 + 2].key())
+        .chain(ctx.accounts.escrow_account.initializer_pnft_mints.iter().copied())
+        .collect::<Vec<Pubkey>>();
+    let taker_royalty_mints = ctx
+        .accounts
+        .escrow_account
+        .taker_nft_mints
+        .iter()
+        .copied()
+        .chain((0..taker_collection_nft_amount_count).map(|index| ctx.remaining_accounts[collection_offset + index * 4 + 2].key()))
+        .chain(ctx.accounts.escrow_account.taker_pnft_mints.iter().copied())
+        .collect::<Vec<Pubkey>>();
+    let (initializer_royalty_payments, initializer_royalty_accounts_count) = collect_royalties(
+        &ctx.remaining_accounts[remaining_accounts_count..],
+        &initializer_royalty_mints,
+        taker_additional_sol_amount,
+    )?;
+    let taker_royalty_offset = remaining_accounts_count + initializer_royalty_accounts_count;
+    let (taker_royalty_payments, taker_royalty_accounts_count) = collect_royalties(
+        &ctx.remaining_accounts[taker_royalty_offset..],
+        &taker_royalty_mints,
+        initializer_additional_sol_amount,
+    )?;
+    // transfer hookのあるmintのaccountは最後に渡す
+    let transfer_hook_accounts = &ctx.remaining_accounts[taker_royalty_offset + taker_royalty_accounts_count..];
+
     // initializerがtokenをget
+    // NFTとSFTはdecimals 0
     for index in 0..taker_nft_amount_count {
-        token::transfer(
-            ctx.accounts.into_transfer_to_initializer_context(
-                &ctx.remaining_accounts[offset + initializer_nft_amount_count * 2 + index * 2],
-                &ctx.remaining_accounts[initializer_nft_amount_count * 3 + index * 2],
-            ),
+        transfer_checked(
+            ctx.accounts
+                .into_transfer_to_initializer_context(
+                    &ctx.remaining_accounts[offset + initializer_nft_amount_count * 2 + index * 2],
+                    &ctx.remaining_accounts[initializer_nft_amount_count * 3 + index * 2],
+                    &ctx.remaining_accounts[offset + initializer_nft_amount_count * 2 + index * 2 + 1],
+                )
+                .with_remaining_accounts(transfer_hook_accounts.to_vec()),
+            ctx.accounts.escrow_account.taker_nft_quantities[index],
+            0,
+        )?;
+    }
+
+    for index in 0..taker_collection_nft_amount_count {
+        transfer_checked(
+            ctx.accounts
+                .into_transfer_to_initializer_context(
+                    &ctx.remaining_accounts[collection_offset + index * 4],
+                    &ctx.remaining_accounts[collection_offset + index * 4 + 1],
+                    &ctx.remaining_accounts[collection_offset + index * 4 + 2],
+                )
+                .with_remaining_accounts(transfer_hook_accounts.to_vec()),
             1,
+            0,
+        )?;
+    }
+
+    for (index, fungible_leg) in ctx.accounts.escrow_account.taker_fungible_legs.iter().enumerate() {
+        let mint_account = &ctx.remaining_accounts[taker_fungible_offset + index * 3 + 2];
+        // transfer feeのあるmintは、feeが引かれてもlegの量がinitializerに届くように上乗せする
+        let amount = transfer_amount_with_fee(mint_account, fungible_leg.amount)?;
+        transfer_checked(
+            ctx.accounts
+                .into_transfer_to_initializer_context(
+                    &ctx.remaining_accounts[taker_fungible_offset + index * 3],
+                    &ctx.remaining_accounts[taker_fungible_offset + index * 3 + 1],
+                    mint_account,
+                )
+                .with_remaining_accounts(transfer_hook_accounts.to_vec()),
+            amount,
+            fungible_leg.decimals,
         )?;
     }
 
     // initializerがsolをget
     // walletからの引き出しなら以下のようにやる
     // taker mutでOK　Error: failed to send transaction: Transaction simulation failed: Error processing Instruction 0: Cross-program invocation with unauthorized signer or writable account
-    let taker_additional_sol_amount = ctx.accounts.escrow_account.taker_additional_sol_amount;
-    if taker_additional_sol_amount > 0 {
+    // royaltyと手数料とreferrerの取り分はinitializerの受け取り分から差し引いてtakerからcreatorとtreasuryとreferrerに直接払う
+    let initializer_split = SolSplit::new(
+        taker_additional_sol_amount,
+        initializer_royalty_payments,
+        &ctx.accounts.config,
+        &ctx.accounts.escrow_account,
+    )?;
+    pay_sol_split(
+        &SolSplitAccounts {
+            payer: ctx.accounts.taker.to_account_info(),
+            receiver: ctx.accounts.initializer.to_account_info(),
+            treasury: ctx.accounts.treasury.to_account_info(),
+            referrer: ctx.accounts.referrer.to_account_info(),
+        },
+        &initializer_split,
+    )?;
+
+    // SOLが動かないswapは手数料をbpsでは計算できないので、takerが固定の手数料を払う
+    let flat_fee_amount = if taker_additional_sol_amount == 0 && initializer_additional_sol_amount == 0 {
+        ctx.accounts.config.flat_fee
+    } else {
+        0
+    };
+    if flat_fee_amount > 0 {
         let ix = anchor_lang::solana_program::system_instruction::transfer(
             &ctx.accounts.taker.key(),
-            &ctx.accounts.initializer.key(), // sends to event host pda
-            taker_additional_sol_amount,
+            &ctx.accounts.treasury.key(),
+            flat_fee_amount,
         );
         anchor_lang::solana_program::program::invoke(
             &ix,
             &[
                 ctx.accounts.taker.to_account_info().clone(),
-                ctx.accounts.initializer.to_account_info().clone(),
+                ctx.accounts.treasury.to_account_info().clone(),
             ],
         )?;
     }
@@ -170,43 +405,269 @@ pub fn handler<'info>(
     for index in 0..initializer_nft_amount_count {
         let vault_account = &ctx.remaining_accounts[index * 3 + 1];
         let taker_nft_token_account = &ctx.remaining_accounts[offset + index * 2];
+        let mint_account = &ctx.remaining_accounts[index * 3 + 2];
+
+        transfer_checked(
+            ctx.accounts
+                .into_transfer_to_taker_context(
+                    vault_account,
+                    taker_nft_token_account,
+                    mint_account,
+                )
+                .with_signer(&[&[
+                    VAULT_AUTHORITY_PDA_SEED,
+                    ctx.accounts.escrow_account.key().as_ref(),
+                    &[ctx.accounts.vault_authority.bump],
+                ]])
+                .with_remaining_accounts(transfer_hook_accounts.to_vec()),
+            ctx.accounts.escrow_account.initializer_nft_quantities[index],
+            0,
+        )?;
 
-        token::transfer(
+        close_account(
             ctx.accounts
-                .into_transfer_to_taker_context(vault_account, taker_nft_token_account)
+                .into_close_context(vault_account)
                 .with_signer(&[&[
                     VAULT_AUTHORITY_PDA_SEED,
-                    ctx.accounts.initializer.key().as_ref(),
-                    ctx.accounts.taker.key().as_ref(),
+                    ctx.accounts.escrow_account.key().as_ref(),
                     &[ctx.accounts.vault_authority.bump],
                 ]]),
-            1,
         )?;
+    }
+
+    for (index, fungible_leg) in ctx.accounts.escrow_account.initializer_fungible_legs.iter().enumerate() {
+        let vault_account = &ctx.remaining_accounts[fungible_offset + index * 3];
+        // vaultにはtakerに送るときのfeeの分も入っているので、全て送ってcloseする
+        let vault_amount = unpack_token_account(vault_account)?.amount;
 
-        token::close_account(
+        transfer_checked(
+            ctx.accounts
+                .into_transfer_to_taker_context(
+                    vault_account,
+                    &ctx.remaining_accounts[fungible_offset + index * 3 + 1],
+                    &ctx.remaining_accounts[fungible_offset + index * 3 + 2],
+                )
+                .with_signer(&[&[
+                    VAULT_AUTHORITY_PDA_SEED,
+                    ctx.accounts.escrow_account.key().as_ref(),
+                    &[ctx.accounts.vault_authority.bump],
+                ]])
+                .with_remaining_accounts(transfer_hook_accounts.to_vec()),
+            vault_amount,
+            fungible_leg.decimals,
+        )?;
+
+        // transfer feeがvaultにwithholdされているとcloseできないのでmintに回収する
+        let mint_account = &ctx.remaining_accounts[fungible_offset + index * 3 + 2];
+        harvest_withheld_fees(
+            &ctx.accounts.token_program_for(mint_account),
+            mint_account,
+            vault_account,
+        )?;
+
+        close_account(
             ctx.accounts
                 .into_close_context(vault_account)
                 .with_signer(&[&[
                     VAULT_AUTHORITY_PDA_SEED,
-                    ctx.accounts.initializer.key().as_ref(),
-                    ctx.accounts.taker.key().as_ref(),
+                    ctx.accounts.escrow_account.key().as_ref(),
                     &[ctx.accounts.vault_authority.bump],
                 ]]),
         )?;
     }
 
+    // pNFTはToken MetadataのTransferで動かす initializerの分はvault authorityのATAから、takerの分はtakerのATAから払い出す
+    if initializer_pnft_amount_count + taker_pnft_amount_count > 0 {
+        let pnft_offset = taker_fungible_offset + taker_fungible_amount_count * 3;
+        let pnft = PnftContextAccounts::new(
+            ctx.accounts.taker.to_account_info(),
+            &ctx.remaining_accounts[pnft_offset..pnft_offset + PNFT_PROGRAM_ACCOUNTS],
+        )?;
+        let taker = ctx.accounts.taker.to_account_info();
+        let initializer = ctx.accounts.initializer.to_account_info();
+        let vault_authority = ctx.accounts.vault_authority.to_account_info();
+        let pnft_mints = ctx
+            .accounts
+            .escrow_account
+            .initializer_pnft_mints
+            .iter()
+            .chain(ctx.accounts.escrow_account.taker_pnft_mints.iter());
+        for (index, pnft_mint) in pnft_mints.enumerate() {
+            let start = pnft_offset + PNFT_PROGRAM_ACCOUNTS + index * PNFT_ACCOUNTS_PER_ITEM;
+            let pnft_accounts = &ctx.remaining_accounts[start..start + PNFT_ACCOUNTS_PER_ITEM];
+
+            if index < initializer_pnft_amount_count {
+                transfer_pnft(
+                    pnft.into_transfer_pnft_context(
+                        pnft_accounts,
+                        pnft_mint,
+                        &vault_authority,
+                        &taker,
+                        &vault_authority,
+                    )?
+                    .with_signer(&[&[
+                        VAULT_AUTHORITY_PDA_SEED,
+                        ctx.accounts.escrow_account.key().as_ref(),
+                        &[ctx.accounts.vault_authority.bump],
+                    ]]),
+                )?;
+
+                // 空になったvault authorityのATAをcloseしてrentをinitializerに返す
+                // Token Metadataのversionによってはtransferでcloseされるので、残っている場合だけ
+                if pnft_accounts[0].lamports() > 0 {
+                    close_account(
+                        ctx.accounts
+                            .into_close_context(&pnft_accounts[0])
+                            .with_signer(&[&[
+                                VAULT_AUTHORITY_PDA_SEED,
+                                ctx.accounts.escrow_account.key().as_ref(),
+                                &[ctx.accounts.vault_authority.bump],
+                            ]]),
+                    )?;
+                }
+            } else {
+                transfer_pnft(pnft.into_transfer_pnft_context(
+                    pnft_accounts,
+                    pnft_mint,
+                    &taker,
+                    &initializer,
+                    &taker,
+                )?)?;
+            }
+        }
+    }
+
+    // cNFTはBubblegumのTransferでleaf ownerを変える initializerの分はvault authorityから、takerの分はtakerから
+    let cnft_offset = taker_fungible_offset
+        + taker_fungible_amount_count * 3
+        + pnft_accounts_count(initializer_pnft_amount_count + taker_pnft_amount_count);
+    if !cnft_leaves.is_empty() {
+        let cnft = CnftContextAccounts::new(
+            &ctx.remaining_accounts[cnft_offset..cnft_offset + CNFT_PROGRAM_ACCOUNTS],
+        )?;
+        let taker = ctx.accounts.taker.to_account_info();
+        let initializer = ctx.accounts.initializer.to_account_info();
+        let vault_authority = ctx.accounts.vault_authority.to_account_info();
+        let cnft_assets = ctx
+            .accounts
+            .escrow_account
+            .initializer_cnft_assets
+            .iter()
+            .chain(ctx.accounts.escrow_account.taker_cnft_assets.iter());
+        let mut start = cnft_offset + CNFT_PROGRAM_ACCOUNTS;
+        for (index, (cnft_leaf, cnft_asset)) in cnft_leaves.iter().zip(cnft_assets).enumerate() {
+            let end = start + CNFT_ACCOUNTS_PER_ITEM + cnft_leaf.proof_len as usize;
+            let cnft_accounts = &ctx.remaining_accounts[start..end];
+
+            if index < initializer_cnft_amount_count {
+                transfer_cnft(
+                    cnft.into_transfer_cnft_context(
+                        cnft_accounts,
+                        cnft_leaf,
+                        cnft_asset,
+                        &vault_authority,
+                        &taker,
+                    )?
+                    .with_signer(&[&[
+                        VAULT_AUTHORITY_PDA_SEED,
+                        ctx.accounts.escrow_account.key().as_ref(),
+                        &[ctx.accounts.vault_authority.bump],
+                    ]]),
+                    cnft_leaf,
+                )?;
+            } else {
+                transfer_cnft(
+                    cnft.into_transfer_cnft_context(
+                        cnft_accounts,
+                        cnft_leaf,
+                        cnft_asset,
+                        &taker,
+                        &initializer,
+                    )?,
+                    cnft_leaf,
+                )?;
+            }
+            start = end;
+        }
+    }
+
+    // Core assetはmpl coreのTransferでownerを変える initializerの分はvault authorityから、takerの分はtakerから
+    if initializer_core_amount_count + taker_core_amount_count > 0 {
+        let core_offset = cnft_offset + cnft_accounts_count(&cnft_leaves);
+        let core = CoreContextAccounts::new(
+            ctx.accounts.taker.to_account_info(),
+            &ctx.remaining_accounts[core_offset..core_offset + CORE_PROGRAM_ACCOUNTS],
+        )?;
+        let taker = ctx.accounts.taker.to_account_info();
+        let initializer = ctx.accounts.initializer.to_account_info();
+        let vault_authority = ctx.accounts.vault_authority.to_account_info();
+        let core_assets = ctx
+            .accounts
+            .escrow_account
+            .initializer_core_assets
+            .iter()
+            .chain(ctx.accounts.escrow_account.taker_core_assets.iter());
+        for (index, core_asset) in core_assets.enumerate() {
+            let start = core_offset + CORE_PROGRAM_ACCOUNTS + index * CORE_ACCOUNTS_PER_ITEM;
+            let core_accounts = &ctx.remaining_accounts[start..start + CORE_ACCOUNTS_PER_ITEM];
+
+            if index < initializer_core_amount_count {
+                transfer_core(
+                    core.into_transfer_core_context(
+                        core_accounts,
+                        core_asset,
+                        &vault_authority,
+                        &taker,
+                    )?
+                    .with_signer(&[&[
+                        VAULT_AUTHORITY_PDA_SEED,
+                        ctx.accounts.escrow_account.key().as_ref(),
+                        &[ctx.accounts.vault_authority.bump],
+                    ]]),
+                )?;
+            } else {
+                transfer_core(core.into_transfer_core_context(
+                    core_accounts,
+                    core_asset,
+                    &taker,
+                    &initializer,
+                )?)?;
+            }
+        }
+    }
+
     // takerがsolをget
     // PDAからの引き出しなら以下のようにやる
     // 　Error: failed to send transaction: Transaction simulation failed: Error processing Instruction 0: instruction changed the balance of a read-only account
     // 以下の構文はPDAのときだけしか使えない
-    if initializer_additional_sol_amount > 0 {
-        **ctx
-        .accounts
-        .escrow_account
-        .to_account_info()
-        .try_borrow_mut_lamports()? -= initializer_additional_sol_amount;
-        **ctx.accounts.taker.try_borrow_mut_lamports()? += initializer_additional_sol_amount;
-    }
+    // royaltyと手数料とreferrerの取り分はtakerの受け取り分から差し引いてescrowからcreatorとtreasuryとreferrerに直接払う
+    let taker_split = SolSplit::new(
+        initializer_additional_sol_amount,
+        taker_royalty_payments,
+        &ctx.accounts.config,
+        &ctx.accounts.escrow_account,
+    )?;
+    pay_sol_split(
+        &SolSplitAccounts {
+            payer: ctx.accounts.escrow_account.to_account_info(),
+            receiver: ctx.accounts.taker.to_account_info(),
+            treasury: ctx.accounts.treasury.to_account_info(),
+            referrer: ctx.accounts.referrer.to_account_info(),
+        },
+        &taker_split,
+    )?;
+
+    emit!(ExchangeSettled {
+        escrow_key: ctx.accounts.escrow_account.key(),
+        initializer_key: ctx.accounts.initializer.key(),
+        taker_key: ctx.accounts.taker.key(),
+        initializer_additional_sol_amount,
+        taker_additional_sol_amount,
+        referrer_key: ctx.accounts.escrow_account.referrer_key,
+        referrer_amount: initializer_split.referrer_amount + taker_split.referrer_amount,
+        fee_amount: initializer_split.fee_amount + taker_split.fee_amount + flat_fee_amount,
+        royalty_amount: initializer_split.royalty_amount + taker_split.royalty_amount,
+    });
 
     /*
     //　vault_sol_accountから齋藤に送る
@@ -224,26 +685,40 @@ impl<'info> Exchange<'info> {
         &self,
         taker_nft_token_account: &AccountInfo<'info>,
         initializer_nft_token_account: &AccountInfo<'info>,
-    ) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
-        let cpi_accounts = Transfer {
+        mint_account: &AccountInfo<'info>,
+    ) -> CpiContext<'_, '_, '_, 'info, TransferChecked<'info>> {
+        let cpi_accounts = TransferChecked {
             from: taker_nft_token_account.clone(),
+            mint: mint_account.clone(),
             to: initializer_nft_token_account.clone(),
             authority: self.taker.to_account_info().clone(),
         };
-        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+        CpiContext::new(self.token_program_for(mint_account), cpi_accounts)
     }
 
     fn into_transfer_to_taker_context(
         &self,
         vault_account: &AccountInfo<'info>,
         taker_nft_token_account: &AccountInfo<'info>,
-    ) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
-        let cpi_accounts = Transfer {
+        mint_account: &AccountInfo<'info>,
+    ) -> CpiContext<'_, '_, '_, 'info, TransferChecked<'info>> {
+        let cpi_accounts = TransferChecked {
             from: vault_account.clone(),
+            mint: mint_account.clone(),
             to: taker_nft_token_account.clone(),
             authority: self.vault_authority.to_account_info().clone(),
         };
-        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
+        CpiContext::new(self.token_program_for(mint_account), cpi_accounts)
+    }
+}
+
+impl<'info> TokenPrograms<'info> for Exchange<'info> {
+    fn token_program(&self) -> &AccountInfo<'info> {
+        return &self.token_program;
+    }
+
+    fn token_2022_program(&self) -> &AccountInfo<'info> {
+        return &self.token_2022_program;
     }
 }
 
@@ -255,8 +730,4 @@ impl<'info> Common<'info> for Exchange<'info> {
     fn initializer(&self) -> &AccountInfo<'info> {
         return &self.initializer;
     }
-
-    fn token_program(&self) -> &AccountInfo<'info> {
-        return &self.token_program;
-    }
 }
\ No newline at end of file
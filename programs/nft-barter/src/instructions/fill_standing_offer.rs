@@ -0,0 +1,112 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Token;
+
+use crate::{
+    errors::MyError,
+    state::{StandingOfferAccount, STANDING_OFFER_PDA_SEED},
+    traits::{transfer_checked, Token2022, TokenPrograms, TransferChecked},
+    utils::{assert_is_ata, assert_is_collection_nft},
+};
+
+// sellerがcollectionのNFTを1つ渡して、standing offerの予算から1つ分のSOLを受け取る
+#[derive(Accounts)]
+pub struct FillStandingOffer<'info> {
+    #[account(mut)]
+    pub seller: Signer<'info>,
+    pub buyer: SystemAccount<'info>,
+    #[account(
+        mut,
+        seeds = [
+            STANDING_OFFER_PDA_SEED,
+            buyer.key().as_ref(),
+            standing_offer_account.nonce.to_le_bytes().as_ref()
+        ],
+        bump = standing_offer_account.bump,
+        constraint = standing_offer_account.buyer_key == *buyer.key @ MyError::InitializerPublicKeyMismatch,
+        constraint = standing_offer_account.remaining_count > 0 @ MyError::StandingOfferFilled,
+    )]
+    pub standing_offer_account: Box<Account<'info, StandingOfferAccount>>,
+    pub token_program: Program<'info, Token>,
+    pub token_2022_program: Program<'info, Token2022>,
+}
+
+pub fn handler<'info>(ctx: Context<'_, '_, '_, 'info, FillStandingOffer<'info>>) -> Result<()> {
+    msg!("start fill_standing_offer");
+
+    // remaining accountsの数の検証 sellerのtoken account, buyerのtoken account, mint, metadataの順
+    // transfer hookのあるmintのaccountは最後に渡す
+    let transfer_hook_offset = 4;
+    require_gte!(
+        ctx.remaining_accounts.len(),
+        transfer_hook_offset,
+        MyError::NftAmountMismatch
+    );
+    let transfer_hook_accounts = &ctx.remaining_accounts[transfer_hook_offset..];
+
+    let seller_token_account = &ctx.remaining_accounts[0];
+    let buyer_token_account = &ctx.remaining_accounts[1];
+    let mint_account = &ctx.remaining_accounts[2];
+    let metadata_account = &ctx.remaining_accounts[3];
+
+    assert_is_collection_nft(
+        mint_account,
+        metadata_account,
+        &ctx.accounts.standing_offer_account.collection_key,
+    )?;
+    assert_is_ata(
+        seller_token_account,
+        ctx.accounts.seller.key,
+        mint_account,
+        1,
+    )?;
+    assert_is_ata(buyer_token_account, ctx.accounts.buyer.key, mint_account, 0)?;
+
+    transfer_checked(
+        ctx.accounts
+            .into_transfer_to_buyer_context(seller_token_account, buyer_token_account, mint_account)
+            .with_remaining_accounts(transfer_hook_accounts.to_vec()),
+        1,
+        0,
+    )?;
+
+    // 最難関： solanaのbugで金額を動かすのはCPIの後にする必要がある
+    let price_per_nft = ctx.accounts.standing_offer_account.price_per_nft;
+    **ctx
+        .accounts
+        .standing_offer_account
+        .to_account_info()
+        .try_borrow_mut_lamports()? -= price_per_nft;
+    **ctx.accounts.seller.try_borrow_mut_lamports()? += price_per_nft;
+
+    ctx.accounts.standing_offer_account.remaining_count -= 1;
+
+    msg!("end fill_standing_offer");
+    Ok(())
+}
+
+impl<'info> FillStandingOffer<'info> {
+    fn into_transfer_to_buyer_context(
+        &self,
+        seller_token_account: &AccountInfo<'info>,
+        buyer_token_account: &AccountInfo<'info>,
+        mint_account: &AccountInfo<'info>,
+    ) -> CpiContext<'_, '_, '_, 'info, TransferChecked<'info>> {
+        let cpi_accounts = TransferChecked {
+            from: seller_token_account.clone(),
+            mint: mint_account.clone(),
+            to: buyer_token_account.clone(),
+            authority: self.seller.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program_for(mint_account), cpi_accounts)
+    }
+}
+
+impl<'info> TokenPrograms<'info> for FillStandingOffer<'info> {
+    fn token_program(&self) -> &AccountInfo<'info> {
+        &self.token_program
+    }
+
+    fn token_2022_program(&self) -> &AccountInfo<'info> {
+        &self.token_2022_program
+    }
+}
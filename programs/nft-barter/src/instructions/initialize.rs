@@ -1,46 +1,104 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{Token, self, SetAuthority, Transfer};
-use spl_token::instruction::AuthorityType;
+use anchor_spl::token::Token;
 
-use crate::state::{EscrowAccount, VAULT_AUTHORITY_PDA_SEED, VaultAuthority};
+use crate::state::{MAX_KEEPER_TIP_BASIS_POINTS, MAX_REFERRER_BASIS_POINTS, cnft_accounts_count, core_accounts_count, pnft_accounts_count, CnftLeaf, DutchPricing, EscrowAccount, EscrowLegCounts, FungibleLeg, ESCROW_PDA_SEED, CNFT_ACCOUNTS_PER_ITEM, CNFT_PROGRAM_ACCOUNTS, CORE_ACCOUNTS_PER_ITEM, CORE_PROGRAM_ACCOUNTS, PNFT_ACCOUNTS_PER_ITEM, PNFT_PROGRAM_ACCOUNTS, VAULT_AUTHORITY_PDA_SEED, VaultAuthority};
 use crate::errors::*;
-use crate::utils::{assert_is_ata, assert_rent_exempt};
+use crate::traits::*;
+use crate::utils::{get_cnft_asset_id, assert_is_ata, assert_is_core_asset, assert_is_nft_mint, assert_is_sft_mint, assert_owned_by_token_program, assert_supported_mint_extensions, unpack_mint};
 
-use anchor_lang::solana_program::{
-    program::{invoke, invoke_signed},
-    program_pack::Pack,
-    system_instruction,
-};
+// initializeの引数 initializerとtakerがそれぞれ出す資産を種類ごとに指定する
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct InitializeArgs {
+    pub initializer_additional_sol_amount: u64,
+    pub taker_additional_sol_amount: u64,
+    pub initializer_nft_amount: u8,
+    pub taker_nft_amount: u8,
+    pub vault_account_bumps: Vec<u8>,
+    pub taker_collection_key: Pubkey, // collectionを指定しない場合はPubkey::default()
+    pub taker_collection_nft_amount: u8,
+    pub nonce: u64,
+    pub expires_at: i64, // 0の場合は期限なし
+    pub initializer_fungible_amounts: Vec<u64>,
+    pub fungible_vault_account_bumps: Vec<u8>,
+    pub taker_fungible_amounts: Vec<u64>,
+    pub initializer_nft_quantities: Vec<u64>,
+    pub taker_nft_quantities: Vec<u64>,
+    pub initializer_pnft_amount: u8,
+    pub taker_pnft_amount: u8,
+    pub initializer_cnft_leaves: Vec<CnftLeaf>,
+    pub taker_cnft_assets: Vec<Pubkey>,
+    pub initializer_core_amount: u8,
+    pub taker_core_amount: u8,
+    pub referrer_key: Pubkey, // referrerがいない場合はPubkey::default()
+    pub referrer_basis_points: u16,
+    pub taker_dutch_pricing: DutchPricing,
+    pub keeper_tip_basis_points: u16, // match_offersでkeeperに払ってよい割合 払わない場合は0
+}
+
+impl InitializeArgs {
+    pub fn initializer_leg_counts(&self) -> EscrowLegCounts {
+        EscrowLegCounts {
+            nft: self.initializer_nft_amount as usize,
+            fungible: self.initializer_fungible_amounts.len(),
+            pnft: self.initializer_pnft_amount as usize,
+            cnft: self.initializer_cnft_leaves.len(),
+            core: self.initializer_core_amount as usize,
+        }
+    }
+
+    pub fn taker_leg_counts(&self) -> EscrowLegCounts {
+        EscrowLegCounts {
+            nft: self.taker_nft_amount as usize,
+            fungible: self.taker_fungible_amounts.len(),
+            pnft: self.taker_pnft_amount as usize,
+            cnft: self.taker_cnft_assets.len(),
+            core: self.taker_core_amount as usize,
+        }
+    }
+}
 
 #[derive(Accounts)]
-#[instruction(initializer_additional_sol_amount: u64, taker_additional_sol_amount: u64, initializer_nft_amount: u8, taker_nft_amount: u8, vault_account_bumps: Vec<u8>)]
+#[instruction(args: InitializeArgs)]
 pub struct Initialize<'info> {
     #[account(
         mut, 
-        constraint = initializer_additional_sol_amount as usize + initializer_nft_amount as usize > 0 @ MyError::NotProvidedInitializerAssets,
-        constraint = initializer.to_account_info().try_lamports().unwrap() >= initializer_additional_sol_amount @ MyError::InitializerInsufficientFunds,
-        constraint = initializer_nft_amount as usize == vault_account_bumps.len() @ MyError::VaultAccountBumpsMismatch
+        constraint = args.initializer_additional_sol_amount as usize + args.initializer_nft_amount as usize + args.initializer_fungible_amounts.len() + args.initializer_pnft_amount as usize + args.initializer_cnft_leaves.len() + args.initializer_core_amount as usize > 0 @ MyError::NotProvidedInitializerAssets,
+        constraint = initializer.to_account_info().try_lamports().unwrap() >= args.initializer_additional_sol_amount @ MyError::InitializerInsufficientFunds,
+        constraint = args.initializer_nft_amount as usize == args.vault_account_bumps.len() @ MyError::VaultAccountBumpsMismatch,
+        constraint = args.initializer_nft_amount as usize == args.initializer_nft_quantities.len() @ MyError::NftAmountMismatch,
+        constraint = args.taker_nft_amount as usize == args.taker_nft_quantities.len() @ MyError::NftAmountMismatch,
+        // referrerがいない場合は0
+        constraint = args.referrer_basis_points <= MAX_REFERRER_BASIS_POINTS && (args.referrer_key != Pubkey::default() || args.referrer_basis_points == 0) @ MyError::InvalidReferrerBasisPoints,
+        constraint = args.keeper_tip_basis_points <= MAX_KEEPER_TIP_BASIS_POINTS @ MyError::InvalidKeeperTipBasisPoints
     )]
     pub initializer: Signer<'info>,
+    /// CHECK: open offerの場合はPubkey::default()を渡す それ以外はSystemAccountであることをconstraintで検証
     #[account(
-        constraint = taker_additional_sol_amount as usize + taker_nft_amount as usize > 0 @ MyError::NotProvidedTakerAssets,
-        constraint = taker.to_account_info().try_lamports().unwrap() >= taker_additional_sol_amount @ MyError::TakerInsufficientFunds,
+        constraint = args.taker_additional_sol_amount as usize + args.taker_nft_amount as usize + args.taker_collection_nft_amount as usize + args.taker_fungible_amounts.len() + args.taker_pnft_amount as usize + args.taker_cnft_assets.len() + args.taker_core_amount as usize > 0 @ MyError::NotProvidedTakerAssets,
+        constraint = args.taker_collection_nft_amount == 0 || args.taker_collection_key != Pubkey::default() @ MyError::CollectionPublicKeyMismatch,
+        constraint = taker.key() == Pubkey::default() || *taker.owner == System::id() @ MyError::IncorrectAccountInfoOwner,
+        constraint = taker.key() == Pubkey::default() || taker.to_account_info().try_lamports().unwrap() >= args.taker_additional_sol_amount @ MyError::TakerInsufficientFunds,
     )]
-    pub taker: SystemAccount<'info>,
+    pub taker: UncheckedAccount<'info>,
     // account(zero)でuninitializedを保証できるので、ts側でinitしようとするとなぜかError: 3003: Failed to deserialize the account　エラー　調べる限りspace問題なのでrustでspaceを指定することで解決
-    #[account(init, payer = initializer, space = 8 // internal anchor discriminator 
-        + 32 // initializerKey
-        + 8 // initializerAdditionalSolAmount
-        + 4 + 32 * initializer_nft_amount as usize // initializerNftTokenAccounts
-        + 32 // takerKey
-        + 8 // takerAdditionalSolAmount
-        + 4 + 32 * taker_nft_amount as usize // takerNftTokenAccounts
-        + 4 + vault_account_bumps.len() // vault_account_bumps vec
+    // clientがaddressを導出できるようにinitializer, taker, nonceのPDAにする
+    #[account(
+        init,
+        payer = initializer,
+        seeds = [
+            ESCROW_PDA_SEED,
+            initializer.key().as_ref(),
+            taker.key().as_ref(),
+            args.nonce.to_le_bytes().as_ref()
+        ],
+        bump,
+        space = EscrowAccount::space(&args.initializer_leg_counts(), &args.taker_leg_counts())
     )]
     pub escrow_account: Box<Account<'info, EscrowAccount>>, // ownerはFRd6p3td6akTgfhHgJZHyhVeyYUhGWiM9dApVucDGer2
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
     pub token_program: Program<'info, Token>,
+    pub token_2022_program: Program<'info, Token2022>,
     #[account(
         init,
         payer = initializer,
@@ -48,8 +106,7 @@ pub struct Initialize<'info> {
             + 1 , // bump
         seeds = [
             VAULT_AUTHORITY_PDA_SEED,
-            initializer.key().as_ref(),
-            taker.key().as_ref()
+            escrow_account.key().as_ref() // escrowごとにvaultの権限を分ける
         ],
         bump,
       )]
@@ -62,137 +119,268 @@ pub struct Initialize<'info> {
 // lifetime mismatch these two types are declared with different lifetimes...
 pub fn handler<'info>(
     ctx: Context<'_, '_, '_, 'info, Initialize<'info>>,
-    initializer_additional_sol_amount: u64, // こいつはstateで使っているから変数の先にもってきている
-    taker_additional_sol_amount: u64,       // こいつはstateで使っているから変数の先にもってきている
-    initializer_nft_amount: u8,
-    taker_nft_amount: u8,
-    vault_account_bumps: Vec<u8>,
+    args: InitializeArgs,
 ) -> Result<()> {
     msg!("start initialize");
 
+    let InitializeArgs {
+        initializer_additional_sol_amount,
+        taker_additional_sol_amount,
+        initializer_nft_amount,
+        taker_nft_amount,
+        vault_account_bumps,
+        taker_collection_key,
+        taker_collection_nft_amount,
+        nonce,
+        expires_at,
+        initializer_fungible_amounts,
+        fungible_vault_account_bumps,
+        taker_fungible_amounts,
+        initializer_nft_quantities,
+        taker_nft_quantities,
+        initializer_pnft_amount,
+        taker_pnft_amount,
+        initializer_cnft_leaves,
+        taker_cnft_assets,
+        initializer_core_amount,
+        taker_core_amount,
+        referrer_key,
+        referrer_basis_points,
+        taker_dutch_pricing,
+        keeper_tip_basis_points,
+    } = args;
+
+    // 過去の期限ではexchangeできないescrowになってしまう
+    if expires_at != 0 {
+        require_gt!(expires_at, Clock::get()?.unix_timestamp, MyError::EscrowExpired);
+    }
+
     // remaining_accountsの数の検証
     let initializer_nft_amount_count = initializer_nft_amount as usize;
     let taker_nft_amount_count = taker_nft_amount as usize;
+    // open offerはtakerが未定なのでtoken accountは渡せない
+    let is_open_offer = ctx.accounts.taker.key() == Pubkey::default();
+    let taker_accounts_per_nft = if is_open_offer { 1 } else { 2 };
     let offset = initializer_nft_amount_count * 3 as usize;
-    let remaining_accounts_count = offset as usize + taker_nft_amount_count * 2 as usize; // initializerはtoken accountとbump takerは直接initializerに払い出すのでtoken accountのみ
-    require_eq!(
+    let fungible_offset = offset + taker_nft_amount_count * taker_accounts_per_nft; // initializerはtoken accountとbump takerは直接initializerに払い出すのでtoken accountのみ
+    let taker_fungible_offset = fungible_offset + initializer_fungible_amounts.len() * 3; // fungible tokenはinitializerがtoken account, vault account, mint takerはmintのみ
+    // pNFTはinitializerがprogramのaccountとitemごとのaccount takerはmintのみ
+    let pnft_offset = taker_fungible_offset + taker_fungible_amounts.len();
+    let taker_pnft_offset = pnft_offset + pnft_accounts_count(initializer_pnft_amount as usize);
+    // cNFTはprogramのaccountとleafごとの[merkle tree, tree authority, proof...] takerはasset idを引数で渡すのでaccountは不要
+    let cnft_offset = taker_pnft_offset + taker_pnft_amount as usize;
+    // Core assetはinitializerがprogramのaccountとassetごとの[asset, collection] takerはassetのみ
+    let core_offset = cnft_offset + cnft_accounts_count(&initializer_cnft_leaves);
+    let taker_core_offset = core_offset + core_accounts_count(initializer_core_amount as usize);
+    // transfer hookのあるmintのaccountは最後に渡す
+    let transfer_hook_offset = taker_core_offset + taker_core_amount as usize;
+    require_gte!(
         ctx.remaining_accounts.len(),
-        remaining_accounts_count,
+        transfer_hook_offset,
         MyError::NftAmountMismatch
     );
 
-    // takerにはvaultがないため、token accountとmintだけ
+    // takerにはvaultがないため、token accountとmintだけ open offerの場合はmintだけ
     for index in 0..taker_nft_amount_count {
-        let token_account = &ctx.remaining_accounts[offset + index * 2];
-        let mint_account = &ctx.remaining_accounts[offset + index * 2 + 1];
+        let quantity = taker_nft_quantities[index];
+        require_gt!(quantity, 0, MyError::InvalidNftQuantity);
 
-        // Token Accountの検証
-        assert_is_ata(token_account, ctx.accounts.taker.key, mint_account, true)?;
+        if is_open_offer {
+            let mint_account = &ctx.remaining_accounts[offset + index];
 
-        ctx.accounts
-            .escrow_account
-            .taker_nft_token_accounts
-            .push(token_account.key());
+            // 誰がtakerになっても同じNFTを要求できるようにmintを検証
+            assert_is_sft_mint(mint_account, quantity)?;
+
+            ctx.accounts
+                .escrow_account
+                .taker_nft_mints
+                .push(mint_account.key());
+        } else {
+            let token_account = &ctx.remaining_accounts[offset + index * 2];
+            let mint_account = &ctx.remaining_accounts[offset + index * 2 + 1];
+
+            // Token Accountの検証
+            assert_is_ata(token_account, ctx.accounts.taker.key, mint_account, quantity)?;
+            // exchangeでdecimals 0としてtransfer_checkedするので、NFTとSFTだけを受け付ける
+            assert_is_sft_mint(mint_account, quantity)?;
+
+            ctx.accounts
+                .escrow_account
+                .taker_nft_token_accounts
+                .push(token_account.key());
+            ctx.accounts
+                .escrow_account
+                .taker_nft_mints
+                .push(mint_account.key());
+        }
     }
 
     // 3で割ってあまり0にtoken account 1にvault account 2にmint account mint accountがないとspl_token::instruction::initialize_accountが無理
-    for index in 0..initializer_nft_amount_count {
-        // Token Accountの検証
-        let token_account = &ctx.remaining_accounts[index * 3];
-        let vault_account = &ctx.remaining_accounts[index * 3 + 1];
-        let mint_account = &ctx.remaining_accounts[index * 3 + 2];
-
-        assert_is_ata(
-            token_account,
-            ctx.accounts.initializer.key,
-            mint_account,
-            true,
+    let deposit_context = &DepositContext {
+        accounts: &DepositContextAccounts {
+            initializer: ctx.accounts.initializer.to_account_info().clone(),
+            vault_authority: ctx.accounts.vault_authority.to_account_info().clone(),
+            token_program: ctx.accounts.token_program.to_account_info().clone(),
+            token_2022_program: ctx.accounts.token_2022_program.to_account_info().clone(),
+            rent: ctx.accounts.rent.clone(),
+        },
+        remaining_accounts: &ctx.remaining_accounts[..offset],
+        transfer_hook_accounts: &ctx.remaining_accounts[transfer_hook_offset..],
+        program_id: ctx.program_id,
+    };
+    ctx.accounts.escrow_account.initializer_nft_token_accounts =
+        deposit(deposit_context, &ctx.accounts.escrow_account.key(), &vault_account_bumps, &initializer_nft_quantities)?;
+    ctx.accounts.escrow_account.initializer_nft_quantities = initializer_nft_quantities;
+    ctx.accounts.escrow_account.taker_nft_quantities = taker_nft_quantities;
+
+    // fungible tokenもvaultに預ける
+    let deposit_fungible_context = &DepositContext {
+        accounts: deposit_context.accounts,
+        remaining_accounts: &ctx.remaining_accounts[fungible_offset..taker_fungible_offset],
+        transfer_hook_accounts: deposit_context.transfer_hook_accounts,
+        program_id: ctx.program_id,
+    };
+    ctx.accounts.escrow_account.initializer_fungible_legs = deposit_fungible(
+        deposit_fungible_context,
+        &ctx.accounts.escrow_account.key(),
+        &initializer_fungible_amounts,
+        &fungible_vault_account_bumps,
+    )?;
+    ctx.accounts
+        .escrow_account
+        .initializer_fungible_vault_account_bumps = fungible_vault_account_bumps;
+
+    // takerのfungible tokenはexchangeのときにtakerのtoken accountから直接払い出す
+    for (index, amount) in taker_fungible_amounts.iter().enumerate() {
+        let mint_account = &ctx.remaining_accounts[taker_fungible_offset + index];
+
+        require_gt!(*amount, 0, MyError::InvalidFungibleAmount);
+        assert_owned_by_token_program(mint_account)?;
+        assert_supported_mint_extensions(mint_account)?;
+        let mint = unpack_mint(mint_account)?;
+
+        ctx.accounts.escrow_account.taker_fungible_legs.push(FungibleLeg {
+            mint: mint_account.key(),
+            amount: *amount,
+            decimals: mint.decimals,
+        });
+    }
+
+    // pNFTはToken MetadataのTransferでvault authorityのATAに預ける
+    if initializer_pnft_amount > 0 {
+        let pnft = PnftContextAccounts::new(
+            ctx.accounts.initializer.to_account_info(),
+            &ctx.remaining_accounts[pnft_offset..pnft_offset + PNFT_PROGRAM_ACCOUNTS],
         )?;
+        let initializer = ctx.accounts.initializer.to_account_info();
+        let vault_authority = ctx.accounts.vault_authority.to_account_info();
+        for index in 0..initializer_pnft_amount as usize {
+            let start = pnft_offset + PNFT_PROGRAM_ACCOUNTS + index * PNFT_ACCOUNTS_PER_ITEM;
+            let pnft_accounts = &ctx.remaining_accounts[start..start + PNFT_ACCOUNTS_PER_ITEM];
+            let mint_account = &pnft_accounts[2];
 
-        // 渡されたPDAの検証
-        let vault_account_bump = vault_account_bumps[index];
-        let vault_pda = Pubkey::create_program_address(
-            &[
-                b"vault-account",
-                token_account.key().as_ref(),
-                &[vault_account_bump],
-            ],
-            &ctx.program_id,
-        )
-        .unwrap();
-
-        require_keys_eq!(
-            vault_pda,
-            vault_account.key(),
-            MyError::PdaPublicKeyMismatch
-        );
+            assert_is_nft_mint(mint_account)?;
+            assert_is_ata(&pnft_accounts[0], ctx.accounts.initializer.key, mint_account, 1)?;
+
+            transfer_pnft(pnft.into_transfer_pnft_context(
+                pnft_accounts,
+                mint_account.key,
+                &initializer,
+                &vault_authority,
+                &initializer,
+            )?)?;
 
-        let create_account_ix = system_instruction::create_account(
-            &ctx.accounts.initializer.key(),
-            &vault_account.key(),
             ctx.accounts
-                .rent
-                .minimum_balance(spl_token::state::Account::LEN),
-            spl_token::state::Account::LEN as u64,
-            &spl_token::id(),
-        );
+                .escrow_account
+                .initializer_pnft_mints
+                .push(mint_account.key());
+        }
+    }
 
-        // ただのinvokeだとError: failed to send transaction: Transaction simulation failed: Error processing Instruction 1: Cross-program invocation with unauthorized signer or writable account
-        invoke_signed(
-            &create_account_ix,
-            // ctx.accounts.token_program.clone(), token programはなくても通る
-            &[
-                ctx.accounts.initializer.to_account_info().clone(),
-                vault_account.clone(), // これないとError: failed to send transaction: Transaction simulation failed: Error processing Instruction 1: An account required by the instruction is missing
-            ],
-            &[&[
-                b"vault-account",
-                token_account.key().as_ref(),
-                &[vault_account_bump],
-            ]],
-        )?;
+    // takerのpNFTはexchangeのときにtakerのATAから直接払い出すのでmintだけ
+    for index in 0..taker_pnft_amount as usize {
+        let mint_account = &ctx.remaining_accounts[taker_pnft_offset + index];
 
-        // 以下はpub fn initialize_account<'a, 'b, 'c, 'info>(を使って書き換えられそう
-        let initialize_account_ix = spl_token::instruction::initialize_account(
-            &spl_token::id(),
-            &vault_account.key(),
-            &mint_account.key(),
-            &ctx.accounts.initializer.key(),
-        )?;
+        assert_is_nft_mint(mint_account)?;
 
-        // ここはinvoke_signedでなくても動く ちなみにinvoke_signedでも動く
-        invoke(
-            &initialize_account_ix,
-            &[
-                ctx.accounts.initializer.to_account_info().clone(),
-                vault_account.clone(),
-                mint_account.clone(), // なくすとmissing error
-                ctx.accounts.rent.to_account_info().clone(), // 超難関ポイント　rentがないと動かない　Error: failed to send transaction: Transaction simulation failed: Error processing Instruction 1: An account required by the instruction is missing
-            ],
+        ctx.accounts
+            .escrow_account
+            .taker_pnft_mints
+            .push(mint_account.key());
+    }
+
+    // cNFTはBubblegumのTransferでleaf ownerをvault authorityにする
+    if !initializer_cnft_leaves.is_empty() {
+        let cnft = CnftContextAccounts::new(
+            &ctx.remaining_accounts[cnft_offset..cnft_offset + CNFT_PROGRAM_ACCOUNTS],
         )?;
+        let initializer = ctx.accounts.initializer.to_account_info();
+        let vault_authority = ctx.accounts.vault_authority.to_account_info();
+        let mut start = cnft_offset + CNFT_PROGRAM_ACCOUNTS;
+        for cnft_leaf in initializer_cnft_leaves.iter() {
+            let end = start + CNFT_ACCOUNTS_PER_ITEM + cnft_leaf.proof_len as usize;
+            let cnft_accounts = &ctx.remaining_accounts[start..end];
+            let cnft_asset = get_cnft_asset_id(cnft_accounts[0].key, cnft_leaf.nonce);
+
+            transfer_cnft(
+                cnft.into_transfer_cnft_context(
+                    cnft_accounts,
+                    cnft_leaf,
+                    &cnft_asset,
+                    &initializer,
+                    &vault_authority,
+                )?,
+                cnft_leaf,
+            )?;
+
+            ctx.accounts
+                .escrow_account
+                .initializer_cnft_assets
+                .push(cnft_asset);
+            start = end;
+        }
+    }
 
-        // check rent exempt
-        assert_rent_exempt(&ctx.accounts.rent, vault_account)?;
+    // takerのcNFTはexchangeのときにleafとproofを受け取って検証する
+    ctx.accounts.escrow_account.taker_cnft_assets = taker_cnft_assets;
 
-        // set_authorityをしないとError: failed to send transaction: Transaction simulation failed: Error processing Instruction 1: Cross-program invocation with unauthorized signer or writable account?
-        // classでdeserializeするときは、token::authority = initializerを指定しているので、initializerがownerになっている　今回はPDAをわたしているだけなので、signが必要
-        token::set_authority(
-            ctx.accounts.into_set_authority_context(vault_account),
-            AuthorityType::AccountOwner,
-            Some(ctx.accounts.vault_authority.key()),
+    // Core assetはmpl coreのTransferでvault authorityに預ける collectionのpluginはmpl coreが検証する
+    if initializer_core_amount > 0 {
+        let core = CoreContextAccounts::new(
+            ctx.accounts.initializer.to_account_info(),
+            &ctx.remaining_accounts[core_offset..core_offset + CORE_PROGRAM_ACCOUNTS],
         )?;
+        let initializer = ctx.accounts.initializer.to_account_info();
+        let vault_authority = ctx.accounts.vault_authority.to_account_info();
+        for index in 0..initializer_core_amount as usize {
+            let start = core_offset + CORE_PROGRAM_ACCOUNTS + index * CORE_ACCOUNTS_PER_ITEM;
+            let core_accounts = &ctx.remaining_accounts[start..start + CORE_ACCOUNTS_PER_ITEM];
+
+            transfer_core(core.into_transfer_core_context(
+                core_accounts,
+                core_accounts[0].key,
+                &initializer,
+                &vault_authority,
+            )?)?;
 
-        // NFTをinitializerからvaultに移す
-        token::transfer(
             ctx.accounts
-                .into_transfer_to_pda_context(&token_account, &vault_account),
-            1,
-        )?;
+                .escrow_account
+                .initializer_core_assets
+                .push(core_accounts[0].key());
+        }
+    }
+
+    // takerのCore assetはexchangeのときにtakerから直接払い出す open offerの場合はownerが未定なのでassetだけ
+    for index in 0..taker_core_amount as usize {
+        let asset = &ctx.remaining_accounts[taker_core_offset + index];
+
+        let owner = if is_open_offer { None } else { Some(ctx.accounts.taker.key) };
+        assert_is_core_asset(asset, owner)?;
 
         ctx.accounts
             .escrow_account
-            .initializer_nft_token_accounts
-            .push(token_account.key());
+            .taker_core_assets
+            .push(asset.key());
     }
 
     // ここで入れたtoken accountはexchangeのときに検証
@@ -202,7 +390,20 @@ pub fn handler<'info>(
         .initializer_additional_sol_amount = initializer_additional_sol_amount;
     ctx.accounts.escrow_account.taker_key = *ctx.accounts.taker.key;
     ctx.accounts.escrow_account.taker_additional_sol_amount = taker_additional_sol_amount;
+    ctx.accounts.escrow_account.taker_collection_key = taker_collection_key; // collectionのNFTはexchange時にtakerが選ぶ
+    ctx.accounts.escrow_account.taker_collection_nft_amount = taker_collection_nft_amount;
     ctx.accounts.escrow_account.vault_account_bumps = vault_account_bumps;
+    ctx.accounts.escrow_account.nonce = nonce;
+    ctx.accounts.escrow_account.bump = *ctx.bumps.get("escrow_account").unwrap();
+    ctx.accounts.escrow_account.expires_at = expires_at;
+    ctx.accounts.escrow_account.referrer_key = referrer_key;
+    ctx.accounts.escrow_account.referrer_basis_points = referrer_basis_points;
+    ctx.accounts.escrow_account.taker_dutch_pricing = taker_dutch_pricing;
+    ctx.accounts.escrow_account.keeper_tip_basis_points = keeper_tip_basis_points;
+    require!(
+        ctx.accounts.escrow_account.is_valid_dutch_pricing(),
+        MyError::InvalidDutchPricing
+    );
     ctx.accounts.vault_authority.bump = *ctx.bumps.get("vault_authority").unwrap();
 
     if initializer_additional_sol_amount > 0 {
@@ -227,29 +428,3 @@ pub fn handler<'info>(
     msg!("end initialize");
     Ok(())
 }
-
-impl<'info> Initialize<'info> {
-    fn into_transfer_to_pda_context(
-        &self,
-        initializer_nft_token_account: &AccountInfo<'info>,
-        vault_account: &AccountInfo<'info>,
-    ) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
-        let cpi_accounts = Transfer {
-            from: initializer_nft_token_account.clone(),
-            to: vault_account.clone(),
-            authority: self.initializer.to_account_info().clone(),
-        };
-        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
-    }
-
-    fn into_set_authority_context(
-        &self,
-        vault_account: &AccountInfo<'info>,
-    ) -> CpiContext<'_, '_, '_, 'info, SetAuthority<'info>> {
-        let cpi_accounts = SetAuthority {
-            account_or_mint: vault_account.clone(),
-            current_authority: self.initializer.to_account_info().clone(),
-        };
-        CpiContext::new(self.token_program.to_account_info().clone(), cpi_accounts)
-    }
-}
\ No newline at end of file
@@ -0,0 +1,54 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    errors::MyError,
+    program::NftBarter,
+    state::{Config, CONFIG_PDA_SEED, MAX_FEE_BASIS_POINTS},
+};
+
+// 誰でも先にinitializeできるとtreasuryを奪われるので、programのupgrade authorityだけが最初のadminになれる
+#[derive(Accounts)]
+#[instruction(fee_basis_points: u16)]
+pub struct InitializeConfig<'info> {
+    #[account(
+        mut,
+        constraint = fee_basis_points <= MAX_FEE_BASIS_POINTS @ MyError::InvalidFeeBasisPoints
+    )]
+    pub admin: Signer<'info>,
+    #[account(
+        init,
+        payer = admin,
+        space = Config::SPACE,
+        seeds = [CONFIG_PDA_SEED],
+        bump,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        constraint = program.programdata_address()? == Some(program_data.key()) @ MyError::AdminPublicKeyMismatch
+    )]
+    pub program: Program<'info, NftBarter>,
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(admin.key()) @ MyError::AdminPublicKeyMismatch
+    )]
+    pub program_data: Account<'info, ProgramData>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler(
+    ctx: Context<InitializeConfig>,
+    fee_basis_points: u16,
+    flat_fee: u64,
+    treasury_key: Pubkey,
+) -> Result<()> {
+    msg!("start initialize_config");
+
+    let config = &mut ctx.accounts.config;
+    config.admin_key = ctx.accounts.admin.key();
+    config.fee_basis_points = fee_basis_points;
+    config.flat_fee = flat_fee;
+    config.treasury_key = treasury_key;
+    config.bump = *ctx.bumps.get("config").unwrap();
+
+    msg!("end initialize_config");
+    Ok(())
+}
@@ -0,0 +1,122 @@
+use anchor_lang::prelude::*;
+
+use crate::errors::*;
+use crate::state::{
+    RingAccount, RingLeg, VaultAuthority, MAX_RING_PARTICIPANTS, RING_PDA_SEED,
+    VAULT_AUTHORITY_PDA_SEED,
+};
+use crate::utils::assert_is_nft_mint;
+
+// ringの条件だけを決める 資産はparticipantごとにdeposit_ringで預ける
+#[derive(Accounts)]
+#[instruction(participant_keys: Vec<Pubkey>, additional_sol_amounts: Vec<u64>, nft_amounts: Vec<u8>, nonce: u64)]
+pub struct InitializeRing<'info> {
+    #[account(
+        mut,
+        constraint = participant_keys.contains(creator.key) @ MyError::NotRingParticipant
+    )]
+    pub creator: Signer<'info>,
+    #[account(
+        init,
+        payer = creator,
+        seeds = [
+            RING_PDA_SEED,
+            creator.key().as_ref(),
+            nonce.to_le_bytes().as_ref()
+        ],
+        bump,
+        space = RingAccount::space(&nft_amounts)
+    )]
+    pub ring_account: Box<Account<'info, RingAccount>>,
+    #[account(
+        init,
+        payer = creator,
+        space = 8 // internal anchor discriminator
+            + 1, // bump
+        seeds = [
+            VAULT_AUTHORITY_PDA_SEED,
+            ring_account.key().as_ref() // ringごとにvaultの権限を分ける
+        ],
+        bump,
+    )]
+    pub vault_authority: Box<Account<'info, VaultAuthority>>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+pub fn handler<'info>(
+    ctx: Context<'_, '_, '_, 'info, InitializeRing<'info>>,
+    participant_keys: Vec<Pubkey>,
+    additional_sol_amounts: Vec<u64>,
+    nft_amounts: Vec<u8>,
+    nonce: u64,
+) -> Result<()> {
+    msg!("start initialize_ring");
+
+    // 2者の交換はescrowで足りるが、ringとしては2者以上であればよい
+    let participant_count = participant_keys.len();
+    require!(
+        (2..=MAX_RING_PARTICIPANTS).contains(&participant_count),
+        MyError::InvalidRingSize
+    );
+    require_eq!(
+        additional_sol_amounts.len(),
+        participant_count,
+        MyError::InvalidRingSize
+    );
+    require_eq!(nft_amounts.len(), participant_count, MyError::InvalidRingSize);
+
+    // 同じwalletが複数のlegを持つとどのlegをdepositするか決まらない
+    for (index, participant_key) in participant_keys.iter().enumerate() {
+        require!(
+            !participant_keys[..index].contains(participant_key),
+            MyError::DuplicateRingParticipant
+        );
+    }
+
+    // remaining_accountsはlegの順にmintだけ
+    let nft_amount_total = nft_amounts
+        .iter()
+        .map(|nft_amount| *nft_amount as usize)
+        .sum::<usize>();
+    require_eq!(
+        ctx.remaining_accounts.len(),
+        nft_amount_total,
+        MyError::NftAmountMismatch
+    );
+
+    let mut offset = 0;
+    let mut legs = Vec::with_capacity(participant_count);
+    for index in 0..participant_count {
+        let nft_amount_count = nft_amounts[index] as usize;
+
+        // 何も渡さないlegがあるとringの意味がない
+        require!(
+            additional_sol_amounts[index] as usize + nft_amount_count > 0,
+            MyError::NotProvidedInitializerAssets
+        );
+
+        let mut nft_mints = Vec::with_capacity(nft_amount_count);
+        for mint_account in &ctx.remaining_accounts[offset..offset + nft_amount_count] {
+            assert_is_nft_mint(mint_account)?;
+            nft_mints.push(mint_account.key());
+        }
+        offset += nft_amount_count;
+
+        legs.push(RingLeg {
+            participant_key: participant_keys[index],
+            additional_sol_amount: additional_sol_amounts[index],
+            nft_mints,
+            ..Default::default()
+        });
+    }
+
+    ctx.accounts.ring_account.creator_key = *ctx.accounts.creator.key;
+    ctx.accounts.ring_account.legs = legs;
+    ctx.accounts.ring_account.nonce = nonce;
+    ctx.accounts.ring_account.bump = *ctx.bumps.get("ring_account").unwrap();
+    ctx.accounts.vault_authority.bump = *ctx.bumps.get("vault_authority").unwrap();
+
+    msg!("end initialize_ring");
+    Ok(())
+}
@@ -0,0 +1,398 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{CloseAccount, Token};
+
+use crate::{
+    errors::MyError,
+    events::ExchangeSettled,
+    state::{Config, EscrowAccount, VaultAuthority, CONFIG_PDA_SEED, ESCROW_PDA_SEED, VAULT_AUTHORITY_PDA_SEED},
+    traits::{
+        close_account, collect_royalties, pay_sol_split, transfer_checked, SolSplit,
+        SolSplitAccounts, Token2022, TokenPrograms, TransferChecked,
+    },
+    utils::{assert_is_ata, assert_is_pda, assert_keys_equal},
+};
+
+// 互いの欲しいものを出し合っている2つのescrowを誰でも決済できる 両方のinitializerの資産がvaultにあるのでsignerは何も払わない
+#[derive(Accounts)]
+pub struct MatchOffers<'info> {
+    #[account(mut)] // initializerが決めた上限までのtipを受け取る
+    pub keeper: Signer<'info>,
+    #[account(mut)]
+    pub initializer_a: SystemAccount<'info>,
+    #[account(mut)]
+    pub initializer_b: SystemAccount<'info>,
+    #[account(
+        mut,
+        seeds = [
+            ESCROW_PDA_SEED,
+            initializer_a.key().as_ref(),
+            escrow_account_a.taker_key.as_ref(),
+            escrow_account_a.nonce.to_le_bytes().as_ref()
+        ],
+        bump = escrow_account_a.bump,
+        constraint = escrow_account_a.initializer_key == *initializer_a.key @ MyError::InitializerPublicKeyMismatch,
+        constraint = escrow_account_a.is_matchable() @ MyError::UnmatchableOffer,
+        constraint = escrow_account_a.can_fill(&escrow_account_b) @ MyError::OffersNotCompatible,
+        close = initializer_a
+    )]
+    pub escrow_account_a: Box<Account<'info, EscrowAccount>>,
+    #[account(
+        mut,
+        seeds = [
+            VAULT_AUTHORITY_PDA_SEED,
+            escrow_account_a.key().as_ref()
+        ],
+        bump = vault_authority_a.bump,
+        close = initializer_a
+    )]
+    pub vault_authority_a: Box<Account<'info, VaultAuthority>>,
+    #[account(
+        mut,
+        seeds = [
+            ESCROW_PDA_SEED,
+            initializer_b.key().as_ref(),
+            escrow_account_b.taker_key.as_ref(),
+            escrow_account_b.nonce.to_le_bytes().as_ref()
+        ],
+        bump = escrow_account_b.bump,
+        constraint = escrow_account_b.key() != escrow_account_a.key() @ MyError::OffersNotCompatible,
+        constraint = escrow_account_b.initializer_key == *initializer_b.key @ MyError::InitializerPublicKeyMismatch,
+        constraint = escrow_account_b.is_matchable() @ MyError::UnmatchableOffer,
+        constraint = escrow_account_b.can_fill(&escrow_account_a) @ MyError::OffersNotCompatible,
+        close = initializer_b
+    )]
+    pub escrow_account_b: Box<Account<'info, EscrowAccount>>,
+    #[account(
+        mut,
+        seeds = [
+            VAULT_AUTHORITY_PDA_SEED,
+            escrow_account_b.key().as_ref()
+        ],
+        bump = vault_authority_b.bump,
+        close = initializer_b
+    )]
+    pub vault_authority_b: Box<Account<'info, VaultAuthority>>,
+    #[account(
+        seeds = [CONFIG_PDA_SEED],
+        bump = config.bump,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        mut,
+        constraint = treasury.key() == config.treasury_key @ MyError::TreasuryPublicKeyMismatch
+    )]
+    pub treasury: SystemAccount<'info>,
+    /// CHECK: escrow aのreferrer いない場合は使わないので何を渡してもよい
+    #[account(
+        mut,
+        constraint = escrow_account_a.referrer_key == Pubkey::default() || referrer_a.key() == escrow_account_a.referrer_key @ MyError::ReferrerPublicKeyMismatch
+    )]
+    pub referrer_a: UncheckedAccount<'info>,
+    /// CHECK: escrow bのreferrer いない場合は使わないので何を渡してもよい
+    #[account(
+        mut,
+        constraint = escrow_account_b.referrer_key == Pubkey::default() || referrer_b.key() == escrow_account_b.referrer_key @ MyError::ReferrerPublicKeyMismatch
+    )]
+    pub referrer_b: UncheckedAccount<'info>,
+    pub token_program: Program<'info, Token>,
+    pub token_2022_program: Program<'info, Token2022>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler<'info>(ctx: Context<'_, '_, '_, 'info, MatchOffers<'info>>) -> Result<()> {
+    msg!("start match_offers");
+
+    let now = Clock::get()?.unix_timestamp;
+    require!(
+        !ctx.accounts.escrow_account_a.is_expired(now)
+            && !ctx.accounts.escrow_account_b.is_expired(now),
+        MyError::EscrowExpired
+    );
+
+    // remaining accountsの数の検証
+    // 預けたtoken account, vault account, 受け取るtoken account, mintの順 escrow aのinitializerの分の後にescrow bのinitializerの分
+    let nft_amount_count_a = ctx
+        .accounts
+        .escrow_account_a
+        .initializer_nft_token_accounts
+        .len();
+    let nft_amount_count_b = ctx
+        .accounts
+        .escrow_account_b
+        .initializer_nft_token_accounts
+        .len();
+    let remaining_accounts_count = (nft_amount_count_a + nft_amount_count_b) * 4;
+    require_gte!(
+        ctx.remaining_accounts.len(),
+        remaining_accounts_count,
+        MyError::NftAmountMismatch
+    );
+
+    // exchangeと同じくSOLと逆向きに動くNFTのcreatorにroyaltyを払う
+    // escrow aのinitializerが受け取るSOLの分はescrow aのNFT、escrow bのinitializerが受け取るSOLの分はescrow bのNFTの順にmetadataとcreatorを渡す
+    let taker_additional_sol_amount_a = ctx.accounts.escrow_account_a.taker_additional_sol_amount;
+    let taker_additional_sol_amount_b = ctx.accounts.escrow_account_b.taker_additional_sol_amount;
+    let royalty_mints_a = (0..nft_amount_count_a)
+        .map(|index| ctx.remaining_accounts[index * 4 + 3].key())
+        .collect::<Vec<Pubkey>>();
+    let royalty_mints_b = (nft_amount_count_a..nft_amount_count_a + nft_amount_count_b)
+        .map(|index| ctx.remaining_accounts[index * 4 + 3].key())
+        .collect::<Vec<Pubkey>>();
+    let (royalty_payments_a, royalty_accounts_count_a) = collect_royalties(
+        &ctx.remaining_accounts[remaining_accounts_count..],
+        &royalty_mints_a,
+        taker_additional_sol_amount_a,
+    )?;
+    let royalty_offset_b = remaining_accounts_count + royalty_accounts_count_a;
+    let (royalty_payments_b, royalty_accounts_count_b) = collect_royalties(
+        &ctx.remaining_accounts[royalty_offset_b..],
+        &royalty_mints_b,
+        taker_additional_sol_amount_b,
+    )?;
+    // transfer hookのあるmintのaccountは最後に渡す
+    let transfer_hook_accounts = &ctx.remaining_accounts[royalty_offset_b + royalty_accounts_count_b..];
+
+    let escrow_key_a = ctx.accounts.escrow_account_a.key();
+    let escrow_key_b = ctx.accounts.escrow_account_b.key();
+    let vault_authority_bump_a = ctx.accounts.vault_authority_a.bump;
+    let vault_authority_bump_b = ctx.accounts.vault_authority_b.bump;
+
+    for index in 0..nft_amount_count_a + nft_amount_count_b {
+        let token_account = &ctx.remaining_accounts[index * 4];
+        let vault_account = &ctx.remaining_accounts[index * 4 + 1];
+        let receiver_token_account = &ctx.remaining_accounts[index * 4 + 2];
+        let mint_account = &ctx.remaining_accounts[index * 4 + 3];
+
+        // escrow aのNFTはescrow bのinitializerへ、escrow bのNFTはescrow aのinitializerへ
+        let is_escrow_a_nft = index < nft_amount_count_a;
+        let (
+            owner,
+            receiver,
+            escrow_account,
+            counter_escrow_account,
+            vault_authority,
+            escrow_key,
+            vault_authority_bump,
+            nft_index,
+        ) = if is_escrow_a_nft {
+            (
+                &ctx.accounts.initializer_a,
+                &ctx.accounts.initializer_b,
+                &ctx.accounts.escrow_account_a,
+                &ctx.accounts.escrow_account_b,
+                ctx.accounts.vault_authority_a.to_account_info(),
+                escrow_key_a,
+                vault_authority_bump_a,
+                index,
+            )
+        } else {
+            (
+                &ctx.accounts.initializer_b,
+                &ctx.accounts.initializer_a,
+                &ctx.accounts.escrow_account_b,
+                &ctx.accounts.escrow_account_a,
+                ctx.accounts.vault_authority_b.to_account_info(),
+                escrow_key_b,
+                vault_authority_bump_b,
+                index - nft_amount_count_a,
+            )
+        };
+        let quantity = escrow_account.initializer_nft_quantities[nft_index];
+
+        // escrow accountの中身と検証 相手のescrowが欲しいmintであること
+        assert_keys_equal(
+            &token_account.key(),
+            &escrow_account.initializer_nft_token_accounts[nft_index],
+            MyError::AssociatedTokenPublicKeyMismatch,
+        )?;
+        assert_keys_equal(
+            &mint_account.key(),
+            &counter_escrow_account.taker_nft_mints[nft_index],
+            MyError::MintPublicKeyMismatch,
+        )?;
+
+        assert_is_ata(token_account, owner.key, mint_account, 0)?;
+
+        assert_is_pda(
+            token_account,
+            &escrow_key,
+            escrow_account.vault_account_bumps[nft_index],
+            vault_account,
+            &vault_authority.key(),
+            ctx.program_id,
+            quantity,
+        )?;
+
+        assert_is_ata(receiver_token_account, receiver.key, mint_account, 0)?;
+
+        transfer_checked(
+            ctx.accounts
+                .into_transfer_from_vault_context(
+                    &vault_authority,
+                    vault_account,
+                    receiver_token_account,
+                    mint_account,
+                )
+                .with_signer(&[&[
+                    VAULT_AUTHORITY_PDA_SEED,
+                    escrow_key.as_ref(),
+                    &[vault_authority_bump],
+                ]])
+                .with_remaining_accounts(transfer_hook_accounts.to_vec()),
+            quantity,
+            0,
+        )?;
+
+        // vaultのrentは預けた側に返す
+        close_account(
+            ctx.accounts
+                .into_close_vault_context(&vault_authority, vault_account, owner)
+                .with_signer(&[&[
+                    VAULT_AUTHORITY_PDA_SEED,
+                    escrow_key.as_ref(),
+                    &[vault_authority_bump],
+                ]]),
+        )?;
+    }
+
+    // 最難関： solanaのbugで金額を動かすのはinto_close_contextの後にする必要がある
+    // 相手のtakerの条件の分をexchangeと同じ内訳で相手のinitializerに払い、残りからinitializerが決めた上限までをkeeperに払う 残りはcloseでinitializerに戻る
+    let keeper_tip_a = ctx
+        .accounts
+        .escrow_account_a
+        .keeper_tip(&ctx.accounts.escrow_account_b);
+    let keeper_tip_b = ctx
+        .accounts
+        .escrow_account_b
+        .keeper_tip(&ctx.accounts.escrow_account_a);
+
+    // escrow bのtakerの条件はescrow aが払い、売り手のescrow bのreferrerに取り分を払う
+    let split_b = SolSplit::new(
+        taker_additional_sol_amount_b,
+        royalty_payments_b,
+        &ctx.accounts.config,
+        &ctx.accounts.escrow_account_b,
+    )?;
+    pay_sol_split(
+        &SolSplitAccounts {
+            payer: ctx.accounts.escrow_account_a.to_account_info(),
+            receiver: ctx.accounts.initializer_b.to_account_info(),
+            treasury: ctx.accounts.treasury.to_account_info(),
+            referrer: ctx.accounts.referrer_b.to_account_info(),
+        },
+        &split_b,
+    )?;
+
+    let split_a = SolSplit::new(
+        taker_additional_sol_amount_a,
+        royalty_payments_a,
+        &ctx.accounts.config,
+        &ctx.accounts.escrow_account_a,
+    )?;
+    pay_sol_split(
+        &SolSplitAccounts {
+            payer: ctx.accounts.escrow_account_b.to_account_info(),
+            receiver: ctx.accounts.initializer_a.to_account_info(),
+            treasury: ctx.accounts.treasury.to_account_info(),
+            referrer: ctx.accounts.referrer_a.to_account_info(),
+        },
+        &split_a,
+    )?;
+
+    // SOLが動かないswapは手数料をbpsでは計算できないので、exchangeでtakerが払う固定の手数料を両方のescrowから半分ずつ払う
+    // tipで手数料をまかなえないとkeeperが損をするので、keeperには払わせない
+    let (flat_fee_amount_a, flat_fee_amount_b) =
+        if taker_additional_sol_amount_a == 0 && taker_additional_sol_amount_b == 0 {
+            let flat_fee_amount_a = ctx.accounts.config.flat_fee / 2;
+            (flat_fee_amount_a, ctx.accounts.config.flat_fee - flat_fee_amount_a)
+        } else {
+            (0, 0)
+        };
+
+    // escrowのrentとinitializerが預けたSOLから払う 残りはcloseでinitializerに戻る
+    let escrow_account_info_a = ctx.accounts.escrow_account_a.to_account_info();
+    let escrow_lamports_a = escrow_account_info_a
+        .lamports()
+        .checked_sub(keeper_tip_a + flat_fee_amount_a)
+        .ok_or(MyError::InitializerInsufficientFunds)?;
+    **escrow_account_info_a.try_borrow_mut_lamports()? = escrow_lamports_a;
+    let escrow_account_info_b = ctx.accounts.escrow_account_b.to_account_info();
+    let escrow_lamports_b = escrow_account_info_b
+        .lamports()
+        .checked_sub(keeper_tip_b + flat_fee_amount_b)
+        .ok_or(MyError::InitializerInsufficientFunds)?;
+    **escrow_account_info_b.try_borrow_mut_lamports()? = escrow_lamports_b;
+    **ctx.accounts.keeper.try_borrow_mut_lamports()? += keeper_tip_a + keeper_tip_b;
+    **ctx.accounts.treasury.try_borrow_mut_lamports()? += flat_fee_amount_a + flat_fee_amount_b;
+
+    // escrowごとにexchangeと同じ内訳を残す 相手のinitializerがtakerになる
+    // escrow aのinitializerが払ったのはescrow bのtakerの条件、受け取ったのはescrow aのtakerの条件
+    emit!(ExchangeSettled {
+        escrow_key: escrow_key_a,
+        initializer_key: ctx.accounts.initializer_a.key(),
+        taker_key: ctx.accounts.initializer_b.key(),
+        initializer_additional_sol_amount: taker_additional_sol_amount_b,
+        taker_additional_sol_amount: taker_additional_sol_amount_a,
+        referrer_key: ctx.accounts.escrow_account_a.referrer_key,
+        referrer_amount: split_a.referrer_amount,
+        fee_amount: split_a.fee_amount + flat_fee_amount_a,
+        royalty_amount: split_a.royalty_amount,
+    });
+    emit!(ExchangeSettled {
+        escrow_key: escrow_key_b,
+        initializer_key: ctx.accounts.initializer_b.key(),
+        taker_key: ctx.accounts.initializer_a.key(),
+        initializer_additional_sol_amount: taker_additional_sol_amount_a,
+        taker_additional_sol_amount: taker_additional_sol_amount_b,
+        referrer_key: ctx.accounts.escrow_account_b.referrer_key,
+        referrer_amount: split_b.referrer_amount,
+        fee_amount: split_b.fee_amount + flat_fee_amount_b,
+        royalty_amount: split_b.royalty_amount,
+    });
+
+    msg!("end match_offers");
+    Ok(())
+}
+
+impl<'info> MatchOffers<'info> {
+    // vault authorityがescrowごとにあるのでRelease traitは使わずに渡す
+    fn into_transfer_from_vault_context(
+        &self,
+        vault_authority: &AccountInfo<'info>,
+        vault_account: &AccountInfo<'info>,
+        receiver_token_account: &AccountInfo<'info>,
+        mint_account: &AccountInfo<'info>,
+    ) -> CpiContext<'_, '_, '_, 'info, TransferChecked<'info>> {
+        let cpi_accounts = TransferChecked {
+            from: vault_account.clone(),
+            mint: mint_account.clone(),
+            to: receiver_token_account.clone(),
+            authority: vault_authority.clone(),
+        };
+        CpiContext::new(self.token_program_for(mint_account), cpi_accounts)
+    }
+
+    fn into_close_vault_context(
+        &self,
+        vault_authority: &AccountInfo<'info>,
+        vault_account: &AccountInfo<'info>,
+        destination: &AccountInfo<'info>,
+    ) -> CpiContext<'_, '_, '_, 'info, CloseAccount<'info>> {
+        let cpi_accounts = CloseAccount {
+            account: vault_account.clone(),
+            destination: destination.clone(),
+            authority: vault_authority.clone(),
+        };
+        CpiContext::new(self.token_program_for(vault_account), cpi_accounts)
+    }
+}
+
+impl<'info> TokenPrograms<'info> for MatchOffers<'info> {
+    fn token_program(&self) -> &AccountInfo<'info> {
+        &self.token_program
+    }
+
+    fn token_2022_program(&self) -> &AccountInfo<'info> {
+        &self.token_2022_program
+    }
+}
@@ -1,9 +1,65 @@
+pub mod accept;
+pub mod amend;
 pub mod cancel_by_initializer;
 pub mod cancel_by_taker;
+pub mod cancel_ring;
+pub mod close_pool;
+pub mod commit_sealed_bid;
+pub mod counter_offer;
+pub mod create_auction;
+pub mod create_pool;
+pub mod create_rental;
+pub mod create_sealed_auction;
+pub mod create_standing_offer;
+pub mod deposit_ring;
+pub mod end_rental;
 pub mod exchange;
+pub mod expire;
+pub mod fill_standing_offer;
 pub mod initialize;
+pub mod initialize_config;
+pub mod initialize_ring;
+pub mod match_offers;
+pub mod place_bid;
+pub mod rent_nft;
+pub mod reveal_sealed_bid;
+pub mod settle;
+pub mod settle_auction;
+pub mod settle_ring;
+pub mod settle_sealed_auction;
+pub mod swap_pool;
+pub mod update_config;
+pub mod withdraw_standing_offer;
 
+pub use accept::*;
+pub use amend::*;
 pub use cancel_by_initializer::*;
 pub use cancel_by_taker::*;
+pub use cancel_ring::*;
+pub use close_pool::*;
+pub use commit_sealed_bid::*;
+pub use counter_offer::*;
+pub use create_auction::*;
+pub use create_pool::*;
+pub use create_rental::*;
+pub use create_sealed_auction::*;
+pub use create_standing_offer::*;
+pub use deposit_ring::*;
+pub use end_rental::*;
 pub use exchange::*;
+pub use expire::*;
+pub use fill_standing_offer::*;
 pub use initialize::*;
+pub use initialize_config::*;
+pub use initialize_ring::*;
+pub use match_offers::*;
+pub use place_bid::*;
+pub use rent_nft::*;
+pub use reveal_sealed_bid::*;
+pub use settle::*;
+pub use settle_auction::*;
+pub use settle_ring::*;
+pub use settle_sealed_auction::*;
+pub use swap_pool::*;
+pub use update_config::*;
+pub use withdraw_standing_offer::*;
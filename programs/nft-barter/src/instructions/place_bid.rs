@@ -0,0 +1,92 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    errors::MyError,
+    state::{AuctionAccount, BidAccount, AUCTION_PDA_SEED, BID_PDA_SEED},
+};
+
+#[derive(Accounts)]
+#[instruction(amount: u64)]
+pub struct PlaceBid<'info> {
+    #[account(
+        mut,
+        constraint = bidder.to_account_info().try_lamports().unwrap() >= amount @ MyError::TakerInsufficientFunds
+    )]
+    pub bidder: Signer<'info>,
+    #[account(
+        seeds = [
+            AUCTION_PDA_SEED,
+            auction_account.seller_key.as_ref(),
+            auction_account.nonce.to_le_bytes().as_ref()
+        ],
+        bump = auction_account.bump,
+    )]
+    pub auction_account: Box<Account<'info, AuctionAccount>>,
+    #[account(
+        mut,
+        seeds = [
+            BID_PDA_SEED,
+            auction_account.key().as_ref()
+        ],
+        bump = bid_account.bump,
+    )]
+    pub bid_account: Box<Account<'info, BidAccount>>,
+    /// CHECK: 最初のbidの場合は返金しないので何を渡してもよい
+    #[account(
+        mut,
+        constraint = !bid_account.has_bid() || previous_bidder.key() == bid_account.bidder_key @ MyError::BidderPublicKeyMismatch
+    )]
+    pub previous_bidder: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler(ctx: Context<PlaceBid>, amount: u64) -> Result<()> {
+    msg!("start place_bid");
+
+    let auction_account = &ctx.accounts.auction_account;
+    require!(
+        !auction_account.is_ended(Clock::get()?.unix_timestamp),
+        MyError::AuctionEnded
+    );
+    require_gte!(amount, auction_account.reserve_price, MyError::BidBelowReservePrice);
+
+    // 前のbidを上回る場合だけ受け付ける
+    let previous_amount = ctx.accounts.bid_account.amount;
+    let has_bid = ctx.accounts.bid_account.has_bid();
+    if has_bid {
+        let min_amount = previous_amount
+            .checked_add(auction_account.min_bid_increment)
+            .ok_or(MyError::BidIncrementTooSmall)?;
+        require_gte!(amount, min_amount, MyError::BidIncrementTooSmall);
+    }
+
+    let ix = anchor_lang::solana_program::system_instruction::transfer(
+        &ctx.accounts.bidder.key(),
+        &ctx.accounts.bid_account.key(),
+        amount,
+    );
+    anchor_lang::solana_program::program::invoke(
+        &ix,
+        &[
+            ctx.accounts.bidder.to_account_info().clone(),
+            ctx.accounts.bid_account.to_account_info().clone(),
+        ],
+    )?;
+
+    // 最難関： solanaのbugで金額を動かすのはCPIの後にする必要がある
+    if has_bid {
+        **ctx
+            .accounts
+            .bid_account
+            .to_account_info()
+            .try_borrow_mut_lamports()? -= previous_amount;
+        **ctx.accounts.previous_bidder.try_borrow_mut_lamports()? += previous_amount;
+    }
+
+    let bid_account = &mut ctx.accounts.bid_account;
+    bid_account.bidder_key = ctx.accounts.bidder.key();
+    bid_account.amount = amount;
+
+    msg!("end place_bid");
+    Ok(())
+}
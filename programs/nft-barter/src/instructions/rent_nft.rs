@@ -0,0 +1,183 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::{approve, Approve, Token};
+use mpl_token_metadata::pda::find_master_edition_account;
+
+use crate::{
+    errors::MyError,
+    state::{RentalAccount, VaultAuthority, RENTAL_PDA_SEED, SECONDS_PER_DAY, VAULT_AUTHORITY_PDA_SEED},
+    traits::{
+        close_account, freeze_delegated_nft, transfer_checked, DelegatedNftAccounts, Release,
+        Token2022, TokenMetadata, TokenPrograms,
+    },
+    utils::{assert_is_ata, assert_is_pda, assert_keys_equal},
+};
+
+// renterが日数分のSOLを先にlenderへ払って借りる NFTはrenterのATAに渡し、期間中はvault authorityがdelegateとしてfreezeしておく
+#[derive(Accounts)]
+#[instruction(days: u16)]
+pub struct RentNft<'info> {
+    #[account(mut)]
+    pub renter: Signer<'info>,
+    #[account(mut)]
+    pub lender: SystemAccount<'info>,
+    #[account(
+        mut,
+        seeds = [
+            RENTAL_PDA_SEED,
+            lender.key().as_ref(),
+            rental_account.nonce.to_le_bytes().as_ref()
+        ],
+        bump = rental_account.bump,
+        constraint = rental_account.lender_key == *lender.key @ MyError::InitializerPublicKeyMismatch,
+        constraint = !rental_account.is_rented() @ MyError::RentalAlreadyRented,
+        constraint = days > 0 && days <= rental_account.max_days @ MyError::InvalidRentalDays,
+    )]
+    pub rental_account: Box<Account<'info, RentalAccount>>,
+    #[account(
+        seeds = [
+            VAULT_AUTHORITY_PDA_SEED,
+            rental_account.key().as_ref()
+        ],
+        bump = vault_authority.bump,
+    )]
+    pub vault_authority: Box<Account<'info, VaultAuthority>>,
+    pub token_program: Program<'info, Token>,
+    pub token_2022_program: Program<'info, Token2022>,
+    pub token_metadata_program: Program<'info, TokenMetadata>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler<'info>(ctx: Context<'_, '_, '_, 'info, RentNft<'info>>, days: u16) -> Result<()> {
+    msg!("start rent_nft");
+
+    // lenderのtoken account, vault account, renterのtoken account, mint, master editionの順
+    require_gte!(
+        ctx.remaining_accounts.len(),
+        5,
+        MyError::NotFoundRemainingAccounts
+    );
+    let token_account = &ctx.remaining_accounts[0];
+    let vault_account = &ctx.remaining_accounts[1];
+    let renter_token_account = &ctx.remaining_accounts[2];
+    let mint_account = &ctx.remaining_accounts[3];
+    let edition_account = &ctx.remaining_accounts[4];
+
+    // rental accountの中身と検証
+    let rental_account = &ctx.accounts.rental_account;
+    assert_keys_equal(
+        &token_account.key(),
+        &rental_account.nft_token_account,
+        MyError::AssociatedTokenPublicKeyMismatch,
+    )?;
+    assert_is_ata(token_account, ctx.accounts.lender.key, mint_account, 0)?;
+    assert_is_pda(
+        token_account,
+        &rental_account.key(),
+        rental_account.vault_account_bump,
+        vault_account,
+        &ctx.accounts.vault_authority.key(),
+        ctx.program_id,
+        1, // 貸すのはNFTだけ
+    )?;
+    assert_is_ata(renter_token_account, ctx.accounts.renter.key, mint_account, 0)?;
+    assert_keys_equal(
+        &find_master_edition_account(mint_account.key).0,
+        edition_account.key,
+        MyError::MetadataPublicKeyMismatch,
+    )?;
+
+    let price = rental_account
+        .daily_price
+        .checked_mul(days as u64)
+        .ok_or(MyError::TakerInsufficientFunds)?;
+    require_gte!(
+        ctx.accounts.renter.to_account_info().try_lamports()?,
+        price,
+        MyError::TakerInsufficientFunds
+    );
+
+    if price > 0 {
+        let ix = anchor_lang::solana_program::system_instruction::transfer(
+            &ctx.accounts.renter.key(),
+            &ctx.accounts.lender.key(),
+            price,
+        );
+        anchor_lang::solana_program::program::invoke(
+            &ix,
+            &[
+                ctx.accounts.renter.to_account_info().clone(),
+                ctx.accounts.lender.to_account_info().clone(),
+            ],
+        )?;
+    }
+
+    let rental_key = rental_account.key();
+    let vault_authority_bump = ctx.accounts.vault_authority.bump;
+    let signer_seeds: &[&[&[u8]]] = &[&[
+        VAULT_AUTHORITY_PDA_SEED,
+        rental_key.as_ref(),
+        &[vault_authority_bump],
+    ]];
+
+    // NFTをrenterに渡し、vaultのrentはlenderに返す
+    transfer_checked(
+        ctx.accounts
+            .into_transfer_to_receiver_context(vault_account, renter_token_account, mint_account)
+            .with_signer(signer_seeds),
+        1,
+        0,
+    )?;
+    close_account(
+        ctx.accounts
+            .into_close_to_context(vault_account, &ctx.accounts.lender)
+            .with_signer(signer_seeds),
+    )?;
+
+    // renterがvault authorityをdelegateにし、期間中は動かせないようにfreezeする
+    approve(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Approve {
+                to: renter_token_account.clone(),
+                delegate: ctx.accounts.vault_authority.to_account_info(),
+                authority: ctx.accounts.renter.to_account_info(),
+            },
+        ),
+        1,
+    )?;
+    freeze_delegated_nft(
+        &DelegatedNftAccounts {
+            token_metadata_program: ctx.accounts.token_metadata_program.to_account_info(),
+            delegate: ctx.accounts.vault_authority.to_account_info(),
+            token_account: renter_token_account.clone(),
+            edition: edition_account.clone(),
+            mint: mint_account.clone(),
+            token_program: ctx.accounts.token_program.to_account_info(),
+        },
+        signer_seeds,
+    )?;
+
+    let rental_account = &mut ctx.accounts.rental_account;
+    rental_account.renter_key = ctx.accounts.renter.key();
+    rental_account.renter_token_account = renter_token_account.key();
+    rental_account.rented_until = Clock::get()?.unix_timestamp + days as i64 * SECONDS_PER_DAY;
+
+    msg!("end rent_nft");
+    Ok(())
+}
+
+impl<'info> TokenPrograms<'info> for RentNft<'info> {
+    fn token_program(&self) -> &AccountInfo<'info> {
+        &self.token_program
+    }
+
+    fn token_2022_program(&self) -> &AccountInfo<'info> {
+        &self.token_2022_program
+    }
+}
+
+impl<'info> Release<'info> for RentNft<'info> {
+    fn vault_authority(&self) -> &Box<Account<'info, VaultAuthority>> {
+        &self.vault_authority
+    }
+}
@@ -0,0 +1,72 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    errors::MyError,
+    state::{SealedAuctionAccount, SealedBidAccount, SEALED_AUCTION_PDA_SEED, SEALED_BID_PDA_SEED},
+    utils::get_sealed_bid_commitment,
+};
+
+#[derive(Accounts)]
+pub struct RevealSealedBid<'info> {
+    pub bidder: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [
+            SEALED_AUCTION_PDA_SEED,
+            sealed_auction_account.seller_key.as_ref(),
+            sealed_auction_account.nonce.to_le_bytes().as_ref()
+        ],
+        bump = sealed_auction_account.bump,
+    )]
+    pub sealed_auction_account: Box<Account<'info, SealedAuctionAccount>>,
+    #[account(
+        mut,
+        seeds = [
+            SEALED_BID_PDA_SEED,
+            sealed_auction_account.key().as_ref(),
+            bidder.key().as_ref()
+        ],
+        bump = sealed_bid_account.bump,
+        constraint = !sealed_bid_account.revealed @ MyError::SealedBidAlreadyRevealed,
+    )]
+    pub sealed_bid_account: Box<Account<'info, SealedBidAccount>>,
+}
+
+pub fn handler(ctx: Context<RevealSealedBid>, amount: u64, salt: [u8; 32]) -> Result<()> {
+    msg!("start reveal_sealed_bid");
+
+    require!(
+        ctx.accounts
+            .sealed_auction_account
+            .is_reveal_window(Clock::get()?.unix_timestamp),
+        MyError::NotInRevealWindow
+    );
+
+    let sealed_bid_account = &mut ctx.accounts.sealed_bid_account;
+    require!(
+        get_sealed_bid_commitment(amount, &salt, ctx.accounts.bidder.key)
+            == sealed_bid_account.commitment,
+        MyError::SealedBidCommitmentMismatch
+    );
+    // depositを超える額は払えない
+    require_gte!(
+        sealed_bid_account.deposit_amount,
+        amount,
+        MyError::BidExceedsDeposit
+    );
+    sealed_bid_account.revealed = true;
+    sealed_bid_account.amount = amount;
+
+    // reserve price未満のrevealは返金されるだけで落札できない 同額の場合は先にrevealした方
+    let sealed_auction_account = &mut ctx.accounts.sealed_auction_account;
+    if amount >= sealed_auction_account.reserve_price
+        && (!sealed_auction_account.has_winner()
+            || amount > sealed_auction_account.highest_bid_amount)
+    {
+        sealed_auction_account.highest_bidder_key = ctx.accounts.bidder.key();
+        sealed_auction_account.highest_bid_amount = amount;
+    }
+
+    msg!("end reveal_sealed_bid");
+    Ok(())
+}
@@ -0,0 +1,284 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Token;
+
+use crate::{
+    errors::MyError,
+    events::ExchangeSettled,
+    state::{Config, EscrowAccount, CONFIG_PDA_SEED, ESCROW_PDA_SEED, VAULT_AUTHORITY_PDA_SEED, VaultAuthority},
+    traits::{
+        close_account, collect_royalties, pay_sol_split, transfer_checked, Release, SolSplit,
+        SolSplitAccounts, Token2022, TokenPrograms,
+    },
+    utils::{assert_is_ata, assert_is_pda, assert_keys_equal},
+};
+
+// acceptされたescrowは両者の資産がvaultにあるので、initializerとtakerのどちらが呼んでも同じ結果になる
+#[derive(Accounts)]
+pub struct Settle<'info> {
+    #[account(
+        mut, // SOLが動かないswapの固定の手数料を払う
+        constraint = *authority.key == initializer.key() || *authority.key == taker.key() @ MyError::AssociatedAuthorityMismatch
+    )]
+    pub authority: Signer<'info>,
+    #[account(mut)]
+    pub initializer: SystemAccount<'info>,
+    #[account(mut)]
+    pub taker: SystemAccount<'info>,
+    #[account(
+        mut,
+        seeds = [
+            ESCROW_PDA_SEED,
+            initializer.key().as_ref(),
+            escrow_account.taker_key.as_ref(),
+            escrow_account.nonce.to_le_bytes().as_ref()
+        ],
+        bump = escrow_account.bump,
+        constraint = escrow_account.initializer_key == *initializer.key @ MyError::InitializerPublicKeyMismatch,
+        constraint = escrow_account.is_accepted() @ MyError::EscrowNotAccepted,
+        constraint = escrow_account.accepted_taker_key == *taker.key @ MyError::TakerPublicKeyMismatch,
+        close = initializer
+    )]
+    pub escrow_account: Box<Account<'info, EscrowAccount>>,
+    #[account(
+        mut,
+        seeds = [
+            VAULT_AUTHORITY_PDA_SEED,
+            escrow_account.key().as_ref()
+        ],
+        bump = vault_authority.bump,
+        close = initializer
+    )]
+    pub vault_authority: Box<Account<'info, VaultAuthority>>,
+    #[account(
+        seeds = [CONFIG_PDA_SEED],
+        bump = config.bump,
+    )]
+    pub config: Box<Account<'info, Config>>,
+    #[account(
+        mut,
+        constraint = treasury.key() == config.treasury_key @ MyError::TreasuryPublicKeyMismatch
+    )]
+    pub treasury: SystemAccount<'info>,
+    /// CHECK: referrerがいない場合は使わないので何を渡してもよい
+    #[account(
+        mut,
+        constraint = escrow_account.referrer_key == Pubkey::default() || referrer.key() == escrow_account.referrer_key @ MyError::ReferrerPublicKeyMismatch
+    )]
+    pub referrer: UncheckedAccount<'info>,
+    pub token_program: Program<'info, Token>,
+    pub token_2022_program: Program<'info, Token2022>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+pub fn handler<'info>(ctx: Context<'_, '_, '_, 'info, Settle<'info>>) -> Result<()> {
+    msg!("start settle");
+
+    // remaining accountsの数の検証
+    // 預けたtoken account, vault account, 受け取るtoken account, mintの順 initializerの分の後にtakerの分
+    let initializer_nft_amount_count = ctx
+        .accounts
+        .escrow_account
+        .initializer_nft_token_accounts
+        .len();
+    let taker_nft_amount_count = ctx.accounts.escrow_account.taker_nft_token_accounts.len();
+    let remaining_accounts_count = (initializer_nft_amount_count + taker_nft_amount_count) * 4;
+    require_gte!(
+        ctx.remaining_accounts.len(),
+        remaining_accounts_count,
+        MyError::NftAmountMismatch
+    );
+
+    // exchangeと同じくSOLと逆向きに動くNFTのcreatorにroyaltyを払う
+    // initializerのNFTのmetadataとcreator、takerのNFTのmetadataとcreatorの順で渡す SOLが0の向きは不要
+    let initializer_additional_sol_amount = ctx
+        .accounts
+        .escrow_account
+        .initializer_additional_sol_amount;
+    let taker_additional_sol_amount = ctx.accounts.escrow_account.taker_additional_sol_amount;
+    let initializer_royalty_mints = (0..initializer_nft_amount_count)
+        .map(|index| ctx.remaining_accounts[index * 4 + 3].key())
+        .collect::<Vec<Pubkey>>();
+    let taker_royalty_mints = (initializer_nft_amount_count..initializer_nft_amount_count + taker_nft_amount_count)
+        .map(|index| ctx.remaining_accounts[index * 4 + 3].key())
+        .collect::<Vec<Pubkey>>();
+    let (initializer_royalty_payments, initializer_royalty_accounts_count) = collect_royalties(
+        &ctx.remaining_accounts[remaining_accounts_count..],
+        &initializer_royalty_mints,
+        taker_additional_sol_amount,
+    )?;
+    let taker_royalty_offset = remaining_accounts_count + initializer_royalty_accounts_count;
+    let (taker_royalty_payments, taker_royalty_accounts_count) = collect_royalties(
+        &ctx.remaining_accounts[taker_royalty_offset..],
+        &taker_royalty_mints,
+        initializer_additional_sol_amount,
+    )?;
+    // transfer hookのあるmintのaccountは最後に渡す
+    let transfer_hook_accounts = &ctx.remaining_accounts[taker_royalty_offset + taker_royalty_accounts_count..];
+
+    let escrow_key = ctx.accounts.escrow_account.key();
+    let vault_authority_bump = ctx.accounts.vault_authority.bump;
+
+    for index in 0..initializer_nft_amount_count + taker_nft_amount_count {
+        let token_account = &ctx.remaining_accounts[index * 4];
+        let vault_account = &ctx.remaining_accounts[index * 4 + 1];
+        let receiver_token_account = &ctx.remaining_accounts[index * 4 + 2];
+        let mint_account = &ctx.remaining_accounts[index * 4 + 3];
+
+        // initializerのNFTはtakerへ、takerのNFTはinitializerへ
+        let is_initializer_nft = index < initializer_nft_amount_count;
+        let (owner, receiver, expected_token_account, vault_account_bump, quantity) = if is_initializer_nft {
+            (
+                &ctx.accounts.initializer,
+                &ctx.accounts.taker,
+                ctx.accounts.escrow_account.initializer_nft_token_accounts[index],
+                ctx.accounts.escrow_account.vault_account_bumps[index],
+                ctx.accounts.escrow_account.initializer_nft_quantities[index],
+            )
+        } else {
+            let taker_index = index - initializer_nft_amount_count;
+            (
+                &ctx.accounts.taker,
+                &ctx.accounts.initializer,
+                ctx.accounts.escrow_account.taker_nft_token_accounts[taker_index],
+                ctx.accounts.escrow_account.taker_vault_account_bumps[taker_index],
+                ctx.accounts.escrow_account.taker_nft_quantities[taker_index],
+            )
+        };
+
+        // escrow accountの中身と検証
+        assert_keys_equal(
+            &token_account.key(),
+            &expected_token_account,
+            MyError::AssociatedTokenPublicKeyMismatch,
+        )?;
+
+        assert_is_ata(token_account, owner.key, mint_account, 0)?;
+
+        assert_is_pda(
+            token_account,
+            &ctx.accounts.escrow_account.key(),
+            vault_account_bump,
+            vault_account,
+            &ctx.accounts.vault_authority.key(),
+            ctx.program_id,
+            quantity,
+        )?;
+
+        assert_is_ata(receiver_token_account, receiver.key, mint_account, 0)?;
+
+        transfer_checked(
+            ctx.accounts
+                .into_transfer_to_receiver_context(
+                    vault_account,
+                    receiver_token_account,
+                    mint_account,
+                )
+                .with_signer(&[&[
+                    VAULT_AUTHORITY_PDA_SEED,
+                    escrow_key.as_ref(),
+                    &[vault_authority_bump],
+                ]])
+                .with_remaining_accounts(transfer_hook_accounts.to_vec()),
+            quantity,
+            0,
+        )?;
+
+        // vaultのrentは預けた側に返す
+        close_account(
+            ctx.accounts
+                .into_close_to_context(vault_account, owner)
+                .with_signer(&[&[
+                    VAULT_AUTHORITY_PDA_SEED,
+                    escrow_key.as_ref(),
+                    &[vault_authority_bump],
+                ]]),
+        )?;
+    }
+
+    // SOLが動かないswapは手数料をbpsでは計算できないので、settleを呼んだ側が固定の手数料を払う
+    let flat_fee_amount = if taker_additional_sol_amount == 0 && initializer_additional_sol_amount == 0 {
+        ctx.accounts.config.flat_fee
+    } else {
+        0
+    };
+    if flat_fee_amount > 0 {
+        let ix = anchor_lang::solana_program::system_instruction::transfer(
+            &ctx.accounts.authority.key(),
+            &ctx.accounts.treasury.key(),
+            flat_fee_amount,
+        );
+        anchor_lang::solana_program::program::invoke(
+            &ix,
+            &[
+                ctx.accounts.authority.to_account_info().clone(),
+                ctx.accounts.treasury.to_account_info().clone(),
+            ],
+        )?;
+    }
+
+    // 最難関： solanaのbugで金額を動かすのはinto_close_contextの後にする必要がある
+    // 両者のSOLはescrowにあるので、exchangeと同じ内訳でescrowから払う
+    let initializer_split = SolSplit::new(
+        taker_additional_sol_amount,
+        initializer_royalty_payments,
+        &ctx.accounts.config,
+        &ctx.accounts.escrow_account,
+    )?;
+    pay_sol_split(
+        &SolSplitAccounts {
+            payer: ctx.accounts.escrow_account.to_account_info(),
+            receiver: ctx.accounts.initializer.to_account_info(),
+            treasury: ctx.accounts.treasury.to_account_info(),
+            referrer: ctx.accounts.referrer.to_account_info(),
+        },
+        &initializer_split,
+    )?;
+
+    let taker_split = SolSplit::new(
+        initializer_additional_sol_amount,
+        taker_royalty_payments,
+        &ctx.accounts.config,
+        &ctx.accounts.escrow_account,
+    )?;
+    pay_sol_split(
+        &SolSplitAccounts {
+            payer: ctx.accounts.escrow_account.to_account_info(),
+            receiver: ctx.accounts.taker.to_account_info(),
+            treasury: ctx.accounts.treasury.to_account_info(),
+            referrer: ctx.accounts.referrer.to_account_info(),
+        },
+        &taker_split,
+    )?;
+
+    emit!(ExchangeSettled {
+        escrow_key: ctx.accounts.escrow_account.key(),
+        initializer_key: ctx.accounts.initializer.key(),
+        taker_key: ctx.accounts.taker.key(),
+        initializer_additional_sol_amount,
+        taker_additional_sol_amount,
+        referrer_key: ctx.accounts.escrow_account.referrer_key,
+        referrer_amount: initializer_split.referrer_amount + taker_split.referrer_amount,
+        fee_amount: initializer_split.fee_amount + taker_split.fee_amount + flat_fee_amount,
+        royalty_amount: initializer_split.royalty_amount + taker_split.royalty_amount,
+    });
+
+    msg!("end settle");
+    Ok(())
+}
+
+impl<'info> TokenPrograms<'info> for Settle<'info> {
+    fn token_program(&self) -> &AccountInfo<'info> {
+        &self.token_program
+    }
+
+    fn token_2022_program(&self) -> &AccountInfo<'info> {
+        &self.token_2022_program
+    }
+}
+
+impl<'info> Release<'info> for Settle<'info> {
+    fn vault_authority(&self) -> &Box<Account<'info, VaultAuthority>> {
+        &self.vault_authority
+    }
+}
@@ -0,0 +1,165 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Token;
+
+use crate::{
+    errors::MyError,
+    state::{
+        AuctionAccount, BidAccount, VaultAuthority, AUCTION_PDA_SEED, BID_PDA_SEED,
+        VAULT_AUTHORITY_PDA_SEED,
+    },
+    traits::{close_account, transfer_checked, Release, Token2022, TokenPrograms},
+    utils::{assert_is_ata, assert_is_pda, assert_keys_equal},
+};
+
+// 終了後は誰が呼んでも同じ結果になるのでsignerは不要
+#[derive(Accounts)]
+pub struct SettleAuction<'info> {
+    #[account(mut)]
+    pub seller: SystemAccount<'info>,
+    /// CHECK: bidがない場合はNFTをsellerに返すので何を渡してもよい
+    #[account(
+        constraint = !bid_account.has_bid() || winner.key() == bid_account.bidder_key @ MyError::BidderPublicKeyMismatch
+    )]
+    pub winner: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [
+            AUCTION_PDA_SEED,
+            seller.key().as_ref(),
+            auction_account.nonce.to_le_bytes().as_ref()
+        ],
+        bump = auction_account.bump,
+        constraint = auction_account.seller_key == *seller.key @ MyError::InitializerPublicKeyMismatch,
+        close = seller
+    )]
+    pub auction_account: Box<Account<'info, AuctionAccount>>,
+    #[account(
+        mut,
+        seeds = [
+            VAULT_AUTHORITY_PDA_SEED,
+            auction_account.key().as_ref()
+        ],
+        bump = vault_authority.bump,
+        close = seller
+    )]
+    pub vault_authority: Box<Account<'info, VaultAuthority>>,
+    // 最高額のbidはrentと一緒にsellerに払われる
+    #[account(
+        mut,
+        seeds = [
+            BID_PDA_SEED,
+            auction_account.key().as_ref()
+        ],
+        bump = bid_account.bump,
+        close = seller
+    )]
+    pub bid_account: Box<Account<'info, BidAccount>>,
+    pub token_program: Program<'info, Token>,
+    pub token_2022_program: Program<'info, Token2022>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler<'info>(ctx: Context<'_, '_, '_, 'info, SettleAuction<'info>>) -> Result<()> {
+    msg!("start settle_auction");
+
+    require!(
+        ctx.accounts
+            .auction_account
+            .is_ended(Clock::get()?.unix_timestamp),
+        MyError::AuctionNotEnded
+    );
+
+    // remaining accountsの数の検証
+    // 預けたtoken account, vault account, 受け取るtoken account, mintの順
+    let nft_amount_count = ctx.accounts.auction_account.nft_token_accounts.len();
+    // transfer hookのあるmintのaccountは最後に渡す
+    let transfer_hook_offset = nft_amount_count * 4;
+    require_gte!(
+        ctx.remaining_accounts.len(),
+        transfer_hook_offset,
+        MyError::NftAmountMismatch
+    );
+    let transfer_hook_accounts = &ctx.remaining_accounts[transfer_hook_offset..];
+
+    // reserve priceを満たすbidがなければNFTはsellerに戻る
+    let receiver = if ctx.accounts.bid_account.has_bid() {
+        ctx.accounts.winner.to_account_info()
+    } else {
+        ctx.accounts.seller.to_account_info()
+    };
+
+    let auction_key = ctx.accounts.auction_account.key();
+    let vault_authority_bump = ctx.accounts.vault_authority.bump;
+
+    for index in 0..nft_amount_count {
+        let token_account = &ctx.remaining_accounts[index * 4];
+        let vault_account = &ctx.remaining_accounts[index * 4 + 1];
+        let receiver_token_account = &ctx.remaining_accounts[index * 4 + 2];
+        let mint_account = &ctx.remaining_accounts[index * 4 + 3];
+
+        // auction accountの中身と検証
+        assert_keys_equal(
+            &token_account.key(),
+            &ctx.accounts.auction_account.nft_token_accounts[index],
+            MyError::AssociatedTokenPublicKeyMismatch,
+        )?;
+
+        assert_is_ata(token_account, ctx.accounts.seller.key, mint_account, 0)?;
+
+        let quantity = ctx.accounts.auction_account.nft_quantities[index];
+        assert_is_pda(
+            token_account,
+            &ctx.accounts.auction_account.key(),
+            ctx.accounts.auction_account.vault_account_bumps[index],
+            vault_account,
+            &ctx.accounts.vault_authority.key(),
+            ctx.program_id,
+            quantity,
+        )?;
+
+        assert_is_ata(receiver_token_account, receiver.key, mint_account, 0)?;
+
+        transfer_checked(
+            ctx.accounts
+                .into_transfer_to_receiver_context(vault_account, receiver_token_account, mint_account)
+                .with_signer(&[&[
+                    VAULT_AUTHORITY_PDA_SEED,
+                    auction_key.as_ref(),
+                    &[vault_authority_bump],
+                ]])
+                .with_remaining_accounts(transfer_hook_accounts.to_vec()),
+            quantity,
+            0,
+        )?;
+
+        // vaultのrentは預けた側に返す
+        close_account(
+            ctx.accounts
+                .into_close_to_context(vault_account, &ctx.accounts.seller)
+                .with_signer(&[&[
+                    VAULT_AUTHORITY_PDA_SEED,
+                    auction_key.as_ref(),
+                    &[vault_authority_bump],
+                ]]),
+        )?;
+    }
+
+    msg!("end settle_auction");
+    Ok(())
+}
+
+impl<'info> TokenPrograms<'info> for SettleAuction<'info> {
+    fn token_program(&self) -> &AccountInfo<'info> {
+        &self.token_program
+    }
+
+    fn token_2022_program(&self) -> &AccountInfo<'info> {
+        &self.token_2022_program
+    }
+}
+
+impl<'info> Release<'info> for SettleAuction<'info> {
+    fn vault_authority(&self) -> &Box<Account<'info, VaultAuthority>> {
+        &self.vault_authority
+    }
+}
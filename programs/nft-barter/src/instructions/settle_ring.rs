@@ -0,0 +1,172 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Token;
+
+use crate::{
+    errors::MyError,
+    state::{RingAccount, VaultAuthority, RING_PDA_SEED, VAULT_AUTHORITY_PDA_SEED},
+    traits::{close_account, transfer_checked, Release, Token2022, TokenPrograms},
+    utils::{assert_is_ata, assert_is_pda, assert_keys_equal},
+};
+
+// 全員がdepositした後は誰が呼んでも同じ結果になるのでsignerは不要
+#[derive(Accounts)]
+pub struct SettleRing<'info> {
+    #[account(mut)]
+    pub creator: SystemAccount<'info>,
+    #[account(
+        mut,
+        seeds = [
+            RING_PDA_SEED,
+            creator.key().as_ref(),
+            ring_account.nonce.to_le_bytes().as_ref()
+        ],
+        bump = ring_account.bump,
+        constraint = ring_account.creator_key == *creator.key @ MyError::InitializerPublicKeyMismatch,
+        constraint = ring_account.is_fully_deposited() @ MyError::RingNotFullyDeposited,
+        close = creator
+    )]
+    pub ring_account: Box<Account<'info, RingAccount>>,
+    #[account(
+        mut,
+        seeds = [
+            VAULT_AUTHORITY_PDA_SEED,
+            ring_account.key().as_ref()
+        ],
+        bump = vault_authority.bump,
+        close = creator
+    )]
+    pub vault_authority: Box<Account<'info, VaultAuthority>>,
+    pub token_program: Program<'info, Token>,
+    pub token_2022_program: Program<'info, Token2022>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler<'info>(ctx: Context<'_, '_, '_, 'info, SettleRing<'info>>) -> Result<()> {
+    msg!("start settle_ring");
+
+    // remaining accountsの数の検証
+    // 先頭にlegの順でparticipant その後legごとに預けたtoken account, vault account, 受け取るtoken account, mintの順
+    let participant_count = ctx.accounts.ring_account.legs.len();
+    let nft_amount_total = ctx
+        .accounts
+        .ring_account
+        .legs
+        .iter()
+        .map(|leg| leg.nft_token_accounts.len())
+        .sum::<usize>();
+    // transfer hookのあるmintのaccountは最後に渡す
+    let transfer_hook_offset = participant_count + nft_amount_total * 4;
+    require_gte!(
+        ctx.remaining_accounts.len(),
+        transfer_hook_offset,
+        MyError::NftAmountMismatch
+    );
+    let transfer_hook_accounts = &ctx.remaining_accounts[transfer_hook_offset..];
+
+    let participants = &ctx.remaining_accounts[..participant_count];
+    for (participant, leg) in participants.iter().zip(ctx.accounts.ring_account.legs.iter()) {
+        assert_keys_equal(
+            participant.key,
+            &leg.participant_key,
+            MyError::NotRingParticipant,
+        )?;
+    }
+
+    let ring_key = ctx.accounts.ring_account.key();
+    let vault_authority_bump = ctx.accounts.vault_authority.bump;
+
+    let mut offset = participant_count;
+    for (leg_index, leg) in ctx.accounts.ring_account.legs.iter().enumerate() {
+        let owner = &participants[leg_index];
+        let receiver = &participants[ctx.accounts.ring_account.receiver_index(leg_index)];
+
+        for (index, nft_token_account) in leg.nft_token_accounts.iter().enumerate() {
+            let token_account = &ctx.remaining_accounts[offset + index * 4];
+            let vault_account = &ctx.remaining_accounts[offset + index * 4 + 1];
+            let receiver_token_account = &ctx.remaining_accounts[offset + index * 4 + 2];
+            let mint_account = &ctx.remaining_accounts[offset + index * 4 + 3];
+
+            // ring accountの中身と検証
+            assert_keys_equal(
+                &token_account.key(),
+                nft_token_account,
+                MyError::AssociatedTokenPublicKeyMismatch,
+            )?;
+
+            assert_is_ata(token_account, owner.key, mint_account, 0)?;
+
+            assert_is_pda(
+                token_account,
+                &ctx.accounts.ring_account.key(),
+                leg.vault_account_bumps[index],
+                vault_account,
+                &ctx.accounts.vault_authority.key(),
+                ctx.program_id,
+                1, // ringはNFTだけ
+            )?;
+
+            assert_is_ata(receiver_token_account, receiver.key, mint_account, 0)?;
+
+            transfer_checked(
+                ctx.accounts
+                    .into_transfer_to_receiver_context(
+                        vault_account,
+                        receiver_token_account,
+                        mint_account,
+                    )
+                    .with_signer(&[&[
+                        VAULT_AUTHORITY_PDA_SEED,
+                        ring_key.as_ref(),
+                        &[vault_authority_bump],
+                    ]])
+                    .with_remaining_accounts(transfer_hook_accounts.to_vec()),
+                1,
+                0,
+            )?;
+
+            // vaultのrentは預けた側に返す
+            close_account(
+                ctx.accounts
+                    .into_close_to_context(vault_account, owner)
+                    .with_signer(&[&[
+                        VAULT_AUTHORITY_PDA_SEED,
+                        ring_key.as_ref(),
+                        &[vault_authority_bump],
+                    ]]),
+            )?;
+        }
+        offset += leg.nft_token_accounts.len() * 4;
+    }
+
+    // 最難関： solanaのbugで金額を動かすのはinto_close_contextの後にする必要がある
+    for (leg_index, leg) in ctx.accounts.ring_account.legs.iter().enumerate() {
+        if leg.additional_sol_amount > 0 {
+            let receiver = &participants[ctx.accounts.ring_account.receiver_index(leg_index)];
+            **ctx
+                .accounts
+                .ring_account
+                .to_account_info()
+                .try_borrow_mut_lamports()? -= leg.additional_sol_amount;
+            **receiver.try_borrow_mut_lamports()? += leg.additional_sol_amount;
+        }
+    }
+
+    msg!("end settle_ring");
+    Ok(())
+}
+
+impl<'info> TokenPrograms<'info> for SettleRing<'info> {
+    fn token_program(&self) -> &AccountInfo<'info> {
+        &self.token_program
+    }
+
+    fn token_2022_program(&self) -> &AccountInfo<'info> {
+        &self.token_2022_program
+    }
+}
+
+impl<'info> Release<'info> for SettleRing<'info> {
+    fn vault_authority(&self) -> &Box<Account<'info, VaultAuthority>> {
+        &self.vault_authority
+    }
+}
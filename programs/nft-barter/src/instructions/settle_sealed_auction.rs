@@ -0,0 +1,196 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Token;
+
+use crate::{
+    errors::MyError,
+    state::{
+        SealedAuctionAccount, SealedBidAccount, VaultAuthority, MAX_FEE_BASIS_POINTS,
+        SEALED_AUCTION_PDA_SEED, VAULT_AUTHORITY_PDA_SEED,
+    },
+    traits::{close_account, transfer_checked, Release, Token2022, TokenPrograms},
+    utils::{assert_is_ata, assert_is_pda, assert_keys_equal},
+};
+
+// revealの期間が終わった後は誰が呼んでも同じ結果になるのでsignerは不要
+#[derive(Accounts)]
+pub struct SettleSealedAuction<'info> {
+    #[account(mut)]
+    pub seller: SystemAccount<'info>,
+    /// CHECK: 有効なrevealがない場合はNFTをsellerに返すので何を渡してもよい
+    #[account(
+        constraint = !sealed_auction_account.has_winner() || winner.key() == sealed_auction_account.highest_bidder_key @ MyError::BidderPublicKeyMismatch
+    )]
+    pub winner: UncheckedAccount<'info>,
+    #[account(
+        mut,
+        seeds = [
+            SEALED_AUCTION_PDA_SEED,
+            seller.key().as_ref(),
+            sealed_auction_account.nonce.to_le_bytes().as_ref()
+        ],
+        bump = sealed_auction_account.bump,
+        constraint = sealed_auction_account.seller_key == *seller.key @ MyError::InitializerPublicKeyMismatch,
+        close = seller
+    )]
+    pub sealed_auction_account: Box<Account<'info, SealedAuctionAccount>>,
+    #[account(
+        mut,
+        seeds = [
+            VAULT_AUTHORITY_PDA_SEED,
+            sealed_auction_account.key().as_ref()
+        ],
+        bump = vault_authority.bump,
+        close = seller
+    )]
+    pub vault_authority: Box<Account<'info, VaultAuthority>>,
+    pub token_program: Program<'info, Token>,
+    pub token_2022_program: Program<'info, Token2022>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler<'info>(ctx: Context<'_, '_, '_, 'info, SettleSealedAuction<'info>>) -> Result<()> {
+    msg!("start settle_sealed_auction");
+
+    require!(
+        Clock::get()?.unix_timestamp >= ctx.accounts.sealed_auction_account.reveal_end_at,
+        MyError::AuctionNotEnded
+    );
+
+    // remaining accountsの数の検証
+    // NFTごとに預けたtoken account, vault account, 受け取るtoken account, mint その後に全てのbidの[sealed bid account, bidder]
+    let nft_amount_count = ctx.accounts.sealed_auction_account.nft_token_accounts.len();
+    let bid_count = ctx.accounts.sealed_auction_account.bid_count as usize;
+    let bid_offset = nft_amount_count * 4;
+    // transfer hookのあるmintのaccountは最後に渡す
+    let transfer_hook_offset = bid_offset + bid_count * 2;
+    require_gte!(
+        ctx.remaining_accounts.len(),
+        transfer_hook_offset,
+        MyError::NftAmountMismatch
+    );
+    let transfer_hook_accounts = &ctx.remaining_accounts[transfer_hook_offset..];
+
+    // 有効なrevealがなければNFTはsellerに戻る
+    let receiver = if ctx.accounts.sealed_auction_account.has_winner() {
+        ctx.accounts.winner.to_account_info()
+    } else {
+        ctx.accounts.seller.to_account_info()
+    };
+
+    let sealed_auction_key = ctx.accounts.sealed_auction_account.key();
+    let vault_authority_bump = ctx.accounts.vault_authority.bump;
+
+    for index in 0..nft_amount_count {
+        let token_account = &ctx.remaining_accounts[index * 4];
+        let vault_account = &ctx.remaining_accounts[index * 4 + 1];
+        let receiver_token_account = &ctx.remaining_accounts[index * 4 + 2];
+        let mint_account = &ctx.remaining_accounts[index * 4 + 3];
+
+        // auction accountの中身と検証
+        assert_keys_equal(
+            &token_account.key(),
+            &ctx.accounts.sealed_auction_account.nft_token_accounts[index],
+            MyError::AssociatedTokenPublicKeyMismatch,
+        )?;
+
+        assert_is_ata(token_account, ctx.accounts.seller.key, mint_account, 0)?;
+
+        let quantity = ctx.accounts.sealed_auction_account.nft_quantities[index];
+        assert_is_pda(
+            token_account,
+            &ctx.accounts.sealed_auction_account.key(),
+            ctx.accounts.sealed_auction_account.vault_account_bumps[index],
+            vault_account,
+            &ctx.accounts.vault_authority.key(),
+            ctx.program_id,
+            quantity,
+        )?;
+
+        assert_is_ata(receiver_token_account, receiver.key, mint_account, 0)?;
+
+        transfer_checked(
+            ctx.accounts
+                .into_transfer_to_receiver_context(vault_account, receiver_token_account, mint_account)
+                .with_signer(&[&[
+                    VAULT_AUTHORITY_PDA_SEED,
+                    sealed_auction_key.as_ref(),
+                    &[vault_authority_bump],
+                ]])
+                .with_remaining_accounts(transfer_hook_accounts.to_vec()),
+            quantity,
+            0,
+        )?;
+
+        // vaultのrentは預けた側に返す
+        close_account(
+            ctx.accounts
+                .into_close_to_context(vault_account, &ctx.accounts.seller)
+                .with_signer(&[&[
+                    VAULT_AUTHORITY_PDA_SEED,
+                    sealed_auction_key.as_ref(),
+                    &[vault_authority_bump],
+                ]]),
+        )?;
+    }
+
+    // 最難関： solanaのbugで金額を動かすのはinto_close_contextの後にする必要がある
+    // 落札したbidの額とrevealしなかったbidの没収分をsellerに払い、残りはrentと一緒にbidderに返してbidを閉じる
+    let mut bidder_keys: Vec<Pubkey> = Vec::with_capacity(bid_count);
+    for index in 0..bid_count {
+        let sealed_bid_account_info = &ctx.remaining_accounts[bid_offset + index * 2];
+        let bidder = &ctx.remaining_accounts[bid_offset + index * 2 + 1];
+
+        // ownerとdiscriminatorはtry_fromで検証される auctionとbidderごとに1つなので全員分が渡されていること
+        let sealed_bid_account = Account::<SealedBidAccount>::try_from(sealed_bid_account_info)?;
+        assert_keys_equal(
+            &sealed_bid_account.auction_key,
+            &sealed_auction_key,
+            MyError::SealedBidAccountsMismatch,
+        )?;
+        assert_keys_equal(
+            bidder.key,
+            &sealed_bid_account.bidder_key,
+            MyError::BidderPublicKeyMismatch,
+        )?;
+        require!(
+            !bidder_keys.contains(bidder.key),
+            MyError::SealedBidAccountsMismatch
+        );
+        bidder_keys.push(bidder.key());
+
+        let seller_amount = if !sealed_bid_account.revealed {
+            (sealed_bid_account.deposit_amount as u128
+                * ctx.accounts.sealed_auction_account.unrevealed_forfeit_basis_points as u128
+                / MAX_FEE_BASIS_POINTS as u128) as u64
+        } else if *bidder.key == ctx.accounts.sealed_auction_account.highest_bidder_key {
+            sealed_bid_account.amount
+        } else {
+            0
+        };
+        let lamports = sealed_bid_account_info.lamports();
+
+        **sealed_bid_account_info.try_borrow_mut_lamports()? = 0;
+        **ctx.accounts.seller.try_borrow_mut_lamports()? += seller_amount;
+        **bidder.try_borrow_mut_lamports()? += lamports - seller_amount;
+        sealed_bid_account_info.try_borrow_mut_data()?.fill(0);
+    }
+
+    msg!("end settle_sealed_auction");
+    Ok(())
+}
+
+impl<'info> TokenPrograms<'info> for SettleSealedAuction<'info> {
+    fn token_program(&self) -> &AccountInfo<'info> {
+        &self.token_program
+    }
+
+    fn token_2022_program(&self) -> &AccountInfo<'info> {
+        &self.token_2022_program
+    }
+}
+
+impl<'info> Release<'info> for SettleSealedAuction<'info> {
+    fn vault_authority(&self) -> &Box<Account<'info, VaultAuthority>> {
+        &self.vault_authority
+    }
+}
@@ -0,0 +1,214 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token::Token;
+
+use crate::{
+    errors::MyError,
+    state::{PoolAccount, VaultAuthority, POOL_PDA_SEED, VAULT_AUTHORITY_PDA_SEED},
+    traits::{close_account, transfer_checked, Release, Token2022, TokenPrograms, TransferChecked},
+    utils::{assert_is_ata, assert_is_collection_nft, assert_is_pda},
+};
+
+// poolのNFTを1つ選び、受け取る側のcollectionのNFTをaccepted_nft_ratioの数だけ渡す poolが空になるまで誰でも何度でもswapできる
+#[derive(Accounts)]
+pub struct SwapPool<'info> {
+    #[account(
+        mut,
+        constraint = swapper.to_account_info().try_lamports().unwrap() >= pool_account.swap_fee @ MyError::TakerInsufficientFunds
+    )]
+    pub swapper: Signer<'info>,
+    #[account(mut)]
+    pub owner: SystemAccount<'info>,
+    #[account(
+        mut,
+        seeds = [
+            POOL_PDA_SEED,
+            owner.key().as_ref(),
+            pool_account.nonce.to_le_bytes().as_ref()
+        ],
+        bump = pool_account.bump,
+        constraint = pool_account.owner_key == *owner.key @ MyError::InitializerPublicKeyMismatch,
+        constraint = !pool_account.nft_token_accounts.is_empty() @ MyError::PoolEmpty,
+    )]
+    pub pool_account: Box<Account<'info, PoolAccount>>,
+    #[account(
+        seeds = [
+            VAULT_AUTHORITY_PDA_SEED,
+            pool_account.key().as_ref()
+        ],
+        bump = vault_authority.bump,
+    )]
+    pub vault_authority: Box<Account<'info, VaultAuthority>>,
+    pub token_program: Program<'info, Token>,
+    pub token_2022_program: Program<'info, Token2022>,
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler<'info>(ctx: Context<'_, '_, '_, 'info, SwapPool<'info>>) -> Result<()> {
+    msg!("start swap_pool");
+
+    // remaining accountsの数の検証
+    // poolのNFTの[預けたtoken account, vault account, mint, swapperのtoken account]の後に
+    // 渡すNFTごとに[swapperのtoken account, ownerのtoken account, mint, metadata]
+    let accepted_nft_amount_count = ctx.accounts.pool_account.accepted_nft_ratio as usize;
+    // transfer hookのあるmintのaccountは最後に渡す
+    let transfer_hook_offset = 4 + accepted_nft_amount_count * 4;
+    require_gte!(
+        ctx.remaining_accounts.len(),
+        transfer_hook_offset,
+        MyError::NftAmountMismatch
+    );
+    let transfer_hook_accounts = &ctx.remaining_accounts[transfer_hook_offset..];
+
+    let token_account = &ctx.remaining_accounts[0];
+    let vault_account = &ctx.remaining_accounts[1];
+    let mint_account = &ctx.remaining_accounts[2];
+    let swapper_token_account = &ctx.remaining_accounts[3];
+
+    // poolに残っているNFTであること
+    let position = ctx
+        .accounts
+        .pool_account
+        .nft_token_accounts
+        .iter()
+        .position(|key| key == token_account.key)
+        .ok_or(MyError::AssociatedTokenPublicKeyMismatch)?;
+
+    assert_is_ata(token_account, ctx.accounts.owner.key, mint_account, 0)?;
+    assert_is_pda(
+        token_account,
+        &ctx.accounts.pool_account.key(),
+        ctx.accounts.pool_account.vault_account_bumps[position],
+        vault_account,
+        &ctx.accounts.vault_authority.key(),
+        ctx.program_id,
+        1, // poolはNFTだけ
+    )?;
+    assert_is_ata(
+        swapper_token_account,
+        ctx.accounts.swapper.key,
+        mint_account,
+        0,
+    )?;
+
+    // ownerが受け取るNFTは同じcollectionのどれでもよい
+    for index in 0..accepted_nft_amount_count {
+        let accepted_offset = 4 + index * 4;
+        let swapper_nft_token_account = &ctx.remaining_accounts[accepted_offset];
+        let owner_nft_token_account = &ctx.remaining_accounts[accepted_offset + 1];
+        let accepted_mint_account = &ctx.remaining_accounts[accepted_offset + 2];
+        let metadata_account = &ctx.remaining_accounts[accepted_offset + 3];
+
+        assert_is_collection_nft(
+            accepted_mint_account,
+            metadata_account,
+            &ctx.accounts.pool_account.accepted_collection_key,
+        )?;
+        assert_is_ata(
+            swapper_nft_token_account,
+            ctx.accounts.swapper.key,
+            accepted_mint_account,
+            1,
+        )?;
+        assert_is_ata(
+            owner_nft_token_account,
+            ctx.accounts.owner.key,
+            accepted_mint_account,
+            0,
+        )?;
+
+        transfer_checked(
+            ctx.accounts
+                .into_transfer_to_owner_context(
+                    swapper_nft_token_account,
+                    owner_nft_token_account,
+                    accepted_mint_account,
+                )
+                .with_remaining_accounts(transfer_hook_accounts.to_vec()),
+            1,
+            0,
+        )?;
+    }
+
+    let pool_key = ctx.accounts.pool_account.key();
+    let vault_authority_bump = ctx.accounts.vault_authority.bump;
+
+    transfer_checked(
+        ctx.accounts
+            .into_transfer_to_receiver_context(vault_account, swapper_token_account, mint_account)
+            .with_signer(&[&[
+                VAULT_AUTHORITY_PDA_SEED,
+                pool_key.as_ref(),
+                &[vault_authority_bump],
+            ]])
+            .with_remaining_accounts(transfer_hook_accounts.to_vec()),
+        1,
+        0,
+    )?;
+
+    // vaultのrentは預けた側に返す
+    close_account(
+        ctx.accounts
+            .into_close_to_context(vault_account, &ctx.accounts.owner)
+            .with_signer(&[&[
+                VAULT_AUTHORITY_PDA_SEED,
+                pool_key.as_ref(),
+                &[vault_authority_bump],
+            ]]),
+    )?;
+
+    let pool_account = &mut ctx.accounts.pool_account;
+    pool_account.nft_token_accounts.remove(position);
+    pool_account.vault_account_bumps.remove(position);
+
+    let swap_fee = ctx.accounts.pool_account.swap_fee;
+    if swap_fee > 0 {
+        let ix = anchor_lang::solana_program::system_instruction::transfer(
+            &ctx.accounts.swapper.key(),
+            &ctx.accounts.owner.key(),
+            swap_fee,
+        );
+        anchor_lang::solana_program::program::invoke(
+            &ix,
+            &[
+                ctx.accounts.swapper.to_account_info().clone(),
+                ctx.accounts.owner.to_account_info().clone(),
+            ],
+        )?;
+    }
+
+    msg!("end swap_pool");
+    Ok(())
+}
+
+impl<'info> SwapPool<'info> {
+    fn into_transfer_to_owner_context(
+        &self,
+        swapper_nft_token_account: &AccountInfo<'info>,
+        owner_nft_token_account: &AccountInfo<'info>,
+        mint_account: &AccountInfo<'info>,
+    ) -> CpiContext<'_, '_, '_, 'info, TransferChecked<'info>> {
+        let cpi_accounts = TransferChecked {
+            from: swapper_nft_token_account.clone(),
+            mint: mint_account.clone(),
+            to: owner_nft_token_account.clone(),
+            authority: self.swapper.to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program_for(mint_account), cpi_accounts)
+    }
+}
+
+impl<'info> TokenPrograms<'info> for SwapPool<'info> {
+    fn token_program(&self) -> &AccountInfo<'info> {
+        &self.token_program
+    }
+
+    fn token_2022_program(&self) -> &AccountInfo<'info> {
+        &self.token_2022_program
+    }
+}
+
+impl<'info> Release<'info> for SwapPool<'info> {
+    fn vault_authority(&self) -> &Box<Account<'info, VaultAuthority>> {
+        &self.vault_authority
+    }
+}
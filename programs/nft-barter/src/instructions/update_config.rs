@@ -0,0 +1,42 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    errors::MyError,
+    state::{Config, CONFIG_PDA_SEED, MAX_FEE_BASIS_POINTS},
+};
+
+// adminの変更もここで行う
+#[derive(Accounts)]
+#[instruction(admin_key: Pubkey, fee_basis_points: u16)]
+pub struct UpdateConfig<'info> {
+    #[account(
+        constraint = fee_basis_points <= MAX_FEE_BASIS_POINTS @ MyError::InvalidFeeBasisPoints
+    )]
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [CONFIG_PDA_SEED],
+        bump = config.bump,
+        constraint = config.admin_key == *admin.key @ MyError::AdminPublicKeyMismatch,
+    )]
+    pub config: Box<Account<'info, Config>>,
+}
+
+pub fn handler(
+    ctx: Context<UpdateConfig>,
+    admin_key: Pubkey,
+    fee_basis_points: u16,
+    flat_fee: u64,
+    treasury_key: Pubkey,
+) -> Result<()> {
+    msg!("start update_config");
+
+    let config = &mut ctx.accounts.config;
+    config.admin_key = admin_key;
+    config.fee_basis_points = fee_basis_points;
+    config.flat_fee = flat_fee;
+    config.treasury_key = treasury_key;
+
+    msg!("end update_config");
+    Ok(())
+}
@@ -0,0 +1,31 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    errors::MyError,
+    state::{StandingOfferAccount, STANDING_OFFER_PDA_SEED},
+};
+
+// buyerがいつでもstanding offerを閉じて、残っている予算とrentを引き出す
+#[derive(Accounts)]
+pub struct WithdrawStandingOffer<'info> {
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [
+            STANDING_OFFER_PDA_SEED,
+            buyer.key().as_ref(),
+            standing_offer_account.nonce.to_le_bytes().as_ref()
+        ],
+        bump = standing_offer_account.bump,
+        constraint = standing_offer_account.buyer_key == *buyer.key @ MyError::InitializerPublicKeyMismatch,
+        close = buyer
+    )]
+    pub standing_offer_account: Box<Account<'info, StandingOfferAccount>>,
+}
+
+pub fn handler(_ctx: Context<WithdrawStandingOffer>) -> Result<()> {
+    msg!("start withdraw_standing_offer");
+    msg!("end withdraw_standing_offer");
+    Ok(())
+}
@@ -1,12 +1,14 @@
 use anchor_lang::prelude::*;
 
 pub mod errors;
+pub mod events;
 pub mod instructions;
 pub mod state;
 pub mod traits;
 pub mod utils;
 
 use instructions::*;
+use state::CnftLeaf;
 
 declare_id!("FRd6p3td6akTgfhHgJZHyhVeyYUhGWiM9dApVucDGer2");
 
@@ -17,43 +19,263 @@ pub mod nft_barter {
 
     pub fn initialize<'info>(
         ctx: Context<'_, '_, '_, 'info, Initialize<'info>>,
-        initializer_additional_sol_amount: u64, // こいつはstateで使っているから変数の先にもってきている
-        taker_additional_sol_amount: u64, // こいつはstateで使っているから変数の先にもってきている
-        initializer_nft_amount: u8,
-        taker_nft_amount: u8,
-        vault_account_bumps: Vec<u8>,
+        args: InitializeArgs,
     ) -> Result<()> {
-        instructions::initialize::handler(
-            ctx,
-            initializer_additional_sol_amount,
-            taker_additional_sol_amount,
-            initializer_nft_amount,
-            taker_nft_amount,
-            vault_account_bumps,
-        )
+        instructions::initialize::handler(ctx, args)
     }
 
     pub fn exchange<'info>(
         ctx: Context<'_, '_, '_, 'info, Exchange<'info>>,
         initializer_additional_sol_amount: u64,
-        _taker_additional_sol_amount: u64,
+        max_taker_additional_sol_amount: u64, // dutch auctionの場合はtakerが払ってもよい上限
+        cnft_leaves: Vec<CnftLeaf>,
     ) -> Result<()> {
         instructions::exchange::handler(
             ctx,
             initializer_additional_sol_amount,
-            _taker_additional_sol_amount,
+            max_taker_additional_sol_amount,
+            cnft_leaves,
         )
     }
 
+    pub fn amend<'info>(
+        ctx: Context<'_, '_, '_, 'info, Amend<'info>>,
+        args: AmendArgs,
+    ) -> Result<()> {
+        instructions::amend::handler(ctx, args)
+    }
+
+    pub fn accept<'info>(
+        ctx: Context<'_, '_, '_, 'info, Accept<'info>>,
+        taker_additional_sol_amount: u64,
+        vault_account_bumps: Vec<u8>,
+    ) -> Result<()> {
+        instructions::accept::handler(ctx, taker_additional_sol_amount, vault_account_bumps)
+    }
+
+    pub fn settle<'info>(ctx: Context<'_, '_, '_, 'info, Settle<'info>>) -> Result<()> {
+        instructions::settle::handler(ctx)
+    }
+
     pub fn cancel_by_initializer<'info>(
         ctx: Context<'_, '_, '_, 'info, CancelByInitializer<'info>>,
+        cnft_leaves: Vec<CnftLeaf>,
     ) -> Result<()> {
-        instructions::cancel_by_initializer::handler(ctx)
+        instructions::cancel_by_initializer::handler(ctx, cnft_leaves)
     }
 
     pub fn cancel_by_taker<'info>(
         ctx: Context<'_, '_, '_, 'info, CancelByTaker<'info>>,
+        cnft_leaves: Vec<CnftLeaf>,
+    ) -> Result<()> {
+        instructions::cancel_by_taker::handler(ctx, cnft_leaves)
+    }
+
+    pub fn counter_offer<'info>(
+        ctx: Context<'_, '_, '_, 'info, CounterOffer<'info>>,
+        args: CounterOfferArgs,
+    ) -> Result<()> {
+        instructions::counter_offer::handler(ctx, args)
+    }
+
+    pub fn expire<'info>(
+        ctx: Context<'_, '_, '_, 'info, Expire<'info>>,
+        cnft_leaves: Vec<CnftLeaf>,
+    ) -> Result<()> {
+        instructions::expire::handler(ctx, cnft_leaves)
+    }
+
+    pub fn initialize_ring<'info>(
+        ctx: Context<'_, '_, '_, 'info, InitializeRing<'info>>,
+        participant_keys: Vec<Pubkey>,
+        additional_sol_amounts: Vec<u64>,
+        nft_amounts: Vec<u8>,
+        nonce: u64,
+    ) -> Result<()> {
+        instructions::initialize_ring::handler(
+            ctx,
+            participant_keys,
+            additional_sol_amounts,
+            nft_amounts,
+            nonce,
+        )
+    }
+
+    pub fn deposit_ring<'info>(
+        ctx: Context<'_, '_, '_, 'info, DepositRing<'info>>,
+        vault_account_bumps: Vec<u8>,
+    ) -> Result<()> {
+        instructions::deposit_ring::handler(ctx, vault_account_bumps)
+    }
+
+    pub fn settle_ring<'info>(ctx: Context<'_, '_, '_, 'info, SettleRing<'info>>) -> Result<()> {
+        instructions::settle_ring::handler(ctx)
+    }
+
+    pub fn cancel_ring<'info>(ctx: Context<'_, '_, '_, 'info, CancelRing<'info>>) -> Result<()> {
+        instructions::cancel_ring::handler(ctx)
+    }
+
+    pub fn create_auction<'info>(
+        ctx: Context<'_, '_, '_, 'info, CreateAuction<'info>>,
+        vault_account_bumps: Vec<u8>,
+        nft_quantities: Vec<u64>,
+        reserve_price: u64,
+        min_bid_increment: u64,
+        end_at: i64,
+        nonce: u64,
+    ) -> Result<()> {
+        instructions::create_auction::handler(
+            ctx,
+            vault_account_bumps,
+            nft_quantities,
+            reserve_price,
+            min_bid_increment,
+            end_at,
+            nonce,
+        )
+    }
+
+    pub fn place_bid(ctx: Context<PlaceBid>, amount: u64) -> Result<()> {
+        instructions::place_bid::handler(ctx, amount)
+    }
+
+    pub fn settle_auction<'info>(
+        ctx: Context<'_, '_, '_, 'info, SettleAuction<'info>>,
+    ) -> Result<()> {
+        instructions::settle_auction::handler(ctx)
+    }
+
+    pub fn create_sealed_auction<'info>(
+        ctx: Context<'_, '_, '_, 'info, CreateSealedAuction<'info>>,
+        args: CreateSealedAuctionArgs,
     ) -> Result<()> {
-        instructions::cancel_by_taker::handler(ctx)
+        instructions::create_sealed_auction::handler(ctx, args)
+    }
+
+    pub fn commit_sealed_bid(
+        ctx: Context<CommitSealedBid>,
+        commitment: [u8; 32],
+        deposit_amount: u64,
+    ) -> Result<()> {
+        instructions::commit_sealed_bid::handler(ctx, commitment, deposit_amount)
+    }
+
+    pub fn reveal_sealed_bid(
+        ctx: Context<RevealSealedBid>,
+        amount: u64,
+        salt: [u8; 32],
+    ) -> Result<()> {
+        instructions::reveal_sealed_bid::handler(ctx, amount, salt)
+    }
+
+    pub fn settle_sealed_auction<'info>(
+        ctx: Context<'_, '_, '_, 'info, SettleSealedAuction<'info>>,
+    ) -> Result<()> {
+        instructions::settle_sealed_auction::handler(ctx)
+    }
+
+    pub fn create_pool<'info>(
+        ctx: Context<'_, '_, '_, 'info, CreatePool<'info>>,
+        vault_account_bumps: Vec<u8>,
+        offered_collection_key: Pubkey,
+        accepted_collection_key: Pubkey,
+        accepted_nft_ratio: u8,
+        swap_fee: u64,
+        nonce: u64,
+    ) -> Result<()> {
+        instructions::create_pool::handler(
+            ctx,
+            vault_account_bumps,
+            offered_collection_key,
+            accepted_collection_key,
+            accepted_nft_ratio,
+            swap_fee,
+            nonce,
+        )
+    }
+
+    pub fn swap_pool<'info>(ctx: Context<'_, '_, '_, 'info, SwapPool<'info>>) -> Result<()> {
+        instructions::swap_pool::handler(ctx)
+    }
+
+    pub fn close_pool<'info>(ctx: Context<'_, '_, '_, 'info, ClosePool<'info>>) -> Result<()> {
+        instructions::close_pool::handler(ctx)
+    }
+
+    pub fn match_offers<'info>(ctx: Context<'_, '_, '_, 'info, MatchOffers<'info>>) -> Result<()> {
+        instructions::match_offers::handler(ctx)
+    }
+
+    pub fn create_standing_offer(
+        ctx: Context<CreateStandingOffer>,
+        collection_key: Pubkey,
+        price_per_nft: u64,
+        count: u8,
+        nonce: u64,
+    ) -> Result<()> {
+        instructions::create_standing_offer::handler(
+            ctx,
+            collection_key,
+            price_per_nft,
+            count,
+            nonce,
+        )
+    }
+
+    pub fn fill_standing_offer<'info>(
+        ctx: Context<'_, '_, '_, 'info, FillStandingOffer<'info>>,
+    ) -> Result<()> {
+        instructions::fill_standing_offer::handler(ctx)
+    }
+
+    pub fn withdraw_standing_offer(ctx: Context<WithdrawStandingOffer>) -> Result<()> {
+        instructions::withdraw_standing_offer::handler(ctx)
+    }
+
+    pub fn create_rental<'info>(
+        ctx: Context<'_, '_, '_, 'info, CreateRental<'info>>,
+        vault_account_bump: u8,
+        daily_price: u64,
+        max_days: u16,
+        nonce: u64,
+    ) -> Result<()> {
+        instructions::create_rental::handler(ctx, vault_account_bump, daily_price, max_days, nonce)
+    }
+
+    pub fn rent_nft<'info>(
+        ctx: Context<'_, '_, '_, 'info, RentNft<'info>>,
+        days: u16,
+    ) -> Result<()> {
+        instructions::rent_nft::handler(ctx, days)
+    }
+
+    pub fn end_rental<'info>(ctx: Context<'_, '_, '_, 'info, EndRental<'info>>) -> Result<()> {
+        instructions::end_rental::handler(ctx)
+    }
+
+    pub fn initialize_config(
+        ctx: Context<InitializeConfig>,
+        fee_basis_points: u16,
+        flat_fee: u64,
+        treasury_key: Pubkey,
+    ) -> Result<()> {
+        instructions::initialize_config::handler(ctx, fee_basis_points, flat_fee, treasury_key)
+    }
+
+    pub fn update_config(
+        ctx: Context<UpdateConfig>,
+        admin_key: Pubkey,
+        fee_basis_points: u16,
+        flat_fee: u64,
+        treasury_key: Pubkey,
+    ) -> Result<()> {
+        instructions::update_config::handler(
+            ctx,
+            admin_key,
+            fee_basis_points,
+            flat_fee,
+            treasury_key,
+        )
     }
 }
@@ -1,16 +1,580 @@
 use anchor_lang::prelude::*;
 
 pub const VAULT_AUTHORITY_PDA_SEED: &[u8] = b"vault-authority";
+pub const ESCROW_PDA_SEED: &[u8] = b"escrow";
+pub const RING_PDA_SEED: &[u8] = b"ring";
+pub const VAULT_ACCOUNT_PDA_SEED: &[u8] = b"vault-account";
+pub const FUNGIBLE_VAULT_ACCOUNT_PDA_SEED: &[u8] = b"fungible-vault-account";
+pub const CONFIG_PDA_SEED: &[u8] = b"config";
+pub const AUCTION_PDA_SEED: &[u8] = b"auction";
+pub const BID_PDA_SEED: &[u8] = b"bid";
+pub const SEALED_AUCTION_PDA_SEED: &[u8] = b"sealed-auction";
+pub const SEALED_BID_PDA_SEED: &[u8] = b"sealed-bid";
+pub const POOL_PDA_SEED: &[u8] = b"pool";
+pub const STANDING_OFFER_PDA_SEED: &[u8] = b"standing-offer";
+pub const RENTAL_PDA_SEED: &[u8] = b"rental";
+pub const MAX_FEE_BASIS_POINTS: u16 = 10000;
+pub const MAX_REFERRER_BASIS_POINTS: u16 = 500; // 提携marketplaceに払えるSOLのlegの上限
+pub const PNFT_ACCOUNTS_PER_ITEM: usize = 8; // token account, destination token account, mint, metadata, edition, owner token record, destination token record, authorization rules
+pub const PNFT_PROGRAM_ACCOUNTS: usize = 6; // token metadata, system, sysvar instructions, token, associated token, authorization rules pNFTがある場合だけ渡す
+pub const CNFT_PROGRAM_ACCOUNTS: usize = 4; // bubblegum, account compression, noop, system cNFTがある場合だけ渡す
+pub const CNFT_ACCOUNTS_PER_ITEM: usize = 2; // merkle tree, tree authority この後にproofを渡す
+pub const CORE_PROGRAM_ACCOUNTS: usize = 2; // mpl core, system Core assetがある場合だけ渡す
+pub const CORE_ACCOUNTS_PER_ITEM: usize = 2; // asset, collection collectionがない場合はmpl coreのprogram idを渡す
+pub const MAX_RING_PARTICIPANTS: usize = 8; // settle_ringのremaining accountsがtransactionに収まる程度
+pub const MAX_SEALED_BIDS: u8 = 8; // settle_sealed_auctionで全員に返金するのでremaining accountsがtransactionに収まる程度
+pub const MAX_KEEPER_TIP_BASIS_POINTS: u16 = 100; // escrowごとのkeeperへのtipの割合に指定できるのはSOLの1%まで
+pub const SECONDS_PER_DAY: i64 = 86400;
 
 #[account]
 pub struct EscrowAccount {
     pub initializer_key: Pubkey,
     pub initializer_additional_sol_amount: u64,
     pub initializer_nft_token_accounts: Vec<Pubkey>,
-    pub taker_key: Pubkey,
+    pub taker_key: Pubkey, // open offerの場合はPubkey::default()
     pub taker_additional_sol_amount: u64,
-    pub taker_nft_token_accounts: Vec<Pubkey>,
+    pub taker_nft_token_accounts: Vec<Pubkey>, // open offerの場合はtakerが未定なので空
+    pub taker_nft_mints: Vec<Pubkey>,
+    pub taker_collection_key: Pubkey, // collectionを指定しない場合はPubkey::default()
+    pub taker_collection_nft_amount: u8,
     pub vault_account_bumps: Vec<u8>,
+    pub nonce: u64, // 同じinitializerとtakerで複数のescrowを持てるようにする
+    pub bump: u8,
+    pub expires_at: i64, // unix timestamp 0の場合は期限なし
+    pub previous_escrow_key: Pubkey, // counter offerの場合は置き換えたescrow それ以外はPubkey::default()
+    pub accepted_taker_key: Pubkey, // acceptでtakerが資産を預けた場合のtaker それ以外はPubkey::default()
+    pub taker_vault_account_bumps: Vec<u8>,
+    pub initializer_fungible_legs: Vec<FungibleLeg>,
+    pub initializer_fungible_vault_account_bumps: Vec<u8>,
+    pub taker_fungible_legs: Vec<FungibleLeg>,
+    pub initializer_nft_quantities: Vec<u64>, // initializer_nft_token_accountsと同じ順 NFTなら1 SFTなら預けた量
+    pub taker_nft_quantities: Vec<u64>, // taker_nft_mintsと同じ順
+    pub initializer_pnft_mints: Vec<Pubkey>, // pNFTはvault authorityのATAに預けるのでmintだけでvaultが決まる
+    pub taker_pnft_mints: Vec<Pubkey>,
+    pub initializer_cnft_assets: Vec<Pubkey>, // cNFTはtoken accountがないのでasset idで管理する vault authorityがleaf owner
+    pub taker_cnft_assets: Vec<Pubkey>,
+    pub initializer_core_assets: Vec<Pubkey>, // Core assetはmintもtoken accountもないのでasset accountで管理する vault authorityがowner
+    pub taker_core_assets: Vec<Pubkey>,
+    pub referrer_key: Pubkey, // offerを持ち込んだmarketplace いない場合はPubkey::default()
+    pub referrer_basis_points: u16, // exchangeでSOLのlegからreferrerに払う割合
+    pub taker_dutch_pricing: DutchPricing, // end_atが0の場合はtaker_additional_sol_amountの固定価格
+    pub keeper_tip_basis_points: u16, // match_offersでinitializerのSOLからkeeperに払ってよい割合 0の場合は払わない
+}
+
+// USDCなどのSPL tokenを(mint, amount)で指定する
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct FungibleLeg {
+    pub mint: Pubkey,
+    pub amount: u64, // decimalsを考慮した最小単位
+    pub decimals: u8,
+}
+
+// cNFTのleafを指定する rootとproofはtransferのたびに変わるので、cNFTを動かすinstructionで毎回受け取る
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct CnftLeaf {
+    pub root: [u8; 32],
+    pub data_hash: [u8; 32],
+    pub creator_hash: [u8; 32],
+    pub nonce: u64, // merkle treeとnonceでasset idが決まる
+    pub index: u32,
+    pub proof_len: u8, // remaining_accountsに渡すproofの数 canopyの分は不要
+}
+
+// takerのSOLをtaker_additional_sol_amountからend_sol_amountまでstart_atからend_atにかけて直線的に下げる
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct DutchPricing {
+    pub end_sol_amount: u64,
+    pub start_at: i64, // unix timestamp
+    pub end_at: i64, // unix timestamp この後はend_sol_amountのまま
+}
+
+// escrowの片側が出す資産の種類ごとの数 spaceの計算に使う
+#[derive(Clone, Copy, Default)]
+pub struct EscrowLegCounts {
+    pub nft: usize,
+    pub fungible: usize,
+    pub pnft: usize,
+    pub cnft: usize,
+    pub core: usize,
+}
+
+impl DutchPricing {
+    pub const SPACE: usize = 8 // endSolAmount
+        + 8 // startAt
+        + 8; // endAt
+}
+
+impl FungibleLeg {
+    pub const SPACE: usize = 32 // mint
+        + 8 // amount
+        + 1; // decimals
+}
+
+impl EscrowAccount {
+    pub fn space(initializer: &EscrowLegCounts, taker: &EscrowLegCounts) -> usize {
+        8 // internal anchor discriminator
+        + 32 // initializerKey
+        + 8 // initializerAdditionalSolAmount
+        + 4 + 32 * initializer.nft // initializerNftTokenAccounts
+        + 32 // takerKey
+        + 8 // takerAdditionalSolAmount
+        + 4 + 32 * taker.nft // takerNftTokenAccounts
+        + 4 + 32 * taker.nft // takerNftMints
+        + 32 // takerCollectionKey
+        + 1 // takerCollectionNftAmount
+        + 4 + initializer.nft // vault_account_bumps vec
+        + 8 // nonce
+        + 1 // bump
+        + 8 // expiresAt
+        + 32 // previousEscrowKey
+        + 32 // acceptedTakerKey
+        + 4 + taker.nft // takerVaultAccountBumps
+        + 4 + FungibleLeg::SPACE * initializer.fungible // initializerFungibleLegs
+        + 4 + initializer.fungible // initializerFungibleVaultAccountBumps
+        + 4 + FungibleLeg::SPACE * taker.fungible // takerFungibleLegs
+        + 4 + 8 * initializer.nft // initializerNftQuantities
+        + 4 + 8 * taker.nft // takerNftQuantities
+        + 4 + 32 * initializer.pnft // initializerPnftMints
+        + 4 + 32 * taker.pnft // takerPnftMints
+        + 4 + 32 * initializer.cnft // initializerCnftAssets
+        + 4 + 32 * taker.cnft // takerCnftAssets
+        + 4 + 32 * initializer.core // initializerCoreAssets
+        + 4 + 32 * taker.core // takerCoreAssets
+        + 32 // referrerKey
+        + 2 // referrerBasisPoints
+        + DutchPricing::SPACE // takerDutchPricing
+        + 2 // keeperTipBasisPoints
+    }
+
+    // 今の中身のspace amendで資産の数が変わったときにrentを精算する
+    pub fn current_space(&self) -> usize {
+        EscrowAccount::space(
+            &EscrowLegCounts {
+                nft: self.initializer_nft_token_accounts.len(),
+                fungible: self.initializer_fungible_legs.len(),
+                pnft: self.initializer_pnft_mints.len(),
+                cnft: self.initializer_cnft_assets.len(),
+                core: self.initializer_core_assets.len(),
+            },
+            &EscrowLegCounts {
+                nft: self.taker_nft_mints.len(),
+                fungible: self.taker_fungible_legs.len(),
+                pnft: self.taker_pnft_mints.len(),
+                cnft: self.taker_cnft_assets.len(),
+                core: self.taker_core_assets.len(),
+            },
+        )
+    }
+
+    // takerを指定しないofferは最初にexchangeしたwalletがtakerになる
+    pub fn is_open_offer(&self) -> bool {
+        self.taker_key == Pubkey::default()
+    }
+
+    // acceptされたescrowは両者の資産がvaultにあるので、settle以外では動かせない
+    pub fn is_accepted(&self) -> bool {
+        self.accepted_taker_key != Pubkey::default()
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at != 0 && now >= self.expires_at
+    }
+
+    // cancelでremaining_accountsに渡すNFTとfungible tokenの[token account, vault account, mint]とpNFTとcNFTとCore assetのaccountの数
+    pub fn cancel_accounts_count(&self, cnft_leaves: &[CnftLeaf]) -> usize {
+        (self.initializer_nft_token_accounts.len() + self.initializer_fungible_legs.len()) * 3
+            + pnft_accounts_count(self.initializer_pnft_mints.len())
+            + cnft_accounts_count(cnft_leaves)
+            + core_accounts_count(self.initializer_core_assets.len())
+    }
+
+    pub fn referrer_fee(&self, sol_amount: u64) -> u64 {
+        (sol_amount as u128 * self.referrer_basis_points as u128 / MAX_FEE_BASIS_POINTS as u128) as u64
+    }
+
+    pub fn has_dutch_pricing(&self) -> bool {
+        self.taker_dutch_pricing.end_at != 0
+    }
+
+    // 価格が下がり続けても開始価格を上回ることはない
+    pub fn is_valid_dutch_pricing(&self) -> bool {
+        let pricing = &self.taker_dutch_pricing;
+        !self.has_dutch_pricing()
+            || (pricing.start_at < pricing.end_at
+                && pricing.end_sol_amount < self.taker_additional_sol_amount)
+    }
+
+    // exchangeの時点でtakerが払うSOL
+    pub fn current_taker_sol_amount(&self, now: i64) -> u64 {
+        let pricing = &self.taker_dutch_pricing;
+        if !self.has_dutch_pricing() || now <= pricing.start_at {
+            return self.taker_additional_sol_amount;
+        }
+        if now >= pricing.end_at {
+            return pricing.end_sol_amount;
+        }
+        let decay = (self.taker_additional_sol_amount - pricing.end_sol_amount) as u128
+            * (now - pricing.start_at) as u128
+            / (pricing.end_at - pricing.start_at) as u128;
+        self.taker_additional_sol_amount - decay as u64
+    }
+
+    pub fn has_pnft(&self) -> bool {
+        !self.initializer_pnft_mints.is_empty() || !self.taker_pnft_mints.is_empty()
+    }
+
+    pub fn has_cnft(&self) -> bool {
+        !self.initializer_cnft_assets.is_empty() || !self.taker_cnft_assets.is_empty()
+    }
+
+    pub fn has_core_asset(&self) -> bool {
+        !self.initializer_core_assets.is_empty() || !self.taker_core_assets.is_empty()
+    }
+
+    // match_offersは両方のescrowのinitializerの資産だけで決済するので、vaultに預けたSPL tokenとSOLだけのofferに限る
+    pub fn is_matchable(&self) -> bool {
+        !self.is_accepted()
+            && self.taker_collection_nft_amount == 0
+            && self.initializer_fungible_legs.is_empty()
+            && self.taker_fungible_legs.is_empty()
+            && !self.has_pnft()
+            && !self.has_cnft()
+            && !self.has_core_asset()
+            && !self.has_dutch_pricing()
+    }
+
+    // selfのinitializerが預けた資産でotherのtakerの条件を満たすか mintはremaining accountsで検証する
+    pub fn can_fill(&self, other: &EscrowAccount) -> bool {
+        (other.is_open_offer() || other.taker_key == self.initializer_key)
+            && self.initializer_nft_quantities == other.taker_nft_quantities
+            && self.initializer_additional_sol_amount >= other.taker_additional_sol_amount
+    }
+
+    // otherのtakerに払った残りのSOLからinitializerが決めた上限までをkeeperに払う 残りはinitializerに返す
+    pub fn keeper_tip(&self, other: &EscrowAccount) -> u64 {
+        let surplus = self.initializer_additional_sol_amount - other.taker_additional_sol_amount;
+        let max_tip = (self.initializer_additional_sol_amount as u128
+            * self.keeper_tip_basis_points as u128
+            / MAX_FEE_BASIS_POINTS as u128) as u64;
+        surplus.min(max_tip)
+    }
+}
+
+// pNFTのprogramのaccountとitemごとのaccountの数 pNFTがなければprogramのaccountも不要
+pub fn pnft_accounts_count(pnft_amount: usize) -> usize {
+    if pnft_amount == 0 {
+        return 0;
+    }
+    PNFT_PROGRAM_ACCOUNTS + pnft_amount * PNFT_ACCOUNTS_PER_ITEM
+}
+
+// cNFTのprogramのaccountとleafごとの[merkle tree, tree authority, proof...]の数
+pub fn cnft_accounts_count(cnft_leaves: &[CnftLeaf]) -> usize {
+    if cnft_leaves.is_empty() {
+        return 0;
+    }
+    CNFT_PROGRAM_ACCOUNTS
+        + cnft_leaves
+            .iter()
+            .map(|leaf| CNFT_ACCOUNTS_PER_ITEM + leaf.proof_len as usize)
+            .sum::<usize>()
+}
+
+// Core assetのprogramのaccountとassetごとの[asset, collection]の数
+pub fn core_accounts_count(core_amount: usize) -> usize {
+    if core_amount == 0 {
+        return 0;
+    }
+    CORE_PROGRAM_ACCOUNTS + core_amount * CORE_ACCOUNTS_PER_ITEM
+}
+
+// 3者以上の交換 legs[i]の資産はlegs[(i + 1) % legs.len()]のparticipantに渡る
+#[account]
+pub struct RingAccount {
+    pub creator_key: Pubkey,
+    pub legs: Vec<RingLeg>,
+    pub nonce: u64, // 同じcreatorで複数のringを持てるようにする
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct RingLeg {
+    pub participant_key: Pubkey,
+    pub additional_sol_amount: u64,
+    pub nft_mints: Vec<Pubkey>,
+    pub nft_token_accounts: Vec<Pubkey>, // depositするまでは空
+    pub vault_account_bumps: Vec<u8>, // depositするまでは空
+    pub deposited: bool,
+}
+
+impl RingAccount {
+    pub fn space(nft_amounts: &[u8]) -> usize {
+        8 // internal anchor discriminator
+        + 32 // creatorKey
+        + 4 + nft_amounts.iter().map(|nft_amount| RingLeg::space(*nft_amount as usize)).sum::<usize>() // legs
+        + 8 // nonce
+        + 1 // bump
+    }
+
+    pub fn receiver_index(&self, leg_index: usize) -> usize {
+        (leg_index + 1) % self.legs.len()
+    }
+
+    pub fn is_fully_deposited(&self) -> bool {
+        self.legs.iter().all(|leg| leg.deposited)
+    }
+}
+
+impl RingLeg {
+    pub fn space(nft_amount: usize) -> usize {
+        32 // participantKey
+        + 8 // additionalSolAmount
+        + 4 + 32 * nft_amount // nftMints
+        + 4 + 32 * nft_amount // nftTokenAccounts
+        + 4 + nft_amount // vaultAccountBumps
+        + 1 // deposited
+    }
+}
+
+// vaultに預けたNFTをまとめてSOLで競りにかける 最高額のbidはbid accountが預かる
+#[account]
+pub struct AuctionAccount {
+    pub seller_key: Pubkey,
+    pub nft_token_accounts: Vec<Pubkey>,
+    pub vault_account_bumps: Vec<u8>,
+    pub nft_quantities: Vec<u64>, // nft_token_accountsと同じ順 NFTなら1 SFTなら預けた量
+    pub reserve_price: u64, // これ未満のbidは受け付けない lamport
+    pub min_bid_increment: u64, // 最高額のbidをこれ以上上回る必要がある lamport
+    pub end_at: i64, // unix timestamp この後は誰でもsettleできる
+    pub nonce: u64, // 同じsellerで複数のauctionを持てるようにする
+    pub bump: u8,
+}
+
+impl AuctionAccount {
+    pub fn space(nft_amount: usize) -> usize {
+        8 // internal anchor discriminator
+        + 32 // sellerKey
+        + 4 + 32 * nft_amount // nftTokenAccounts
+        + 4 + nft_amount // vaultAccountBumps
+        + 4 + 8 * nft_amount // nftQuantities
+        + 8 // reservePrice
+        + 8 // minBidIncrement
+        + 8 // endAt
+        + 8 // nonce
+        + 1 // bump
+    }
+
+    pub fn is_ended(&self, now: i64) -> bool {
+        now >= self.end_at
+    }
+}
+
+// auctionごとに1つ 最高額のbidのSOLをrentに加えて持つ 上回るbidが来たら前のbidderに返す
+#[account]
+pub struct BidAccount {
+    pub bidder_key: Pubkey, // bidがない場合はPubkey::default()
+    pub amount: u64,
+    pub bump: u8,
+}
+
+impl BidAccount {
+    pub const SPACE: usize = 8 // internal anchor discriminator
+        + 32 // bidderKey
+        + 8 // amount
+        + 1; // bump
+
+    pub fn has_bid(&self) -> bool {
+        self.bidder_key != Pubkey::default()
+    }
+}
+
+// bidの額を隠したまま預けるauction commitの期間にhashとdepositを受け付け、revealの期間に額を公開する
+#[account]
+pub struct SealedAuctionAccount {
+    pub seller_key: Pubkey,
+    pub nft_token_accounts: Vec<Pubkey>,
+    pub vault_account_bumps: Vec<u8>,
+    pub nft_quantities: Vec<u64>, // nft_token_accountsと同じ順 NFTなら1 SFTなら預けた量
+    pub reserve_price: u64, // これ未満のrevealは落札できない lamport
+    pub min_deposit: u64, // commitに必要なdeposit 安いcommitでbidの枠を埋められないようにsellerが決める lamport
+    pub commit_end_at: i64, // unix timestamp
+    pub reveal_end_at: i64, // unix timestamp この後は誰でもsettleできる
+    pub unrevealed_forfeit_basis_points: u16, // revealしなかったbidのdepositからsellerに払う割合
+    pub bid_count: u8,
+    pub highest_bidder_key: Pubkey, // 有効なrevealがない場合はPubkey::default()
+    pub highest_bid_amount: u64,
+    pub nonce: u64, // 同じsellerで複数のauctionを持てるようにする
+    pub bump: u8,
+}
+
+impl SealedAuctionAccount {
+    pub fn space(nft_amount: usize) -> usize {
+        8 // internal anchor discriminator
+        + 32 // sellerKey
+        + 4 + 32 * nft_amount // nftTokenAccounts
+        + 4 + nft_amount // vaultAccountBumps
+        + 4 + 8 * nft_amount // nftQuantities
+        + 8 // reservePrice
+        + 8 // minDeposit
+        + 8 // commitEndAt
+        + 8 // revealEndAt
+        + 2 // unrevealedForfeitBasisPoints
+        + 1 // bidCount
+        + 32 // highestBidderKey
+        + 8 // highestBidAmount
+        + 8 // nonce
+        + 1 // bump
+    }
+
+    pub fn has_winner(&self) -> bool {
+        self.highest_bidder_key != Pubkey::default()
+    }
+
+    pub fn is_commit_window(&self, now: i64) -> bool {
+        now < self.commit_end_at
+    }
+
+    pub fn is_reveal_window(&self, now: i64) -> bool {
+        self.commit_end_at <= now && now < self.reveal_end_at
+    }
+}
+
+// bidderごとに1つ depositのSOLをrentに加えて持つ
+#[account]
+pub struct SealedBidAccount {
+    pub auction_key: Pubkey,
+    pub bidder_key: Pubkey,
+    pub commitment: [u8; 32], // get_sealed_bid_commitment(amount, salt, bidder)
+    pub deposit_amount: u64, // 額を推測されないようにbidより多く預けてもよい
+    pub revealed: bool,
+    pub amount: u64, // revealするまでは0
+    pub bump: u8,
+}
+
+impl SealedBidAccount {
+    pub const SPACE: usize = 8 // internal anchor discriminator
+        + 32 // auctionKey
+        + 32 // bidderKey
+        + 32 // commitment
+        + 8 // depositAmount
+        + 1 // revealed
+        + 8 // amount
+        + 1; // bump
+}
+
+// collectionのNFTを預けて、別のcollectionのNFTとのswapを何度でも受け付ける escrowと違ってswapでは閉じない
+#[account]
+pub struct PoolAccount {
+    pub owner_key: Pubkey,
+    pub offered_collection_key: Pubkey, // poolが出すNFTのcollection
+    pub accepted_collection_key: Pubkey, // swapで受け取るNFTのcollection
+    pub accepted_nft_ratio: u8, // poolのNFT 1つに対して受け取るNFTの数
+    pub swap_fee: u64, // swapごとにownerに払うSOL lamport
+    pub nft_token_accounts: Vec<Pubkey>, // まだ残っているNFT swapされたものは取り除く
+    pub vault_account_bumps: Vec<u8>,
+    pub nonce: u64, // 同じownerで複数のpoolを持てるようにする
+    pub bump: u8,
+}
+
+impl PoolAccount {
+    pub fn space(nft_amount: usize) -> usize {
+        8 // internal anchor discriminator
+        + 32 // ownerKey
+        + 32 // offeredCollectionKey
+        + 32 // acceptedCollectionKey
+        + 1 // acceptedNftRatio
+        + 8 // swapFee
+        + 4 + 32 * nft_amount // nftTokenAccounts
+        + 4 + nft_amount // vaultAccountBumps
+        + 8 // nonce
+        + 1 // bump
+    }
+}
+
+// collectionのNFTを1つあたりの価格で何度でも買う 予算のSOLをrentに加えて持ち、sellerが1つずつfillする
+#[account]
+pub struct StandingOfferAccount {
+    pub buyer_key: Pubkey,
+    pub collection_key: Pubkey,
+    pub price_per_nft: u64, // NFT 1つごとにsellerに払うSOL lamport
+    pub remaining_count: u8, // あと何個買えるか 予算はprice_per_nft * remaining_count
+    pub nonce: u64, // 同じbuyerで複数のofferを持てるようにする
+    pub bump: u8,
+}
+
+impl StandingOfferAccount {
+    pub const SPACE: usize = 8 // internal anchor discriminator
+        + 32 // buyerKey
+        + 32 // collectionKey
+        + 8 // pricePerNft
+        + 1 // remainingCount
+        + 8 // nonce
+        + 1; // bump
+
+    pub fn remaining_budget(&self) -> Option<u64> {
+        self.price_per_nft.checked_mul(self.remaining_count as u64)
+    }
+}
+
+// lenderがNFTをvaultに預けて日割りで貸す 借りられている間はrenterのATAに渡し、vault authorityをdelegateにしてfreezeしておく
+#[account]
+pub struct RentalAccount {
+    pub lender_key: Pubkey,
+    pub nft_token_account: Pubkey, // lenderが預けたtoken account end_rentalでここに返す
+    pub vault_account_bump: u8,
+    pub daily_price: u64, // 1日ごとにlenderに払うSOL lamport
+    pub max_days: u16,
+    pub renter_key: Pubkey, // まだ借りられていない場合はPubkey::default()
+    pub renter_token_account: Pubkey, // 期間中にNFTをfreezeしておくrenterのATA まだ借りられていない場合はPubkey::default()
+    pub rented_until: i64, // unix timestamp まだ借りられていない場合は0
+    pub nonce: u64, // 同じlenderで複数のNFTを貸せるようにする
+    pub bump: u8,
+}
+
+impl RentalAccount {
+    pub const SPACE: usize = 8 // internal anchor discriminator
+        + 32 // lenderKey
+        + 32 // nftTokenAccount
+        + 1 // vaultAccountBump
+        + 8 // dailyPrice
+        + 2 // maxDays
+        + 32 // renterKey
+        + 32 // renterTokenAccount
+        + 8 // rentedUntil
+        + 8 // nonce
+        + 1; // bump
+
+    pub fn is_rented(&self) -> bool {
+        self.renter_key != Pubkey::default()
+    }
+
+    // 期間中だけrenterがNFTを使える
+    pub fn is_active(&self, now: i64) -> bool {
+        self.is_rented() && now < self.rented_until
+    }
+}
+
+// programで1つだけの設定 exchangeの手数料をtreasuryに送る
+#[account]
+pub struct Config {
+    pub admin_key: Pubkey,
+    pub fee_basis_points: u16, // SOLのlegにかける手数料
+    pub flat_fee: u64, // SOLが動かないswapにかける手数料 lamport
+    pub treasury_key: Pubkey,
+    pub bump: u8,
+}
+
+impl Config {
+    pub const SPACE: usize = 8 // internal anchor discriminator
+        + 32 // adminKey
+        + 2 // feeBasisPoints
+        + 8 // flatFee
+        + 32 // treasuryKey
+        + 1; // bump
+
+    pub fn fee(&self, sol_amount: u64) -> u64 {
+        (sol_amount as u128 * self.fee_basis_points as u128 / MAX_FEE_BASIS_POINTS as u128) as u64
+    }
 }
 
 #[account]
@@ -1,10 +1,29 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, CloseAccount, Token, Transfer};
+use anchor_lang::solana_program::{
+    program::{get_return_data, invoke, invoke_signed},
+    program_pack::Pack,
+    system_instruction,
+};
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token::{CloseAccount, Token};
+use mpl_token_metadata::pda::{find_master_edition_account, find_metadata_account};
 
 use crate::{
     errors::MyError,
-    state::{EscrowAccount, VaultAuthority, VAULT_AUTHORITY_PDA_SEED},
-    utils::{assert_is_ata, assert_is_pda, assert_keys_equal},
+    state::{
+        cnft_accounts_count, pnft_accounts_count, CnftLeaf, Config, EscrowAccount, FungibleLeg,
+        VaultAuthority, CNFT_ACCOUNTS_PER_ITEM, CNFT_PROGRAM_ACCOUNTS, CORE_ACCOUNTS_PER_ITEM,
+        CORE_PROGRAM_ACCOUNTS, FUNGIBLE_VAULT_ACCOUNT_PDA_SEED, PNFT_ACCOUNTS_PER_ITEM,
+        PNFT_PROGRAM_ACCOUNTS, VAULT_ACCOUNT_PDA_SEED, VAULT_AUTHORITY_PDA_SEED,
+    },
+    utils::{
+        assert_is_ata, assert_is_core_asset, assert_is_fungible_vault, assert_is_metadata, assert_is_pda, assert_keys_equal,
+        get_associated_token_address_with_program_id, get_cnft_asset_id,
+        assert_is_sft_mint, assert_owned_by_token_program, assert_rent_exempt,
+        assert_supported_mint_extensions, assert_transfer_hook_accounts, has_transfer_fee,
+        transfer_amount_with_fee, unpack_mint, unpack_token_account,
+    },
 };
 
 /*　難関　ここで以下の仕様にあわせてlifetimeのa b cを設定しないとlifetimeエラー
@@ -26,6 +45,10 @@ pub struct CancelContext<'a, 'b, 'c, 'info> {
     pub accounts: &'b CancelContextAccounts<'info>,
     /// CHECK: This is not dangerous because we don't read or write from this account
     pub remaining_accounts: &'c [AccountInfo<'info>],
+    // transfer hookのあるmintのためにtransferのCPIにそのまま渡す
+    pub transfer_hook_accounts: &'c [AccountInfo<'info>],
+    // initializer_cnft_assetsと同じ順
+    pub cnft_leaves: &'c [CnftLeaf],
 }
 
 pub fn cancel(cancel_context: &CancelContext) -> Result<()> {
@@ -33,19 +56,33 @@ pub fn cancel(cancel_context: &CancelContext) -> Result<()> {
 
     let ctx = cancel_context;
 
+    // acceptされたescrowはtakerの資産もvaultにあるのでsettleで精算する
+    require!(
+        !ctx.accounts.escrow_account.is_accepted(),
+        MyError::EscrowAlreadyAccepted
+    );
+
     // remaining_accountsの数の検証
+    // NFTの[token account, vault account, mint]の後にfungible tokenの[token account, vault account, mint]
+    let initializer_nft_amount_count = ctx
+        .accounts
+        .escrow_account
+        .initializer_nft_token_accounts
+        .len();
+    let initializer_fungible_amount_count =
+        ctx.accounts.escrow_account.initializer_fungible_legs.len();
     require_eq!(
         ctx.remaining_accounts.len(),
-        ctx.accounts
-            .escrow_account
-            .initializer_nft_token_accounts
-            .len()
-            * 3,
+        ctx.accounts.escrow_account.cancel_accounts_count(ctx.cnft_leaves),
         MyError::NftAmountMismatch
     );
+    require_eq!(
+        ctx.cnft_leaves.len(),
+        ctx.accounts.escrow_account.initializer_cnft_assets.len(),
+        MyError::CnftAssetMismatch
+    );
 
     // NFTをinitializerに戻す
-    let initializer_nft_amount_count = &ctx.remaining_accounts.len() / 3;
     for index in 0..initializer_nft_amount_count {
         let initializer_nft_token_account = &ctx.remaining_accounts[index * 3];
         let vault_account = &ctx.remaining_accounts[index * 3 + 1];
@@ -63,41 +100,245 @@ pub fn cancel(cancel_context: &CancelContext) -> Result<()> {
             initializer_nft_token_account,
             ctx.accounts.initializer.key,
             mint_account,
-            false,
+            0,
         )?;
 
         // PDAの検証
+        let quantity = ctx.accounts.escrow_account.initializer_nft_quantities[index];
         assert_is_pda(
             initializer_nft_token_account,
+            &ctx.accounts.escrow_account.key(),
             ctx.accounts.escrow_account.vault_account_bumps[index],
             vault_account,
             &ctx.accounts.vault_authority.key(),
             ctx.program_id,
+            quantity,
         )?;
 
-        token::transfer(
+        // NFTとSFTはdecimals 0
+        transfer_checked(
             ctx.accounts
-                .into_transfer_to_initializer_context(vault_account, initializer_nft_token_account)
+                .into_transfer_to_initializer_context(
+                    vault_account,
+                    initializer_nft_token_account,
+                    mint_account,
+                )
                 .with_signer(&[&[
                     VAULT_AUTHORITY_PDA_SEED,
-                    ctx.accounts.initializer.key().as_ref(),
-                    ctx.accounts.taker.key().as_ref(),
+                    ctx.accounts.escrow_account.key().as_ref(),
                     &[ctx.accounts.vault_authority.bump],
-                ]]),
-            1,
+                ]])
+                .with_remaining_accounts(ctx.transfer_hook_accounts.to_vec()),
+            quantity,
+            0,
         )?;
 
         // NFTのtoken accountをcloseする
-        token::close_account(
+        close_account(
             ctx.accounts
                 .into_close_context(vault_account)
                 .with_signer(&[&[
                     VAULT_AUTHORITY_PDA_SEED,
-                    ctx.accounts.initializer.key().as_ref(),
-                    ctx.accounts.taker.key().as_ref(),
+                    ctx.accounts.escrow_account.key().as_ref(),
+                    &[ctx.accounts.vault_authority.bump],
+                ]]),
+        )?;
+    }
+
+    // fungible tokenをinitializerに戻す
+    let fungible_offset = initializer_nft_amount_count * 3;
+    for (index, fungible_leg) in ctx
+        .accounts
+        .escrow_account
+        .initializer_fungible_legs
+        .iter()
+        .enumerate()
+    {
+        let initializer_token_account = &ctx.remaining_accounts[fungible_offset + index * 3];
+        let vault_account = &ctx.remaining_accounts[fungible_offset + index * 3 + 1];
+        let mint_account = &ctx.remaining_accounts[fungible_offset + index * 3 + 2];
+
+        assert_keys_equal(
+            &mint_account.key(),
+            &fungible_leg.mint,
+            MyError::MintPublicKeyMismatch,
+        )?;
+
+        assert_is_ata(
+            initializer_token_account,
+            ctx.accounts.initializer.key,
+            mint_account,
+            0,
+        )?;
+
+        // transfer feeの分も含めてvaultにあるものを全て戻す
+        let vault_account_data = assert_is_fungible_vault(
+            vault_account,
+            &ctx.accounts.escrow_account.key(),
+            mint_account,
+            ctx.accounts.escrow_account.initializer_fungible_vault_account_bumps[index],
+            &ctx.accounts.vault_authority.key(),
+            ctx.program_id,
+        )?;
+
+        transfer_checked(
+            ctx.accounts
+                .into_transfer_to_initializer_context(
+                    vault_account,
+                    initializer_token_account,
+                    mint_account,
+                )
+                .with_signer(&[&[
+                    VAULT_AUTHORITY_PDA_SEED,
+                    ctx.accounts.escrow_account.key().as_ref(),
+                    &[ctx.accounts.vault_authority.bump],
+                ]])
+                .with_remaining_accounts(ctx.transfer_hook_accounts.to_vec()),
+            vault_account_data.amount,
+            fungible_leg.decimals,
+        )?;
+
+        // transfer feeがvaultにwithholdされているとcloseできないのでmintに回収する
+        harvest_withheld_fees(
+            &ctx.accounts.token_program_for(mint_account),
+            mint_account,
+            vault_account,
+        )?;
+
+        close_account(
+            ctx.accounts
+                .into_close_context(vault_account)
+                .with_signer(&[&[
+                    VAULT_AUTHORITY_PDA_SEED,
+                    ctx.accounts.escrow_account.key().as_ref(),
+                    &[ctx.accounts.vault_authority.bump],
+                ]]),
+        )?;
+    }
+
+    // pNFTをvault authorityのATAからinitializerに戻す
+    let pnft_offset = fungible_offset + initializer_fungible_amount_count * 3;
+    if !ctx.accounts.escrow_account.initializer_pnft_mints.is_empty() {
+        // pNFTのTransferはpayerの署名が必要
+        let payer = ctx
+            .accounts
+            .payer
+            .as_ref()
+            .ok_or(MyError::UnsupportedPnftOffer)?;
+        let pnft = PnftContextAccounts::new(
+            payer.clone(),
+            &ctx.remaining_accounts[pnft_offset..pnft_offset + PNFT_PROGRAM_ACCOUNTS],
+        )?;
+        let vault_authority = ctx.accounts.vault_authority.to_account_info();
+        for (index, pnft_mint) in ctx
+            .accounts
+            .escrow_account
+            .initializer_pnft_mints
+            .iter()
+            .enumerate()
+        {
+            let start = pnft_offset + PNFT_PROGRAM_ACCOUNTS + index * PNFT_ACCOUNTS_PER_ITEM;
+            let pnft_accounts = &ctx.remaining_accounts[start..start + PNFT_ACCOUNTS_PER_ITEM];
+            transfer_pnft(
+                pnft.into_transfer_pnft_context(
+                    pnft_accounts,
+                    pnft_mint,
+                    &vault_authority,
+                    &ctx.accounts.initializer,
+                    &vault_authority,
+                )?
+                .with_signer(&[&[
+                    VAULT_AUTHORITY_PDA_SEED,
+                    ctx.accounts.escrow_account.key().as_ref(),
                     &[ctx.accounts.vault_authority.bump],
                 ]]),
+            )?;
+
+            // 空になったvault authorityのATAをcloseしてrentをinitializerに返す
+            // Token Metadataのversionによってはtransferでcloseされるので、残っている場合だけ
+            if pnft_accounts[0].lamports() > 0 {
+                close_account(
+                    ctx.accounts
+                        .into_close_context(&pnft_accounts[0])
+                        .with_signer(&[&[
+                            VAULT_AUTHORITY_PDA_SEED,
+                            ctx.accounts.escrow_account.key().as_ref(),
+                            &[ctx.accounts.vault_authority.bump],
+                        ]]),
+                )?;
+            }
+        }
+    }
+
+    // cNFTのleaf ownerをvault authorityからinitializerに戻す
+    let cnft_offset = pnft_offset + pnft_accounts_count(ctx.accounts.escrow_account.initializer_pnft_mints.len());
+    if !ctx.cnft_leaves.is_empty() {
+        let cnft = CnftContextAccounts::new(
+            &ctx.remaining_accounts[cnft_offset..cnft_offset + CNFT_PROGRAM_ACCOUNTS],
         )?;
+        let vault_authority = ctx.accounts.vault_authority.to_account_info();
+        let mut start = cnft_offset + CNFT_PROGRAM_ACCOUNTS;
+        for (cnft_leaf, cnft_asset) in ctx
+            .cnft_leaves
+            .iter()
+            .zip(ctx.accounts.escrow_account.initializer_cnft_assets.iter())
+        {
+            let end = start + CNFT_ACCOUNTS_PER_ITEM + cnft_leaf.proof_len as usize;
+            transfer_cnft(
+                cnft.into_transfer_cnft_context(
+                    &ctx.remaining_accounts[start..end],
+                    cnft_leaf,
+                    cnft_asset,
+                    &vault_authority,
+                    &ctx.accounts.initializer,
+                )?
+                .with_signer(&[&[
+                    VAULT_AUTHORITY_PDA_SEED,
+                    ctx.accounts.escrow_account.key().as_ref(),
+                    &[ctx.accounts.vault_authority.bump],
+                ]]),
+                cnft_leaf,
+            )?;
+            start = end;
+        }
+    }
+
+    // Core assetのownerをvault authorityからinitializerに戻す
+    let core_offset = cnft_offset + cnft_accounts_count(ctx.cnft_leaves);
+    if !ctx.accounts.escrow_account.initializer_core_assets.is_empty() {
+        // Core assetのTransferもpayerの署名が必要
+        let payer = ctx
+            .accounts
+            .payer
+            .as_ref()
+            .ok_or(MyError::UnsupportedCoreOffer)?;
+        let core = CoreContextAccounts::new(
+            payer.clone(),
+            &ctx.remaining_accounts[core_offset..core_offset + CORE_PROGRAM_ACCOUNTS],
+        )?;
+        let vault_authority = ctx.accounts.vault_authority.to_account_info();
+        for (index, core_asset) in ctx
+            .accounts
+            .escrow_account
+            .initializer_core_assets
+            .iter()
+            .enumerate()
+        {
+            let start = core_offset + CORE_PROGRAM_ACCOUNTS + index * CORE_ACCOUNTS_PER_ITEM;
+            transfer_core(
+                core.into_transfer_core_context(
+                    &ctx.remaining_accounts[start..start + CORE_ACCOUNTS_PER_ITEM],
+                    core_asset,
+                    &vault_authority,
+                    &ctx.accounts.initializer,
+                )?
+                .with_signer(&[&[
+                    VAULT_AUTHORITY_PDA_SEED,
+                    ctx.accounts.escrow_account.key().as_ref(),
+                    &[ctx.accounts.vault_authority.bump],
+                ]]),
+            )?;
+        }
     }
 
     // 追加のsolをinitializerに戻す
@@ -128,17 +369,25 @@ pub struct CancelContextAccounts<'info> {
     pub vault_authority: Box<Account<'info, VaultAuthority>>,
     pub escrow_account: Box<Account<'info, EscrowAccount>>,
     pub token_program: Program<'info, Token>,
+    pub token_2022_program: Program<'info, Token2022>,
     pub rent: Sysvar<'info, Rent>,
+    pub payer: Option<AccountInfo<'info>>, // pNFTとCore assetのTransferで署名するaccount signerがいない場合はNone
+}
+
+impl<'info> TokenPrograms<'info> for CancelContextAccounts<'info> {
+    fn token_program(&self) -> &AccountInfo<'info> {
+        return &self.token_program;
+    }
+
+    fn token_2022_program(&self) -> &AccountInfo<'info> {
+        return &self.token_2022_program;
+    }
 }
 
 impl<'info> Cancel<'info> for CancelContextAccounts<'info> {
     fn vault_authority(&self) -> &Box<Account<'info, VaultAuthority>> {
         return &self.vault_authority;
     }
-
-    fn token_program(&self) -> &AccountInfo<'info> {
-        return &self.token_program;
-    }
 }
 
 impl<'info> Common<'info> for CancelContextAccounts<'info> {
@@ -149,34 +398,99 @@ impl<'info> Common<'info> for CancelContextAccounts<'info> {
     fn initializer(&self) -> &AccountInfo<'info> {
         return &self.initializer;
     }
+}
 
-    fn token_program(&self) -> &AccountInfo<'info> {
-        return &self.token_program;
+// anchor_spl 0.24にはToken-2022のProgram型がないので、addressの検証用に定義する
+#[derive(Clone)]
+pub struct Token2022;
+
+impl anchor_lang::Id for Token2022 {
+    fn id() -> Pubkey {
+        spl_token_2022::id()
     }
 }
 
-pub trait Cancel<'info> {
-    fn vault_authority(&self) -> &Box<Account<'info, VaultAuthority>>;
+// pNFTのTransferのCPI先
+#[derive(Clone)]
+pub struct TokenMetadata;
+
+impl anchor_lang::Id for TokenMetadata {
+    fn id() -> Pubkey {
+        mpl_token_metadata::id()
+    }
+}
+
+// pNFTのruleset(authorization rules)を検証するprogram
+pub mod mpl_token_auth_rules {
+    anchor_lang::declare_id!("auth9SigNpDKz4sJJ1DfCTuZrZNSAgh9sFD3rboVmgg");
+}
+
+#[derive(Clone)]
+pub struct TokenAuthRules;
+
+impl anchor_lang::Id for TokenAuthRules {
+    fn id() -> Pubkey {
+        mpl_token_auth_rules::id()
+    }
+}
+
+// cNFTのTransferのCPI先
+pub mod mpl_bubblegum {
+    anchor_lang::declare_id!("BGUMAp9Gq7iTEuizy4pqaxsTyUCBK68MDfK752saRPUY");
+}
+
+// cNFTのmerkle treeを更新するprogram
+pub mod spl_account_compression {
+    anchor_lang::declare_id!("cmtDvXumGCrqC1Age74AVPhSRVXJMd8PJS91L8KbNCK");
+}
+
+// account compressionがleafの変更をlogに残すprogram
+pub mod spl_noop {
+    anchor_lang::declare_id!("noopb9bkMVfRPU8AsbpTUg8AQkHtKwMYZiFUjNRtMmV");
+}
+
+// Core assetのTransferのCPI先
+pub mod mpl_core {
+    anchor_lang::declare_id!("CoREENxT6tW1HoK8ypY1SxRMZTcVPm7R94rH4PZNhX7d");
+}
+
+// itemごとにspl_tokenかToken-2022のどちらかを使うので、accountのownerからCPI先を選ぶ
+pub trait TokenPrograms<'info> {
     fn token_program(&self) -> &AccountInfo<'info>;
+    fn token_2022_program(&self) -> &AccountInfo<'info>;
+
+    // mintでもtoken accountでもownerはtoken program ownerの検証はassert_is_ataなどで済んでいる
+    fn token_program_for(&self, account: &AccountInfo<'info>) -> AccountInfo<'info> {
+        if *account.owner == spl_token_2022::id() {
+            self.token_2022_program().clone()
+        } else {
+            self.token_program().clone()
+        }
+    }
+}
+
+pub trait Cancel<'info>: TokenPrograms<'info> {
+    fn vault_authority(&self) -> &Box<Account<'info, VaultAuthority>>;
 
     fn into_transfer_to_initializer_context(
         &self,
         vault_account: &AccountInfo<'info>,
         initializer_nft_token_account: &AccountInfo<'info>,
-    ) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
-        let cpi_accounts = Transfer {
+        mint_account: &AccountInfo<'info>,
+    ) -> CpiContext<'_, '_, '_, 'info, TransferChecked<'info>> {
+        let cpi_accounts = TransferChecked {
             from: vault_account.clone(),
+            mint: mint_account.clone(),
             to: initializer_nft_token_account.clone(),
             authority: self.vault_authority().to_account_info().clone(),
         };
-        CpiContext::new(self.token_program().clone(), cpi_accounts)
+        CpiContext::new(self.token_program_for(mint_account), cpi_accounts)
     }
 }
 
-pub trait Common<'info> {
+pub trait Common<'info>: TokenPrograms<'info> {
     fn vault_authority(&self) -> &Box<Account<'info, VaultAuthority>>;
     fn initializer(&self) -> &AccountInfo<'info>;
-    fn token_program(&self) -> &AccountInfo<'info>;
 
     fn into_close_context(
         &self,
@@ -187,6 +501,1095 @@ pub trait Common<'info> {
             destination: self.initializer().clone(), // initializerに権限を返却する
             authority: self.vault_authority().to_account_info().clone(),
         };
-        CpiContext::new(self.token_program().clone(), cpi_accounts)
+        CpiContext::new(self.token_program_for(vault_account), cpi_accounts)
+    }
+}
+
+// settleのようにvaultの中身を相手に払い出す場合 vaultのrentは預けた側に返す
+pub trait Release<'info>: TokenPrograms<'info> {
+    fn vault_authority(&self) -> &Box<Account<'info, VaultAuthority>>;
+
+    fn into_transfer_to_receiver_context(
+        &self,
+        vault_account: &AccountInfo<'info>,
+        receiver_token_account: &AccountInfo<'info>,
+        mint_account: &AccountInfo<'info>,
+    ) -> CpiContext<'_, '_, '_, 'info, TransferChecked<'info>> {
+        let cpi_accounts = TransferChecked {
+            from: vault_account.clone(),
+            mint: mint_account.clone(),
+            to: receiver_token_account.clone(),
+            authority: self.vault_authority().to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program_for(mint_account), cpi_accounts)
+    }
+
+    fn into_close_to_context(
+        &self,
+        vault_account: &AccountInfo<'info>,
+        destination: &AccountInfo<'info>,
+    ) -> CpiContext<'_, '_, '_, 'info, CloseAccount<'info>> {
+        let cpi_accounts = CloseAccount {
+            account: vault_account.clone(),
+            destination: destination.clone(),
+            authority: self.vault_authority().to_account_info().clone(),
+        };
+        CpiContext::new(self.token_program_for(vault_account), cpi_accounts)
+    }
+}
+
+// rentalの期間中にrenterのtoken accountをfreezeする NFTのfreeze authorityはmaster editionなので、delegateのvault authorityがToken Metadataを呼ぶ
+pub struct DelegatedNftAccounts<'info> {
+    /// CHECK: This is not dangerous because we have already validated it in the account context
+    pub token_metadata_program: AccountInfo<'info>,
+    /// CHECK: This is not dangerous because we have already validated it in the account context
+    pub delegate: AccountInfo<'info>,
+    /// CHECK: This is not dangerous because we have already validated it in the account context
+    pub token_account: AccountInfo<'info>,
+    /// CHECK: Token Metadataがmintのmaster editionであることを検証する
+    pub edition: AccountInfo<'info>,
+    /// CHECK: This is not dangerous because we have already validated it in the account context
+    pub mint: AccountInfo<'info>,
+    /// CHECK: This is not dangerous because we have already validated it in the account context
+    pub token_program: AccountInfo<'info>,
+}
+
+impl<'info> DelegatedNftAccounts<'info> {
+    fn account_infos(&self) -> Vec<AccountInfo<'info>> {
+        vec![
+            self.delegate.clone(),
+            self.token_account.clone(),
+            self.edition.clone(),
+            self.mint.clone(),
+            self.token_program.clone(),
+            self.token_metadata_program.clone(),
+        ]
+    }
+}
+
+pub fn freeze_delegated_nft(accounts: &DelegatedNftAccounts, signer_seeds: &[&[&[u8]]]) -> Result<()> {
+    invoke_signed(
+        &mpl_token_metadata::instruction::freeze_delegated_account(
+            mpl_token_metadata::id(),
+            *accounts.delegate.key,
+            *accounts.token_account.key,
+            *accounts.edition.key,
+            *accounts.mint.key,
+        ),
+        &accounts.account_infos(),
+        signer_seeds,
+    )?;
+    Ok(())
+}
+
+pub fn thaw_delegated_nft(accounts: &DelegatedNftAccounts, signer_seeds: &[&[&[u8]]]) -> Result<()> {
+    invoke_signed(
+        &mpl_token_metadata::instruction::thaw_delegated_account(
+            mpl_token_metadata::id(),
+            *accounts.delegate.key,
+            *accounts.token_account.key,
+            *accounts.edition.key,
+            *accounts.mint.key,
+        ),
+        &accounts.account_infos(),
+        signer_seeds,
+    )?;
+    Ok(())
+}
+
+pub struct DepositContext<'a, 'b, 'c, 'info> {
+    pub program_id: &'a Pubkey,
+    pub accounts: &'b DepositContextAccounts<'info>,
+    /// CHECK: This is not dangerous because we validate every account in deposit
+    pub remaining_accounts: &'c [AccountInfo<'info>],
+    // transfer hookのあるmintのためにtransferのCPIにそのまま渡す
+    pub transfer_hook_accounts: &'c [AccountInfo<'info>],
+}
+
+// remaining_accountsの[token account, vault account, mint]ごとにvaultを作ってNFTを預ける
+// 預けたtoken accountを順番に返すので、escrow側で保存してexchangeやcancelのときに検証する
+// quantitiesはSFTのように1より多く預ける場合の量 NFTなら1
+// SFTは同じtoken accountから複数のescrowに分けて預けられるので、vaultはescrowとtoken accountのPDAにする
+pub fn deposit(
+    deposit_context: &DepositContext,
+    escrow_key: &Pubkey,
+    vault_account_bumps: &[u8],
+    quantities: &[u64],
+) -> Result<Vec<Pubkey>> {
+    msg!("start deposit");
+
+    let ctx = deposit_context;
+
+    // remaining_accountsの数の検証
+    require_eq!(
+        ctx.remaining_accounts.len(),
+        vault_account_bumps.len() * 3,
+        MyError::VaultAccountBumpsMismatch
+    );
+    require_eq!(
+        quantities.len(),
+        vault_account_bumps.len(),
+        MyError::NftAmountMismatch
+    );
+
+    let mut token_account_keys = Vec::with_capacity(vault_account_bumps.len());
+    for (index, vault_account_bump) in vault_account_bumps.iter().enumerate() {
+        // Token Accountの検証
+        let token_account = &ctx.remaining_accounts[index * 3];
+        let vault_account = &ctx.remaining_accounts[index * 3 + 1];
+        let mint_account = &ctx.remaining_accounts[index * 3 + 2];
+        let quantity = quantities[index];
+
+        require_gt!(quantity, 0, MyError::InvalidNftQuantity);
+        assert_is_ata(
+            token_account,
+            ctx.accounts.initializer.key,
+            mint_account,
+            quantity,
+        )?;
+        // transfer_checkedにdecimals 0を渡すので、NFTとSFTだけを受け付ける
+        assert_is_sft_mint(mint_account, quantity)?;
+
+        // 渡されたPDAの検証
+        let vault_seeds: &[&[u8]] = &[
+            VAULT_ACCOUNT_PDA_SEED,
+            escrow_key.as_ref(),
+            token_account.key.as_ref(),
+            &[*vault_account_bump],
+        ];
+        let vault_pda = Pubkey::create_program_address(vault_seeds, ctx.program_id)
+            .map_err(|_| MyError::PdaPublicKeyMismatch)?;
+
+        require_keys_eq!(
+            vault_pda,
+            vault_account.key(),
+            MyError::PdaPublicKeyMismatch
+        );
+
+        create_vault_account(ctx.accounts, vault_account, mint_account, vault_seeds)?;
+
+        // NFTをinitializerからvaultに移す NFTとSFTはdecimals 0
+        transfer_checked(
+            ctx.accounts
+                .into_transfer_to_pda_context(token_account, vault_account, mint_account)
+                .with_remaining_accounts(ctx.transfer_hook_accounts.to_vec()),
+            quantity,
+            0,
+        )?;
+
+        token_account_keys.push(token_account.key());
+    }
+
+    msg!("end deposit");
+    Ok(token_account_keys)
+}
+
+// fungible tokenはamountが1とは限らず同じtoken accountから複数のescrowに預けられるので、vaultはescrowとmintのPDAにする
+pub fn deposit_fungible(
+    deposit_context: &DepositContext,
+    escrow_key: &Pubkey,
+    amounts: &[u64],
+    vault_account_bumps: &[u8],
+) -> Result<Vec<FungibleLeg>> {
+    msg!("start deposit_fungible");
+
+    let ctx = deposit_context;
+
+    // remaining_accountsの数の検証
+    require_eq!(
+        vault_account_bumps.len(),
+        amounts.len(),
+        MyError::VaultAccountBumpsMismatch
+    );
+    require_eq!(
+        ctx.remaining_accounts.len(),
+        amounts.len() * 3,
+        MyError::NftAmountMismatch
+    );
+
+    let mut fungible_legs = Vec::with_capacity(amounts.len());
+    for (index, amount) in amounts.iter().enumerate() {
+        let token_account = &ctx.remaining_accounts[index * 3];
+        let vault_account = &ctx.remaining_accounts[index * 3 + 1];
+        let mint_account = &ctx.remaining_accounts[index * 3 + 2];
+        let vault_account_bump = vault_account_bumps[index];
+
+        require_gt!(*amount, 0, MyError::InvalidFungibleAmount);
+
+        // decimalsはtransfer_checkedで使うのでmintから読んで保存しておく
+        assert_owned_by_token_program(mint_account)?;
+        assert_supported_mint_extensions(mint_account)?;
+        let mint = unpack_mint(mint_account)?;
+
+        // transfer feeのあるmintは、vaultから出すときのfeeも含めてtakerにamountが届く量をvaultに入れる
+        let vault_amount = transfer_amount_with_fee(mint_account, *amount)?;
+        let deposit_amount = transfer_amount_with_fee(mint_account, vault_amount)?;
+
+        let token_account_data = assert_is_ata(
+            token_account,
+            ctx.accounts.initializer.key,
+            mint_account,
+            0,
+        )?;
+        require_gte!(
+            token_account_data.amount,
+            deposit_amount,
+            MyError::InsufficientTokenBalance
+        );
+
+        let vault_seeds: &[&[u8]] = &[
+            FUNGIBLE_VAULT_ACCOUNT_PDA_SEED,
+            escrow_key.as_ref(),
+            mint_account.key.as_ref(),
+            &[vault_account_bump],
+        ];
+        let vault_pda = Pubkey::create_program_address(vault_seeds, ctx.program_id)
+            .map_err(|_| MyError::PdaPublicKeyMismatch)?;
+        require_keys_eq!(
+            vault_pda,
+            vault_account.key(),
+            MyError::PdaPublicKeyMismatch
+        );
+
+        create_vault_account(ctx.accounts, vault_account, mint_account, vault_seeds)?;
+
+        transfer_checked(
+            ctx.accounts
+                .into_transfer_to_pda_context(token_account, vault_account, mint_account)
+                .with_remaining_accounts(ctx.transfer_hook_accounts.to_vec()),
+            deposit_amount,
+            mint.decimals,
+        )?;
+
+        require_gte!(
+            unpack_token_account(vault_account)?.amount,
+            vault_amount,
+            MyError::InsufficientTokenBalance
+        );
+
+        // legはtakerに届く量
+        fungible_legs.push(FungibleLeg {
+            mint: mint_account.key(),
+            amount: *amount,
+            decimals: mint.decimals,
+        });
+    }
+
+    msg!("end deposit_fungible");
+    Ok(fungible_legs)
+}
+
+// vaultはescrowごとのPDAなので、itemのtoken programでaccountを作ってvault authorityをownerにする
+// Token-2022はmintのextensionによってaccountのsizeが変わるのでtoken programに聞く
+pub fn create_vault_account<'info>(
+    accounts: &DepositContextAccounts<'info>,
+    vault_account: &AccountInfo<'info>,
+    mint_account: &AccountInfo<'info>,
+    vault_seeds: &[&[u8]],
+) -> Result<()> {
+    let token_program = accounts.token_program_for(mint_account);
+    let space = if *token_program.key == spl_token_2022::id() {
+        invoke(
+            &spl_token_2022::instruction::get_account_data_size(
+                token_program.key,
+                mint_account.key,
+                &[],
+            )?,
+            &[mint_account.clone(), token_program.clone()],
+        )?;
+        match get_return_data() {
+            Some((program_id, data)) if program_id == *token_program.key && data.len() == 8 => {
+                u64::from_le_bytes(data.try_into().unwrap()) as usize
+            }
+            _ => return err!(MyError::IncorrectAccountInfoOwner),
+        }
+    } else {
+        spl_token::state::Account::LEN
+    };
+
+    let create_account_ix = system_instruction::create_account(
+        accounts.initializer.key,
+        vault_account.key,
+        accounts.rent.minimum_balance(space),
+        space as u64,
+        token_program.key,
+    );
+
+    // ただのinvokeだとError: failed to send transaction: Transaction simulation failed: Error processing Instruction 1: Cross-program invocation with unauthorized signer or writable account
+    invoke_signed(
+        &create_account_ix,
+        &[accounts.initializer.clone(), vault_account.clone()],
+        &[vault_seeds],
+    )?;
+
+    // initialize_account2でownerをvault authorityにするのでset_authorityは不要
+    let initialize_account_ix = spl_token_2022::instruction::initialize_account2(
+        token_program.key,
+        vault_account.key,
+        mint_account.key,
+        accounts.vault_authority.key,
+    )?;
+    invoke(
+        &initialize_account_ix,
+        &[
+            vault_account.clone(),
+            mint_account.clone(), // なくすとmissing error
+            accounts.rent.to_account_info().clone(), // rentがないと動かない
+            token_program.clone(),
+        ],
+    )?;
+
+    // check rent exempt
+    assert_rent_exempt(&accounts.rent, vault_account)?;
+
+    Ok(())
+}
+
+// anchor_spl 0.24のTransferにはtransfer_checkedがないので、CpiContextで使えるように定義する
+// decimalsがmintと一致しないとtoken programがエラーにするので、桁の取り違えを防げる
+#[derive(Accounts)]
+pub struct TransferChecked<'info> {
+    /// CHECK: token programで検証する
+    pub from: AccountInfo<'info>,
+    /// CHECK: token programで検証する
+    pub mint: AccountInfo<'info>,
+    /// CHECK: token programで検証する
+    pub to: AccountInfo<'info>,
+    /// CHECK: token programで検証する
+    pub authority: AccountInfo<'info>,
+}
+
+// anchor_spl 0.24のtoken::transferなどはspl_token::idに固定されているので、CpiContextのprogramにinstructionを送る
+// spl_token_2022のinstructionはspl_tokenのprogram idも受け付ける
+pub fn transfer_checked<'a, 'b, 'c, 'info>(
+    ctx: CpiContext<'a, 'b, 'c, 'info, TransferChecked<'info>>,
+    amount: u64,
+    decimals: u8,
+) -> Result<()> {
+    assert_transfer_hook_accounts(&ctx.accounts.mint, &ctx.remaining_accounts)?;
+
+    let mut ix = spl_token_2022::instruction::transfer_checked(
+        ctx.program.key,
+        ctx.accounts.from.key,
+        ctx.accounts.mint.key,
+        ctx.accounts.to.key,
+        ctx.accounts.authority.key,
+        &[],
+        amount,
+        decimals,
+    )?;
+    // transfer hookのextra accountsはToken-2022がkeyで探すので、remaining accountsをまとめて後ろにつける
+    // hookのないmintでは使われない
+    ix.accounts.extend(ctx.remaining_accounts.iter().map(|account| {
+        if account.is_writable {
+            AccountMeta::new(*account.key, false)
+        } else {
+            AccountMeta::new_readonly(*account.key, false)
+        }
+    }));
+    let mut account_infos = vec![
+        ctx.accounts.from,
+        ctx.accounts.mint,
+        ctx.accounts.to,
+        ctx.accounts.authority,
+        ctx.program,
+    ];
+    account_infos.extend(ctx.remaining_accounts);
+    invoke_signed(&ix, &account_infos, ctx.signer_seeds)?;
+    Ok(())
+}
+
+// transfer feeのあるmintはwithholdされたfeeが残っているとtoken accountをcloseできない
+// harvestは誰でも呼べてfeeはmintに移るので、vaultをcloseする前に回収する
+pub fn harvest_withheld_fees<'info>(
+    token_program: &AccountInfo<'info>,
+    mint_account: &AccountInfo<'info>,
+    vault_account: &AccountInfo<'info>,
+) -> Result<()> {
+    if !has_transfer_fee(mint_account) {
+        return Ok(());
+    }
+    let ix = spl_token_2022::extension::transfer_fee::instruction::harvest_withheld_tokens_to_mint(
+        token_program.key,
+        mint_account.key,
+        &[vault_account.key],
+    )?;
+    invoke(
+        &ix,
+        &[
+            mint_account.clone(),
+            vault_account.clone(),
+            token_program.clone(),
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn close_account<'a, 'b, 'c, 'info>(
+    ctx: CpiContext<'a, 'b, 'c, 'info, CloseAccount<'info>>,
+) -> Result<()> {
+    let ix = spl_token_2022::instruction::close_account(
+        ctx.program.key,
+        ctx.accounts.account.key,
+        ctx.accounts.destination.key,
+        ctx.accounts.authority.key,
+        &[],
+    )?;
+    invoke_signed(
+        &ix,
+        &[
+            ctx.accounts.account,
+            ctx.accounts.destination,
+            ctx.accounts.authority,
+            ctx.program,
+        ],
+        ctx.signer_seeds,
+    )?;
+    Ok(())
+}
+
+// mpl-token-metadata 1.4.3にはpNFTのTransferがないので、instructionを直接組み立てる
+// pNFTはToken Metadataがfreezeしているのでtoken programのtransferでは動かせず、token recordとrulesetもToken Metadataが検証する
+pub const TOKEN_METADATA_TRANSFER_INSTRUCTION: u8 = 49;
+
+#[derive(Accounts)]
+pub struct TransferPnft<'info> {
+    /// CHECK: Token Metadataで検証する
+    pub token: AccountInfo<'info>,
+    /// CHECK: Token Metadataで検証する
+    pub token_owner: AccountInfo<'info>,
+    /// CHECK: Token Metadataで検証する
+    pub destination: AccountInfo<'info>,
+    /// CHECK: Token Metadataで検証する
+    pub destination_owner: AccountInfo<'info>,
+    /// CHECK: Token Metadataで検証する
+    pub mint: AccountInfo<'info>,
+    /// CHECK: Token Metadataで検証する
+    pub metadata: AccountInfo<'info>,
+    /// CHECK: Token Metadataで検証する
+    pub edition: AccountInfo<'info>,
+    /// CHECK: Token Metadataで検証する
+    pub owner_token_record: AccountInfo<'info>,
+    /// CHECK: Token Metadataで検証する
+    pub destination_token_record: AccountInfo<'info>,
+    /// CHECK: Token Metadataで検証する
+    pub authority: AccountInfo<'info>,
+    /// CHECK: Token Metadataで検証する
+    pub payer: AccountInfo<'info>,
+    /// CHECK: Token Metadataで検証する
+    pub system_program: AccountInfo<'info>,
+    /// CHECK: Token Metadataで検証する
+    pub sysvar_instructions: AccountInfo<'info>,
+    /// CHECK: Token Metadataで検証する
+    pub spl_token_program: AccountInfo<'info>,
+    /// CHECK: Token Metadataで検証する
+    pub spl_ata_program: AccountInfo<'info>,
+    /// CHECK: Token Metadataで検証する
+    pub authorization_rules_program: AccountInfo<'info>,
+    /// CHECK: rulesetがない場合はToken Metadataのprogram idを渡す
+    pub authorization_rules: AccountInfo<'info>,
+}
+
+// pNFTは1つしかないのでamountは1固定 TransferArgs::V1 { amount, authorization_data: None }
+pub fn transfer_pnft<'a, 'b, 'c, 'info>(
+    ctx: CpiContext<'a, 'b, 'c, 'info, TransferPnft<'info>>,
+) -> Result<()> {
+    let mut data = vec![TOKEN_METADATA_TRANSFER_INSTRUCTION, 0];
+    data.extend_from_slice(&1u64.to_le_bytes());
+    data.push(0);
+
+    let accounts = &ctx.accounts;
+    let ix = Instruction {
+        program_id: *ctx.program.key,
+        accounts: vec![
+            AccountMeta::new(*accounts.token.key, false),
+            AccountMeta::new_readonly(*accounts.token_owner.key, false),
+            AccountMeta::new(*accounts.destination.key, false),
+            AccountMeta::new_readonly(*accounts.destination_owner.key, false),
+            AccountMeta::new_readonly(*accounts.mint.key, false),
+            AccountMeta::new(*accounts.metadata.key, false),
+            AccountMeta::new_readonly(*accounts.edition.key, false),
+            AccountMeta::new(*accounts.owner_token_record.key, false),
+            AccountMeta::new(*accounts.destination_token_record.key, false),
+            AccountMeta::new_readonly(*accounts.authority.key, true),
+            AccountMeta::new(*accounts.payer.key, true),
+            AccountMeta::new_readonly(*accounts.system_program.key, false),
+            AccountMeta::new_readonly(*accounts.sysvar_instructions.key, false),
+            AccountMeta::new_readonly(*accounts.spl_token_program.key, false),
+            AccountMeta::new_readonly(*accounts.spl_ata_program.key, false),
+            AccountMeta::new_readonly(*accounts.authorization_rules_program.key, false),
+            AccountMeta::new_readonly(*accounts.authorization_rules.key, false),
+        ],
+        data,
+    };
+    invoke_signed(
+        &ix,
+        &[
+            ctx.accounts.token,
+            ctx.accounts.token_owner,
+            ctx.accounts.destination,
+            ctx.accounts.destination_owner,
+            ctx.accounts.mint,
+            ctx.accounts.metadata,
+            ctx.accounts.edition,
+            ctx.accounts.owner_token_record,
+            ctx.accounts.destination_token_record,
+            ctx.accounts.authority,
+            ctx.accounts.payer,
+            ctx.accounts.system_program,
+            ctx.accounts.sysvar_instructions,
+            ctx.accounts.spl_token_program,
+            ctx.accounts.spl_ata_program,
+            ctx.accounts.authorization_rules_program,
+            ctx.accounts.authorization_rules,
+            ctx.program,
+        ],
+        ctx.signer_seeds,
+    )?;
+    Ok(())
+}
+
+// pNFTのTransferに共通で使うaccount payerはATAやtoken recordを作る場合にrentを払う
+pub struct PnftContextAccounts<'info> {
+    pub payer: AccountInfo<'info>,
+    pub token_metadata_program: AccountInfo<'info>,
+    pub system_program: AccountInfo<'info>,
+    pub sysvar_instructions: AccountInfo<'info>,
+    pub token_program: AccountInfo<'info>,
+    pub associated_token_program: AccountInfo<'info>,
+    pub authorization_rules_program: AccountInfo<'info>,
+}
+
+impl<'info> PnftContextAccounts<'info> {
+    // pNFTを扱わないescrowでもToken Metadataのaccountを要求しないように、programのaccountもremaining_accountsで受け取る
+    // program_accountsは[token metadata, system, sysvar instructions, token, associated token, authorization rules]
+    pub fn new(payer: AccountInfo<'info>, program_accounts: &[AccountInfo<'info>]) -> Result<Self> {
+        let expected_program_ids = [
+            TokenMetadata::id(),
+            System::id(),
+            anchor_lang::solana_program::sysvar::instructions::id(),
+            spl_token::id(),
+            AssociatedToken::id(),
+            TokenAuthRules::id(),
+        ];
+        for (account, expected_program_id) in program_accounts.iter().zip(expected_program_ids.iter()) {
+            assert_keys_equal(account.key, expected_program_id, MyError::PnftProgramMismatch)?;
+        }
+
+        Ok(PnftContextAccounts {
+            payer,
+            token_metadata_program: program_accounts[0].clone(),
+            system_program: program_accounts[1].clone(),
+            sysvar_instructions: program_accounts[2].clone(),
+            token_program: program_accounts[3].clone(),
+            associated_token_program: program_accounts[4].clone(),
+            authorization_rules_program: program_accounts[5].clone(),
+        })
+    }
+
+    // item_accountsは[token account, destination token account, mint, metadata, edition, owner token record, destination token record, authorization rules]
+    // token accountとdestinationはownerのATAであること token recordとrulesetはToken Metadataが検証する
+    pub fn into_transfer_pnft_context(
+        &self,
+        item_accounts: &[AccountInfo<'info>],
+        mint: &Pubkey,
+        token_owner: &AccountInfo<'info>,
+        destination_owner: &AccountInfo<'info>,
+        authority: &AccountInfo<'info>,
+    ) -> Result<CpiContext<'_, '_, '_, 'info, TransferPnft<'info>>> {
+        let token_account = &item_accounts[0];
+        let destination_token_account = &item_accounts[1];
+        let mint_account = &item_accounts[2];
+        let metadata_account = &item_accounts[3];
+        let edition_account = &item_accounts[4];
+
+        assert_keys_equal(mint_account.key, mint, MyError::MintPublicKeyMismatch)?;
+        assert_keys_equal(
+            &get_associated_token_address_with_program_id(token_owner.key, mint, &spl_token::id()),
+            token_account.key,
+            MyError::AssociatedTokenPublicKeyMismatch,
+        )?;
+        assert_keys_equal(
+            &get_associated_token_address_with_program_id(
+                destination_owner.key,
+                mint,
+                &spl_token::id(),
+            ),
+            destination_token_account.key,
+            MyError::AssociatedTokenPublicKeyMismatch,
+        )?;
+        assert_keys_equal(
+            &find_metadata_account(mint).0,
+            metadata_account.key,
+            MyError::MetadataPublicKeyMismatch,
+        )?;
+        assert_keys_equal(
+            &find_master_edition_account(mint).0,
+            edition_account.key,
+            MyError::MetadataPublicKeyMismatch,
+        )?;
+
+        let cpi_accounts = TransferPnft {
+            token: token_account.clone(),
+            token_owner: token_owner.clone(),
+            destination: destination_token_account.clone(),
+            destination_owner: destination_owner.clone(),
+            mint: mint_account.clone(),
+            metadata: metadata_account.clone(),
+            edition: edition_account.clone(),
+            owner_token_record: item_accounts[5].clone(),
+            destination_token_record: item_accounts[6].clone(),
+            authority: authority.clone(),
+            payer: self.payer.clone(),
+            system_program: self.system_program.clone(),
+            sysvar_instructions: self.sysvar_instructions.clone(),
+            spl_token_program: self.token_program.clone(),
+            spl_ata_program: self.associated_token_program.clone(),
+            authorization_rules_program: self.authorization_rules_program.clone(),
+            authorization_rules: item_accounts[7].clone(),
+        };
+        Ok(CpiContext::new(
+            self.token_metadata_program.clone(),
+            cpi_accounts,
+        ))
+    }
+}
+
+// BubblegumはAnchorのprogramなので、instructionのdataはsighash("global:transfer")から始まる
+pub const BUBBLEGUM_TRANSFER_DISCRIMINATOR: [u8; 8] = [163, 52, 200, 231, 140, 3, 69, 186];
+
+#[derive(Accounts)]
+pub struct TransferCnft<'info> {
+    /// CHECK: Bubblegumで検証する
+    pub tree_authority: AccountInfo<'info>,
+    /// CHECK: Bubblegumで検証する
+    pub leaf_owner: AccountInfo<'info>,
+    /// CHECK: Bubblegumで検証する
+    pub leaf_delegate: AccountInfo<'info>,
+    /// CHECK: Bubblegumで検証する
+    pub new_leaf_owner: AccountInfo<'info>,
+    /// CHECK: Bubblegumで検証する
+    pub merkle_tree: AccountInfo<'info>,
+    /// CHECK: Bubblegumで検証する
+    pub log_wrapper: AccountInfo<'info>,
+    /// CHECK: Bubblegumで検証する
+    pub compression_program: AccountInfo<'info>,
+    /// CHECK: Bubblegumで検証する
+    pub system_program: AccountInfo<'info>,
+}
+
+// proofはctx.remaining_accountsで渡す leaf ownerがdelegateも兼ねる
+pub fn transfer_cnft<'a, 'b, 'c, 'info>(
+    ctx: CpiContext<'a, 'b, 'c, 'info, TransferCnft<'info>>,
+    cnft_leaf: &CnftLeaf,
+) -> Result<()> {
+    let mut data = BUBBLEGUM_TRANSFER_DISCRIMINATOR.to_vec();
+    data.extend_from_slice(&cnft_leaf.root);
+    data.extend_from_slice(&cnft_leaf.data_hash);
+    data.extend_from_slice(&cnft_leaf.creator_hash);
+    data.extend_from_slice(&cnft_leaf.nonce.to_le_bytes());
+    data.extend_from_slice(&cnft_leaf.index.to_le_bytes());
+
+    let accounts = &ctx.accounts;
+    let mut account_metas = vec![
+        AccountMeta::new_readonly(*accounts.tree_authority.key, false),
+        AccountMeta::new_readonly(*accounts.leaf_owner.key, true),
+        AccountMeta::new_readonly(*accounts.leaf_delegate.key, false),
+        AccountMeta::new_readonly(*accounts.new_leaf_owner.key, false),
+        AccountMeta::new(*accounts.merkle_tree.key, false),
+        AccountMeta::new_readonly(*accounts.log_wrapper.key, false),
+        AccountMeta::new_readonly(*accounts.compression_program.key, false),
+        AccountMeta::new_readonly(*accounts.system_program.key, false),
+    ];
+    account_metas.extend(
+        ctx.remaining_accounts
+            .iter()
+            .map(|proof| AccountMeta::new_readonly(*proof.key, false)),
+    );
+    let ix = Instruction {
+        program_id: *ctx.program.key,
+        accounts: account_metas,
+        data,
+    };
+
+    let mut account_infos = vec![
+        ctx.accounts.tree_authority,
+        ctx.accounts.leaf_owner,
+        ctx.accounts.leaf_delegate,
+        ctx.accounts.new_leaf_owner,
+        ctx.accounts.merkle_tree,
+        ctx.accounts.log_wrapper,
+        ctx.accounts.compression_program,
+        ctx.accounts.system_program,
+    ];
+    account_infos.extend(ctx.remaining_accounts);
+    account_infos.push(ctx.program);
+    invoke_signed(&ix, &account_infos, ctx.signer_seeds)?;
+    Ok(())
+}
+
+// cNFTのTransferに共通で使うprogramのaccount
+pub struct CnftContextAccounts<'info> {
+    pub bubblegum_program: AccountInfo<'info>,
+    pub compression_program: AccountInfo<'info>,
+    pub log_wrapper: AccountInfo<'info>,
+    pub system_program: AccountInfo<'info>,
+}
+
+impl<'info> CnftContextAccounts<'info> {
+    // program_accountsは[bubblegum, account compression, noop, system]
+    pub fn new(program_accounts: &[AccountInfo<'info>]) -> Result<Self> {
+        let expected_program_ids = [
+            mpl_bubblegum::id(),
+            spl_account_compression::id(),
+            spl_noop::id(),
+            System::id(),
+        ];
+        for (account, expected_program_id) in program_accounts.iter().zip(expected_program_ids.iter()) {
+            assert_keys_equal(account.key, expected_program_id, MyError::CnftProgramMismatch)?;
+        }
+
+        Ok(CnftContextAccounts {
+            bubblegum_program: program_accounts[0].clone(),
+            compression_program: program_accounts[1].clone(),
+            log_wrapper: program_accounts[2].clone(),
+            system_program: program_accounts[3].clone(),
+        })
+    }
+
+    // item_accountsは[merkle tree, tree authority, proof...]
+    // leafがasset idのcNFTであることはmerkle treeとnonceから検証し、leafの中身とproofはBubblegumが検証する
+    pub fn into_transfer_cnft_context(
+        &self,
+        item_accounts: &[AccountInfo<'info>],
+        cnft_leaf: &CnftLeaf,
+        cnft_asset: &Pubkey,
+        leaf_owner: &AccountInfo<'info>,
+        new_leaf_owner: &AccountInfo<'info>,
+    ) -> Result<CpiContext<'_, '_, '_, 'info, TransferCnft<'info>>> {
+        let merkle_tree = &item_accounts[0];
+        assert_keys_equal(
+            &get_cnft_asset_id(merkle_tree.key, cnft_leaf.nonce),
+            cnft_asset,
+            MyError::CnftAssetMismatch,
+        )?;
+
+        let cpi_accounts = TransferCnft {
+            tree_authority: item_accounts[1].clone(),
+            leaf_owner: leaf_owner.clone(),
+            leaf_delegate: leaf_owner.clone(),
+            new_leaf_owner: new_leaf_owner.clone(),
+            merkle_tree: merkle_tree.clone(),
+            log_wrapper: self.log_wrapper.clone(),
+            compression_program: self.compression_program.clone(),
+            system_program: self.system_program.clone(),
+        };
+        Ok(CpiContext::new(self.bubblegum_program.clone(), cpi_accounts)
+            .with_remaining_accounts(item_accounts[CNFT_ACCOUNTS_PER_ITEM..].to_vec()))
+    }
+}
+
+// mpl coreのinstructionはborshのenumなので、TransferV1の番号から始まる
+pub const CORE_TRANSFER_V1_INSTRUCTION: u8 = 14;
+
+#[derive(Accounts)]
+pub struct TransferCore<'info> {
+    /// CHECK: mpl coreで検証する
+    pub asset: AccountInfo<'info>,
+    /// CHECK: collectionがない場合はmpl coreのprogram idを渡す
+    pub collection: AccountInfo<'info>,
+    /// CHECK: mpl coreで検証する
+    pub payer: AccountInfo<'info>,
+    /// CHECK: mpl coreで検証する
+    pub authority: AccountInfo<'info>,
+    /// CHECK: mpl coreで検証する
+    pub new_owner: AccountInfo<'info>,
+    /// CHECK: mpl coreで検証する
+    pub system_program: AccountInfo<'info>,
+}
+
+// TransferV1Args { compression_proof: None } freezeやroyaltiesのpluginはmpl coreがTransferの中で検証する
+pub fn transfer_core<'a, 'b, 'c, 'info>(
+    ctx: CpiContext<'a, 'b, 'c, 'info, TransferCore<'info>>,
+) -> Result<()> {
+    let data = vec![CORE_TRANSFER_V1_INSTRUCTION, 0];
+
+    let accounts = &ctx.accounts;
+    let ix = Instruction {
+        program_id: *ctx.program.key,
+        accounts: vec![
+            AccountMeta::new(*accounts.asset.key, false),
+            AccountMeta::new_readonly(*accounts.collection.key, false),
+            AccountMeta::new(*accounts.payer.key, true),
+            AccountMeta::new_readonly(*accounts.authority.key, true),
+            AccountMeta::new_readonly(*accounts.new_owner.key, false),
+            AccountMeta::new_readonly(*accounts.system_program.key, false),
+            // log wrapperは使わないのでmpl coreのprogram idを渡す
+            AccountMeta::new_readonly(*ctx.program.key, false),
+        ],
+        data,
+    };
+    invoke_signed(
+        &ix,
+        &[
+            ctx.accounts.asset,
+            ctx.accounts.collection,
+            ctx.accounts.payer,
+            ctx.accounts.authority,
+            ctx.accounts.new_owner,
+            ctx.accounts.system_program,
+            ctx.program,
+        ],
+        ctx.signer_seeds,
+    )?;
+    Ok(())
+}
+
+// Core assetのTransferに共通で使うaccount
+pub struct CoreContextAccounts<'info> {
+    pub payer: AccountInfo<'info>,
+    pub core_program: AccountInfo<'info>,
+    pub system_program: AccountInfo<'info>,
+}
+
+impl<'info> CoreContextAccounts<'info> {
+    // program_accountsは[mpl core, system]
+    pub fn new(payer: AccountInfo<'info>, program_accounts: &[AccountInfo<'info>]) -> Result<Self> {
+        assert_keys_equal(program_accounts[0].key, &mpl_core::id(), MyError::CoreProgramMismatch)?;
+        assert_keys_equal(program_accounts[1].key, &System::id(), MyError::CoreProgramMismatch)?;
+
+        Ok(CoreContextAccounts {
+            payer,
+            core_program: program_accounts[0].clone(),
+            system_program: program_accounts[1].clone(),
+        })
+    }
+
+    // item_accountsは[asset, collection] ownerがauthorityとして署名する
+    pub fn into_transfer_core_context(
+        &self,
+        item_accounts: &[AccountInfo<'info>],
+        core_asset: &Pubkey,
+        owner: &AccountInfo<'info>,
+        new_owner: &AccountInfo<'info>,
+    ) -> Result<CpiContext<'_, '_, '_, 'info, TransferCore<'info>>> {
+        let asset = &item_accounts[0];
+        let collection = &item_accounts[1];
+        assert_keys_equal(asset.key, core_asset, MyError::InvalidCoreAsset)?;
+        assert_keys_equal(
+            collection.key,
+            &assert_is_core_asset(asset, Some(owner.key))?,
+            MyError::CollectionPublicKeyMismatch,
+        )?;
+
+        let cpi_accounts = TransferCore {
+            asset: asset.clone(),
+            collection: collection.clone(),
+            payer: self.payer.clone(),
+            authority: owner.clone(),
+            new_owner: new_owner.clone(),
+            system_program: self.system_program.clone(),
+        };
+        Ok(CpiContext::new(self.core_program.clone(), cpi_accounts))
+    }
+}
+
+// royaltyを払うcreatorと金額
+pub struct RoyaltyPayment<'info> {
+    pub creator: AccountInfo<'info>,
+    pub amount: u64,
+}
+
+// royalty_accountsの先頭からmintごとに[metadata, verifiedのcreator...]を読み、読んだaccountの数も返す
+// sol_amountはSOLと逆向きに動くNFTの代金なので、mintの数で等分した価格にseller_fee_basis_pointsをかける
+// mintsにはToken MetadataのNFT(SFTとpNFTを含む)だけを渡す cNFTとCore assetはToken Metadataのmetadataがなくここではroyaltyを払えないので、
+// 等分する数にも含めずsol_amountは全部mintsのNFTの代金とみなす
+pub fn collect_royalties<'info>(
+    royalty_accounts: &[AccountInfo<'info>],
+    mints: &[Pubkey],
+    sol_amount: u64,
+) -> Result<(Vec<RoyaltyPayment<'info>>, usize)> {
+    let mut royalty_payments = Vec::new();
+    let mut offset = 0;
+    if sol_amount == 0 || mints.is_empty() {
+        return Ok((royalty_payments, offset));
+    }
+
+    let price = sol_amount / mints.len() as u64;
+    for mint in mints.iter() {
+        let metadata = royalty_accounts
+            .get(offset)
+            .ok_or(MyError::NotFoundRemainingAccounts)?;
+        offset += 1;
+
+        // metadataのないmintはroyaltyもない metadataのPDAを渡させるので、metadataを省いてroyaltyを逃れることはできない
+        assert_keys_equal(
+            &find_metadata_account(mint).0,
+            metadata.key,
+            MyError::MetadataPublicKeyMismatch,
+        )?;
+        if metadata.data_is_empty() {
+            continue;
+        }
+        let metadata_account = assert_is_metadata(metadata, mint)?;
+
+        let royalty = price as u128 * metadata_account.data.seller_fee_basis_points as u128 / 10000;
+        // verifiedされていないcreatorは誰でも名乗れるので払わない その分は売り手が受け取る
+        for creator in metadata_account
+            .data
+            .creators
+            .unwrap_or_default()
+            .iter()
+            .filter(|creator| creator.verified)
+        {
+            let creator_account = royalty_accounts
+                .get(offset)
+                .ok_or(MyError::NotFoundRemainingAccounts)?;
+            assert_keys_equal(
+                creator_account.key,
+                &creator.address,
+                MyError::CreatorPublicKeyMismatch,
+            )?;
+            offset += 1;
+
+            let amount = (royalty * creator.share as u128 / 100) as u64;
+            if amount > 0 {
+                royalty_payments.push(RoyaltyPayment {
+                    creator: creator_account.clone(),
+                    amount,
+                });
+            }
+        }
+    }
+
+    Ok((royalty_payments, offset))
+}
+
+// SOLの支払いからroyaltyと手数料とreferrerの取り分を差し引いた内訳 残りが売り手の受け取り分
+pub struct SolSplit<'info> {
+    pub receive_amount: u64,
+    pub royalty_payments: Vec<RoyaltyPayment<'info>>,
+    pub royalty_amount: u64,
+    pub fee_amount: u64,
+    pub referrer_amount: u64,
+}
+
+impl<'info> SolSplit<'info> {
+    // 手数料はconfig、referrerの取り分は売り手のescrowの設定で計算する
+    pub fn new(
+        sol_amount: u64,
+        royalty_payments: Vec<RoyaltyPayment<'info>>,
+        config: &Config,
+        escrow_account: &EscrowAccount,
+    ) -> Result<Self> {
+        let royalty_amount: u64 = royalty_payments.iter().map(|payment| payment.amount).sum();
+        let fee_amount = config.fee(sol_amount);
+        let referrer_amount = escrow_account.referrer_fee(sol_amount);
+        let receive_amount = sol_amount
+            .checked_sub(royalty_amount)
+            .and_then(|amount| amount.checked_sub(fee_amount))
+            .and_then(|amount| amount.checked_sub(referrer_amount))
+            .ok_or(MyError::FeeExceedsSolAmount)?;
+        Ok(Self {
+            receive_amount,
+            royalty_payments,
+            royalty_amount,
+            fee_amount,
+            referrer_amount,
+        })
+    }
+}
+
+pub struct SolSplitAccounts<'info> {
+    /// CHECK: This is not dangerous because we have already validated it in the account context
+    pub payer: AccountInfo<'info>, // takerのwalletかSOLを預かっているescrow
+    /// CHECK: This is not dangerous because we have already validated it in the account context
+    pub receiver: AccountInfo<'info>,
+    /// CHECK: This is not dangerous because we have already validated it in the account context
+    pub treasury: AccountInfo<'info>,
+    /// CHECK: This is not dangerous because we have already validated it in the account context
+    pub referrer: AccountInfo<'info>,
+}
+
+// payerがこのprogramのaccountならlamportを直接動かし、walletならsystem programで送る
+// 最難関： solanaのbugでlamportを直接動かすのはCPIの後にする必要がある
+pub fn pay_sol_split<'info>(
+    accounts: &SolSplitAccounts<'info>,
+    split: &SolSplit<'info>,
+) -> Result<()> {
+    let payments = [
+        (&accounts.receiver, split.receive_amount),
+        (&accounts.treasury, split.fee_amount),
+        (&accounts.referrer, split.referrer_amount),
+    ]
+    .into_iter()
+    .chain(
+        split
+            .royalty_payments
+            .iter()
+            .map(|payment| (&payment.creator, payment.amount)),
+    )
+    .filter(|(_, amount)| *amount > 0);
+
+    if *accounts.payer.owner == crate::ID {
+        for (to, amount) in payments {
+            **accounts.payer.try_borrow_mut_lamports()? -= amount;
+            **to.try_borrow_mut_lamports()? += amount;
+        }
+    } else {
+        for (to, amount) in payments {
+            invoke(
+                &system_instruction::transfer(accounts.payer.key, to.key, amount),
+                &[accounts.payer.clone(), to.clone()],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+pub struct DepositContextAccounts<'info> {
+    /// CHECK: This is not dangerous because we have already validated it in the account context
+    pub initializer: AccountInfo<'info>,
+    /// CHECK: This is not dangerous because we have already validated it in the account context
+    pub vault_authority: AccountInfo<'info>,
+    /// CHECK: This is not dangerous because we have already validated it in the account context
+    pub token_program: AccountInfo<'info>,
+    /// CHECK: This is not dangerous because we have already validated it in the account context
+    pub token_2022_program: AccountInfo<'info>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+impl<'info> TokenPrograms<'info> for DepositContextAccounts<'info> {
+    fn token_program(&self) -> &AccountInfo<'info> {
+        return &self.token_program;
+    }
+
+    fn token_2022_program(&self) -> &AccountInfo<'info> {
+        return &self.token_2022_program;
+    }
+}
+
+impl<'info> Deposit<'info> for DepositContextAccounts<'info> {
+    fn initializer(&self) -> &AccountInfo<'info> {
+        return &self.initializer;
+    }
+}
+
+pub trait Deposit<'info>: TokenPrograms<'info> {
+    fn initializer(&self) -> &AccountInfo<'info>;
+
+    fn into_transfer_to_pda_context(
+        &self,
+        initializer_nft_token_account: &AccountInfo<'info>,
+        vault_account: &AccountInfo<'info>,
+        mint_account: &AccountInfo<'info>,
+    ) -> CpiContext<'_, '_, '_, 'info, TransferChecked<'info>> {
+        let cpi_accounts = TransferChecked {
+            from: initializer_nft_token_account.clone(),
+            mint: mint_account.clone(),
+            to: vault_account.clone(),
+            authority: self.initializer().clone(),
+        };
+        CpiContext::new(self.token_program_for(mint_account), cpi_accounts)
     }
 }
@@ -1,22 +1,33 @@
 use crate::errors::MyError;
+use crate::state::{FUNGIBLE_VAULT_ACCOUNT_PDA_SEED, MAX_FEE_BASIS_POINTS, VAULT_ACCOUNT_PDA_SEED};
+use crate::traits::{mpl_bubblegum, mpl_core};
 use {
     anchor_lang::{
         prelude::*,
         solana_program::program_pack::{IsInitialized, Pack},
     },
-    spl_associated_token_account::get_associated_token_address,
+    mpl_token_metadata::{
+        pda::find_metadata_account,
+        state::{Metadata, TokenMetadataAccount},
+    },
+    spl_token_2022::{
+        extension::StateWithExtensions,
+        state::{Account, Mint},
+    },
 };
 
 pub fn assert_is_ata<'a>(
     ata: &AccountInfo,
     wallet: &Pubkey,
     mint: &AccountInfo,
-    has_nft: bool,
-) -> Result<spl_token::state::Account> {
+    amount: u64,
+) -> Result<Account> {
     // AccountInfoのownerは、Program that owns this account　https://docs.rs/solana-program/1.5.0/solana_program/account_info/struct.AccountInfo.html
-    assert_owned_by(ata, &spl_token::id())?;
+    // itemごとにspl_tokenかToken-2022のどちらか mintと同じprogramであること
+    let token_program = assert_owned_by_token_program(ata)?;
+    assert_owned_by(mint, &token_program)?;
 
-    let ata_account: spl_token::state::Account = assert_initialized(ata)?;
+    let ata_account = unpack_token_account(ata)?;
 
     // Accountのownerは、The owner of this account. https://docs.rs/spl-token/latest/spl_token/state/struct.Account.html
     assert_keys_equal(
@@ -26,31 +37,30 @@ pub fn assert_is_ata<'a>(
     )?;
 
     assert_keys_equal(
-        &get_associated_token_address(wallet, mint.key),
+        &get_associated_token_address_with_program_id(wallet, mint.key, &token_program),
         ata.key,
         MyError::AssociatedTokenPublicKeyMismatch,
     )?;
+    assert_keys_equal(&ata_account.mint, mint.key, MyError::MintPublicKeyMismatch)?;
 
-    // NFTをちゃんと持っていることの検証
-    if has_nft {
-        require_eq!(ata_account.amount, 1, MyError::NotFoundNft);
-    } else {
-        require_eq!(ata_account.amount, 0, MyError::NotFoundNft);
-    }
+    // SFTやfungible tokenは他にも持っている場合があるので、必要な量以上を持っていることだけを検証 受け取る側は0を渡す
+    require_gte!(ata_account.amount, amount, MyError::NotFoundNft);
 
     Ok(ata_account)
 }
 
 pub fn assert_is_pda<'a>(
     token_account_info: &AccountInfo,
+    escrow_key: &Pubkey,
     bump: u8,
     vault_account_info: &AccountInfo,
     vault_authority: &Pubkey,
     program_id: &'a Pubkey,
-) -> Result<spl_token::state::Account> {
-    assert_owned_by(vault_account_info, &spl_token::id())?;
+    amount: u64,
+) -> Result<Account> {
+    assert_owned_by_token_program(vault_account_info)?;
 
-    let vault_account: spl_token::state::Account = assert_initialized(vault_account_info)?;
+    let vault_account = unpack_token_account(vault_account_info)?;
 
     assert_keys_equal(
         &vault_account.owner,
@@ -59,7 +69,12 @@ pub fn assert_is_pda<'a>(
     )?;
 
     let vault_pda = Pubkey::create_program_address(
-        &[b"vault-account", token_account_info.key().as_ref(), &[bump]],
+        &[
+            VAULT_ACCOUNT_PDA_SEED,
+            escrow_key.as_ref(),
+            token_account_info.key().as_ref(),
+            &[bump],
+        ],
         program_id,
     )
     .unwrap();
@@ -70,8 +85,8 @@ pub fn assert_is_pda<'a>(
         MyError::AssociatedTokenPublicKeyMismatch,
     )?;
 
-    // NFTをちゃんと持っていることの検証
-    let token_account: spl_token::state::Account = assert_initialized(token_account_info)?;
+    // 預けた量がvaultにあることの検証
+    let token_account = unpack_token_account(token_account_info)?;
 
     assert_keys_equal(
         &token_account.mint,
@@ -83,12 +98,267 @@ pub fn assert_is_pda<'a>(
         vault_authority,
         MyError::AssociatedAuthorityMismatch,
     )?;
-    require_eq!(token_account.amount, 0, MyError::NotFoundNft);
-    require_eq!(vault_account.amount, 1, MyError::NotFoundNft);
+    require_gte!(vault_account.amount, amount, MyError::NotFoundNft);
+
+    Ok(vault_account)
+}
+
+pub fn assert_is_fungible_vault<'a>(
+    vault_account_info: &AccountInfo,
+    escrow_key: &Pubkey,
+    mint: &AccountInfo,
+    bump: u8,
+    vault_authority: &Pubkey,
+    program_id: &'a Pubkey,
+) -> Result<Account> {
+    assert_owned_by_token_program(vault_account_info)?;
+
+    let vault_account = unpack_token_account(vault_account_info)?;
+
+    assert_keys_equal(
+        &vault_account.owner,
+        vault_authority,
+        MyError::AssociatedAuthorityMismatch,
+    )?;
+
+    let vault_pda = Pubkey::create_program_address(
+        &[
+            FUNGIBLE_VAULT_ACCOUNT_PDA_SEED,
+            escrow_key.as_ref(),
+            mint.key.as_ref(),
+            &[bump],
+        ],
+        program_id,
+    )
+    .map_err(|_| MyError::PdaPublicKeyMismatch)?;
+
+    assert_keys_equal(
+        &vault_pda,
+        vault_account_info.key,
+        MyError::PdaPublicKeyMismatch,
+    )?;
 
     Ok(vault_account)
 }
 
+// SFTはsupplyが1より大きいので、decimalsが0で要求する量以上のsupplyがあるかを検証
+pub fn assert_is_sft_mint(mint: &AccountInfo, amount: u64) -> Result<Mint> {
+    assert_owned_by_token_program(mint)?;
+
+    let mint_account = unpack_mint(mint)?;
+
+    require_eq!(mint_account.decimals, 0, MyError::NotNftMint);
+    require_gte!(mint_account.supply, amount, MyError::NotNftMint);
+    assert_supported_nft_extensions(mint)?;
+
+    Ok(mint_account)
+}
+
+pub fn assert_is_nft_mint(mint: &AccountInfo) -> Result<Mint> {
+    assert_owned_by_token_program(mint)?;
+
+    let mint_account = unpack_mint(mint)?;
+
+    // supplyが1でdecimalsが0のものだけをNFTとみなす
+    require_eq!(mint_account.decimals, 0, MyError::NotNftMint);
+    require_eq!(mint_account.supply, 1, MyError::NotNftMint);
+    assert_supported_nft_extensions(mint)?;
+
+    Ok(mint_account)
+}
+
+// spl-token-2022 0.2.0のExtensionTypeはMemoTransferまでしか知らず、get_extensionは知らないtypeがあるとエラーになるので、TLVを直接読む
+pub const TRANSFER_FEE_CONFIG_EXTENSION: u16 = 1;
+pub const NON_TRANSFERABLE_EXTENSION: u16 = 9;
+pub const PERMANENT_DELEGATE_EXTENSION: u16 = 12;
+pub const TRANSFER_HOOK_EXTENSION: u16 = 14;
+pub const EXTRA_ACCOUNT_METAS_SEED: &[u8] = b"extra-account-metas";
+
+// Token-2022のmintはAccount::LENまでpaddingされ、account typeの1byteの後にtype(u16), length(u16), valueが並ぶ
+pub fn get_mint_extension_data(data: &[u8], extension_type: u16) -> Option<&[u8]> {
+    let mut index = Account::LEN + 1;
+    while index + 4 <= data.len() {
+        let current_type = u16::from_le_bytes([data[index], data[index + 1]]);
+        let length = u16::from_le_bytes([data[index + 2], data[index + 3]]) as usize;
+        let value_start = index + 4;
+        // 0はUninitialized ここから先にextensionはない
+        if current_type == 0 || value_start + length > data.len() {
+            return None;
+        }
+        if current_type == extension_type {
+            return Some(&data[value_start..value_start + length]);
+        }
+        index = value_start + length;
+    }
+    None
+}
+
+// 移転できないmintはexchangeできず、permanent delegateはvaultからでも引き出せるのでescrowできない
+pub fn assert_supported_mint_extensions(mint: &AccountInfo) -> Result<()> {
+    if *mint.owner != spl_token_2022::id() {
+        return Ok(());
+    }
+    let data = mint.data.borrow();
+    if get_mint_extension_data(&data, NON_TRANSFERABLE_EXTENSION).is_some() {
+        return err!(MyError::NonTransferableMint);
+    }
+    // delegateが全て0ならnone
+    if let Some(delegate) = get_mint_extension_data(&data, PERMANENT_DELEGATE_EXTENSION) {
+        if delegate.iter().any(|byte| *byte != 0) {
+            return err!(MyError::PermanentDelegateMint);
+        }
+    }
+    Ok(())
+}
+
+// NFTとSFTは預けた量がそのまま届かないと困るので、transfer feeのあるmintも受け付けない
+pub fn assert_supported_nft_extensions(mint: &AccountInfo) -> Result<()> {
+    assert_supported_mint_extensions(mint)?;
+    if has_transfer_fee(mint) {
+        return err!(MyError::UnsupportedTransferFee);
+    }
+    Ok(())
+}
+
+pub fn has_transfer_fee(mint: &AccountInfo) -> bool {
+    *mint.owner == spl_token_2022::id()
+        && get_mint_extension_data(&mint.data.borrow(), TRANSFER_FEE_CONFIG_EXTENSION).is_some()
+}
+
+// transfer feeのあるmintで受け取る側にamountが届くよう、現在のepochのfeeから逆算して送る量を返す
+// feeのないmintはamountをそのまま返す
+pub fn transfer_amount_with_fee(mint: &AccountInfo, amount: u64) -> Result<u64> {
+    if *mint.owner != spl_token_2022::id() {
+        return Ok(amount);
+    }
+    let data = mint.data.borrow();
+    // authority(32byte), withdraw authority(32byte), withheld amount(8byte)の後に
+    // older, newerのfeeがepoch(u64), maximum fee(u64), basis points(u16)の順で並ぶ
+    let config = match get_mint_extension_data(&data, TRANSFER_FEE_CONFIG_EXTENSION) {
+        Some(value) if value.len() >= 108 => value,
+        _ => return Ok(amount),
+    };
+    let newer_epoch = u64::from_le_bytes(config[90..98].try_into().unwrap());
+    let transfer_fee = if Clock::get()?.epoch >= newer_epoch {
+        &config[90..108]
+    } else {
+        &config[72..90]
+    };
+    let maximum_fee = u64::from_le_bytes(transfer_fee[8..16].try_into().unwrap());
+    let basis_points = u16::from_le_bytes(transfer_fee[16..18].try_into().unwrap()) as u128;
+
+    // Token-2022はfeeを切り上げるので、逆算も切り上げる
+    let fee = if basis_points == 0 {
+        0
+    } else if basis_points >= MAX_FEE_BASIS_POINTS as u128 {
+        maximum_fee
+    } else {
+        let denominator = MAX_FEE_BASIS_POINTS as u128 - basis_points;
+        let gross = (amount as u128 * MAX_FEE_BASIS_POINTS as u128).div_ceil(denominator);
+        u64::try_from(gross - amount as u128)
+            .unwrap_or(u64::MAX)
+            .min(maximum_fee)
+    };
+    Ok(amount
+        .checked_add(fee)
+        .ok_or(MyError::InsufficientTokenBalance)?)
+}
+
+// transfer hookのあるmintは、hook programとextra account metasのPDAがtransferのremaining accountsにあることを検証
+// extra accountsの中身はToken-2022から呼ばれるhook program側で検証される
+pub fn assert_transfer_hook_accounts(
+    mint: &AccountInfo,
+    transfer_hook_accounts: &[AccountInfo],
+) -> Result<()> {
+    if *mint.owner != spl_token_2022::id() {
+        return Ok(());
+    }
+    // authority(32byte)の後にprogram id(32byte) 全て0ならhookなし
+    let program_id = match get_mint_extension_data(&mint.data.borrow(), TRANSFER_HOOK_EXTENSION) {
+        Some(value) if value.len() >= 64 && value[32..64].iter().any(|byte| *byte != 0) => {
+            Pubkey::new(&value[32..64])
+        }
+        _ => return Ok(()),
+    };
+    let (extra_account_metas, _) = Pubkey::find_program_address(
+        &[EXTRA_ACCOUNT_METAS_SEED, mint.key.as_ref()],
+        &program_id,
+    );
+    for key in [program_id, extra_account_metas] {
+        if !transfer_hook_accounts.iter().any(|account| *account.key == key) {
+            return err!(MyError::TransferHookAccountsNotFound);
+        }
+    }
+    Ok(())
+}
+
+pub fn assert_is_metadata(metadata: &AccountInfo, mint: &Pubkey) -> Result<Metadata> {
+    // metadataはToken Metadata Programが持っているmintのPDAであること
+    assert_owned_by(metadata, &mpl_token_metadata::id())?;
+    let (metadata_pda, _) = find_metadata_account(mint);
+    assert_keys_equal(
+        &metadata_pda,
+        metadata.key,
+        MyError::MetadataPublicKeyMismatch,
+    )?;
+
+    let metadata_account = Metadata::from_account_info(metadata)?;
+    assert_keys_equal(
+        &metadata_account.mint,
+        mint,
+        MyError::MintPublicKeyMismatch,
+    )?;
+
+    Ok(metadata_account)
+}
+
+pub fn assert_is_collection_nft(
+    mint: &AccountInfo,
+    metadata: &AccountInfo,
+    collection_key: &Pubkey,
+) -> Result<Metadata> {
+    assert_is_nft_mint(mint)?;
+    let metadata_account = assert_is_metadata(metadata, mint.key)?;
+
+    // verifiedされていないcollectionは誰でも名乗れるので受け付けない
+    match &metadata_account.collection {
+        Some(collection) if collection.verified => {
+            assert_keys_equal(
+                &collection.key,
+                collection_key,
+                MyError::CollectionPublicKeyMismatch,
+            )?;
+        }
+        _ => return err!(MyError::UnverifiedCollection),
+    }
+
+    Ok(metadata_account)
+}
+
+// Core assetのBaseAssetV1は[key, owner, update authority, ...] update authorityがcollectionの場合はcollectionのpluginも検証されるので、collectionを渡す必要がある
+pub const CORE_ASSET_V1_KEY: u8 = 1;
+pub const CORE_UPDATE_AUTHORITY_COLLECTION: u8 = 2;
+
+// open offerのtakerのassetはownerが未定なのでNoneを渡す TransferV1に渡すcollectionを返す
+pub fn assert_is_core_asset(asset: &AccountInfo, owner: Option<&Pubkey>) -> Result<Pubkey> {
+    assert_owned_by(asset, &mpl_core::id())?;
+
+    let data = asset.try_borrow_data()?;
+    require!(
+        data.len() >= 66 && data[0] == CORE_ASSET_V1_KEY,
+        MyError::InvalidCoreAsset
+    );
+    if let Some(owner) = owner {
+        assert_keys_equal(&Pubkey::new(&data[1..33]), owner, MyError::InvalidCoreAsset)?;
+    }
+
+    // collectionに属さないassetにはmpl coreのprogram idを渡す
+    if data[33] == CORE_UPDATE_AUTHORITY_COLLECTION {
+        return Ok(Pubkey::new(&data[34..66]));
+    }
+    Ok(mpl_core::id())
+}
+
 pub fn assert_initialized<T: Pack + IsInitialized>(account_info: &AccountInfo) -> Result<T> {
     let account: T = T::unpack_unchecked(&account_info.data.borrow())?;
     if !account.is_initialized() {
@@ -97,6 +367,61 @@ pub fn assert_initialized<T: Pack + IsInitialized>(account_info: &AccountInfo) -
     Ok(account)
 }
 
+// Token-2022のaccountはextensionの分だけdataが長いので、Pack::unpackではなくbaseだけを読む
+pub fn unpack_token_account(account_info: &AccountInfo) -> Result<Account> {
+    let data = account_info.data.borrow();
+    let account = StateWithExtensions::<Account>::unpack(&data)
+        .map_err(|_| MyError::UninitializedAssociatedToken)?;
+    Ok(account.base)
+}
+
+pub fn unpack_mint(account_info: &AccountInfo) -> Result<Mint> {
+    let data = account_info.data.borrow();
+    let mint = StateWithExtensions::<Mint>::unpack(&data)
+        .map_err(|_| MyError::UninitializedAssociatedToken)?;
+    Ok(mint.base)
+}
+
+// spl_tokenかToken-2022のどちらかが持っていることを検証して、そのprogramを返す
+pub fn assert_owned_by_token_program(account_info: &AccountInfo) -> Result<Pubkey> {
+    if *account_info.owner != spl_token::id() && *account_info.owner != spl_token_2022::id() {
+        return err!(MyError::IncorrectAccountInfoOwner);
+    }
+    Ok(*account_info.owner)
+}
+
+// spl-associated-token-account 1.0.5のget_associated_token_addressはspl_token固定なので、token programをseedsに入れて導出する
+pub fn get_associated_token_address_with_program_id(
+    wallet: &Pubkey,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+) -> Pubkey {
+    Pubkey::find_program_address(
+        &[wallet.as_ref(), token_program.as_ref(), mint.as_ref()],
+        &spl_associated_token_account::id(),
+    )
+    .0
+}
+
+// cNFTのasset idはBubblegumのPDA merkle treeとleafのnonceで決まる
+pub fn get_cnft_asset_id(merkle_tree: &Pubkey, nonce: u64) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"asset", merkle_tree.as_ref(), nonce.to_le_bytes().as_ref()],
+        &mpl_bubblegum::id(),
+    )
+    .0
+}
+
+// 他のbidderのhashを写しても同じ額でrevealできないようにbidderも含める
+pub fn get_sealed_bid_commitment(amount: u64, salt: &[u8; 32], bidder: &Pubkey) -> [u8; 32] {
+    anchor_lang::solana_program::hash::hashv(&[
+        amount.to_le_bytes().as_ref(),
+        salt.as_ref(),
+        bidder.as_ref(),
+    ])
+    .to_bytes()
+}
+
 pub fn assert_owned_by(account_info: &AccountInfo, owner: &Pubkey) -> Result<()> {
     require_keys_eq!(
         *account_info.owner,
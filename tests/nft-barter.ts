@@ -4,13 +4,50 @@ import { NftBarter } from "../target/types/nft_barter";
 import { PublicKey, SystemProgram, Transaction } from "@solana/web3.js";
 import {
   TOKEN_PROGRAM_ID,
+  TOKEN_2022_PROGRAM_ID,
   createMint,
   mintTo,
   getOrCreateAssociatedTokenAccount,
   getAccount,
   Account,
+  ExtensionType,
+  getMintLen,
+  createInitializeTransferFeeConfigInstruction,
+  createInitializeMintInstruction,
+  getAssociatedTokenAddress,
+  ASSOCIATED_TOKEN_PROGRAM_ID,
+  transfer,
 } from "@solana/spl-token";
+import {
+  TokenStandard,
+  createCreateInstruction,
+  createCreateMasterEditionV3Instruction,
+  createCreateMetadataAccountV3Instruction,
+  createMintInstruction,
+  createSignMetadataInstruction,
+  createVerifyCollectionInstruction,
+} from "@metaplex-foundation/mpl-token-metadata";
+import {
+  PROGRAM_ID as BUBBLEGUM_PROGRAM_ID,
+  MetadataArgs,
+  TokenProgramVersion,
+  TokenStandard as BubblegumTokenStandard,
+  computeCompressedNFTHash,
+  computeCreatorHash,
+  computeDataHash,
+  createCreateTreeInstruction,
+  createMintV1Instruction,
+  getLeafAssetId,
+} from "@metaplex-foundation/mpl-bubblegum";
+import {
+  ConcurrentMerkleTreeAccount,
+  SPL_ACCOUNT_COMPRESSION_PROGRAM_ID,
+  SPL_NOOP_PROGRAM_ID,
+  createAllocTreeIx,
+  createVerifyLeafIx,
+} from "@solana/spl-account-compression";
 import { assert } from "chai";
+import { createHash } from "crypto";
 
 describe("anchor-escrow", () => {
   // Configure the client to use the local cluster.
@@ -65,12 +102,271 @@ describe("anchor-escrow", () => {
   const initializerAdditionalSolAmount = 500_000_000; // lamport
   const takerAdditionalSolAmount = 1_000_000_000; // lamport
 
-  const escrowAccount: anchor.web3.Keypair = anchor.web3.Keypair.generate();
+  const TOKEN_METADATA_PROGRAM_ID = new PublicKey(
+    "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s"
+  );
+  // exchangeでSOLが動く場合はroyaltyのためにmetadataのPDAを渡す metadataがなければroyaltyもない
+  const getMetadataPda = async (mint: PublicKey) =>
+    (
+      await PublicKey.findProgramAddress(
+        [
+          Buffer.from("metadata"),
+          TOKEN_METADATA_PROGRAM_ID.toBuffer(),
+          mint.toBuffer(),
+        ],
+        TOKEN_METADATA_PROGRAM_ID
+      )
+    )[0];
+
+  // rentalのfreezeはmaster editionがmintのfreeze authorityであることが必要
+  const getMasterEditionPda = async (mint: PublicKey) =>
+    (
+      await PublicKey.findProgramAddress(
+        [
+          Buffer.from("metadata"),
+          TOKEN_METADATA_PROGRAM_ID.toBuffer(),
+          mint.toBuffer(),
+          Buffer.from("edition"),
+        ],
+        TOKEN_METADATA_PROGRAM_ID
+      )
+    )[0];
+
+  // pNFTはtoken accountごとにtoken recordを持つ
+  const getTokenRecordPda = async (mint: PublicKey, tokenAccount: PublicKey) =>
+    (
+      await PublicKey.findProgramAddress(
+        [
+          Buffer.from("metadata"),
+          TOKEN_METADATA_PROGRAM_ID.toBuffer(),
+          mint.toBuffer(),
+          Buffer.from("token_record"),
+          tokenAccount.toBuffer(),
+        ],
+        TOKEN_METADATA_PROGRAM_ID
+      )
+    )[0];
+
+  // royaltyのテスト用にcreatorつきのmetadataを作る
+  // update authorityのmintAuthorityは作成時に、signerを渡したcreatorは自分の署名でverifiedになる
+  const createMetadata = async (
+    mint: PublicKey,
+    sellerFeeBasisPoints: number,
+    creators: {
+      address: PublicKey;
+      share: number;
+      signer?: anchor.web3.Keypair;
+    }[],
+    collection: PublicKey | null = null // verifyはcollectionのupdate authorityが別に行う
+  ) => {
+    const metadata = await getMetadataPda(mint);
+    const tx = new Transaction().add(
+      createCreateMetadataAccountV3Instruction(
+        {
+          metadata,
+          mint,
+          mintAuthority: mintAuthority.publicKey,
+          payer: payer.publicKey,
+          updateAuthority: mintAuthority.publicKey,
+        },
+        {
+          createMetadataAccountArgsV3: {
+            data: {
+              name: "Royalty",
+              symbol: "",
+              uri: "",
+              sellerFeeBasisPoints,
+              creators: creators.map(({ address, share }) => ({
+                address,
+                share,
+                verified: address.equals(mintAuthority.publicKey),
+              })),
+              collection: collection && { key: collection, verified: false },
+              uses: null,
+            },
+            isMutable: true,
+            collectionDetails: null,
+          },
+        }
+      )
+    );
+    const signers = creators
+      .filter(({ signer }) => signer)
+      .map(({ signer }) => signer);
+    for (const signer of signers) {
+      tx.add(
+        createSignMetadataInstruction({ metadata, creator: signer.publicKey })
+      );
+    }
+    await provider.send(tx, [payer, mintAuthority, ...signers]);
+    return metadata;
+  };
+
+  const createMasterEdition = async (mint: PublicKey, metadata: PublicKey) => {
+    const edition = await getMasterEditionPda(mint);
+    await provider.send(
+      new Transaction().add(
+        createCreateMasterEditionV3Instruction(
+          {
+            edition,
+            mint,
+            updateAuthority: mintAuthority.publicKey,
+            mintAuthority: mintAuthority.publicKey,
+            payer: payer.publicKey,
+            metadata,
+          },
+          { createMasterEditionArgs: { maxSupply: 0 } }
+        )
+      ),
+      [payer, mintAuthority]
+    );
+    return edition;
+  };
+
+  // collection NFTはmaster editionつきのNFT
+  type CollectionNft = {
+    mint: PublicKey;
+    metadata: PublicKey;
+    edition: PublicKey;
+  };
+  const createCollectionNft = async (): Promise<CollectionNft> => {
+    const mint = await createMint(
+      provider.connection,
+      payer,
+      mintAuthority.publicKey,
+      mintAuthority.publicKey,
+      0
+    );
+    const tokenAccount = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      payer,
+      mint,
+      payer.publicKey
+    );
+    await mintTo(
+      provider.connection,
+      payer,
+      mint,
+      tokenAccount.address,
+      mintAuthority,
+      1
+    );
+    const metadata = await createMetadata(mint, 0, [
+      { address: mintAuthority.publicKey, share: 100 },
+    ]);
+    const edition = await createMasterEdition(mint, metadata);
+    return { mint, metadata, edition };
+  };
+
+  // verifyしていないNFTは誰でもcollectionを名乗れる
+  const createCollectionMember = async (
+    collection: CollectionNft,
+    owner: PublicKey,
+    verified = true
+  ) => {
+    const mint = await createMint(
+      provider.connection,
+      payer,
+      mintAuthority.publicKey,
+      null,
+      0
+    );
+    const tokenAccount = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      payer,
+      mint,
+      owner
+    );
+    await mintTo(
+      provider.connection,
+      payer,
+      mint,
+      tokenAccount.address,
+      mintAuthority,
+      1
+    );
+    const metadata = await createMetadata(
+      mint,
+      0,
+      [{ address: mintAuthority.publicKey, share: 100 }],
+      collection.mint
+    );
+    if (verified) {
+      await provider.send(
+        new Transaction().add(
+          createVerifyCollectionInstruction({
+            metadata,
+            collectionAuthority: mintAuthority.publicKey,
+            payer: payer.publicKey,
+            collectionMint: collection.mint,
+            collection: collection.metadata,
+            collectionMasterEditionAccount: collection.edition,
+          })
+        ),
+        [payer, mintAuthority]
+      );
+    }
+    return { mint, metadata, tokenAccount: tokenAccount.address };
+  };
+
+  // 固定価格のofferはend_atを0にする
+  const noDutchPricing = {
+    endSolAmount: new anchor.BN(0),
+    startAt: new anchor.BN(0),
+    endAt: new anchor.BN(0),
+  };
+
+  // validatorの時刻がtimestampを過ぎるまで待つ
+  const waitUntil = async (timestamp: number) => {
+    while (
+      (await provider.connection.getBlockTime(
+        await provider.connection.getSlot()
+      )) <= timestamp
+    ) {
+      await new Promise((resolve) => setTimeout(resolve, 1000));
+    }
+  };
+
+  let escrowAccountPda: anchor.web3.PublicKey = null;
+  let escrowNonce = 0; // 同じinitializerとtakerで複数のescrowを持てるようにescrowごとにnonceを変える
   const payer: anchor.web3.Keypair = anchor.web3.Keypair.generate();
   const mintAuthority: anchor.web3.Keypair = anchor.web3.Keypair.generate();
   const initializerMainAccount: anchor.web3.Keypair =
     anchor.web3.Keypair.generate();
   const takerMainAccount: anchor.web3.Keypair = anchor.web3.Keypair.generate();
+  const treasury: anchor.web3.Keypair = anchor.web3.Keypair.generate();
+  let configPda: anchor.web3.PublicKey = null;
+
+  // initializeの引数 指定しないものは何も出さず何も要求しない nonceは今のescrowNonce
+  const initializeArgs = (
+    overrides: Partial<Parameters<typeof program.rpc.initialize>[0]>
+  ) => ({
+    initializerAdditionalSolAmount: new anchor.BN(0),
+    takerAdditionalSolAmount: new anchor.BN(0),
+    initializerNftAmount: 0,
+    takerNftAmount: 0,
+    vaultAccountBumps: Buffer.from([]),
+    takerCollectionKey: PublicKey.default, // collectionを指定しない
+    takerCollectionNftAmount: 0,
+    nonce: new anchor.BN(escrowNonce),
+    expiresAt: new anchor.BN(0), // 期限なし
+    initializerFungibleAmounts: [], // fungible tokenなし
+    fungibleVaultAccountBumps: Buffer.from([]),
+    takerFungibleAmounts: [],
+    initializerNftQuantities: [],
+    takerNftQuantities: [],
+    initializerPnftAmount: 0, // pNFTなし
+    takerPnftAmount: 0,
+    initializerCnftLeaves: [], // cNFTなし
+    takerCnftAssets: [],
+    initializerCoreAmount: 0, // Core assetなし
+    takerCoreAmount: 0,
+    referrerKey: PublicKey.default, // referrerなし
+    referrerBasisPoints: 0,
+    takerDutchPricing: noDutchPricing,
+    keeperTipBasisPoints: 0, // keeperへのtipなし
+    ...overrides,
+  });
 
   it("Initialize program state", async () => {
     console.log("start airdrop");
@@ -95,6 +391,12 @@ describe("anchor-escrow", () => {
             fromPubkey: payer.publicKey,
             toPubkey: takerMainAccount.publicKey,
             lamports: takerStartSolAmount,
+          }),
+          // 手数料を受け取る前にrent exemptにしておく
+          SystemProgram.transfer({
+            fromPubkey: payer.publicKey,
+            toPubkey: treasury.publicKey,
+            lamports: 100_000_000,
           })
         );
         return tx;
@@ -346,13 +648,56 @@ describe("anchor-escrow", () => {
     assert.ok(Number(_takerTokenAccountE.amount) === 1);
   });
 
+  it("Initialize config", async () => {
+    const [_configPda] = await PublicKey.findProgramAddress(
+      [Buffer.from(anchor.utils.bytes.utf8.encode("config"))],
+      program.programId
+    );
+    configPda = _configPda;
+
+    // upgrade authorityであるprovider walletが最初のadminになる
+    const [programDataPda] = await PublicKey.findProgramAddress(
+      [program.programId.toBuffer()],
+      new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+    );
+
+    // 既存のテストの残高が変わらないように手数料は0にしておく
+    await program.rpc.initializeConfig(0, new anchor.BN(0), treasury.publicKey, {
+      accounts: {
+        admin: provider.wallet.publicKey,
+        config: configPda,
+        program: program.programId,
+        programData: programDataPda,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      },
+    });
+
+    const config = await program.account.config.fetch(configPda);
+    assert.ok(config.adminKey.equals(provider.wallet.publicKey));
+    assert.ok(config.treasuryKey.equals(treasury.publicKey));
+    assert.equal(config.feeBasisPoints, 0);
+  });
+
   it("Initialize escrow", async () => {
     console.log("start creating PDAs");
 
+    escrowNonce += 1;
+    const [_escrowAccountPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("escrow"),
+        initializerMainAccount.publicKey.toBuffer(),
+        takerMainAccount.publicKey.toBuffer(),
+        new anchor.BN(escrowNonce).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    escrowAccountPda = _escrowAccountPda;
+
     const [_vaultAccountPdaA, _vaultAccountBumpA] =
       await PublicKey.findProgramAddress(
         [
           Buffer.from(anchor.utils.bytes.utf8.encode("vault-account")),
+          escrowAccountPda.toBuffer(),
           initializerTokenAccountA.address.toBuffer(),
         ],
         program.programId
@@ -367,6 +712,7 @@ describe("anchor-escrow", () => {
       await PublicKey.findProgramAddress(
         [
           Buffer.from(anchor.utils.bytes.utf8.encode("vault-account")),
+          escrowAccountPda.toBuffer(),
           initializerTokenAccountB.address.toBuffer(),
         ],
         program.programId
@@ -384,8 +730,7 @@ describe("anchor-escrow", () => {
       await PublicKey.findProgramAddress(
         [
           anchor.utils.bytes.utf8.encode("vault-authority"),
-          initializerMainAccount.publicKey.toBuffer(),
-          takerMainAccount.publicKey.toBuffer(),
+          escrowAccountPda.toBuffer(),
         ], // sampleコードではBufferが書いてあったがBufferはいらないと思われる anchor bookにはない
         program.programId
       );
@@ -462,26 +807,32 @@ describe("anchor-escrow", () => {
     });
 
     await program.rpc.initialize(
-      new anchor.BN(initializerAdditionalSolAmount),
-      new anchor.BN(takerAdditionalSolAmount),
-      initializerNftAmount,
-      takerNftAmount,
-      Buffer.from(vaultAccountBumps), // 難関　Buffer.fromしないとTypeError: Blob.encode[data] requires (length 2) Buffer as src
+      initializeArgs({
+        initializerAdditionalSolAmount: new anchor.BN(
+          initializerAdditionalSolAmount
+        ),
+        takerAdditionalSolAmount: new anchor.BN(takerAdditionalSolAmount),
+        initializerNftAmount,
+        takerNftAmount,
+        vaultAccountBumps: Buffer.from(vaultAccountBumps), // 難関　Buffer.fromしないとTypeError: Blob.encode[data] requires (length 2) Buffer as src
+        initializerNftQuantities: Array(initializerNftAmount).fill(
+          new anchor.BN(1)
+        ), // NFTなので1
+        takerNftQuantities: Array(takerNftAmount).fill(new anchor.BN(1)),
+      }),
       {
         accounts: {
           initializer: initializerMainAccount.publicKey,
           taker: takerMainAccount.publicKey,
-          escrowAccount: escrowAccount.publicKey,
+          escrowAccount: escrowAccountPda,
           systemProgram: anchor.web3.SystemProgram.programId,
           rent: anchor.web3.SYSVAR_RENT_PUBKEY,
           tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
           vaultAuthority: vaultAuthorityPda,
         },
         remainingAccounts,
-        signers: [
-          escrowAccount, // rust側でinitするために必要　escrowAccount抜かすとError: Signature verification failed
-          initializerMainAccount,
-        ],
+        signers: [initializerMainAccount], // escrowAccountはPDAなので署名は不要
       }
     );
 
@@ -577,7 +928,7 @@ describe("anchor-escrow", () => {
 
     // Check the escrowAccount
     let _escrowAccount = await program.account.escrowAccount.fetch(
-      escrowAccount.publicKey
+      escrowAccountPda
     );
 
     assert.ok(
@@ -653,7 +1004,6 @@ describe("anchor-escrow", () => {
     );
 
     // TODO: 連続してinitializeしたときのテスト
-    // TODO: 同じ組み合わせのinitializer, takerで二重に取引できない
   });
 
   it("Exchange escrow state", async () => {
@@ -664,8 +1014,7 @@ describe("anchor-escrow", () => {
       await PublicKey.findProgramAddress(
         [
           anchor.utils.bytes.utf8.encode("vault-authority"),
-          initializerMainAccount.publicKey.toBuffer(),
-          takerMainAccount.publicKey.toBuffer(),
+          escrowAccountPda.toBuffer(),
         ], // sampleコードではBufferが書いてあったがBufferはいらないと思われる anchor bookにはない
         program.programId
       );
@@ -785,6 +1134,14 @@ describe("anchor-escrow", () => {
       isWritable: false,
       isSigner: false,
     });
+    // SOLが両方向に動くので、initializerのNFT、takerのNFTの順にroyaltyのmetadataを渡す
+    for (const mint of [mintA, mintB, mintC, mintD, mintE]) {
+      remainingAccounts.push({
+        pubkey: await getMetadataPda(mint),
+        isWritable: false,
+        isSigner: false,
+      });
+    }
     console.log("initializerMainAccount", initializerMainAccount);
     console.log(
       "initializerMainAccount.publicKey",
@@ -795,27 +1152,35 @@ describe("anchor-escrow", () => {
     console.log("vaultAuthorityPda", vaultAuthorityPda);
 
     /* escrowに入っているお金を抜けないかテスト
-      programでやると抜ける
-      tsでやろうとすると次のエラー Error: failed to send transaction: Transaction simulation failed: Error processing Instruction 0: invalid program argument
+      escrowはprogramのPDAなので秘密鍵がなく、SystemProgramのtransferに署名できない
+      またsystem program以外がownerのaccountからはSystemProgramのtransferで抜けない
       */
+    const escrowSolAmountBeforeTransfer = await provider.connection.getBalance(
+      escrowAccountPda
+    );
     try {
       await provider.send(
         (() => {
           const tx = new Transaction();
           tx.add(
             SystemProgram.transfer({
-              fromPubkey: escrowAccount.publicKey,
+              fromPubkey: escrowAccountPda,
               toPubkey: takerMainAccount.publicKey,
               lamports: initializerAdditionalSolAmount,
             })
           );
           return tx;
         })(),
-        [escrowAccount]
+        []
       );
+      assert.fail("transfer from the escrow should fail");
     } catch (err) {
-      assert.ok(err !== null);
+      assert.notEqual(err.message, "transfer from the escrow should fail");
     }
+    assert.equal(
+      await provider.connection.getBalance(escrowAccountPda),
+      escrowSolAmountBeforeTransfer
+    );
 
     const beforeInitializerAccounts =
       await provider.connection.getParsedTokenAccountsByOwner(
@@ -855,14 +1220,19 @@ describe("anchor-escrow", () => {
     await program.rpc.exchange(
       new anchor.BN(initializerAdditionalSolAmount), // この変数がないとaccountsが読めず、taker not providedエラーが生じる
       new anchor.BN(takerAdditionalSolAmount),
+      [], // cNFTなし
       {
         accounts: {
           taker: takerMainAccount.publicKey,
           // vaultSolAccount: vaultSolAccountPda, // ここの値が違うと Error: 3012: The program expected this account to be already initialized
           initializer: initializerMainAccount.publicKey,
-          escrowAccount: escrowAccount.publicKey,
+          escrowAccount: escrowAccountPda,
           vaultAuthority: vaultAuthorityPda,
+          config: configPda,
+          treasury: treasury.publicKey,
+          referrer: initializerMainAccount.publicKey, // referrerがいない場合は使われない
           tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
           systemProgram: anchor.web3.SystemProgram.programId,
           rent: anchor.web3.SYSVAR_RENT_PUBKEY,
         },
@@ -938,7 +1308,7 @@ describe("anchor-escrow", () => {
     // close = initializerありなしでもずれる
     /* close = initializer入れてるから以下は取得できない
     let _escrowAccount2 = await provider.connection.getAccountInfo(
-      escrowAccount.publicKey
+      escrowAccountPda
     );
     console.log("_escrowAccount2.lamports", _escrowAccount2.lamports);
     */
@@ -991,7 +1361,7 @@ describe("anchor-escrow", () => {
 
     // check escrow account
     try {
-      await program.account.escrowAccount.fetch(escrowAccount.publicKey);
+      await program.account.escrowAccount.fetch(escrowAccountPda);
     } catch (err) {
       assert.ok(err !== null);
     }
@@ -1037,12 +1407,23 @@ describe("anchor-escrow", () => {
   });
 
   it("Initialize escrow and cancel escrow by A", async () => {
+    escrowNonce += 1;
+    const [_escrowAccountPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("escrow"),
+        initializerMainAccount.publicKey.toBuffer(),
+        takerMainAccount.publicKey.toBuffer(),
+        new anchor.BN(escrowNonce).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    escrowAccountPda = _escrowAccountPda;
+
     const [_vaultAuthorityPda, _vaultAuthorityBump] =
       await PublicKey.findProgramAddress(
         [
           anchor.utils.bytes.utf8.encode("vault-authority"),
-          initializerMainAccount.publicKey.toBuffer(),
-          takerMainAccount.publicKey.toBuffer(),
+          escrowAccountPda.toBuffer(),
         ], // sampleコードではBufferが書いてあったがBufferはいらないと思われる anchor bookにはない
         program.programId
       );
@@ -1056,6 +1437,7 @@ describe("anchor-escrow", () => {
       await PublicKey.findProgramAddress(
         [
           Buffer.from(anchor.utils.bytes.utf8.encode("vault-account")),
+          escrowAccountPda.toBuffer(),
           initializerTokenAccountC.address.toBuffer(),
         ],
         program.programId
@@ -1070,6 +1452,7 @@ describe("anchor-escrow", () => {
       await PublicKey.findProgramAddress(
         [
           Buffer.from(anchor.utils.bytes.utf8.encode("vault-account")),
+          escrowAccountPda.toBuffer(),
           initializerTokenAccountD.address.toBuffer(),
         ],
         program.programId
@@ -1084,6 +1467,7 @@ describe("anchor-escrow", () => {
       await PublicKey.findProgramAddress(
         [
           Buffer.from(anchor.utils.bytes.utf8.encode("vault-account")),
+          escrowAccountPda.toBuffer(),
           initializerTokenAccountE.address.toBuffer(),
         ],
         program.programId
@@ -1172,27 +1556,36 @@ describe("anchor-escrow", () => {
     takerNftAmount = 2;
 
     await program.rpc.initialize(
-      new anchor.BN(initializerAdditionalSolAmount),
-      new anchor.BN(takerAdditionalSolAmount),
-      initializerNftAmount,
-      takerNftAmount,
-      Buffer.from(vaultAccountBumps), // 難関　Buffer.fromしないとTypeError: Blob.encode[data] requires (length 2) Buffer as src
+      initializeArgs({
+        initializerAdditionalSolAmount: new anchor.BN(
+          initializerAdditionalSolAmount
+        ),
+        takerAdditionalSolAmount: new anchor.BN(takerAdditionalSolAmount),
+        initializerNftAmount,
+        takerNftAmount,
+        vaultAccountBumps: Buffer.from(vaultAccountBumps), // 難関　Buffer.fromしないとTypeError: Blob.encode[data] requires (length 2) Buffer as src
+        initializerNftQuantities: Array(initializerNftAmount).fill(
+          new anchor.BN(1)
+        ), // NFTなので1
+        takerNftQuantities: Array(takerNftAmount).fill(new anchor.BN(1)),
+      }),
       {
         accounts: {
           initializer: initializerMainAccount.publicKey,
           taker: takerMainAccount.publicKey,
-          escrowAccount: escrowAccount.publicKey,
+          escrowAccount: escrowAccountPda,
           systemProgram: anchor.web3.SystemProgram.programId,
           rent: anchor.web3.SYSVAR_RENT_PUBKEY,
           tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
           vaultAuthority: vaultAuthorityPda,
         },
         remainingAccounts: remainingAccounts,
-        signers: [escrowAccount, initializerMainAccount], // escrowAccount抜かすとError: Signature verification failed
+        signers: [initializerMainAccount],
       }
     );
     let _escrowAccountInfo = await provider.connection.getAccountInfo(
-      escrowAccount.publicKey
+      escrowAccountPda
     );
     console.log("_escrowAccountInfo.lamports", _escrowAccountInfo.lamports);
 
@@ -1334,13 +1727,14 @@ describe("anchor-escrow", () => {
     });
 
     // Cancel the escrow.
-    await program.rpc.cancelByInitializer({
+    await program.rpc.cancelByInitializer([], {
       accounts: {
         initializer: initializerMainAccount.publicKey,
         taker: takerMainAccount.publicKey,
         vaultAuthority: vaultAuthorityPda,
-        escrowAccount: escrowAccount.publicKey,
+        escrowAccount: escrowAccountPda,
         tokenProgram: TOKEN_PROGRAM_ID,
+        token2022Program: TOKEN_2022_PROGRAM_ID,
         rent: anchor.web3.SYSVAR_RENT_PUBKEY,
       },
       signers: [initializerMainAccount],
@@ -1447,7 +1841,7 @@ describe("anchor-escrow", () => {
 
     // check escrow account
     try {
-      await program.account.escrowAccount.fetch(escrowAccount.publicKey);
+      await program.account.escrowAccount.fetch(escrowAccountPda);
     } catch (err) {
       assert.ok(err !== null);
     }
@@ -1492,12 +1886,23 @@ describe("anchor-escrow", () => {
   });
 
   it("Initialize escrow and cancel escrow by B", async () => {
+    escrowNonce += 1;
+    const [_escrowAccountPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("escrow"),
+        initializerMainAccount.publicKey.toBuffer(),
+        takerMainAccount.publicKey.toBuffer(),
+        new anchor.BN(escrowNonce).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    escrowAccountPda = _escrowAccountPda;
+
     const [_vaultAuthorityPda, _vaultAuthorityBump] =
       await PublicKey.findProgramAddress(
         [
           anchor.utils.bytes.utf8.encode("vault-authority"),
-          initializerMainAccount.publicKey.toBuffer(),
-          takerMainAccount.publicKey.toBuffer(),
+          escrowAccountPda.toBuffer(),
         ], // sampleコードではBufferが書いてあったがBufferはいらないと思われる anchor bookにはない
         program.programId
       );
@@ -1510,6 +1915,7 @@ describe("anchor-escrow", () => {
       await PublicKey.findProgramAddress(
         [
           Buffer.from(anchor.utils.bytes.utf8.encode("vault-account")),
+          escrowAccountPda.toBuffer(),
           initializerTokenAccountC.address.toBuffer(),
         ],
         program.programId
@@ -1524,6 +1930,7 @@ describe("anchor-escrow", () => {
       await PublicKey.findProgramAddress(
         [
           Buffer.from(anchor.utils.bytes.utf8.encode("vault-account")),
+          escrowAccountPda.toBuffer(),
           initializerTokenAccountD.address.toBuffer(),
         ],
         program.programId
@@ -1538,6 +1945,7 @@ describe("anchor-escrow", () => {
       await PublicKey.findProgramAddress(
         [
           Buffer.from(anchor.utils.bytes.utf8.encode("vault-account")),
+          escrowAccountPda.toBuffer(),
           initializerTokenAccountE.address.toBuffer(),
         ],
         program.programId
@@ -1623,27 +2031,36 @@ describe("anchor-escrow", () => {
     });
 
     await program.rpc.initialize(
-      new anchor.BN(initializerAdditionalSolAmount),
-      new anchor.BN(takerAdditionalSolAmount),
-      initializerNftAmount,
-      takerNftAmount,
-      Buffer.from(vaultAccountBumps), // 難関　Buffer.fromしないとTypeError: Blob.encode[data] requires (length 2) Buffer as src
+      initializeArgs({
+        initializerAdditionalSolAmount: new anchor.BN(
+          initializerAdditionalSolAmount
+        ),
+        takerAdditionalSolAmount: new anchor.BN(takerAdditionalSolAmount),
+        initializerNftAmount,
+        takerNftAmount,
+        vaultAccountBumps: Buffer.from(vaultAccountBumps), // 難関　Buffer.fromしないとTypeError: Blob.encode[data] requires (length 2) Buffer as src
+        initializerNftQuantities: Array(initializerNftAmount).fill(
+          new anchor.BN(1)
+        ), // NFTなので1
+        takerNftQuantities: Array(takerNftAmount).fill(new anchor.BN(1)),
+      }),
       {
         accounts: {
           initializer: initializerMainAccount.publicKey,
           taker: takerMainAccount.publicKey,
-          escrowAccount: escrowAccount.publicKey,
+          escrowAccount: escrowAccountPda,
           systemProgram: anchor.web3.SystemProgram.programId,
           rent: anchor.web3.SYSVAR_RENT_PUBKEY,
           tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
           vaultAuthority: vaultAuthorityPda,
         },
         remainingAccounts: remainingAccounts,
-        signers: [escrowAccount, initializerMainAccount], // escrowAccount抜かすとError: Signature verification failed
+        signers: [initializerMainAccount],
       }
     );
     let _escrowAccountInfo = await provider.connection.getAccountInfo(
-      escrowAccount.publicKey
+      escrowAccountPda
     );
     console.log("_escrowAccountInfo.lamports", _escrowAccountInfo.lamports);
 
@@ -1785,13 +2202,14 @@ describe("anchor-escrow", () => {
     });
 
     // Cancel the escrow.
-    await program.rpc.cancelByTaker({
+    await program.rpc.cancelByTaker([], {
       accounts: {
         initializer: initializerMainAccount.publicKey,
         taker: takerMainAccount.publicKey,
         vaultAuthority: vaultAuthorityPda,
-        escrowAccount: escrowAccount.publicKey,
+        escrowAccount: escrowAccountPda,
         tokenProgram: TOKEN_PROGRAM_ID,
+        token2022Program: TOKEN_2022_PROGRAM_ID,
         rent: anchor.web3.SYSVAR_RENT_PUBKEY,
       },
       signers: [takerMainAccount],
@@ -1898,7 +2316,7 @@ describe("anchor-escrow", () => {
 
     // check escrow account
     try {
-      await program.account.escrowAccount.fetch(escrowAccount.publicKey);
+      await program.account.escrowAccount.fetch(escrowAccountPda);
     } catch (err) {
       assert.ok(err !== null);
     }
@@ -1944,6 +2362,5866 @@ describe("anchor-escrow", () => {
     );
   });
 
+  it("Initialize open offer and exchange by any wallet", async () => {
+    // takerを指定しないofferはPublicKey.defaultをtakerとして渡す
+    escrowNonce += 1;
+    const [_escrowAccountPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("escrow"),
+        initializerMainAccount.publicKey.toBuffer(),
+        PublicKey.default.toBuffer(),
+        new anchor.BN(escrowNonce).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    escrowAccountPda = _escrowAccountPda;
+
+    const [_vaultAuthorityPda, _vaultAuthorityBump] =
+      await PublicKey.findProgramAddress(
+        [
+          anchor.utils.bytes.utf8.encode("vault-authority"),
+          escrowAccountPda.toBuffer(),
+        ],
+        program.programId
+      );
+    vaultAuthorityPda = _vaultAuthorityPda;
+    vaultAuthorityBump = _vaultAuthorityBump;
+
+    // cancelした直後なのでinitializerがC D EのNFT takerがA BのNFTを持っている
+    const [_vaultAccountPdaC, _vaultAccountBumpC] =
+      await PublicKey.findProgramAddress(
+        [
+          Buffer.from(anchor.utils.bytes.utf8.encode("vault-account")),
+          escrowAccountPda.toBuffer(),
+          initializerTokenAccountC.address.toBuffer(),
+        ],
+        program.programId
+      );
+    vaultAccountPdaC = _vaultAccountPdaC;
+    vaultAccountBumpC = _vaultAccountBumpC;
+
+    // open offerのtaker側はtoken accountが未定なのでmintだけ渡す
+    await program.rpc.initialize(
+      initializeArgs({
+        initializerNftAmount: 1,
+        takerNftAmount: 1,
+        vaultAccountBumps: Buffer.from([vaultAccountBumpC]),
+        initializerNftQuantities: [new anchor.BN(1)], // NFTなので1
+        takerNftQuantities: [new anchor.BN(1)],
+      }),
+      {
+        accounts: {
+          initializer: initializerMainAccount.publicKey,
+          taker: PublicKey.default,
+          escrowAccount: escrowAccountPda,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+          vaultAuthority: vaultAuthorityPda,
+        },
+        remainingAccounts: [
+          {
+            pubkey: initializerTokenAccountC.address,
+            isWritable: true,
+            isSigner: false,
+          },
+          { pubkey: vaultAccountPdaC, isWritable: true, isSigner: false },
+          { pubkey: mintC, isWritable: false, isSigner: false },
+          { pubkey: mintA, isWritable: false, isSigner: false },
+        ],
+        signers: [initializerMainAccount],
+      }
+    );
+
+    let _escrowAccount = await program.account.escrowAccount.fetch(
+      escrowAccountPda
+    );
+    assert.ok(_escrowAccount.takerKey.equals(PublicKey.default));
+    assert.ok(_escrowAccount.takerNftTokenAccounts.length === 0);
+    assert.ok(_escrowAccount.takerNftMints.length === 1);
+    assert.ok(_escrowAccount.takerNftMints[0].equals(mintA));
+
+    // escrowに登録されていないwalletでもexchangeできる
+    await program.rpc.exchange(new anchor.BN(0), new anchor.BN(0), [], {
+      accounts: {
+        taker: takerMainAccount.publicKey,
+        initializer: initializerMainAccount.publicKey,
+        escrowAccount: escrowAccountPda,
+        vaultAuthority: vaultAuthorityPda,
+        config: configPda,
+        treasury: treasury.publicKey,
+        referrer: initializerMainAccount.publicKey, // referrerがいない場合は使われない
+        tokenProgram: TOKEN_PROGRAM_ID,
+        token2022Program: TOKEN_2022_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      remainingAccounts: [
+        {
+          pubkey: initializerTokenAccountC.address,
+          isWritable: false,
+          isSigner: false,
+        },
+        { pubkey: vaultAccountPdaC, isWritable: true, isSigner: false },
+        { pubkey: mintC, isWritable: false, isSigner: false },
+        {
+          pubkey: initializerTokenAccountA.address,
+          isWritable: true,
+          isSigner: false,
+        },
+        { pubkey: mintA, isWritable: false, isSigner: false },
+        {
+          pubkey: takerTokenAccountC.address,
+          isWritable: true,
+          isSigner: false,
+        },
+        { pubkey: mintC, isWritable: false, isSigner: false },
+        {
+          pubkey: takerTokenAccountA.address,
+          isWritable: true,
+          isSigner: false,
+        },
+        { pubkey: mintA, isWritable: false, isSigner: false },
+      ],
+      signers: [takerMainAccount],
+    });
+
+    let _initializerTokenAccountA = await getAccount(
+      provider.connection,
+      initializerTokenAccountA.address
+    );
+    assert.ok(Number(_initializerTokenAccountA.amount) === 1);
+
+    let _takerTokenAccountC = await getAccount(
+      provider.connection,
+      takerTokenAccountC.address
+    );
+    assert.ok(Number(_takerTokenAccountC.amount) === 1);
+
+    const _vaultC = await provider.connection.getParsedAccountInfo(
+      vaultAccountPdaC
+    );
+    assert.ok(_vaultC.value === null);
+  });
+
+  it("Initialize two escrows for the same pair and cancel both", async () => {
+    // open offerの後なのでinitializerがA D EのNFT takerがB CのNFTを持っている
+    const escrows = [];
+    for (const [tokenAccount, mint] of [
+      [initializerTokenAccountD.address, mintD],
+      [initializerTokenAccountE.address, mintE],
+    ]) {
+      escrowNonce += 1;
+      const [_escrowAccountPda] = await PublicKey.findProgramAddress(
+        [
+          anchor.utils.bytes.utf8.encode("escrow"),
+          initializerMainAccount.publicKey.toBuffer(),
+          takerMainAccount.publicKey.toBuffer(),
+          new anchor.BN(escrowNonce).toArrayLike(Buffer, "le", 8),
+        ],
+        program.programId
+      );
+      const [_vaultAuthorityPda] = await PublicKey.findProgramAddress(
+        [
+          anchor.utils.bytes.utf8.encode("vault-authority"),
+          _escrowAccountPda.toBuffer(),
+        ],
+        program.programId
+      );
+      const [_vaultAccountPda, _vaultAccountBump] =
+        await PublicKey.findProgramAddress(
+          [
+            Buffer.from(anchor.utils.bytes.utf8.encode("vault-account")),
+            _escrowAccountPda.toBuffer(),
+            tokenAccount.toBuffer(),
+          ],
+          program.programId
+        );
+
+      await program.rpc.initialize(
+        initializeArgs({
+          takerAdditionalSolAmount: new anchor.BN(takerAdditionalSolAmount),
+          initializerNftAmount: 1,
+          vaultAccountBumps: Buffer.from([_vaultAccountBump]),
+          initializerNftQuantities: [new anchor.BN(1)], // NFTなので1
+        }),
+        {
+          accounts: {
+            initializer: initializerMainAccount.publicKey,
+            taker: takerMainAccount.publicKey,
+            escrowAccount: _escrowAccountPda,
+            systemProgram: anchor.web3.SystemProgram.programId,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            token2022Program: TOKEN_2022_PROGRAM_ID,
+            vaultAuthority: _vaultAuthorityPda,
+          },
+          remainingAccounts: [
+            { pubkey: tokenAccount, isWritable: true, isSigner: false },
+            { pubkey: _vaultAccountPda, isWritable: true, isSigner: false },
+            { pubkey: mint, isWritable: false, isSigner: false },
+          ],
+          signers: [initializerMainAccount],
+        }
+      );
+      escrows.push({
+        escrowAccountPda: _escrowAccountPda,
+        vaultAuthorityPda: _vaultAuthorityPda,
+        remainingAccounts: [
+          { pubkey: tokenAccount, isWritable: true, isSigner: false },
+          { pubkey: _vaultAccountPda, isWritable: true, isSigner: false },
+          { pubkey: mint, isWritable: false, isSigner: false },
+        ],
+      });
+    }
+
+    // 同じinitializerとtakerの組み合わせでも2つのescrowが同時に存在できる
+    assert.ok(!escrows[0].escrowAccountPda.equals(escrows[1].escrowAccountPda));
+    for (const escrow of escrows) {
+      const _escrowAccount = await program.account.escrowAccount.fetch(
+        escrow.escrowAccountPda
+      );
+      assert.ok(_escrowAccount.takerKey.equals(takerMainAccount.publicKey));
+    }
+
+    for (const escrow of escrows) {
+      await program.rpc.cancelByInitializer([], {
+        accounts: {
+          initializer: initializerMainAccount.publicKey,
+          taker: takerMainAccount.publicKey,
+          vaultAuthority: escrow.vaultAuthorityPda,
+          escrowAccount: escrow.escrowAccountPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        signers: [initializerMainAccount],
+        remainingAccounts: escrow.remainingAccounts,
+      });
+    }
+
+    let _initializerTokenAccountD = await getAccount(
+      provider.connection,
+      initializerTokenAccountD.address
+    );
+    assert.ok(Number(_initializerTokenAccountD.amount) === 1);
+
+    let _initializerTokenAccountE = await getAccount(
+      provider.connection,
+      initializerTokenAccountE.address
+    );
+    assert.ok(Number(_initializerTokenAccountE.amount) === 1);
+  });
+
+  it("Initialize escrow and expire it after the deadline", async () => {
+    // initializerがA D EのNFT takerがB CのNFTを持っている
+    escrowNonce += 1;
+    const [_escrowAccountPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("escrow"),
+        initializerMainAccount.publicKey.toBuffer(),
+        takerMainAccount.publicKey.toBuffer(),
+        new anchor.BN(escrowNonce).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    escrowAccountPda = _escrowAccountPda;
+
+    const [_vaultAuthorityPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("vault-authority"),
+        escrowAccountPda.toBuffer(),
+      ],
+      program.programId
+    );
+    vaultAuthorityPda = _vaultAuthorityPda;
+
+    const [_vaultAccountPdaD, _vaultAccountBumpD] =
+      await PublicKey.findProgramAddress(
+        [
+          Buffer.from(anchor.utils.bytes.utf8.encode("vault-account")),
+          escrowAccountPda.toBuffer(),
+          initializerTokenAccountD.address.toBuffer(),
+        ],
+        program.programId
+      );
+    vaultAccountPdaD = _vaultAccountPdaD;
+    vaultAccountBumpD = _vaultAccountBumpD;
+
+    const remainingAccounts = [
+      {
+        pubkey: initializerTokenAccountD.address,
+        isWritable: true,
+        isSigner: false,
+      },
+      { pubkey: vaultAccountPdaD, isWritable: true, isSigner: false },
+      { pubkey: mintD, isWritable: false, isSigner: false },
+    ];
+
+    const expiresAt = Math.floor(Date.now() / 1000) + 2;
+    await program.rpc.initialize(
+      initializeArgs({
+        initializerAdditionalSolAmount: new anchor.BN(
+          initializerAdditionalSolAmount
+        ),
+        takerAdditionalSolAmount: new anchor.BN(takerAdditionalSolAmount),
+        initializerNftAmount: 1,
+        vaultAccountBumps: Buffer.from([vaultAccountBumpD]),
+        expiresAt: new anchor.BN(expiresAt),
+        initializerNftQuantities: [new anchor.BN(1)], // NFTなので1
+      }),
+      {
+        accounts: {
+          initializer: initializerMainAccount.publicKey,
+          taker: takerMainAccount.publicKey,
+          escrowAccount: escrowAccountPda,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+          vaultAuthority: vaultAuthorityPda,
+        },
+        remainingAccounts,
+        signers: [initializerMainAccount],
+      }
+    );
+
+    const _escrowAccount = await program.account.escrowAccount.fetch(
+      escrowAccountPda
+    );
+    assert.ok(_escrowAccount.expiresAt.toNumber() === expiresAt);
+
+    // 期限前はexpireできない
+    const expireAccounts = {
+      payer: provider.wallet.publicKey, // initializerでもtakerでもないwallet
+      initializer: initializerMainAccount.publicKey,
+      taker: takerMainAccount.publicKey,
+      vaultAuthority: vaultAuthorityPda,
+      escrowAccount: escrowAccountPda,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      token2022Program: TOKEN_2022_PROGRAM_ID,
+      rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+    };
+    try {
+      await program.rpc.expire([], {
+        accounts: expireAccounts,
+        remainingAccounts,
+      });
+      assert.fail("expire should fail before the deadline");
+    } catch (err) {
+      assert.equal(err.msg, "EscrowNotExpired");
+    }
+
+    await new Promise((resolve) => setTimeout(resolve, 4000));
+
+    // 期限後はexchangeできない
+    try {
+      await program.rpc.exchange(
+        new anchor.BN(initializerAdditionalSolAmount),
+        new anchor.BN(takerAdditionalSolAmount),
+        [], // cNFTなし
+        {
+          accounts: {
+            taker: takerMainAccount.publicKey,
+            initializer: initializerMainAccount.publicKey,
+            escrowAccount: escrowAccountPda,
+            vaultAuthority: vaultAuthorityPda,
+            config: configPda,
+            treasury: treasury.publicKey,
+            referrer: initializerMainAccount.publicKey, // referrerがいない場合は使われない
+            tokenProgram: TOKEN_PROGRAM_ID,
+            token2022Program: TOKEN_2022_PROGRAM_ID,
+            systemProgram: anchor.web3.SystemProgram.programId,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          },
+          remainingAccounts: [
+            {
+              pubkey: initializerTokenAccountD.address,
+              isWritable: false,
+              isSigner: false,
+            },
+            { pubkey: vaultAccountPdaD, isWritable: true, isSigner: false },
+            { pubkey: mintD, isWritable: false, isSigner: false },
+            {
+              pubkey: takerTokenAccountD.address,
+              isWritable: true,
+              isSigner: false,
+            },
+            { pubkey: mintD, isWritable: false, isSigner: false },
+          ],
+          signers: [takerMainAccount],
+        }
+      );
+      assert.fail("exchange should fail after the deadline");
+    } catch (err) {
+      assert.equal(err.msg, "EscrowExpired");
+    }
+
+    // initializerでもtakerでもないwalletがexpireできる 手数料はproviderのwalletが払う
+    await program.rpc.expire([], {
+      accounts: expireAccounts,
+      remainingAccounts,
+    });
+
+    let _initializerTokenAccountD = await getAccount(
+      provider.connection,
+      initializerTokenAccountD.address
+    );
+    assert.ok(Number(_initializerTokenAccountD.amount) === 1);
+
+    const _vaultD = await provider.connection.getParsedAccountInfo(
+      vaultAccountPdaD
+    );
+    assert.ok(_vaultD.value === null);
+
+    const _escrowAccountInfo = await provider.connection.getAccountInfo(
+      escrowAccountPda
+    );
+    assert.ok(_escrowAccountInfo === null);
+  });
+
+  it("Initialize escrow and counter offer by B", async () => {
+    // initializerがA D EのNFT takerがB CのNFTを持っている
+    escrowNonce += 1;
+    const [_escrowAccountPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("escrow"),
+        initializerMainAccount.publicKey.toBuffer(),
+        takerMainAccount.publicKey.toBuffer(),
+        new anchor.BN(escrowNonce).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    escrowAccountPda = _escrowAccountPda;
+
+    const [_vaultAuthorityPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("vault-authority"),
+        escrowAccountPda.toBuffer(),
+      ],
+      program.programId
+    );
+    vaultAuthorityPda = _vaultAuthorityPda;
+
+    const [_vaultAccountPdaD, _vaultAccountBumpD] =
+      await PublicKey.findProgramAddress(
+        [
+          Buffer.from(anchor.utils.bytes.utf8.encode("vault-account")),
+          escrowAccountPda.toBuffer(),
+          initializerTokenAccountD.address.toBuffer(),
+        ],
+        program.programId
+      );
+    vaultAccountPdaD = _vaultAccountPdaD;
+    vaultAccountBumpD = _vaultAccountBumpD;
+
+    // initializerはDを預けてBを要求する
+    await program.rpc.initialize(
+      initializeArgs({
+        initializerNftAmount: 1,
+        takerNftAmount: 1,
+        vaultAccountBumps: Buffer.from([vaultAccountBumpD]),
+        initializerNftQuantities: [new anchor.BN(1)], // NFTなので1
+        takerNftQuantities: [new anchor.BN(1)],
+      }),
+      {
+        accounts: {
+          initializer: initializerMainAccount.publicKey,
+          taker: takerMainAccount.publicKey,
+          escrowAccount: escrowAccountPda,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+          vaultAuthority: vaultAuthorityPda,
+        },
+        remainingAccounts: [
+          {
+            pubkey: initializerTokenAccountD.address,
+            isWritable: true,
+            isSigner: false,
+          },
+          { pubkey: vaultAccountPdaD, isWritable: true, isSigner: false },
+          { pubkey: mintD, isWritable: false, isSigner: false },
+          {
+            pubkey: takerTokenAccountB.address,
+            isWritable: false,
+            isSigner: false,
+          },
+          { pubkey: mintB, isWritable: false, isSigner: false },
+        ],
+        signers: [initializerMainAccount],
+      }
+    );
+
+    // counter offerではtakerがinitializerになるのでseedの順番が逆になる
+    escrowNonce += 1;
+    const [counterEscrowAccountPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("escrow"),
+        takerMainAccount.publicKey.toBuffer(),
+        initializerMainAccount.publicKey.toBuffer(),
+        new anchor.BN(escrowNonce).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const [counterVaultAuthorityPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("vault-authority"),
+        counterEscrowAccountPda.toBuffer(),
+      ],
+      program.programId
+    );
+    const [_vaultAccountPdaC, _vaultAccountBumpC] =
+      await PublicKey.findProgramAddress(
+        [
+          Buffer.from(anchor.utils.bytes.utf8.encode("vault-account")),
+          counterEscrowAccountPda.toBuffer(),
+          takerTokenAccountC.address.toBuffer(),
+        ],
+        program.programId
+      );
+    vaultAccountPdaC = _vaultAccountPdaC;
+    vaultAccountBumpC = _vaultAccountBumpC;
+
+    // takerはBの代わりにCとSOLを出してDを要求する
+    await program.rpc.counterOffer(
+      {
+        initializerAdditionalSolAmount: new anchor.BN(takerAdditionalSolAmount),
+        takerAdditionalSolAmount: new anchor.BN(0),
+        initializerNftAmount: 1,
+        takerNftAmount: 1,
+        vaultAccountBumps: Buffer.from([vaultAccountBumpC]),
+        nonce: new anchor.BN(escrowNonce),
+        expiresAt: new anchor.BN(0), // 期限なし
+        initializerNftQuantities: [new anchor.BN(1)], // NFTなので1
+        takerNftQuantities: [new anchor.BN(1)],
+      },
+      {
+        accounts: {
+          taker: takerMainAccount.publicKey,
+          initializer: initializerMainAccount.publicKey,
+          vaultAuthority: vaultAuthorityPda,
+          escrowAccount: escrowAccountPda,
+          counterEscrowAccount: counterEscrowAccountPda,
+          counterVaultAuthority: counterVaultAuthorityPda,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+        },
+        remainingAccounts: [
+          // 元のescrowのvaultをinitializerに戻す
+          {
+            pubkey: initializerTokenAccountD.address,
+            isWritable: true,
+            isSigner: false,
+          },
+          { pubkey: vaultAccountPdaD, isWritable: true, isSigner: false },
+          { pubkey: mintD, isWritable: false, isSigner: false },
+          // takerのNFTを預ける
+          {
+            pubkey: takerTokenAccountC.address,
+            isWritable: true,
+            isSigner: false,
+          },
+          { pubkey: vaultAccountPdaC, isWritable: true, isSigner: false },
+          { pubkey: mintC, isWritable: false, isSigner: false },
+          // initializerに要求するNFT
+          {
+            pubkey: initializerTokenAccountD.address,
+            isWritable: false,
+            isSigner: false,
+          },
+          { pubkey: mintD, isWritable: false, isSigner: false },
+        ],
+        signers: [takerMainAccount],
+      }
+    );
+
+    // 元のescrowはcloseされてDはinitializerに戻る
+    const _escrowAccountInfo = await provider.connection.getAccountInfo(
+      escrowAccountPda
+    );
+    assert.ok(_escrowAccountInfo === null);
+    let _initializerTokenAccountD = await getAccount(
+      provider.connection,
+      initializerTokenAccountD.address
+    );
+    assert.ok(Number(_initializerTokenAccountD.amount) === 1);
+
+    const _counterEscrowAccount = await program.account.escrowAccount.fetch(
+      counterEscrowAccountPda
+    );
+    assert.ok(
+      _counterEscrowAccount.initializerKey.equals(takerMainAccount.publicKey)
+    );
+    assert.ok(
+      _counterEscrowAccount.takerKey.equals(initializerMainAccount.publicKey)
+    );
+    assert.ok(
+      _counterEscrowAccount.previousEscrowKey.equals(escrowAccountPda)
+    );
+    assert.ok(
+      _counterEscrowAccount.initializerAdditionalSolAmount.toNumber() ===
+        takerAdditionalSolAmount
+    );
+    assert.ok(_counterEscrowAccount.takerNftMints[0].equals(mintD));
+
+    let _takerTokenAccountC = await getAccount(
+      provider.connection,
+      takerTokenAccountC.address
+    );
+    assert.ok(Number(_takerTokenAccountC.amount) === 0);
+
+    // counter offerをtakerがcancelして元の状態に戻す
+    await program.rpc.cancelByInitializer([], {
+      accounts: {
+        initializer: takerMainAccount.publicKey,
+        taker: initializerMainAccount.publicKey,
+        vaultAuthority: counterVaultAuthorityPda,
+        escrowAccount: counterEscrowAccountPda,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        token2022Program: TOKEN_2022_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [takerMainAccount],
+      remainingAccounts: [
+        {
+          pubkey: takerTokenAccountC.address,
+          isWritable: true,
+          isSigner: false,
+        },
+        { pubkey: vaultAccountPdaC, isWritable: true, isSigner: false },
+        { pubkey: mintC, isWritable: false, isSigner: false },
+      ],
+    });
+
+    _takerTokenAccountC = await getAccount(
+      provider.connection,
+      takerTokenAccountC.address
+    );
+    assert.ok(Number(_takerTokenAccountC.amount) === 1);
+  });
+
+  it("Initialize escrow and amend it in place", async () => {
+    // initializerがA D EのNFT takerがB CのNFTを持っている
+    escrowNonce += 1;
+    const [_escrowAccountPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("escrow"),
+        initializerMainAccount.publicKey.toBuffer(),
+        takerMainAccount.publicKey.toBuffer(),
+        new anchor.BN(escrowNonce).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    escrowAccountPda = _escrowAccountPda;
+
+    const [_vaultAuthorityPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("vault-authority"),
+        escrowAccountPda.toBuffer(),
+      ],
+      program.programId
+    );
+    vaultAuthorityPda = _vaultAuthorityPda;
+
+    const [_vaultAccountPdaD, _vaultAccountBumpD] =
+      await PublicKey.findProgramAddress(
+        [
+          Buffer.from(anchor.utils.bytes.utf8.encode("vault-account")),
+          escrowAccountPda.toBuffer(),
+          initializerTokenAccountD.address.toBuffer(),
+        ],
+        program.programId
+      );
+    vaultAccountPdaD = _vaultAccountPdaD;
+    vaultAccountBumpD = _vaultAccountBumpD;
+
+    const [_vaultAccountPdaE, _vaultAccountBumpE] =
+      await PublicKey.findProgramAddress(
+        [
+          Buffer.from(anchor.utils.bytes.utf8.encode("vault-account")),
+          escrowAccountPda.toBuffer(),
+          initializerTokenAccountE.address.toBuffer(),
+        ],
+        program.programId
+      );
+    vaultAccountPdaE = _vaultAccountPdaE;
+    vaultAccountBumpE = _vaultAccountBumpE;
+
+    // initializerはDを預けてBを要求する
+    await program.rpc.initialize(
+      initializeArgs({
+        initializerNftAmount: 1,
+        takerNftAmount: 1,
+        vaultAccountBumps: Buffer.from([vaultAccountBumpD]),
+        initializerNftQuantities: [new anchor.BN(1)], // NFTなので1
+        takerNftQuantities: [new anchor.BN(1)],
+      }),
+      {
+        accounts: {
+          initializer: initializerMainAccount.publicKey,
+          taker: takerMainAccount.publicKey,
+          escrowAccount: escrowAccountPda,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+          vaultAuthority: vaultAuthorityPda,
+        },
+        remainingAccounts: [
+          {
+            pubkey: initializerTokenAccountD.address,
+            isWritable: true,
+            isSigner: false,
+          },
+          { pubkey: vaultAccountPdaD, isWritable: true, isSigner: false },
+          { pubkey: mintD, isWritable: false, isSigner: false },
+          {
+            pubkey: takerTokenAccountB.address,
+            isWritable: false,
+            isSigner: false,
+          },
+          { pubkey: mintB, isWritable: false, isSigner: false },
+        ],
+        signers: [initializerMainAccount],
+      }
+    );
+
+    // Dを引き出してEを預け、Bの代わりにB Cを要求してSOLも追加する
+    await program.rpc.amend(
+      {
+        initializerAdditionalSolAmount: new anchor.BN(
+          initializerAdditionalSolAmount
+        ),
+        takerAdditionalSolAmount: new anchor.BN(0),
+        withdrawNftAmount: 1,
+        depositNftAmount: 1,
+        takerNftAmount: 2,
+        vaultAccountBumps: Buffer.from([vaultAccountBumpE]),
+        depositNftQuantities: [new anchor.BN(1)], // NFTなので1
+        takerNftQuantities: [new anchor.BN(1), new anchor.BN(1)],
+      },
+      {
+        accounts: {
+          initializer: initializerMainAccount.publicKey,
+          escrowAccount: escrowAccountPda,
+          vaultAuthority: vaultAuthorityPda,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+        },
+        remainingAccounts: [
+          {
+            pubkey: initializerTokenAccountD.address,
+            isWritable: true,
+            isSigner: false,
+          },
+          { pubkey: vaultAccountPdaD, isWritable: true, isSigner: false },
+          { pubkey: mintD, isWritable: false, isSigner: false },
+          {
+            pubkey: initializerTokenAccountE.address,
+            isWritable: true,
+            isSigner: false,
+          },
+          { pubkey: vaultAccountPdaE, isWritable: true, isSigner: false },
+          { pubkey: mintE, isWritable: false, isSigner: false },
+          {
+            pubkey: takerTokenAccountB.address,
+            isWritable: false,
+            isSigner: false,
+          },
+          { pubkey: mintB, isWritable: false, isSigner: false },
+          {
+            pubkey: takerTokenAccountC.address,
+            isWritable: false,
+            isSigner: false,
+          },
+          { pubkey: mintC, isWritable: false, isSigner: false },
+        ],
+        signers: [initializerMainAccount],
+      }
+    );
+
+    let _initializerTokenAccountD = await getAccount(
+      provider.connection,
+      initializerTokenAccountD.address
+    );
+    assert.ok(Number(_initializerTokenAccountD.amount) === 1);
+    let _initializerTokenAccountE = await getAccount(
+      provider.connection,
+      initializerTokenAccountE.address
+    );
+    assert.ok(Number(_initializerTokenAccountE.amount) === 0);
+
+    const _vaultD = await provider.connection.getParsedAccountInfo(
+      vaultAccountPdaD
+    );
+    assert.ok(_vaultD.value === null);
+
+    const _escrowAccount = await program.account.escrowAccount.fetch(
+      escrowAccountPda
+    );
+    assert.ok(_escrowAccount.initializerNftTokenAccounts.length === 1);
+    assert.ok(
+      _escrowAccount.initializerNftTokenAccounts[0].equals(
+        initializerTokenAccountE.address
+      )
+    );
+    assert.ok(
+      (_escrowAccount.vaultAccountBumps as Buffer).equals(
+        Buffer.from([vaultAccountBumpE])
+      )
+    );
+    assert.ok(_escrowAccount.takerNftMints.length === 2);
+    assert.ok(
+      _escrowAccount.initializerAdditionalSolAmount.toNumber() ===
+        initializerAdditionalSolAmount
+    );
+
+    // escrow accountはrentと追加のSOLだけを持っている
+    const _escrowAccountInfo = await provider.connection.getAccountInfo(
+      escrowAccountPda
+    );
+    const rentExemption =
+      await provider.connection.getMinimumBalanceForRentExemption(
+        _escrowAccountInfo.data.length
+      );
+    assert.ok(
+      _escrowAccountInfo.lamports ===
+        rentExemption + initializerAdditionalSolAmount
+    );
+
+    await program.rpc.cancelByInitializer([], {
+      accounts: {
+        initializer: initializerMainAccount.publicKey,
+        taker: takerMainAccount.publicKey,
+        vaultAuthority: vaultAuthorityPda,
+        escrowAccount: escrowAccountPda,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        token2022Program: TOKEN_2022_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [initializerMainAccount],
+      remainingAccounts: [
+        {
+          pubkey: initializerTokenAccountE.address,
+          isWritable: true,
+          isSigner: false,
+        },
+        { pubkey: vaultAccountPdaE, isWritable: true, isSigner: false },
+        { pubkey: mintE, isWritable: false, isSigner: false },
+      ],
+    });
+
+    _initializerTokenAccountE = await getAccount(
+      provider.connection,
+      initializerTokenAccountE.address
+    );
+    assert.ok(Number(_initializerTokenAccountE.amount) === 1);
+  });
+
+  it("Initialize escrow, accept by B and settle", async () => {
+    // initializerがA D EのNFT takerがB CのNFTを持っている
+    escrowNonce += 1;
+    const [_escrowAccountPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("escrow"),
+        initializerMainAccount.publicKey.toBuffer(),
+        takerMainAccount.publicKey.toBuffer(),
+        new anchor.BN(escrowNonce).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    escrowAccountPda = _escrowAccountPda;
+
+    const [_vaultAuthorityPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("vault-authority"),
+        escrowAccountPda.toBuffer(),
+      ],
+      program.programId
+    );
+    vaultAuthorityPda = _vaultAuthorityPda;
+
+    const [_vaultAccountPdaD, _vaultAccountBumpD] =
+      await PublicKey.findProgramAddress(
+        [
+          Buffer.from(anchor.utils.bytes.utf8.encode("vault-account")),
+          escrowAccountPda.toBuffer(),
+          initializerTokenAccountD.address.toBuffer(),
+        ],
+        program.programId
+      );
+    vaultAccountPdaD = _vaultAccountPdaD;
+    vaultAccountBumpD = _vaultAccountBumpD;
+
+    // takerのNFTもvaultに預けるのでtakerのtoken accountからvaultを導出
+    const [_vaultAccountPdaB, _vaultAccountBumpB] =
+      await PublicKey.findProgramAddress(
+        [
+          Buffer.from(anchor.utils.bytes.utf8.encode("vault-account")),
+          escrowAccountPda.toBuffer(),
+          takerTokenAccountB.address.toBuffer(),
+        ],
+        program.programId
+      );
+    vaultAccountPdaB = _vaultAccountPdaB;
+    vaultAccountBumpB = _vaultAccountBumpB;
+
+    // initializerはDとSOLを預けてBを要求する
+    await program.rpc.initialize(
+      initializeArgs({
+        initializerAdditionalSolAmount: new anchor.BN(
+          initializerAdditionalSolAmount
+        ),
+        initializerNftAmount: 1,
+        takerNftAmount: 1,
+        vaultAccountBumps: Buffer.from([vaultAccountBumpD]),
+        initializerNftQuantities: [new anchor.BN(1)], // NFTなので1
+        takerNftQuantities: [new anchor.BN(1)],
+      }),
+      {
+        accounts: {
+          initializer: initializerMainAccount.publicKey,
+          taker: takerMainAccount.publicKey,
+          escrowAccount: escrowAccountPda,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+          vaultAuthority: vaultAuthorityPda,
+        },
+        remainingAccounts: [
+          {
+            pubkey: initializerTokenAccountD.address,
+            isWritable: true,
+            isSigner: false,
+          },
+          { pubkey: vaultAccountPdaD, isWritable: true, isSigner: false },
+          { pubkey: mintD, isWritable: false, isSigner: false },
+          {
+            pubkey: takerTokenAccountB.address,
+            isWritable: false,
+            isSigner: false,
+          },
+          { pubkey: mintB, isWritable: false, isSigner: false },
+        ],
+        signers: [initializerMainAccount],
+      }
+    );
+
+    // takerがBをvaultに預けてacceptする
+    await program.rpc.accept(new anchor.BN(0), Buffer.from([vaultAccountBumpB]), {
+      accounts: {
+        taker: takerMainAccount.publicKey,
+        initializer: initializerMainAccount.publicKey,
+        escrowAccount: escrowAccountPda,
+        vaultAuthority: vaultAuthorityPda,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        token2022Program: TOKEN_2022_PROGRAM_ID,
+      },
+      remainingAccounts: [
+        {
+          pubkey: takerTokenAccountB.address,
+          isWritable: true,
+          isSigner: false,
+        },
+        { pubkey: vaultAccountPdaB, isWritable: true, isSigner: false },
+        { pubkey: mintB, isWritable: false, isSigner: false },
+      ],
+      signers: [takerMainAccount],
+    });
+
+    let _takerTokenAccountB = await getAccount(
+      provider.connection,
+      takerTokenAccountB.address
+    );
+    assert.ok(Number(_takerTokenAccountB.amount) === 0);
+    const _escrowAccount = await program.account.escrowAccount.fetch(
+      escrowAccountPda
+    );
+    assert.ok(
+      _escrowAccount.acceptedTakerKey.equals(takerMainAccount.publicKey)
+    );
+
+    // accept後はinitializerがcancelできない
+    try {
+      await program.rpc.cancelByInitializer([], {
+        accounts: {
+          initializer: initializerMainAccount.publicKey,
+          taker: takerMainAccount.publicKey,
+          vaultAuthority: vaultAuthorityPda,
+          escrowAccount: escrowAccountPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        signers: [initializerMainAccount],
+        remainingAccounts: [
+          {
+            pubkey: initializerTokenAccountD.address,
+            isWritable: true,
+            isSigner: false,
+          },
+          { pubkey: vaultAccountPdaD, isWritable: true, isSigner: false },
+          { pubkey: mintD, isWritable: false, isSigner: false },
+        ],
+      });
+      assert.fail("cancel after accept should fail");
+    } catch (err) {
+      assert.equal(err.msg, "EscrowAlreadyAccepted");
+    }
+
+    const _takerBalanceBeforeSettle = await provider.connection.getBalance(
+      takerMainAccount.publicKey
+    );
+
+    // どちらが呼んでもいいのでtakerがsettleする
+    await program.rpc.settle({
+      accounts: {
+        authority: takerMainAccount.publicKey,
+        initializer: initializerMainAccount.publicKey,
+        taker: takerMainAccount.publicKey,
+        escrowAccount: escrowAccountPda,
+        vaultAuthority: vaultAuthorityPda,
+        config: configPda,
+        treasury: treasury.publicKey,
+        referrer: initializerMainAccount.publicKey, // referrerがいない場合は使われない
+        tokenProgram: TOKEN_PROGRAM_ID,
+        token2022Program: TOKEN_2022_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      remainingAccounts: [
+        {
+          pubkey: initializerTokenAccountD.address,
+          isWritable: true,
+          isSigner: false,
+        },
+        { pubkey: vaultAccountPdaD, isWritable: true, isSigner: false },
+        {
+          pubkey: takerTokenAccountD.address,
+          isWritable: true,
+          isSigner: false,
+        },
+        { pubkey: mintD, isWritable: false, isSigner: false },
+        {
+          pubkey: takerTokenAccountB.address,
+          isWritable: true,
+          isSigner: false,
+        },
+        { pubkey: vaultAccountPdaB, isWritable: true, isSigner: false },
+        {
+          pubkey: initializerTokenAccountB.address,
+          isWritable: true,
+          isSigner: false,
+        },
+        { pubkey: mintB, isWritable: false, isSigner: false },
+      ],
+      signers: [takerMainAccount],
+    });
+
+    const _takerTokenAccountD = await getAccount(
+      provider.connection,
+      takerTokenAccountD.address
+    );
+    assert.ok(Number(_takerTokenAccountD.amount) === 1);
+    const _initializerTokenAccountB = await getAccount(
+      provider.connection,
+      initializerTokenAccountB.address
+    );
+    assert.ok(Number(_initializerTokenAccountB.amount) === 1);
+
+    // vault Bのrentとinitializerの追加SOLがtakerに入る 手数料はtakerが払う
+    const _takerBalanceAfterSettle = await provider.connection.getBalance(
+      takerMainAccount.publicKey
+    );
+    assert.ok(
+      _takerBalanceAfterSettle - _takerBalanceBeforeSettle >
+        initializerAdditionalSolAmount
+    );
+
+    const _escrow = await provider.connection.getAccountInfo(escrowAccountPda);
+    assert.ok(_escrow === null);
+    const _vaultB = await provider.connection.getAccountInfo(vaultAccountPdaB);
+    assert.ok(_vaultB === null);
+    // initializerがA B E takerがC Dを持っている
+  });
+
+  it("Initialize ring of three wallets and settle it", async () => {
+    // initializerがA B EのNFT takerがC DのNFTを持っている
+    // initializerのA -> taker takerのC -> third thirdのSOL -> initializerの順で渡る
+    const thirdMainAccount = anchor.web3.Keypair.generate();
+    const thirdAdditionalSolAmount = 100_000_000; // lamport
+    await provider.send(
+      (() => {
+        const tx = new Transaction();
+        tx.add(
+          SystemProgram.transfer({
+            fromPubkey: payer.publicKey,
+            toPubkey: thirdMainAccount.publicKey,
+            lamports: 1_000_000_000,
+          })
+        );
+        return tx;
+      })(),
+      [payer]
+    );
+    const thirdTokenAccountC = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      thirdMainAccount,
+      mintC,
+      thirdMainAccount.publicKey
+    );
+
+    const ringNonce = 0;
+    const [ringAccountPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("ring"),
+        initializerMainAccount.publicKey.toBuffer(),
+        new anchor.BN(ringNonce).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const [ringVaultAuthorityPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("vault-authority"),
+        ringAccountPda.toBuffer(),
+      ],
+      program.programId
+    );
+
+    await program.rpc.initializeRing(
+      [
+        initializerMainAccount.publicKey,
+        takerMainAccount.publicKey,
+        thirdMainAccount.publicKey,
+      ],
+      [new anchor.BN(0), new anchor.BN(0), new anchor.BN(thirdAdditionalSolAmount)],
+      Buffer.from([1, 1, 0]),
+      new anchor.BN(ringNonce),
+      {
+        accounts: {
+          creator: initializerMainAccount.publicKey,
+          ringAccount: ringAccountPda,
+          vaultAuthority: ringVaultAuthorityPda,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        remainingAccounts: [
+          { pubkey: mintA, isWritable: false, isSigner: false },
+          { pubkey: mintC, isWritable: false, isSigner: false },
+        ],
+        signers: [initializerMainAccount],
+      }
+    );
+
+    const [_vaultAccountPdaA, _vaultAccountBumpA] =
+      await PublicKey.findProgramAddress(
+        [
+          Buffer.from(anchor.utils.bytes.utf8.encode("vault-account")),
+          ringAccountPda.toBuffer(),
+          initializerTokenAccountA.address.toBuffer(),
+        ],
+        program.programId
+      );
+    vaultAccountPdaA = _vaultAccountPdaA;
+    vaultAccountBumpA = _vaultAccountBumpA;
+
+    const [_vaultAccountPdaC, _vaultAccountBumpC] =
+      await PublicKey.findProgramAddress(
+        [
+          Buffer.from(anchor.utils.bytes.utf8.encode("vault-account")),
+          ringAccountPda.toBuffer(),
+          takerTokenAccountC.address.toBuffer(),
+        ],
+        program.programId
+      );
+    vaultAccountPdaC = _vaultAccountPdaC;
+    vaultAccountBumpC = _vaultAccountBumpC;
+
+    const depositRingAccounts = {
+      ringAccount: ringAccountPda,
+      vaultAuthority: ringVaultAuthorityPda,
+      systemProgram: anchor.web3.SystemProgram.programId,
+      rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      token2022Program: TOKEN_2022_PROGRAM_ID,
+    };
+    await program.rpc.depositRing(Buffer.from([vaultAccountBumpA]), {
+      accounts: {
+        participant: initializerMainAccount.publicKey,
+        ...depositRingAccounts,
+      },
+      remainingAccounts: [
+        {
+          pubkey: initializerTokenAccountA.address,
+          isWritable: true,
+          isSigner: false,
+        },
+        { pubkey: vaultAccountPdaA, isWritable: true, isSigner: false },
+        { pubkey: mintA, isWritable: false, isSigner: false },
+      ],
+      signers: [initializerMainAccount],
+    });
+    await program.rpc.depositRing(Buffer.from([vaultAccountBumpC]), {
+      accounts: {
+        participant: takerMainAccount.publicKey,
+        ...depositRingAccounts,
+      },
+      remainingAccounts: [
+        {
+          pubkey: takerTokenAccountC.address,
+          isWritable: true,
+          isSigner: false,
+        },
+        { pubkey: vaultAccountPdaC, isWritable: true, isSigner: false },
+        { pubkey: mintC, isWritable: false, isSigner: false },
+      ],
+      signers: [takerMainAccount],
+    });
+
+    const settleRingAccounts = {
+      creator: initializerMainAccount.publicKey,
+      ringAccount: ringAccountPda,
+      vaultAuthority: ringVaultAuthorityPda,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      token2022Program: TOKEN_2022_PROGRAM_ID,
+      systemProgram: anchor.web3.SystemProgram.programId,
+    };
+    const participantAccounts = [
+      {
+        pubkey: initializerMainAccount.publicKey,
+        isWritable: true,
+        isSigner: false,
+      },
+      { pubkey: takerMainAccount.publicKey, isWritable: true, isSigner: false },
+      { pubkey: thirdMainAccount.publicKey, isWritable: true, isSigner: false },
+    ];
+    const settleRingRemainingAccounts = [
+      ...participantAccounts,
+      {
+        pubkey: initializerTokenAccountA.address,
+        isWritable: true,
+        isSigner: false,
+      },
+      { pubkey: vaultAccountPdaA, isWritable: true, isSigner: false },
+      { pubkey: takerTokenAccountA.address, isWritable: true, isSigner: false },
+      { pubkey: mintA, isWritable: false, isSigner: false },
+      { pubkey: takerTokenAccountC.address, isWritable: true, isSigner: false },
+      { pubkey: vaultAccountPdaC, isWritable: true, isSigner: false },
+      { pubkey: thirdTokenAccountC.address, isWritable: true, isSigner: false },
+      { pubkey: mintC, isWritable: false, isSigner: false },
+    ];
+
+    // thirdがdepositするまではsettleできない
+    try {
+      await program.rpc.settleRing({
+        accounts: settleRingAccounts,
+        remainingAccounts: settleRingRemainingAccounts,
+      });
+      assert.fail("settle_ring should fail before every leg is deposited");
+    } catch (err) {
+      assert.equal(err.msg, "RingNotFullyDeposited");
+    }
+
+    await program.rpc.depositRing(Buffer.from([]), {
+      accounts: {
+        participant: thirdMainAccount.publicKey,
+        ...depositRingAccounts,
+      },
+      signers: [thirdMainAccount],
+    });
+
+    const _initializerBalanceBeforeSettle = await provider.connection.getBalance(
+      initializerMainAccount.publicKey
+    );
+
+    // 誰が呼んでもいいのでproviderのwalletがsettleする
+    await program.rpc.settleRing({
+      accounts: settleRingAccounts,
+      remainingAccounts: settleRingRemainingAccounts,
+    });
+
+    const _takerTokenAccountA = await getAccount(
+      provider.connection,
+      takerTokenAccountA.address
+    );
+    assert.ok(Number(_takerTokenAccountA.amount) === 1);
+    const _thirdTokenAccountC = await getAccount(
+      provider.connection,
+      thirdTokenAccountC.address
+    );
+    assert.ok(Number(_thirdTokenAccountC.amount) === 1);
+
+    // thirdのSOLとring accountのrentがinitializerに入る
+    const _initializerBalanceAfterSettle = await provider.connection.getBalance(
+      initializerMainAccount.publicKey
+    );
+    assert.ok(
+      _initializerBalanceAfterSettle - _initializerBalanceBeforeSettle >
+        thirdAdditionalSolAmount
+    );
+
+    const _ringAccount = await provider.connection.getAccountInfo(
+      ringAccountPda
+    );
+    assert.ok(_ringAccount === null);
+    // initializerがB E takerがA Dを持っている
+  });
+
+  it("Initialize escrow with fungible tokens and exchange", async () => {
+    // initializerがB EのNFT takerがA DのNFTを持っている
+    // initializerはUSDCを払ってtakerのAを要求する
+    const usdcDecimals = 6;
+    const mintUsdc = await createMint(
+      provider.connection,
+      payer,
+      mintAuthority.publicKey,
+      null,
+      usdcDecimals
+    );
+    const initializerTokenAccountUsdc = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      initializerMainAccount,
+      mintUsdc,
+      initializerMainAccount.publicKey
+    );
+    const takerTokenAccountUsdc = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      takerMainAccount,
+      mintUsdc,
+      takerMainAccount.publicKey
+    );
+    await mintTo(
+      provider.connection,
+      initializerMainAccount,
+      mintUsdc,
+      initializerTokenAccountUsdc.address,
+      mintAuthority,
+      100 * 10 ** usdcDecimals,
+      [mintAuthority]
+    );
+    const initializerUsdcAmount = 10.5 * 10 ** usdcDecimals; // 10.5 USDC
+
+    escrowNonce += 1;
+    const [_escrowAccountPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("escrow"),
+        initializerMainAccount.publicKey.toBuffer(),
+        takerMainAccount.publicKey.toBuffer(),
+        new anchor.BN(escrowNonce).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    escrowAccountPda = _escrowAccountPda;
+
+    const [_vaultAuthorityPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("vault-authority"),
+        escrowAccountPda.toBuffer(),
+      ],
+      program.programId
+    );
+    vaultAuthorityPda = _vaultAuthorityPda;
+
+    // fungible tokenのvaultはescrowとmintから導出
+    const [fungibleVaultAccountPdaUsdc, fungibleVaultAccountBumpUsdc] =
+      await PublicKey.findProgramAddress(
+        [
+          Buffer.from(anchor.utils.bytes.utf8.encode("fungible-vault-account")),
+          escrowAccountPda.toBuffer(),
+          mintUsdc.toBuffer(),
+        ],
+        program.programId
+      );
+
+    await program.rpc.initialize(
+      initializeArgs({
+        takerNftAmount: 1,
+        initializerFungibleAmounts: [new anchor.BN(initializerUsdcAmount)],
+        fungibleVaultAccountBumps: Buffer.from([fungibleVaultAccountBumpUsdc]),
+        takerNftQuantities: [new anchor.BN(1)],
+      }),
+      {
+        accounts: {
+          initializer: initializerMainAccount.publicKey,
+          taker: takerMainAccount.publicKey,
+          escrowAccount: escrowAccountPda,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+          vaultAuthority: vaultAuthorityPda,
+        },
+        remainingAccounts: [
+          {
+            pubkey: takerTokenAccountA.address,
+            isWritable: false,
+            isSigner: false,
+          },
+          { pubkey: mintA, isWritable: false, isSigner: false },
+          {
+            pubkey: initializerTokenAccountUsdc.address,
+            isWritable: true,
+            isSigner: false,
+          },
+          {
+            pubkey: fungibleVaultAccountPdaUsdc,
+            isWritable: true,
+            isSigner: false,
+          },
+          { pubkey: mintUsdc, isWritable: false, isSigner: false },
+        ],
+        signers: [initializerMainAccount],
+      }
+    );
+
+    const _fungibleVaultUsdc = await getAccount(
+      provider.connection,
+      fungibleVaultAccountPdaUsdc
+    );
+    assert.ok(Number(_fungibleVaultUsdc.amount) === initializerUsdcAmount);
+    const _escrowAccount = await program.account.escrowAccount.fetch(
+      escrowAccountPda
+    );
+    assert.ok(_escrowAccount.initializerFungibleLegs[0].mint.equals(mintUsdc));
+    assert.ok(_escrowAccount.initializerFungibleLegs[0].decimals === usdcDecimals);
+
+    await program.rpc.exchange(new anchor.BN(0), new anchor.BN(0), [], {
+      accounts: {
+        taker: takerMainAccount.publicKey,
+        initializer: initializerMainAccount.publicKey,
+        escrowAccount: escrowAccountPda,
+        vaultAuthority: vaultAuthorityPda,
+        config: configPda,
+        treasury: treasury.publicKey,
+        referrer: initializerMainAccount.publicKey, // referrerがいない場合は使われない
+        tokenProgram: TOKEN_PROGRAM_ID,
+        token2022Program: TOKEN_2022_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      remainingAccounts: [
+        {
+          pubkey: initializerTokenAccountA.address,
+          isWritable: true,
+          isSigner: false,
+        },
+        { pubkey: mintA, isWritable: false, isSigner: false },
+        {
+          pubkey: takerTokenAccountA.address,
+          isWritable: true,
+          isSigner: false,
+        },
+        { pubkey: mintA, isWritable: false, isSigner: false },
+        {
+          pubkey: fungibleVaultAccountPdaUsdc,
+          isWritable: true,
+          isSigner: false,
+        },
+        {
+          pubkey: takerTokenAccountUsdc.address,
+          isWritable: true,
+          isSigner: false,
+        },
+        { pubkey: mintUsdc, isWritable: false, isSigner: false },
+      ],
+      signers: [takerMainAccount],
+    });
+
+    const _initializerTokenAccountA = await getAccount(
+      provider.connection,
+      initializerTokenAccountA.address
+    );
+    assert.ok(Number(_initializerTokenAccountA.amount) === 1);
+    const _takerTokenAccountUsdc = await getAccount(
+      provider.connection,
+      takerTokenAccountUsdc.address
+    );
+    assert.ok(Number(_takerTokenAccountUsdc.amount) === initializerUsdcAmount);
+    const _vaultUsdc = await provider.connection.getAccountInfo(
+      fungibleVaultAccountPdaUsdc
+    );
+    assert.ok(_vaultUsdc === null);
+    // initializerがA B E takerがDを持っている
+  });
+
+  it("Initialize escrow with semi-fungible tokens and exchange", async () => {
+    // initializerがA B EのNFT takerがDのNFTを持っている
+    // initializerはSFTを3つ預けてtakerのDを要求する
+    const mintSft = await createMint(
+      provider.connection,
+      payer,
+      mintAuthority.publicKey,
+      null,
+      0
+    );
+    const initializerTokenAccountSft = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      initializerMainAccount,
+      mintSft,
+      initializerMainAccount.publicKey
+    );
+    const takerTokenAccountSft = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      takerMainAccount,
+      mintSft,
+      takerMainAccount.publicKey
+    );
+    await mintTo(
+      provider.connection,
+      initializerMainAccount,
+      mintSft,
+      initializerTokenAccountSft.address,
+      mintAuthority,
+      10,
+      [mintAuthority]
+    );
+    const sftQuantity = 3;
+
+    escrowNonce += 1;
+    const [_escrowAccountPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("escrow"),
+        initializerMainAccount.publicKey.toBuffer(),
+        takerMainAccount.publicKey.toBuffer(),
+        new anchor.BN(escrowNonce).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    escrowAccountPda = _escrowAccountPda;
+
+    const [_vaultAuthorityPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("vault-authority"),
+        escrowAccountPda.toBuffer(),
+      ],
+      program.programId
+    );
+    vaultAuthorityPda = _vaultAuthorityPda;
+
+    const [vaultAccountPdaSft, vaultAccountBumpSft] =
+      await PublicKey.findProgramAddress(
+        [
+          Buffer.from(anchor.utils.bytes.utf8.encode("vault-account")),
+          escrowAccountPda.toBuffer(),
+          initializerTokenAccountSft.address.toBuffer(),
+        ],
+        program.programId
+      );
+
+    await program.rpc.initialize(
+      initializeArgs({
+        initializerNftAmount: 1,
+        takerNftAmount: 1,
+        vaultAccountBumps: Buffer.from([vaultAccountBumpSft]),
+        initializerNftQuantities: [new anchor.BN(sftQuantity)],
+        takerNftQuantities: [new anchor.BN(1)],
+      }),
+      {
+        accounts: {
+          initializer: initializerMainAccount.publicKey,
+          taker: takerMainAccount.publicKey,
+          escrowAccount: escrowAccountPda,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+          vaultAuthority: vaultAuthorityPda,
+        },
+        remainingAccounts: [
+          {
+            pubkey: initializerTokenAccountSft.address,
+            isWritable: true,
+            isSigner: false,
+          },
+          { pubkey: vaultAccountPdaSft, isWritable: true, isSigner: false },
+          { pubkey: mintSft, isWritable: false, isSigner: false },
+          {
+            pubkey: takerTokenAccountD.address,
+            isWritable: false,
+            isSigner: false,
+          },
+          { pubkey: mintD, isWritable: false, isSigner: false },
+        ],
+        signers: [initializerMainAccount],
+      }
+    );
+
+    // 預けた分だけvaultにあり、残りはinitializerが持ったまま
+    const _vaultSft = await getAccount(provider.connection, vaultAccountPdaSft);
+    assert.ok(Number(_vaultSft.amount) === sftQuantity);
+    let _initializerTokenAccountSft = await getAccount(
+      provider.connection,
+      initializerTokenAccountSft.address
+    );
+    assert.ok(Number(_initializerTokenAccountSft.amount) === 10 - sftQuantity);
+
+    // 同じtoken accountの残りのSFTを別のescrowにも預けられる vaultはescrowごとに分かれる
+    const splitEscrowNonce = escrowNonce + 1;
+    const [splitEscrowAccountPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("escrow"),
+        initializerMainAccount.publicKey.toBuffer(),
+        takerMainAccount.publicKey.toBuffer(),
+        new anchor.BN(splitEscrowNonce).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const [splitVaultAuthorityPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("vault-authority"),
+        splitEscrowAccountPda.toBuffer(),
+      ],
+      program.programId
+    );
+    const [splitVaultAccountPdaSft, splitVaultAccountBumpSft] =
+      await PublicKey.findProgramAddress(
+        [
+          Buffer.from(anchor.utils.bytes.utf8.encode("vault-account")),
+          splitEscrowAccountPda.toBuffer(),
+          initializerTokenAccountSft.address.toBuffer(),
+        ],
+        program.programId
+      );
+    const splitRemainingAccounts = [
+      {
+        pubkey: initializerTokenAccountSft.address,
+        isWritable: true,
+        isSigner: false,
+      },
+      { pubkey: splitVaultAccountPdaSft, isWritable: true, isSigner: false },
+      { pubkey: mintSft, isWritable: false, isSigner: false },
+    ];
+    const splitQuantity = 2;
+    await program.rpc.initialize(
+      initializeArgs({
+        takerAdditionalSolAmount: new anchor.BN(1_000), // takerの資産としてsolだけ要求する,
+        initializerNftAmount: 1,
+        vaultAccountBumps: Buffer.from([splitVaultAccountBumpSft]),
+        nonce: new anchor.BN(splitEscrowNonce),
+        initializerNftQuantities: [new anchor.BN(splitQuantity)],
+      }),
+      {
+        accounts: {
+          initializer: initializerMainAccount.publicKey,
+          taker: takerMainAccount.publicKey,
+          escrowAccount: splitEscrowAccountPda,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+          vaultAuthority: splitVaultAuthorityPda,
+        },
+        remainingAccounts: splitRemainingAccounts,
+        signers: [initializerMainAccount],
+      }
+    );
+    const _splitVaultSft = await getAccount(
+      provider.connection,
+      splitVaultAccountPdaSft
+    );
+    assert.ok(Number(_splitVaultSft.amount) === splitQuantity);
+    _initializerTokenAccountSft = await getAccount(
+      provider.connection,
+      initializerTokenAccountSft.address
+    );
+    assert.ok(
+      Number(_initializerTokenAccountSft.amount) ===
+        10 - sftQuantity - splitQuantity
+    );
+
+    // 2つ目のescrowをcancelしても1つ目のvaultはそのまま
+    await program.rpc.cancelByInitializer([], {
+      accounts: {
+        initializer: initializerMainAccount.publicKey,
+        taker: takerMainAccount.publicKey,
+        vaultAuthority: splitVaultAuthorityPda,
+        escrowAccount: splitEscrowAccountPda,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        token2022Program: TOKEN_2022_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      remainingAccounts: splitRemainingAccounts,
+      signers: [initializerMainAccount],
+    });
+    _initializerTokenAccountSft = await getAccount(
+      provider.connection,
+      initializerTokenAccountSft.address
+    );
+    assert.ok(Number(_initializerTokenAccountSft.amount) === 10 - sftQuantity);
+    assert.ok(
+      Number(
+        (await getAccount(provider.connection, vaultAccountPdaSft)).amount
+      ) === sftQuantity
+    );
+
+    await program.rpc.exchange(new anchor.BN(0), new anchor.BN(0), [], {
+      accounts: {
+        taker: takerMainAccount.publicKey,
+        initializer: initializerMainAccount.publicKey,
+        escrowAccount: escrowAccountPda,
+        vaultAuthority: vaultAuthorityPda,
+        config: configPda,
+        treasury: treasury.publicKey,
+        referrer: initializerMainAccount.publicKey, // referrerがいない場合は使われない
+        tokenProgram: TOKEN_PROGRAM_ID,
+        token2022Program: TOKEN_2022_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      remainingAccounts: [
+        {
+          pubkey: initializerTokenAccountSft.address,
+          isWritable: false,
+          isSigner: false,
+        },
+        { pubkey: vaultAccountPdaSft, isWritable: true, isSigner: false },
+        { pubkey: mintSft, isWritable: false, isSigner: false },
+        {
+          pubkey: initializerTokenAccountD.address,
+          isWritable: true,
+          isSigner: false,
+        },
+        { pubkey: mintD, isWritable: false, isSigner: false },
+        {
+          pubkey: takerTokenAccountSft.address,
+          isWritable: true,
+          isSigner: false,
+        },
+        { pubkey: mintSft, isWritable: false, isSigner: false },
+        {
+          pubkey: takerTokenAccountD.address,
+          isWritable: true,
+          isSigner: false,
+        },
+        { pubkey: mintD, isWritable: false, isSigner: false },
+      ],
+      signers: [takerMainAccount],
+    });
+
+    const _takerTokenAccountSft = await getAccount(
+      provider.connection,
+      takerTokenAccountSft.address
+    );
+    assert.ok(Number(_takerTokenAccountSft.amount) === sftQuantity);
+    const _initializerTokenAccountD = await getAccount(
+      provider.connection,
+      initializerTokenAccountD.address
+    );
+    assert.ok(Number(_initializerTokenAccountD.amount) === 1);
+    // initializerがA B D E takerがSFTを3つ持っている
+  });
+
+  it("Initialize escrow with Token-2022 NFT and exchange", async () => {
+    // initializerがToken-2022のNFTを預けてtakerのSOLを要求する
+    const mint2022 = await createMint(
+      provider.connection,
+      payer,
+      mintAuthority.publicKey,
+      null,
+      0,
+      undefined,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    const initializerTokenAccount2022 = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      initializerMainAccount,
+      mint2022,
+      initializerMainAccount.publicKey,
+      false,
+      undefined,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    const takerTokenAccount2022 = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      takerMainAccount,
+      mint2022,
+      takerMainAccount.publicKey,
+      false,
+      undefined,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    await mintTo(
+      provider.connection,
+      initializerMainAccount,
+      mint2022,
+      initializerTokenAccount2022.address,
+      mintAuthority,
+      1,
+      [mintAuthority],
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    const takerAmount = 1000;
+
+    escrowNonce += 1;
+    const [_escrowAccountPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("escrow"),
+        initializerMainAccount.publicKey.toBuffer(),
+        takerMainAccount.publicKey.toBuffer(),
+        new anchor.BN(escrowNonce).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    escrowAccountPda = _escrowAccountPda;
+
+    const [_vaultAuthorityPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("vault-authority"),
+        escrowAccountPda.toBuffer(),
+      ],
+      program.programId
+    );
+    vaultAuthorityPda = _vaultAuthorityPda;
+
+    const [vaultAccountPda2022, vaultAccountBump2022] =
+      await PublicKey.findProgramAddress(
+        [
+          Buffer.from(anchor.utils.bytes.utf8.encode("vault-account")),
+          escrowAccountPda.toBuffer(),
+          initializerTokenAccount2022.address.toBuffer(),
+        ],
+        program.programId
+      );
+
+    await program.rpc.initialize(
+      initializeArgs({
+        takerAdditionalSolAmount: new anchor.BN(takerAmount),
+        initializerNftAmount: 1,
+        vaultAccountBumps: Buffer.from([vaultAccountBump2022]),
+        initializerNftQuantities: [new anchor.BN(1)],
+      }),
+      {
+        accounts: {
+          initializer: initializerMainAccount.publicKey,
+          taker: takerMainAccount.publicKey,
+          escrowAccount: escrowAccountPda,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+          vaultAuthority: vaultAuthorityPda,
+        },
+        remainingAccounts: [
+          {
+            pubkey: initializerTokenAccount2022.address,
+            isWritable: true,
+            isSigner: false,
+          },
+          { pubkey: vaultAccountPda2022, isWritable: true, isSigner: false },
+          { pubkey: mint2022, isWritable: false, isSigner: false },
+        ],
+        signers: [initializerMainAccount],
+      }
+    );
+
+    // vaultもToken-2022で作られている
+    const _vault2022 = await getAccount(
+      provider.connection,
+      vaultAccountPda2022,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    assert.ok(Number(_vault2022.amount) === 1);
+    assert.ok(_vault2022.owner.equals(vaultAuthorityPda));
+
+    await program.rpc.exchange(new anchor.BN(0), new anchor.BN(takerAmount), [], {
+      accounts: {
+        taker: takerMainAccount.publicKey,
+        initializer: initializerMainAccount.publicKey,
+        escrowAccount: escrowAccountPda,
+        vaultAuthority: vaultAuthorityPda,
+        config: configPda,
+        treasury: treasury.publicKey,
+        referrer: initializerMainAccount.publicKey, // referrerがいない場合は使われない
+        tokenProgram: TOKEN_PROGRAM_ID,
+        token2022Program: TOKEN_2022_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      remainingAccounts: [
+        {
+          pubkey: initializerTokenAccount2022.address,
+          isWritable: false,
+          isSigner: false,
+        },
+        { pubkey: vaultAccountPda2022, isWritable: true, isSigner: false },
+        { pubkey: mint2022, isWritable: false, isSigner: false },
+        {
+          pubkey: takerTokenAccount2022.address,
+          isWritable: true,
+          isSigner: false,
+        },
+        { pubkey: mint2022, isWritable: false, isSigner: false },
+        // royaltyのmetadata Token-2022のNFTにはmetadataがないのでroyaltyなし
+        {
+          pubkey: await getMetadataPda(mint2022),
+          isWritable: false,
+          isSigner: false,
+        },
+      ],
+      signers: [takerMainAccount],
+    });
+
+    const _takerTokenAccount2022 = await getAccount(
+      provider.connection,
+      takerTokenAccount2022.address,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    assert.ok(Number(_takerTokenAccount2022.amount) === 1);
+    const _vault2022AfterExchange = await provider.connection.getAccountInfo(
+      vaultAccountPda2022
+    );
+    assert.ok(_vault2022AfterExchange === null);
+    // initializerがA B D E takerがSFTを3つとToken-2022のNFTを持っている
+  });
+
+  it("Initialize escrow with Token-2022 transfer fee token and exchange", async () => {
+    // initializerとtakerがtransfer feeのあるtokenを払い合い、takerはSOLも払う
+    const feeDecimals = 6;
+    const feeBasisPoints = 100; // 1%
+    const mintFeeKeypair = anchor.web3.Keypair.generate();
+    const mintFee = mintFeeKeypair.publicKey;
+    const mintLen = getMintLen([ExtensionType.TransferFeeConfig]);
+    const mintLamports =
+      await provider.connection.getMinimumBalanceForRentExemption(mintLen);
+    await provider.send(
+      (() => {
+        const tx = new Transaction();
+        tx.add(
+          SystemProgram.createAccount({
+            fromPubkey: payer.publicKey,
+            newAccountPubkey: mintFee,
+            space: mintLen,
+            lamports: mintLamports,
+            programId: TOKEN_2022_PROGRAM_ID,
+          }),
+          createInitializeTransferFeeConfigInstruction(
+            mintFee,
+            mintAuthority.publicKey,
+            mintAuthority.publicKey,
+            feeBasisPoints,
+            BigInt(10 ** feeDecimals),
+            TOKEN_2022_PROGRAM_ID
+          ),
+          createInitializeMintInstruction(
+            mintFee,
+            feeDecimals,
+            mintAuthority.publicKey,
+            null,
+            TOKEN_2022_PROGRAM_ID
+          )
+        );
+        return tx;
+      })(),
+      [payer, mintFeeKeypair]
+    );
+    const initializerTokenAccountFee = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      initializerMainAccount,
+      mintFee,
+      initializerMainAccount.publicKey,
+      false,
+      undefined,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    const takerTokenAccountFee = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      takerMainAccount,
+      mintFee,
+      takerMainAccount.publicKey,
+      false,
+      undefined,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    await mintTo(
+      provider.connection,
+      initializerMainAccount,
+      mintFee,
+      initializerTokenAccountFee.address,
+      mintAuthority,
+      100 * 10 ** feeDecimals,
+      [mintAuthority],
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    await mintTo(
+      provider.connection,
+      takerMainAccount,
+      mintFee,
+      takerTokenAccountFee.address,
+      mintAuthority,
+      10 * 10 ** feeDecimals,
+      [mintAuthority],
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    const initializerFeeAmount = 10 * 10 ** feeDecimals;
+    const takerFeeAmount = 5 * 10 ** feeDecimals;
+    const takerAmount = 1000;
+    // 受け取る側にamountが届くように上乗せして送る量 programと同じく切り上げる
+    const maximumFee = 10 ** feeDecimals;
+    const withFee = (amount: number) =>
+      amount +
+      Math.min(
+        Math.ceil((amount * 10000) / (10000 - feeBasisPoints)) - amount,
+        maximumFee
+      );
+
+    escrowNonce += 1;
+    const [_escrowAccountPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("escrow"),
+        initializerMainAccount.publicKey.toBuffer(),
+        takerMainAccount.publicKey.toBuffer(),
+        new anchor.BN(escrowNonce).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    escrowAccountPda = _escrowAccountPda;
+
+    const [_vaultAuthorityPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("vault-authority"),
+        escrowAccountPda.toBuffer(),
+      ],
+      program.programId
+    );
+    vaultAuthorityPda = _vaultAuthorityPda;
+
+    const [fungibleVaultAccountPdaFee, fungibleVaultAccountBumpFee] =
+      await PublicKey.findProgramAddress(
+        [
+          Buffer.from(anchor.utils.bytes.utf8.encode("fungible-vault-account")),
+          escrowAccountPda.toBuffer(),
+          mintFee.toBuffer(),
+        ],
+        program.programId
+      );
+
+    await program.rpc.initialize(
+      initializeArgs({
+        takerAdditionalSolAmount: new anchor.BN(takerAmount),
+        initializerFungibleAmounts: [new anchor.BN(initializerFeeAmount)],
+        fungibleVaultAccountBumps: Buffer.from([fungibleVaultAccountBumpFee]),
+        takerFungibleAmounts: [new anchor.BN(takerFeeAmount)],
+      }),
+      {
+        accounts: {
+          initializer: initializerMainAccount.publicKey,
+          taker: takerMainAccount.publicKey,
+          escrowAccount: escrowAccountPda,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+          vaultAuthority: vaultAuthorityPda,
+        },
+        remainingAccounts: [
+          {
+            pubkey: initializerTokenAccountFee.address,
+            isWritable: true,
+            isSigner: false,
+          },
+          {
+            pubkey: fungibleVaultAccountPdaFee,
+            isWritable: true,
+            isSigner: false,
+          },
+          { pubkey: mintFee, isWritable: false, isSigner: false },
+          // takerのfungible tokenのmint
+          { pubkey: mintFee, isWritable: false, isSigner: false },
+        ],
+        signers: [initializerMainAccount],
+      }
+    );
+
+    // vaultからtakerに送るときのfeeの分もvaultに入れ、legにはtakerに届く量が記録される
+    const depositFeeAmount = withFee(withFee(initializerFeeAmount));
+    const _escrowAccount = await program.account.escrowAccount.fetch(
+      escrowAccountPda
+    );
+    assert.ok(
+      _escrowAccount.initializerFungibleLegs[0].amount.toNumber() ===
+        initializerFeeAmount
+    );
+    const _vaultFeeAfterInitialize = await getAccount(
+      provider.connection,
+      fungibleVaultAccountPdaFee,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    assert.ok(
+      Number(_vaultFeeAfterInitialize.amount) ===
+        withFee(initializerFeeAmount)
+    );
+
+    await program.rpc.exchange(new anchor.BN(0), new anchor.BN(takerAmount), [], {
+      accounts: {
+        taker: takerMainAccount.publicKey,
+        initializer: initializerMainAccount.publicKey,
+        escrowAccount: escrowAccountPda,
+        vaultAuthority: vaultAuthorityPda,
+        config: configPda,
+        treasury: treasury.publicKey,
+        referrer: initializerMainAccount.publicKey, // referrerがいない場合は使われない
+        tokenProgram: TOKEN_PROGRAM_ID,
+        token2022Program: TOKEN_2022_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      remainingAccounts: [
+        {
+          pubkey: fungibleVaultAccountPdaFee,
+          isWritable: true,
+          isSigner: false,
+        },
+        {
+          pubkey: takerTokenAccountFee.address,
+          isWritable: true,
+          isSigner: false,
+        },
+        // vaultにwithholdされたfeeをmintに回収するのでwritable
+        { pubkey: mintFee, isWritable: true, isSigner: false },
+        {
+          pubkey: takerTokenAccountFee.address,
+          isWritable: true,
+          isSigner: false,
+        },
+        {
+          pubkey: initializerTokenAccountFee.address,
+          isWritable: true,
+          isSigner: false,
+        },
+        { pubkey: mintFee, isWritable: true, isSigner: false },
+      ],
+      signers: [takerMainAccount],
+    });
+
+    const _takerTokenAccountFee = await getAccount(
+      provider.connection,
+      takerTokenAccountFee.address,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    // お互いに要求した量がそのまま届く
+    assert.ok(
+      Number(_takerTokenAccountFee.amount) ===
+        10 * 10 ** feeDecimals - withFee(takerFeeAmount) + initializerFeeAmount
+    );
+    const _initializerTokenAccountFee = await getAccount(
+      provider.connection,
+      initializerTokenAccountFee.address,
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    assert.ok(
+      Number(_initializerTokenAccountFee.amount) ===
+        100 * 10 ** feeDecimals - depositFeeAmount + takerFeeAmount
+    );
+    const _vaultFee = await provider.connection.getAccountInfo(
+      fungibleVaultAccountPdaFee
+    );
+    assert.ok(_vaultFee === null);
+  });
+
+  it("Initialize escrow with pNFT fails with wrong programs", async () => {
+    // pNFTのprogramのaccountの検証だけを確認する
+    const mintPnft = await createMint(
+      provider.connection,
+      payer,
+      mintAuthority.publicKey,
+      null,
+      0
+    );
+    const initializerTokenAccountPnft = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      initializerMainAccount,
+      mintPnft,
+      initializerMainAccount.publicKey
+    );
+    await mintTo(
+      provider.connection,
+      initializerMainAccount,
+      mintPnft,
+      initializerTokenAccountPnft.address,
+      mintAuthority,
+      1,
+      [mintAuthority]
+    );
+
+    escrowNonce += 1;
+    const [_escrowAccountPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("escrow"),
+        initializerMainAccount.publicKey.toBuffer(),
+        takerMainAccount.publicKey.toBuffer(),
+        new anchor.BN(escrowNonce).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const [_vaultAuthorityPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("vault-authority"),
+        _escrowAccountPda.toBuffer(),
+      ],
+      program.programId
+    );
+    // pNFTはvault authorityのATAに預ける
+    const vaultPnft = await getAssociatedTokenAddress(
+      mintPnft,
+      _vaultAuthorityPda,
+      true
+    );
+    const metadataPnft = await getMetadataPda(mintPnft);
+    const editionPnft = await getMasterEditionPda(mintPnft);
+
+    try {
+      await program.rpc.initialize(
+        initializeArgs({
+          takerAdditionalSolAmount: new anchor.BN(takerAdditionalSolAmount),
+          initializerPnftAmount: 1, // pNFTを1つ預ける
+        }),
+        {
+          accounts: {
+            initializer: initializerMainAccount.publicKey,
+            taker: takerMainAccount.publicKey,
+            escrowAccount: _escrowAccountPda,
+            systemProgram: anchor.web3.SystemProgram.programId,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            token2022Program: TOKEN_2022_PROGRAM_ID,
+            vaultAuthority: _vaultAuthorityPda,
+          },
+          remainingAccounts: [
+            // Token Metadataの代わりにtoken programを渡す
+            { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+            {
+              pubkey: anchor.web3.SystemProgram.programId,
+              isWritable: false,
+              isSigner: false,
+            },
+            {
+              pubkey: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+              isWritable: false,
+              isSigner: false,
+            },
+            { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+            {
+              pubkey: ASSOCIATED_TOKEN_PROGRAM_ID,
+              isWritable: false,
+              isSigner: false,
+            },
+            {
+              pubkey: new PublicKey(
+                "auth9SigNpDKz4sJJ1DfCTuZrZNSAgh9sFD3rboVmgg"
+              ),
+              isWritable: false,
+              isSigner: false,
+            },
+            {
+              pubkey: initializerTokenAccountPnft.address,
+              isWritable: true,
+              isSigner: false,
+            },
+            { pubkey: vaultPnft, isWritable: true, isSigner: false },
+            { pubkey: mintPnft, isWritable: false, isSigner: false },
+            { pubkey: metadataPnft, isWritable: true, isSigner: false },
+            { pubkey: editionPnft, isWritable: false, isSigner: false },
+            {
+              pubkey: await getTokenRecordPda(
+                mintPnft,
+                initializerTokenAccountPnft.address
+              ),
+              isWritable: true,
+              isSigner: false,
+            },
+            {
+              pubkey: await getTokenRecordPda(mintPnft, vaultPnft),
+              isWritable: true,
+              isSigner: false,
+            },
+            // rulesetがない場合はToken Metadataのprogram idを渡す
+            {
+              pubkey: TOKEN_METADATA_PROGRAM_ID,
+              isWritable: false,
+              isSigner: false,
+            },
+          ],
+          signers: [initializerMainAccount],
+        }
+      );
+      assert.fail("initialize with wrong pNFT programs should fail");
+    } catch (err) {
+      assert.equal(err.msg, "PnftProgramMismatch");
+    }
+  });
+
+  it("Initialize escrow with pNFTs, exchange them, expire and cancel by taker", async () => {
+    // pNFTはToken MetadataのCreateとMintで作る ATAは常にfreezeされている
+    const createPnft = async (owner: PublicKey) => {
+      const mint = await createMint(
+        provider.connection,
+        payer,
+        mintAuthority.publicKey,
+        mintAuthority.publicKey,
+        0
+      );
+      const metadata = await getMetadataPda(mint);
+      const edition = await getMasterEditionPda(mint);
+      const tokenAccount = await getAssociatedTokenAddress(mint, owner);
+      await provider.send(
+        new Transaction().add(
+          createCreateInstruction(
+            {
+              metadata,
+              masterEdition: edition,
+              mint,
+              authority: mintAuthority.publicKey,
+              payer: payer.publicKey,
+              updateAuthority: mintAuthority.publicKey,
+              sysvarInstructions: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+              splTokenProgram: TOKEN_PROGRAM_ID,
+            },
+            {
+              createArgs: {
+                __kind: "V1",
+                assetData: {
+                  name: "Pnft",
+                  symbol: "",
+                  uri: "",
+                  sellerFeeBasisPoints: 0,
+                  creators: null,
+                  primarySaleHappened: false,
+                  isMutable: true,
+                  tokenStandard: TokenStandard.ProgrammableNonFungible,
+                  collection: null,
+                  uses: null,
+                  collectionDetails: null,
+                  ruleSet: null, // rulesetなし
+                },
+                decimals: 0,
+                printSupply: { __kind: "Zero" },
+              },
+            }
+          ),
+          createMintInstruction(
+            {
+              token: tokenAccount,
+              tokenOwner: owner,
+              metadata,
+              masterEdition: edition,
+              tokenRecord: await getTokenRecordPda(mint, tokenAccount),
+              mint,
+              authority: mintAuthority.publicKey,
+              payer: payer.publicKey,
+              sysvarInstructions: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+              splTokenProgram: TOKEN_PROGRAM_ID,
+              splAtaProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+            },
+            {
+              mintArgs: { __kind: "V1", amount: 1, authorizationData: null },
+            }
+          )
+        ),
+        [payer, mintAuthority]
+      );
+      return { mint, metadata, edition };
+    };
+    const pnftA = await createPnft(initializerMainAccount.publicKey);
+    const pnftB = await createPnft(takerMainAccount.publicKey);
+
+    // token metadata, system, sysvar instructions, token, associated token, authorization rulesの後に
+    // pNFTごとの[token account, destination token account, mint, metadata, edition, owner token record, destination token record, authorization rules]
+    const pnftProgramAccounts = [
+      { pubkey: TOKEN_METADATA_PROGRAM_ID, isWritable: false, isSigner: false },
+      {
+        pubkey: anchor.web3.SystemProgram.programId,
+        isWritable: false,
+        isSigner: false,
+      },
+      {
+        pubkey: anchor.web3.SYSVAR_INSTRUCTIONS_PUBKEY,
+        isWritable: false,
+        isSigner: false,
+      },
+      { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+      {
+        pubkey: ASSOCIATED_TOKEN_PROGRAM_ID,
+        isWritable: false,
+        isSigner: false,
+      },
+      {
+        pubkey: new PublicKey("auth9SigNpDKz4sJJ1DfCTuZrZNSAgh9sFD3rboVmgg"),
+        isWritable: false,
+        isSigner: false,
+      },
+    ];
+    const pnftItemAccounts = async (
+      pnft: { mint: PublicKey; metadata: PublicKey; edition: PublicKey },
+      owner: PublicKey,
+      destinationOwner: PublicKey
+    ) => {
+      const tokenAccount = await getAssociatedTokenAddress(
+        pnft.mint,
+        owner,
+        true
+      );
+      const destinationTokenAccount = await getAssociatedTokenAddress(
+        pnft.mint,
+        destinationOwner,
+        true
+      );
+      return [
+        { pubkey: tokenAccount, isWritable: true, isSigner: false },
+        { pubkey: destinationTokenAccount, isWritable: true, isSigner: false },
+        { pubkey: pnft.mint, isWritable: false, isSigner: false },
+        { pubkey: pnft.metadata, isWritable: true, isSigner: false },
+        { pubkey: pnft.edition, isWritable: false, isSigner: false },
+        {
+          pubkey: await getTokenRecordPda(pnft.mint, tokenAccount),
+          isWritable: true,
+          isSigner: false,
+        },
+        {
+          pubkey: await getTokenRecordPda(pnft.mint, destinationTokenAccount),
+          isWritable: true,
+          isSigner: false,
+        },
+        // rulesetがない場合はToken Metadataのprogram idを渡す
+        {
+          pubkey: TOKEN_METADATA_PROGRAM_ID,
+          isWritable: false,
+          isSigner: false,
+        },
+      ];
+    };
+    const assertPnftAmount = async (mint: PublicKey, owner: PublicKey) => {
+      const _tokenAccount = await getAccount(
+        provider.connection,
+        await getAssociatedTokenAddress(mint, owner, true)
+      );
+      assert.equal(Number(_tokenAccount.amount), 1);
+    };
+    // 払い出した後のvault authorityのATAはcloseされてrentがinitializerに戻る
+    const assertPnftVaultClosed = async (mint: PublicKey) => {
+      assert.equal(
+        await provider.connection.getAccountInfo(
+          await getAssociatedTokenAddress(mint, vaultAuthorityPda, true)
+        ),
+        null
+      );
+    };
+
+    const initializePnftEscrow = async (
+      pnft: { mint: PublicKey; metadata: PublicKey; edition: PublicKey },
+      takerPnftMints: PublicKey[],
+      takerSolAmount: number,
+      expiresAt: number
+    ) => {
+      escrowNonce += 1;
+      const [_escrowAccountPda] = await PublicKey.findProgramAddress(
+        [
+          anchor.utils.bytes.utf8.encode("escrow"),
+          initializerMainAccount.publicKey.toBuffer(),
+          takerMainAccount.publicKey.toBuffer(),
+          new anchor.BN(escrowNonce).toArrayLike(Buffer, "le", 8),
+        ],
+        program.programId
+      );
+      escrowAccountPda = _escrowAccountPda;
+      const [_vaultAuthorityPda] = await PublicKey.findProgramAddress(
+        [
+          anchor.utils.bytes.utf8.encode("vault-authority"),
+          escrowAccountPda.toBuffer(),
+        ],
+        program.programId
+      );
+      vaultAuthorityPda = _vaultAuthorityPda;
+
+      // pNFTはvault authorityのATAに預ける takerのpNFTはmintだけ
+      await program.rpc.initialize(
+        initializeArgs({
+          takerAdditionalSolAmount: new anchor.BN(takerSolAmount),
+          expiresAt: new anchor.BN(expiresAt),
+          initializerPnftAmount: 1, // pNFTを1つ預ける
+          takerPnftAmount: takerPnftMints.length,
+        }),
+        {
+          accounts: {
+            initializer: initializerMainAccount.publicKey,
+            taker: takerMainAccount.publicKey,
+            escrowAccount: escrowAccountPda,
+            systemProgram: anchor.web3.SystemProgram.programId,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            token2022Program: TOKEN_2022_PROGRAM_ID,
+            vaultAuthority: vaultAuthorityPda,
+          },
+          remainingAccounts: [
+            ...pnftProgramAccounts,
+            ...(await pnftItemAccounts(
+              pnft,
+              initializerMainAccount.publicKey,
+              vaultAuthorityPda
+            )),
+            ...takerPnftMints.map((mint) => ({
+              pubkey: mint,
+              isWritable: false,
+              isSigner: false,
+            })),
+          ],
+          signers: [initializerMainAccount],
+        }
+      );
+    };
+
+    await initializePnftEscrow(pnftA, [pnftB.mint], 0, 0);
+    await assertPnftAmount(pnftA.mint, vaultAuthorityPda);
+
+    // pNFTはinitializerの分、takerの分の順
+    await program.rpc.exchange(
+      new anchor.BN(0),
+      new anchor.BN(0),
+      [], // cNFTなし
+      {
+        accounts: {
+          taker: takerMainAccount.publicKey,
+          initializer: initializerMainAccount.publicKey,
+          escrowAccount: escrowAccountPda,
+          vaultAuthority: vaultAuthorityPda,
+          config: configPda,
+          treasury: treasury.publicKey,
+          referrer: initializerMainAccount.publicKey, // referrerがいない場合は使われない
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        remainingAccounts: [
+          ...pnftProgramAccounts,
+          ...(await pnftItemAccounts(
+            pnftA,
+            vaultAuthorityPda,
+            takerMainAccount.publicKey
+          )),
+          ...(await pnftItemAccounts(
+            pnftB,
+            takerMainAccount.publicKey,
+            initializerMainAccount.publicKey
+          )),
+        ],
+        signers: [takerMainAccount],
+      }
+    );
+    await assertPnftAmount(pnftA.mint, takerMainAccount.publicKey);
+    await assertPnftAmount(pnftB.mint, initializerMainAccount.publicKey);
+    await assertPnftVaultClosed(pnftA.mint);
+
+    // 受け取ったpNFTを期限つきで預け直し、期限後に第三者がexpireしてinitializerに戻す
+    const expiresAt = Math.floor(Date.now() / 1000) + 2;
+    await initializePnftEscrow(pnftB, [], takerAdditionalSolAmount, expiresAt);
+    await assertPnftAmount(pnftB.mint, vaultAuthorityPda);
+    await new Promise((resolve) => setTimeout(resolve, 4000));
+
+    // pNFTのTransferのpayerはexpireを呼んだwallet
+    await program.rpc.expire([], {
+      accounts: {
+        payer: provider.wallet.publicKey, // initializerでもtakerでもないwallet
+        initializer: initializerMainAccount.publicKey,
+        taker: takerMainAccount.publicKey,
+        vaultAuthority: vaultAuthorityPda,
+        escrowAccount: escrowAccountPda,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        token2022Program: TOKEN_2022_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      remainingAccounts: [
+        ...pnftProgramAccounts,
+        ...(await pnftItemAccounts(
+          pnftB,
+          vaultAuthorityPda,
+          initializerMainAccount.publicKey
+        )),
+      ],
+    });
+    await assertPnftAmount(pnftB.mint, initializerMainAccount.publicKey);
+    await assertPnftVaultClosed(pnftB.mint);
+    assert.equal(
+      await provider.connection.getAccountInfo(escrowAccountPda),
+      null
+    );
+
+    // もう一度預け直し、takerがcancelしてinitializerに戻す pNFTのTransferのpayerはtaker
+    await initializePnftEscrow(pnftB, [], takerAdditionalSolAmount, 0);
+    await assertPnftAmount(pnftB.mint, vaultAuthorityPda);
+    await program.rpc.cancelByTaker([], {
+      accounts: {
+        initializer: initializerMainAccount.publicKey,
+        taker: takerMainAccount.publicKey,
+        vaultAuthority: vaultAuthorityPda,
+        escrowAccount: escrowAccountPda,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        token2022Program: TOKEN_2022_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [takerMainAccount],
+      remainingAccounts: [
+        ...pnftProgramAccounts,
+        ...(await pnftItemAccounts(
+          pnftB,
+          vaultAuthorityPda,
+          initializerMainAccount.publicKey
+        )),
+      ],
+    });
+    await assertPnftAmount(pnftB.mint, initializerMainAccount.publicKey);
+    await assertPnftVaultClosed(pnftB.mint);
+    assert.equal(
+      await provider.connection.getAccountInfo(escrowAccountPda),
+      null
+    );
+  });
+
+  it("Initialize escrow with cNFT fails with wrong programs", async () => {
+    // merkle treeを作る前にcNFTのprogramのaccountの検証で失敗することを確認する
+    const merkleTree = anchor.web3.Keypair.generate().publicKey;
+    const [treeAuthority] = await PublicKey.findProgramAddress(
+      [merkleTree.toBuffer()],
+      BUBBLEGUM_PROGRAM_ID
+    );
+
+    escrowNonce += 1;
+    const [_escrowAccountPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("escrow"),
+        initializerMainAccount.publicKey.toBuffer(),
+        takerMainAccount.publicKey.toBuffer(),
+        new anchor.BN(escrowNonce).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const [_vaultAuthorityPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("vault-authority"),
+        _escrowAccountPda.toBuffer(),
+      ],
+      program.programId
+    );
+
+    try {
+      await program.rpc.initialize(
+        initializeArgs({
+          takerAdditionalSolAmount: new anchor.BN(takerAdditionalSolAmount),
+          initializerCnftLeaves: [
+            {
+              root: Array(32).fill(0),
+              dataHash: Array(32).fill(0),
+              creatorHash: Array(32).fill(0),
+              nonce: new anchor.BN(0),
+              index: 0,
+              proofLen: 0, // canopyにproofがすべて入っている場合
+            },
+          ],
+        }),
+        {
+          accounts: {
+            initializer: initializerMainAccount.publicKey,
+            taker: takerMainAccount.publicKey,
+            escrowAccount: _escrowAccountPda,
+            systemProgram: anchor.web3.SystemProgram.programId,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            token2022Program: TOKEN_2022_PROGRAM_ID,
+            vaultAuthority: _vaultAuthorityPda,
+          },
+          remainingAccounts: [
+            // Bubblegumの代わりにtoken programを渡す
+            { pubkey: TOKEN_PROGRAM_ID, isWritable: false, isSigner: false },
+            {
+              pubkey: SPL_ACCOUNT_COMPRESSION_PROGRAM_ID,
+              isWritable: false,
+              isSigner: false,
+            },
+            { pubkey: SPL_NOOP_PROGRAM_ID, isWritable: false, isSigner: false },
+            {
+              pubkey: anchor.web3.SystemProgram.programId,
+              isWritable: false,
+              isSigner: false,
+            },
+            { pubkey: merkleTree, isWritable: true, isSigner: false },
+            { pubkey: treeAuthority, isWritable: false, isSigner: false },
+          ],
+          signers: [initializerMainAccount],
+        }
+      );
+      assert.fail("initialize with wrong cNFT programs should fail");
+    } catch (err) {
+      assert.equal(err.msg, "CnftProgramMismatch");
+    }
+  });
+
+  it("Initialize escrow with cNFTs, exchange them and cancel", async () => {
+    // canopyにtree全体を入れておくとproofを渡さなくてよい
+    const merkleTreeKeypair = anchor.web3.Keypair.generate();
+    const merkleTree = merkleTreeKeypair.publicKey;
+    const [treeAuthority] = await PublicKey.findProgramAddress(
+      [merkleTree.toBuffer()],
+      BUBBLEGUM_PROGRAM_ID
+    );
+    const maxDepthSizePair = { maxDepth: 3, maxBufferSize: 8 };
+    await provider.send(
+      new Transaction().add(
+        await createAllocTreeIx(
+          provider.connection,
+          merkleTree,
+          payer.publicKey,
+          maxDepthSizePair,
+          maxDepthSizePair.maxDepth
+        ),
+        createCreateTreeInstruction(
+          {
+            treeAuthority,
+            merkleTree,
+            payer: payer.publicKey,
+            treeCreator: payer.publicKey,
+            logWrapper: SPL_NOOP_PROGRAM_ID,
+            compressionProgram: SPL_ACCOUNT_COMPRESSION_PROGRAM_ID,
+          },
+          { ...maxDepthSizePair, public: false }
+        )
+      ),
+      [payer, merkleTreeKeypair]
+    );
+
+    // mintした順にnonceとleafのindexが決まる
+    type Cnft = { nonce: number; metadata: MetadataArgs; assetId: PublicKey };
+    let mintedCount = 0;
+    const mintCnft = async (owner: PublicKey, name: string): Promise<Cnft> => {
+      const metadata: MetadataArgs = {
+        name,
+        symbol: "",
+        uri: "",
+        sellerFeeBasisPoints: 0,
+        primarySaleHappened: false,
+        isMutable: false,
+        editionNonce: null,
+        tokenStandard: BubblegumTokenStandard.NonFungible,
+        collection: null,
+        uses: null,
+        tokenProgramVersion: TokenProgramVersion.Original,
+        creators: [],
+      };
+      await provider.send(
+        new Transaction().add(
+          createMintV1Instruction(
+            {
+              treeAuthority,
+              leafOwner: owner,
+              leafDelegate: owner,
+              merkleTree,
+              payer: payer.publicKey,
+              treeDelegate: payer.publicKey,
+              logWrapper: SPL_NOOP_PROGRAM_ID,
+              compressionProgram: SPL_ACCOUNT_COMPRESSION_PROGRAM_ID,
+            },
+            { message: metadata }
+          )
+        ),
+        [payer]
+      );
+      const nonce = mintedCount;
+      mintedCount += 1;
+      const assetId = await getLeafAssetId(merkleTree, new anchor.BN(nonce));
+      return { nonce, metadata, assetId };
+    };
+
+    const getCurrentRoot = async () =>
+      (
+        await ConcurrentMerkleTreeAccount.fromAccountAddress(
+          provider.connection,
+          merkleTree
+        )
+      ).getCurrentRoot();
+    const getCnftLeaf = async (cnft: Cnft) => ({
+      root: Array.from(await getCurrentRoot()),
+      dataHash: Array.from(computeDataHash(cnft.metadata)),
+      creatorHash: Array.from(computeCreatorHash(cnft.metadata.creators)),
+      nonce: new anchor.BN(cnft.nonce),
+      index: cnft.nonce,
+      proofLen: 0, // canopyにproofがすべて入っている
+    });
+    // leaf ownerが変わるとleafのhashも変わるので、account compressionでleafを検証する
+    const assertCnftOwner = async (cnft: Cnft, owner: PublicKey) => {
+      await provider.send(
+        new Transaction().add(
+          createVerifyLeafIx(merkleTree, {
+            root: await getCurrentRoot(),
+            leaf: computeCompressedNFTHash(
+              cnft.assetId,
+              owner,
+              owner,
+              new anchor.BN(cnft.nonce),
+              cnft.metadata
+            ),
+            leafIndex: cnft.nonce,
+            proof: [],
+          })
+        ),
+        []
+      );
+    };
+
+    const initializerCnft = await mintCnft(
+      initializerMainAccount.publicKey,
+      "Initializer"
+    );
+    const takerCnft = await mintCnft(takerMainAccount.publicKey, "Taker");
+
+    // bubblegum, account compression, noop, systemの後にleafごとの[merkle tree, tree authority]
+    const cnftProgramAccounts = [
+      { pubkey: BUBBLEGUM_PROGRAM_ID, isWritable: false, isSigner: false },
+      {
+        pubkey: SPL_ACCOUNT_COMPRESSION_PROGRAM_ID,
+        isWritable: false,
+        isSigner: false,
+      },
+      { pubkey: SPL_NOOP_PROGRAM_ID, isWritable: false, isSigner: false },
+      {
+        pubkey: anchor.web3.SystemProgram.programId,
+        isWritable: false,
+        isSigner: false,
+      },
+    ];
+    const cnftItemAccounts = [
+      { pubkey: merkleTree, isWritable: true, isSigner: false },
+      { pubkey: treeAuthority, isWritable: false, isSigner: false },
+    ];
+
+    const initializeCnftEscrow = async (
+      cnft: Cnft,
+      takerCnftAssets: PublicKey[],
+      takerSolAmount: number
+    ) => {
+      escrowNonce += 1;
+      const [_escrowAccountPda] = await PublicKey.findProgramAddress(
+        [
+          anchor.utils.bytes.utf8.encode("escrow"),
+          initializerMainAccount.publicKey.toBuffer(),
+          takerMainAccount.publicKey.toBuffer(),
+          new anchor.BN(escrowNonce).toArrayLike(Buffer, "le", 8),
+        ],
+        program.programId
+      );
+      escrowAccountPda = _escrowAccountPda;
+      const [_vaultAuthorityPda] = await PublicKey.findProgramAddress(
+        [
+          anchor.utils.bytes.utf8.encode("vault-authority"),
+          escrowAccountPda.toBuffer(),
+        ],
+        program.programId
+      );
+      vaultAuthorityPda = _vaultAuthorityPda;
+
+      await program.rpc.initialize(
+        initializeArgs({
+          takerAdditionalSolAmount: new anchor.BN(takerSolAmount),
+          initializerCnftLeaves: [await getCnftLeaf(cnft)],
+          takerCnftAssets,
+        }),
+        {
+          accounts: {
+            initializer: initializerMainAccount.publicKey,
+            taker: takerMainAccount.publicKey,
+            escrowAccount: escrowAccountPda,
+            systemProgram: anchor.web3.SystemProgram.programId,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            token2022Program: TOKEN_2022_PROGRAM_ID,
+            vaultAuthority: vaultAuthorityPda,
+          },
+          remainingAccounts: [...cnftProgramAccounts, ...cnftItemAccounts],
+          signers: [initializerMainAccount],
+        }
+      );
+    };
+
+    // initializerのcNFTはvault authorityが持ち、takerのcNFTはexchangeまでtakerが持つ
+    await initializeCnftEscrow(initializerCnft, [takerCnft.assetId], 0);
+    await assertCnftOwner(initializerCnft, vaultAuthorityPda);
+    const _escrowAccount = await program.account.escrowAccount.fetch(
+      escrowAccountPda
+    );
+    assert.ok(
+      _escrowAccount.initializerCnftAssets[0].equals(initializerCnft.assetId)
+    );
+    assert.ok(_escrowAccount.takerCnftAssets[0].equals(takerCnft.assetId));
+
+    // leafはinitializerの分、takerの分の順
+    await program.rpc.exchange(
+      new anchor.BN(0),
+      new anchor.BN(0),
+      [await getCnftLeaf(initializerCnft), await getCnftLeaf(takerCnft)],
+      {
+        accounts: {
+          taker: takerMainAccount.publicKey,
+          initializer: initializerMainAccount.publicKey,
+          escrowAccount: escrowAccountPda,
+          vaultAuthority: vaultAuthorityPda,
+          config: configPda,
+          treasury: treasury.publicKey,
+          referrer: initializerMainAccount.publicKey, // referrerがいない場合は使われない
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        remainingAccounts: [
+          ...cnftProgramAccounts,
+          ...cnftItemAccounts,
+          ...cnftItemAccounts,
+        ],
+        signers: [takerMainAccount],
+      }
+    );
+    await assertCnftOwner(initializerCnft, takerMainAccount.publicKey);
+    await assertCnftOwner(takerCnft, initializerMainAccount.publicKey);
+
+    // 受け取ったcNFTを預け直し、cancelでinitializerに戻す
+    await initializeCnftEscrow(takerCnft, [], takerAdditionalSolAmount);
+    await assertCnftOwner(takerCnft, vaultAuthorityPda);
+    await program.rpc.cancelByInitializer([await getCnftLeaf(takerCnft)], {
+      accounts: {
+        initializer: initializerMainAccount.publicKey,
+        taker: takerMainAccount.publicKey,
+        vaultAuthority: vaultAuthorityPda,
+        escrowAccount: escrowAccountPda,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        token2022Program: TOKEN_2022_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      remainingAccounts: [...cnftProgramAccounts, ...cnftItemAccounts],
+      signers: [initializerMainAccount],
+    });
+    await assertCnftOwner(takerCnft, initializerMainAccount.publicKey);
+    assert.equal(
+      await provider.connection.getAccountInfo(escrowAccountPda),
+      null
+    );
+  });
+
+  it("Initialize escrow requesting a non-Core account as Core asset fails", async () => {
+    escrowNonce += 1;
+    const [_escrowAccountPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("escrow"),
+        initializerMainAccount.publicKey.toBuffer(),
+        takerMainAccount.publicKey.toBuffer(),
+        new anchor.BN(escrowNonce).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const [_vaultAuthorityPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("vault-authority"),
+        _escrowAccountPda.toBuffer(),
+      ],
+      program.programId
+    );
+
+    try {
+      await program.rpc.initialize(
+        initializeArgs({
+          initializerAdditionalSolAmount: new anchor.BN(
+            initializerAdditionalSolAmount
+          ),
+          takerCoreAmount: 1, // takerのCore assetを1つ要求する
+        }),
+        {
+          accounts: {
+            initializer: initializerMainAccount.publicKey,
+            taker: takerMainAccount.publicKey,
+            escrowAccount: _escrowAccountPda,
+            systemProgram: anchor.web3.SystemProgram.programId,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            token2022Program: TOKEN_2022_PROGRAM_ID,
+            vaultAuthority: _vaultAuthorityPda,
+          },
+          // Core assetの代わりにSPL tokenのmintを渡す
+          remainingAccounts: [
+            { pubkey: mintB, isWritable: false, isSigner: false },
+          ],
+          signers: [initializerMainAccount],
+        }
+      );
+      assert.fail("initialize with a non-Core asset should fail");
+    } catch (err) {
+      assert.equal(err.msg, "IncorrectAccountInfoOwner");
+    }
+  });
+
+  it("Initialize escrow with Core assets, exchange them, expire and cancel by taker", async () => {
+    const MPL_CORE_PROGRAM_ID = new PublicKey(
+      "CoREENxT6tW1HoK8ypY1SxRMZTcVPm7R94rH4PZNhX7d"
+    );
+    const lengthPrefix = (length: number) => {
+      const buffer = Buffer.alloc(4);
+      buffer.writeUInt32LE(length);
+      return buffer;
+    };
+    // mpl coreのCreateV1 collectionに属さずpluginもないasset
+    // CreateV1Args { data_state: AccountState, name, uri, plugins: None }
+    // 省略するaccountにはmpl coreのprogram idを渡す
+    const omittedAccount = {
+      pubkey: MPL_CORE_PROGRAM_ID,
+      isWritable: false,
+      isSigner: false,
+    };
+    const createCoreAsset = async (owner: PublicKey, name: string) => {
+      const asset = anchor.web3.Keypair.generate();
+      const nameBytes = Buffer.from(name);
+      await provider.send(
+        new Transaction().add(
+          new anchor.web3.TransactionInstruction({
+            programId: MPL_CORE_PROGRAM_ID,
+            keys: [
+              { pubkey: asset.publicKey, isWritable: true, isSigner: true },
+              omittedAccount, // collection
+              omittedAccount, // authority
+              { pubkey: payer.publicKey, isWritable: true, isSigner: true },
+              { pubkey: owner, isWritable: false, isSigner: false },
+              omittedAccount, // update authority
+              {
+                pubkey: anchor.web3.SystemProgram.programId,
+                isWritable: false,
+                isSigner: false,
+              },
+              omittedAccount, // log wrapper
+            ],
+            data: Buffer.concat([
+              Buffer.from([0, 0]), // CreateV1, AccountState
+              lengthPrefix(nameBytes.length),
+              nameBytes,
+              lengthPrefix(0), // uriなし
+              Buffer.from([0]), // pluginなし
+            ]),
+          })
+        ),
+        [payer, asset]
+      );
+      return asset.publicKey;
+    };
+    // BaseAssetV1は[key, owner, ...]
+    const assertCoreOwner = async (asset: PublicKey, owner: PublicKey) => {
+      const _asset = await provider.connection.getAccountInfo(asset);
+      assert.ok(new PublicKey(_asset.data.slice(1, 33)).equals(owner));
+    };
+
+    const assetA = await createCoreAsset(
+      initializerMainAccount.publicKey,
+      "Initializer"
+    );
+    const assetB = await createCoreAsset(takerMainAccount.publicKey, "Taker");
+
+    // mpl core, systemの後にassetごとの[asset, collection] collectionがない場合はmpl coreのprogram id
+    const coreProgramAccounts = [
+      { pubkey: MPL_CORE_PROGRAM_ID, isWritable: false, isSigner: false },
+      {
+        pubkey: anchor.web3.SystemProgram.programId,
+        isWritable: false,
+        isSigner: false,
+      },
+    ];
+    const coreItemAccounts = (asset: PublicKey) => [
+      { pubkey: asset, isWritable: true, isSigner: false },
+      { pubkey: MPL_CORE_PROGRAM_ID, isWritable: false, isSigner: false },
+    ];
+
+    const initializeCoreEscrow = async (
+      asset: PublicKey,
+      takerCoreAssets: PublicKey[],
+      takerSolAmount: number,
+      expiresAt: number
+    ) => {
+      escrowNonce += 1;
+      const [_escrowAccountPda] = await PublicKey.findProgramAddress(
+        [
+          anchor.utils.bytes.utf8.encode("escrow"),
+          initializerMainAccount.publicKey.toBuffer(),
+          takerMainAccount.publicKey.toBuffer(),
+          new anchor.BN(escrowNonce).toArrayLike(Buffer, "le", 8),
+        ],
+        program.programId
+      );
+      escrowAccountPda = _escrowAccountPda;
+      const [_vaultAuthorityPda] = await PublicKey.findProgramAddress(
+        [
+          anchor.utils.bytes.utf8.encode("vault-authority"),
+          escrowAccountPda.toBuffer(),
+        ],
+        program.programId
+      );
+      vaultAuthorityPda = _vaultAuthorityPda;
+
+      // takerのCore assetはassetだけ
+      await program.rpc.initialize(
+        initializeArgs({
+          takerAdditionalSolAmount: new anchor.BN(takerSolAmount),
+          expiresAt: new anchor.BN(expiresAt),
+          initializerCoreAmount: 1, // Core assetを1つ預ける
+          takerCoreAmount: takerCoreAssets.length,
+        }),
+        {
+          accounts: {
+            initializer: initializerMainAccount.publicKey,
+            taker: takerMainAccount.publicKey,
+            escrowAccount: escrowAccountPda,
+            systemProgram: anchor.web3.SystemProgram.programId,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            token2022Program: TOKEN_2022_PROGRAM_ID,
+            vaultAuthority: vaultAuthorityPda,
+          },
+          remainingAccounts: [
+            ...coreProgramAccounts,
+            ...coreItemAccounts(asset),
+            ...takerCoreAssets.map((takerAsset) => ({
+              pubkey: takerAsset,
+              isWritable: false,
+              isSigner: false,
+            })),
+          ],
+          signers: [initializerMainAccount],
+        }
+      );
+    };
+
+    await initializeCoreEscrow(assetA, [assetB], 0, 0);
+    await assertCoreOwner(assetA, vaultAuthorityPda);
+
+    // Core assetはinitializerの分、takerの分の順
+    await program.rpc.exchange(
+      new anchor.BN(0),
+      new anchor.BN(0),
+      [], // cNFTなし
+      {
+        accounts: {
+          taker: takerMainAccount.publicKey,
+          initializer: initializerMainAccount.publicKey,
+          escrowAccount: escrowAccountPda,
+          vaultAuthority: vaultAuthorityPda,
+          config: configPda,
+          treasury: treasury.publicKey,
+          referrer: initializerMainAccount.publicKey, // referrerがいない場合は使われない
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        remainingAccounts: [
+          ...coreProgramAccounts,
+          ...coreItemAccounts(assetA),
+          ...coreItemAccounts(assetB),
+        ],
+        signers: [takerMainAccount],
+      }
+    );
+    await assertCoreOwner(assetA, takerMainAccount.publicKey);
+    await assertCoreOwner(assetB, initializerMainAccount.publicKey);
+
+    // 受け取ったassetを期限つきで預け直し、期限後に第三者がexpireしてinitializerに戻す
+    const expiresAt = Math.floor(Date.now() / 1000) + 2;
+    await initializeCoreEscrow(assetB, [], takerAdditionalSolAmount, expiresAt);
+    await assertCoreOwner(assetB, vaultAuthorityPda);
+    await new Promise((resolve) => setTimeout(resolve, 4000));
+
+    // Core assetのTransferのpayerはexpireを呼んだwallet
+    await program.rpc.expire([], {
+      accounts: {
+        payer: provider.wallet.publicKey, // initializerでもtakerでもないwallet
+        initializer: initializerMainAccount.publicKey,
+        taker: takerMainAccount.publicKey,
+        vaultAuthority: vaultAuthorityPda,
+        escrowAccount: escrowAccountPda,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        token2022Program: TOKEN_2022_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      remainingAccounts: [...coreProgramAccounts, ...coreItemAccounts(assetB)],
+    });
+    await assertCoreOwner(assetB, initializerMainAccount.publicKey);
+    assert.equal(
+      await provider.connection.getAccountInfo(escrowAccountPda),
+      null
+    );
+
+    // もう一度預け直し、takerがcancelしてinitializerに戻す Core assetのTransferのpayerはtaker
+    await initializeCoreEscrow(assetB, [], takerAdditionalSolAmount, 0);
+    await assertCoreOwner(assetB, vaultAuthorityPda);
+    await program.rpc.cancelByTaker([], {
+      accounts: {
+        initializer: initializerMainAccount.publicKey,
+        taker: takerMainAccount.publicKey,
+        vaultAuthority: vaultAuthorityPda,
+        escrowAccount: escrowAccountPda,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        token2022Program: TOKEN_2022_PROGRAM_ID,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      signers: [takerMainAccount],
+      remainingAccounts: [...coreProgramAccounts, ...coreItemAccounts(assetB)],
+    });
+    await assertCoreOwner(assetB, initializerMainAccount.publicKey);
+    assert.equal(
+      await provider.connection.getAccountInfo(escrowAccountPda),
+      null
+    );
+  });
+
+  it("Update config by non-admin fails and exchange charges fee", async () => {
+    try {
+      await program.rpc.updateConfig(
+        takerMainAccount.publicKey,
+        100,
+        new anchor.BN(0),
+        treasury.publicKey,
+        {
+          accounts: {
+            admin: takerMainAccount.publicKey,
+            config: configPda,
+          },
+          signers: [takerMainAccount],
+        }
+      );
+      assert.fail("non-admin should not update config");
+    } catch (err) {
+      assert.equal(err.msg, "AdminPublicKeyMismatch");
+    }
+
+    // 1%の手数料をそれぞれのSOLのlegから差し引く
+    await program.rpc.updateConfig(
+      provider.wallet.publicKey,
+      100,
+      new anchor.BN(0),
+      treasury.publicKey,
+      {
+        accounts: {
+          admin: provider.wallet.publicKey,
+          config: configPda,
+        },
+      }
+    );
+
+    escrowNonce += 1;
+    const [_escrowAccountPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("escrow"),
+        initializerMainAccount.publicKey.toBuffer(),
+        takerMainAccount.publicKey.toBuffer(),
+        new anchor.BN(escrowNonce).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    escrowAccountPda = _escrowAccountPda;
+    const [_vaultAuthorityPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("vault-authority"),
+        escrowAccountPda.toBuffer(),
+      ],
+      program.programId
+    );
+    vaultAuthorityPda = _vaultAuthorityPda;
+
+    const initializerSolAmount = 100_000_000;
+    const takerSolAmount = 200_000_000;
+    await program.rpc.initialize(
+      initializeArgs({
+        initializerAdditionalSolAmount: new anchor.BN(initializerSolAmount),
+        takerAdditionalSolAmount: new anchor.BN(takerSolAmount),
+      }),
+      {
+        accounts: {
+          initializer: initializerMainAccount.publicKey,
+          taker: takerMainAccount.publicKey,
+          escrowAccount: escrowAccountPda,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+          vaultAuthority: vaultAuthorityPda,
+        },
+        signers: [initializerMainAccount],
+      }
+    );
+
+    const treasurySolAmountBefore = await provider.connection.getBalance(
+      treasury.publicKey
+    );
+    await program.rpc.exchange(
+      new anchor.BN(initializerSolAmount),
+      new anchor.BN(takerSolAmount),
+      [], // cNFTなし
+      {
+        accounts: {
+          taker: takerMainAccount.publicKey,
+          initializer: initializerMainAccount.publicKey,
+          escrowAccount: escrowAccountPda,
+          vaultAuthority: vaultAuthorityPda,
+          config: configPda,
+          treasury: treasury.publicKey,
+          referrer: initializerMainAccount.publicKey, // referrerがいない場合は使われない
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        signers: [takerMainAccount],
+      }
+    );
+    const treasurySolAmountAfter = await provider.connection.getBalance(
+      treasury.publicKey
+    );
+    assert.equal(
+      treasurySolAmountAfter - treasurySolAmountBefore,
+      (initializerSolAmount + takerSolAmount) / 100
+    );
+
+    // 後続のテストの残高が変わらないように手数料を0に戻す
+    await program.rpc.updateConfig(
+      provider.wallet.publicKey,
+      0,
+      new anchor.BN(0),
+      treasury.publicKey,
+      {
+        accounts: {
+          admin: provider.wallet.publicKey,
+          config: configPda,
+        },
+      }
+    );
+  });
+
+  it("Initialize escrow with referrer and exchange pays referrer share", async () => {
+    escrowNonce += 1;
+    const [_escrowAccountPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("escrow"),
+        initializerMainAccount.publicKey.toBuffer(),
+        takerMainAccount.publicKey.toBuffer(),
+        new anchor.BN(escrowNonce).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    escrowAccountPda = _escrowAccountPda;
+    const [_vaultAuthorityPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("vault-authority"),
+        escrowAccountPda.toBuffer(),
+      ],
+      program.programId
+    );
+    vaultAuthorityPda = _vaultAuthorityPda;
+
+    const initializerSolAmount = 100_000_000;
+    const takerSolAmount = 200_000_000;
+    const initialize = (referrerBasisPoints: number) =>
+      program.rpc.initialize(
+        initializeArgs({
+          initializerAdditionalSolAmount: new anchor.BN(initializerSolAmount),
+          takerAdditionalSolAmount: new anchor.BN(takerSolAmount),
+          referrerKey: payer.publicKey, // marketplaceのwallet
+          referrerBasisPoints,
+        }),
+        {
+          accounts: {
+            initializer: initializerMainAccount.publicKey,
+            taker: takerMainAccount.publicKey,
+            escrowAccount: escrowAccountPda,
+            systemProgram: anchor.web3.SystemProgram.programId,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            token2022Program: TOKEN_2022_PROGRAM_ID,
+            vaultAuthority: vaultAuthorityPda,
+          },
+          signers: [initializerMainAccount],
+        }
+      );
+
+    // 上限を超える割合は指定できない
+    try {
+      await initialize(600);
+      assert.fail("referrer basis points over the maximum should fail");
+    } catch (err) {
+      assert.equal(err.msg, "InvalidReferrerBasisPoints");
+    }
+
+    // 2%をそれぞれのSOLのlegからreferrerに払う
+    await initialize(200);
+    let _escrowAccount = await program.account.escrowAccount.fetch(
+      escrowAccountPda
+    );
+    assert.ok(_escrowAccount.referrerKey.equals(payer.publicKey));
+    assert.equal(_escrowAccount.referrerBasisPoints, 200);
+
+    const referrerSolAmountBefore = await provider.connection.getBalance(
+      payer.publicKey
+    );
+    await program.rpc.exchange(
+      new anchor.BN(initializerSolAmount),
+      new anchor.BN(takerSolAmount),
+      [], // cNFTなし
+      {
+        accounts: {
+          taker: takerMainAccount.publicKey,
+          initializer: initializerMainAccount.publicKey,
+          escrowAccount: escrowAccountPda,
+          vaultAuthority: vaultAuthorityPda,
+          config: configPda,
+          treasury: treasury.publicKey,
+          referrer: payer.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        signers: [takerMainAccount],
+      }
+    );
+    const referrerSolAmountAfter = await provider.connection.getBalance(
+      payer.publicKey
+    );
+    assert.equal(
+      referrerSolAmountAfter - referrerSolAmountBefore,
+      ((initializerSolAmount + takerSolAmount) * 2) / 100
+    );
+  });
+
+  it("Exchange pays each verified creator its royalty share", async () => {
+    // initializerがroyaltyつきのNFTを預け、takerがSOLで買う
+    const mintRoyalty = await createMint(
+      provider.connection,
+      payer,
+      mintAuthority.publicKey,
+      null,
+      0
+    );
+    const initializerTokenAccountRoyalty =
+      await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        initializerMainAccount,
+        mintRoyalty,
+        initializerMainAccount.publicKey
+      );
+    const takerTokenAccountRoyalty = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      takerMainAccount,
+      mintRoyalty,
+      takerMainAccount.publicKey
+    );
+    await mintTo(
+      provider.connection,
+      payer,
+      mintRoyalty,
+      initializerTokenAccountRoyalty.address,
+      mintAuthority,
+      1
+    );
+
+    // 10%のroyaltyを50:30:20で分ける 20のcreatorはverifiedでないので払われない
+    const verifiedCreator = anchor.web3.Keypair.generate();
+    const unverifiedCreator = anchor.web3.Keypair.generate();
+    const metadataRoyalty = await createMetadata(mintRoyalty, 1000, [
+      { address: mintAuthority.publicKey, share: 50 },
+      {
+        address: verifiedCreator.publicKey,
+        share: 30,
+        signer: verifiedCreator,
+      },
+      { address: unverifiedCreator.publicKey, share: 20 },
+    ]);
+
+    escrowNonce += 1;
+    const [_escrowAccountPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("escrow"),
+        initializerMainAccount.publicKey.toBuffer(),
+        takerMainAccount.publicKey.toBuffer(),
+        new anchor.BN(escrowNonce).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    escrowAccountPda = _escrowAccountPda;
+    const [_vaultAuthorityPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("vault-authority"),
+        escrowAccountPda.toBuffer(),
+      ],
+      program.programId
+    );
+    vaultAuthorityPda = _vaultAuthorityPda;
+    const [vaultAccountPdaRoyalty, vaultAccountBumpRoyalty] =
+      await PublicKey.findProgramAddress(
+        [
+          Buffer.from(anchor.utils.bytes.utf8.encode("vault-account")),
+          escrowAccountPda.toBuffer(),
+          initializerTokenAccountRoyalty.address.toBuffer(),
+        ],
+        program.programId
+      );
+
+    const takerSolAmount = 100_000_000;
+    await program.rpc.initialize(
+      initializeArgs({
+        takerAdditionalSolAmount: new anchor.BN(takerSolAmount),
+        initializerNftAmount: 1,
+        vaultAccountBumps: Buffer.from([vaultAccountBumpRoyalty]),
+        initializerNftQuantities: [new anchor.BN(1)], // NFTなので1
+      }),
+      {
+        accounts: {
+          initializer: initializerMainAccount.publicKey,
+          taker: takerMainAccount.publicKey,
+          escrowAccount: escrowAccountPda,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+          vaultAuthority: vaultAuthorityPda,
+        },
+        remainingAccounts: [
+          {
+            pubkey: initializerTokenAccountRoyalty.address,
+            isWritable: true,
+            isSigner: false,
+          },
+          { pubkey: vaultAccountPdaRoyalty, isWritable: true, isSigner: false },
+          { pubkey: mintRoyalty, isWritable: false, isSigner: false },
+        ],
+        signers: [initializerMainAccount],
+      }
+    );
+
+    const getBalances = () =>
+      Promise.all(
+        [
+          initializerMainAccount.publicKey,
+          mintAuthority.publicKey,
+          verifiedCreator.publicKey,
+          unverifiedCreator.publicKey,
+        ].map((key) => provider.connection.getBalance(key))
+      );
+    const balancesBefore = await getBalances();
+
+    // royaltyはmetadataの後にverifiedのcreatorだけを渡す
+    await program.rpc.exchange(
+      new anchor.BN(0),
+      new anchor.BN(takerSolAmount),
+      [], // cNFTなし
+      {
+        accounts: {
+          taker: takerMainAccount.publicKey,
+          initializer: initializerMainAccount.publicKey,
+          escrowAccount: escrowAccountPda,
+          vaultAuthority: vaultAuthorityPda,
+          config: configPda,
+          treasury: treasury.publicKey,
+          referrer: initializerMainAccount.publicKey, // referrerがいない場合は使われない
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        remainingAccounts: [
+          {
+            pubkey: initializerTokenAccountRoyalty.address,
+            isWritable: true,
+            isSigner: false,
+          },
+          { pubkey: vaultAccountPdaRoyalty, isWritable: true, isSigner: false },
+          { pubkey: mintRoyalty, isWritable: false, isSigner: false },
+          {
+            pubkey: takerTokenAccountRoyalty.address,
+            isWritable: true,
+            isSigner: false,
+          },
+          { pubkey: mintRoyalty, isWritable: false, isSigner: false },
+          { pubkey: metadataRoyalty, isWritable: false, isSigner: false },
+          {
+            pubkey: mintAuthority.publicKey,
+            isWritable: true,
+            isSigner: false,
+          },
+          {
+            pubkey: verifiedCreator.publicKey,
+            isWritable: true,
+            isSigner: false,
+          },
+        ],
+        signers: [takerMainAccount],
+      }
+    );
+
+    const [
+      initializerDelta,
+      mintAuthorityDelta,
+      verifiedCreatorDelta,
+      unverifiedCreatorDelta,
+    ] = (await getBalances()).map(
+      (balance, index) => balance - balancesBefore[index]
+    );
+    const royaltyAmount = takerSolAmount / 10;
+    assert.equal(mintAuthorityDelta, (royaltyAmount * 50) / 100);
+    assert.equal(verifiedCreatorDelta, (royaltyAmount * 30) / 100);
+    assert.equal(unverifiedCreatorDelta, 0);
+    // verifiedでないcreatorの分は売り手のinitializerが受け取る vaultとescrowのrentも戻る
+    assert.ok(initializerDelta >= takerSolAmount - (royaltyAmount * 80) / 100);
+
+    const _takerTokenAccountRoyalty = await getAccount(
+      provider.connection,
+      takerTokenAccountRoyalty.address
+    );
+    assert.equal(Number(_takerTokenAccountRoyalty.amount), 1);
+  });
+
+  it("Settle pays the fee, referrer share and royalties like exchange", async () => {
+    // 1%の手数料をSOLのlegから差し引く
+    await program.rpc.updateConfig(
+      provider.wallet.publicKey,
+      100,
+      new anchor.BN(0),
+      treasury.publicKey,
+      {
+        accounts: {
+          admin: provider.wallet.publicKey,
+          config: configPda,
+        },
+      }
+    );
+
+    // initializerがroyaltyつきのNFTを預け、takerがacceptでSOLを預ける
+    const mintRoyalty = await createMint(
+      provider.connection,
+      payer,
+      mintAuthority.publicKey,
+      null,
+      0
+    );
+    const initializerTokenAccountRoyalty =
+      await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        initializerMainAccount,
+        mintRoyalty,
+        initializerMainAccount.publicKey
+      );
+    const takerTokenAccountRoyalty = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      takerMainAccount,
+      mintRoyalty,
+      takerMainAccount.publicKey
+    );
+    await mintTo(
+      provider.connection,
+      payer,
+      mintRoyalty,
+      initializerTokenAccountRoyalty.address,
+      mintAuthority,
+      1
+    );
+    // 5%のroyaltyを60:40で分ける
+    const creator = anchor.web3.Keypair.generate();
+    const metadataRoyalty = await createMetadata(mintRoyalty, 500, [
+      { address: mintAuthority.publicKey, share: 60 },
+      { address: creator.publicKey, share: 40, signer: creator },
+    ]);
+
+    escrowNonce += 1;
+    const [_escrowAccountPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("escrow"),
+        initializerMainAccount.publicKey.toBuffer(),
+        takerMainAccount.publicKey.toBuffer(),
+        new anchor.BN(escrowNonce).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    escrowAccountPda = _escrowAccountPda;
+    const [_vaultAuthorityPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("vault-authority"),
+        escrowAccountPda.toBuffer(),
+      ],
+      program.programId
+    );
+    vaultAuthorityPda = _vaultAuthorityPda;
+    const [vaultAccountPdaRoyalty, vaultAccountBumpRoyalty] =
+      await PublicKey.findProgramAddress(
+        [
+          Buffer.from(anchor.utils.bytes.utf8.encode("vault-account")),
+          escrowAccountPda.toBuffer(),
+          initializerTokenAccountRoyalty.address.toBuffer(),
+        ],
+        program.programId
+      );
+
+    const takerSolAmount = 100_000_000;
+    await program.rpc.initialize(
+      initializeArgs({
+        takerAdditionalSolAmount: new anchor.BN(takerSolAmount),
+        initializerNftAmount: 1,
+        vaultAccountBumps: Buffer.from([vaultAccountBumpRoyalty]),
+        initializerNftQuantities: [new anchor.BN(1)], // NFTなので1
+        referrerKey: payer.publicKey, // marketplaceのwallet
+        referrerBasisPoints: 200,
+      }),
+      {
+        accounts: {
+          initializer: initializerMainAccount.publicKey,
+          taker: takerMainAccount.publicKey,
+          escrowAccount: escrowAccountPda,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+          vaultAuthority: vaultAuthorityPda,
+        },
+        remainingAccounts: [
+          {
+            pubkey: initializerTokenAccountRoyalty.address,
+            isWritable: true,
+            isSigner: false,
+          },
+          { pubkey: vaultAccountPdaRoyalty, isWritable: true, isSigner: false },
+          { pubkey: mintRoyalty, isWritable: false, isSigner: false },
+        ],
+        signers: [initializerMainAccount],
+      }
+    );
+
+    await program.rpc.accept(new anchor.BN(takerSolAmount), Buffer.from([]), {
+      accounts: {
+        taker: takerMainAccount.publicKey,
+        initializer: initializerMainAccount.publicKey,
+        escrowAccount: escrowAccountPda,
+        vaultAuthority: vaultAuthorityPda,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        token2022Program: TOKEN_2022_PROGRAM_ID,
+      },
+      signers: [takerMainAccount],
+    });
+
+    const getBalances = () =>
+      Promise.all(
+        [
+          treasury.publicKey,
+          payer.publicKey,
+          mintAuthority.publicKey,
+          creator.publicKey,
+        ].map((key) => provider.connection.getBalance(key))
+      );
+    const balancesBefore = await getBalances();
+
+    // initializerがsettleする royaltyのmetadataとverifiedのcreatorはNFTの後に渡す
+    await program.rpc.settle({
+      accounts: {
+        authority: initializerMainAccount.publicKey,
+        initializer: initializerMainAccount.publicKey,
+        taker: takerMainAccount.publicKey,
+        escrowAccount: escrowAccountPda,
+        vaultAuthority: vaultAuthorityPda,
+        config: configPda,
+        treasury: treasury.publicKey,
+        referrer: payer.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        token2022Program: TOKEN_2022_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      remainingAccounts: [
+        {
+          pubkey: initializerTokenAccountRoyalty.address,
+          isWritable: true,
+          isSigner: false,
+        },
+        { pubkey: vaultAccountPdaRoyalty, isWritable: true, isSigner: false },
+        {
+          pubkey: takerTokenAccountRoyalty.address,
+          isWritable: true,
+          isSigner: false,
+        },
+        { pubkey: mintRoyalty, isWritable: false, isSigner: false },
+        { pubkey: metadataRoyalty, isWritable: false, isSigner: false },
+        { pubkey: mintAuthority.publicKey, isWritable: true, isSigner: false },
+        { pubkey: creator.publicKey, isWritable: true, isSigner: false },
+      ],
+      signers: [initializerMainAccount],
+    });
+
+    const [treasuryDelta, referrerDelta, mintAuthorityDelta, creatorDelta] = (
+      await getBalances()
+    ).map((balance, index) => balance - balancesBefore[index]);
+    assert.equal(treasuryDelta, takerSolAmount / 100);
+    assert.equal(referrerDelta, (takerSolAmount * 2) / 100);
+    const royaltyAmount = (takerSolAmount * 5) / 100;
+    assert.equal(mintAuthorityDelta, (royaltyAmount * 60) / 100);
+    assert.equal(creatorDelta, (royaltyAmount * 40) / 100);
+
+    const _takerTokenAccountRoyalty = await getAccount(
+      provider.connection,
+      takerTokenAccountRoyalty.address
+    );
+    assert.equal(Number(_takerTokenAccountRoyalty.amount), 1);
+
+    // 後続のテストの残高が変わらないように手数料を0に戻す
+    await program.rpc.updateConfig(
+      provider.wallet.publicKey,
+      0,
+      new anchor.BN(0),
+      treasury.publicKey,
+      {
+        accounts: {
+          admin: provider.wallet.publicKey,
+          config: configPda,
+        },
+      }
+    );
+  });
+
+  it("Exchange a verified collection NFT chosen by the taker", async () => {
+    const collection = await createCollectionNft();
+    const otherCollection = await createCollectionNft();
+
+    const createMember = async (
+      memberCollection: CollectionNft,
+      verified: boolean
+    ) => {
+      const { mint, metadata, tokenAccount } = await createCollectionMember(
+        memberCollection,
+        takerMainAccount.publicKey,
+        verified
+      );
+      const initializerTokenAccount = await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        payer,
+        mint,
+        initializerMainAccount.publicKey
+      );
+      return {
+        mint,
+        metadata,
+        takerTokenAccount: tokenAccount,
+        initializerTokenAccount: initializerTokenAccount.address,
+      };
+    };
+    const member = await createMember(collection, true);
+    const unverifiedMember = await createMember(collection, false);
+    const otherMember = await createMember(otherCollection, true);
+
+    escrowNonce += 1;
+    const [_escrowAccountPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("escrow"),
+        initializerMainAccount.publicKey.toBuffer(),
+        takerMainAccount.publicKey.toBuffer(),
+        new anchor.BN(escrowNonce).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    escrowAccountPda = _escrowAccountPda;
+    const [_vaultAuthorityPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("vault-authority"),
+        escrowAccountPda.toBuffer(),
+      ],
+      program.programId
+    );
+    vaultAuthorityPda = _vaultAuthorityPda;
+
+    // initializerはSOLを出し、takerにはcollectionのNFTを1つ要求する
+    const initializerSolAmount = 10_000_000;
+    await program.rpc.initialize(
+      initializeArgs({
+        initializerAdditionalSolAmount: new anchor.BN(initializerSolAmount),
+        takerCollectionKey: collection.mint,
+        takerCollectionNftAmount: 1,
+      }),
+      {
+        accounts: {
+          initializer: initializerMainAccount.publicKey,
+          taker: takerMainAccount.publicKey,
+          escrowAccount: escrowAccountPda,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+          vaultAuthority: vaultAuthorityPda,
+        },
+        signers: [initializerMainAccount],
+      }
+    );
+
+    // collectionのNFTはtakerのtoken account, initializerのtoken account, mint, metadataの順
+    // SOLはinitializerから動くので、takerのNFTのroyaltyのmetadataとcreatorを続けて渡す
+    const exchangeCollectionNft = (chosen: {
+      mint: PublicKey;
+      metadata: PublicKey;
+      takerTokenAccount: PublicKey;
+      initializerTokenAccount: PublicKey;
+    }) =>
+      program.rpc.exchange(
+        new anchor.BN(initializerSolAmount),
+        new anchor.BN(0),
+        [], // cNFTなし
+        {
+          accounts: {
+            taker: takerMainAccount.publicKey,
+            initializer: initializerMainAccount.publicKey,
+            escrowAccount: escrowAccountPda,
+            vaultAuthority: vaultAuthorityPda,
+            config: configPda,
+            treasury: treasury.publicKey,
+            referrer: initializerMainAccount.publicKey, // referrerがいない場合は使われない
+            tokenProgram: TOKEN_PROGRAM_ID,
+            token2022Program: TOKEN_2022_PROGRAM_ID,
+            systemProgram: anchor.web3.SystemProgram.programId,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          },
+          remainingAccounts: [
+            {
+              pubkey: chosen.takerTokenAccount,
+              isWritable: true,
+              isSigner: false,
+            },
+            {
+              pubkey: chosen.initializerTokenAccount,
+              isWritable: true,
+              isSigner: false,
+            },
+            { pubkey: chosen.mint, isWritable: false, isSigner: false },
+            { pubkey: chosen.metadata, isWritable: false, isSigner: false },
+            { pubkey: chosen.metadata, isWritable: false, isSigner: false },
+            {
+              pubkey: mintAuthority.publicKey,
+              isWritable: true,
+              isSigner: false,
+            },
+          ],
+          signers: [takerMainAccount],
+        }
+      );
+
+    try {
+      await exchangeCollectionNft(unverifiedMember);
+      assert.fail("exchange with an unverified collection NFT should fail");
+    } catch (err) {
+      assert.equal(err.msg, "UnverifiedCollection");
+    }
+    try {
+      await exchangeCollectionNft(otherMember);
+      assert.fail("exchange with another collection's NFT should fail");
+    } catch (err) {
+      assert.equal(err.msg, "CollectionPublicKeyMismatch");
+    }
+
+    const takerBalanceBefore = await provider.connection.getBalance(
+      takerMainAccount.publicKey
+    );
+    await exchangeCollectionNft(member);
+
+    // royaltyと手数料は0なのでinitializerのSOLはそのままtakerに渡る
+    assert.equal(
+      (await provider.connection.getBalance(takerMainAccount.publicKey)) -
+        takerBalanceBefore,
+      initializerSolAmount
+    );
+    const _initializerTokenAccount = await getAccount(
+      provider.connection,
+      member.initializerTokenAccount
+    );
+    assert.equal(Number(_initializerTokenAccount.amount), 1);
+    const _takerTokenAccount = await getAccount(
+      provider.connection,
+      member.takerTokenAccount
+    );
+    assert.equal(Number(_takerTokenAccount.amount), 0);
+  });
+
+  it("English auction refunds outbid bidder and settles to the winner", async () => {
+    const mintAuction = await createMint(
+      provider.connection,
+      payer,
+      mintAuthority.publicKey,
+      null,
+      0
+    );
+    const sellerTokenAccount = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      payer,
+      mintAuction,
+      initializerMainAccount.publicKey
+    );
+    await mintTo(
+      provider.connection,
+      payer,
+      mintAuction,
+      sellerTokenAccount.address,
+      mintAuthority.publicKey,
+      1,
+      [mintAuthority]
+    );
+
+    // takerが最初にbidし、後から来たbidderに上回られる
+    const bidder: anchor.web3.Keypair = anchor.web3.Keypair.generate();
+    await provider.connection.confirmTransaction(
+      await provider.connection.requestAirdrop(bidder.publicKey, 2_000_000_000),
+      "confirmed"
+    );
+    const winnerTokenAccount = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      payer,
+      mintAuction,
+      bidder.publicKey
+    );
+
+    const auctionNonce = new anchor.BN(1);
+    const [auctionAccountPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("auction"),
+        initializerMainAccount.publicKey.toBuffer(),
+        auctionNonce.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const [auctionVaultAuthorityPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("vault-authority"),
+        auctionAccountPda.toBuffer(),
+      ],
+      program.programId
+    );
+    const [bidAccountPda] = await PublicKey.findProgramAddress(
+      [anchor.utils.bytes.utf8.encode("bid"), auctionAccountPda.toBuffer()],
+      program.programId
+    );
+    const [vaultAccountPda, vaultAccountBump] =
+      await PublicKey.findProgramAddress(
+        [
+          Buffer.from(anchor.utils.bytes.utf8.encode("vault-account")),
+          auctionAccountPda.toBuffer(),
+          sellerTokenAccount.address.toBuffer(),
+        ],
+        program.programId
+      );
+
+    const reservePrice = 100_000_000;
+    const minBidIncrement = 10_000_000;
+    const endAt = Math.floor(Date.now() / 1000) + 5;
+    await program.rpc.createAuction(
+      Buffer.from([vaultAccountBump]),
+      [new anchor.BN(1)], // NFTなので1
+      new anchor.BN(reservePrice),
+      new anchor.BN(minBidIncrement),
+      new anchor.BN(endAt),
+      auctionNonce,
+      {
+        accounts: {
+          seller: initializerMainAccount.publicKey,
+          auctionAccount: auctionAccountPda,
+          vaultAuthority: auctionVaultAuthorityPda,
+          bidAccount: bidAccountPda,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+        },
+        remainingAccounts: [
+          {
+            pubkey: sellerTokenAccount.address,
+            isWritable: true,
+            isSigner: false,
+          },
+          { pubkey: vaultAccountPda, isWritable: true, isSigner: false },
+          { pubkey: mintAuction, isWritable: false, isSigner: false },
+        ],
+        signers: [initializerMainAccount],
+      }
+    );
+
+    const placeBid = (
+      bidderAccount: anchor.web3.Keypair,
+      previousBidder: PublicKey,
+      amount: number
+    ) =>
+      program.rpc.placeBid(new anchor.BN(amount), {
+        accounts: {
+          bidder: bidderAccount.publicKey,
+          auctionAccount: auctionAccountPda,
+          bidAccount: bidAccountPda,
+          previousBidder, // 最初のbidでは使われない
+          systemProgram: anchor.web3.SystemProgram.programId,
+        },
+        signers: [bidderAccount],
+      });
+
+    try {
+      await placeBid(takerMainAccount, takerMainAccount.publicKey, reservePrice - 1);
+      assert.fail("bid below the reserve price should fail");
+    } catch (err) {
+      assert.equal(err.msg, "BidBelowReservePrice");
+    }
+
+    const takerSolAmountBeforeBid = await provider.connection.getBalance(
+      takerMainAccount.publicKey
+    );
+    await placeBid(takerMainAccount, takerMainAccount.publicKey, reservePrice);
+
+    try {
+      await placeBid(
+        bidder,
+        takerMainAccount.publicKey,
+        reservePrice + minBidIncrement - 1
+      );
+      assert.fail("bid below the minimum increment should fail");
+    } catch (err) {
+      assert.equal(err.msg, "BidIncrementTooSmall");
+    }
+
+    const winningBid = reservePrice + minBidIncrement;
+    await placeBid(bidder, takerMainAccount.publicKey, winningBid);
+
+    // 上回られたbidは返金されている
+    assert.equal(
+      await provider.connection.getBalance(takerMainAccount.publicKey),
+      takerSolAmountBeforeBid
+    );
+
+    const settleAuction = () =>
+      program.rpc.settleAuction({
+        accounts: {
+          seller: initializerMainAccount.publicKey,
+          winner: bidder.publicKey,
+          auctionAccount: auctionAccountPda,
+          vaultAuthority: auctionVaultAuthorityPda,
+          bidAccount: bidAccountPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        },
+        remainingAccounts: [
+          {
+            pubkey: sellerTokenAccount.address,
+            isWritable: false,
+            isSigner: false,
+          },
+          { pubkey: vaultAccountPda, isWritable: true, isSigner: false },
+          {
+            pubkey: winnerTokenAccount.address,
+            isWritable: true,
+            isSigner: false,
+          },
+          { pubkey: mintAuction, isWritable: false, isSigner: false },
+        ],
+      });
+
+    try {
+      await settleAuction();
+      assert.fail("settle before the end time should fail");
+    } catch (err) {
+      assert.equal(err.msg, "AuctionNotEnded");
+    }
+
+    await waitUntil(endAt);
+
+    const sellerSolAmountBeforeSettle = await provider.connection.getBalance(
+      initializerMainAccount.publicKey
+    );
+    await settleAuction();
+
+    let _winnerTokenAccount = await getAccount(
+      provider.connection,
+      winnerTokenAccount.address
+    );
+    assert.ok(Number(_winnerTokenAccount.amount) === 1);
+
+    // sellerは最高額のbidに加えてauctionとvaultのrentを受け取る
+    const sellerSolAmountAfterSettle = await provider.connection.getBalance(
+      initializerMainAccount.publicKey
+    );
+    assert.ok(
+      sellerSolAmountAfterSettle - sellerSolAmountBeforeSettle > winningBid
+    );
+  });
+
+  it("Initialize escrow with Dutch pricing and exchange at the current price", async () => {
+    escrowNonce += 1;
+    const [_escrowAccountPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("escrow"),
+        initializerMainAccount.publicKey.toBuffer(),
+        takerMainAccount.publicKey.toBuffer(),
+        new anchor.BN(escrowNonce).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    escrowAccountPda = _escrowAccountPda;
+    const [_vaultAuthorityPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("vault-authority"),
+        escrowAccountPda.toBuffer(),
+      ],
+      program.programId
+    );
+    vaultAuthorityPda = _vaultAuthorityPda;
+
+    // 300_000_000から100_000_000まで下がる途中でexchangeする
+    const initializerSolAmount = 100_000_000;
+    const startSolAmount = 300_000_000;
+    const endSolAmount = 100_000_000;
+    const now = Math.floor(Date.now() / 1000);
+    const initialize = (dutchEndSolAmount: number) =>
+      program.rpc.initialize(
+        initializeArgs({
+          initializerAdditionalSolAmount: new anchor.BN(initializerSolAmount),
+          takerAdditionalSolAmount: new anchor.BN(startSolAmount),
+          takerDutchPricing: {
+            endSolAmount: new anchor.BN(dutchEndSolAmount),
+            startAt: new anchor.BN(now - 1000),
+            endAt: new anchor.BN(now + 1000),
+          },
+        }),
+        {
+          accounts: {
+            initializer: initializerMainAccount.publicKey,
+            taker: takerMainAccount.publicKey,
+            escrowAccount: escrowAccountPda,
+            systemProgram: anchor.web3.SystemProgram.programId,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            token2022Program: TOKEN_2022_PROGRAM_ID,
+            vaultAuthority: vaultAuthorityPda,
+          },
+          signers: [initializerMainAccount],
+        }
+      );
+
+    // 終了価格が開始価格以上だと価格が下がらない
+    try {
+      await initialize(startSolAmount);
+      assert.fail("Dutch pricing that does not decay should fail");
+    } catch (err) {
+      assert.equal(err.msg, "InvalidDutchPricing");
+    }
+
+    await initialize(endSolAmount);
+
+    const exchange = (maxTakerSolAmount: number) =>
+      program.rpc.exchange(
+        new anchor.BN(initializerSolAmount),
+        new anchor.BN(maxTakerSolAmount),
+        [], // cNFTなし
+        {
+          accounts: {
+            taker: takerMainAccount.publicKey,
+            initializer: initializerMainAccount.publicKey,
+            escrowAccount: escrowAccountPda,
+            vaultAuthority: vaultAuthorityPda,
+            config: configPda,
+            treasury: treasury.publicKey,
+            referrer: initializerMainAccount.publicKey, // referrerがいない場合は使われない
+            tokenProgram: TOKEN_PROGRAM_ID,
+            token2022Program: TOKEN_2022_PROGRAM_ID,
+            systemProgram: anchor.web3.SystemProgram.programId,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          },
+          signers: [takerMainAccount],
+        }
+      );
+
+    // 現在の価格は終了価格より高い
+    try {
+      await exchange(endSolAmount);
+      assert.fail("exchange below the current price should fail");
+    } catch (err) {
+      assert.equal(err.msg, "PriceExceedsMaximum");
+    }
+
+    const takerSolAmountBefore = await provider.connection.getBalance(
+      takerMainAccount.publicKey
+    );
+    await exchange(startSolAmount);
+    const takerSolAmountAfter = await provider.connection.getBalance(
+      takerMainAccount.publicKey
+    );
+
+    // takerは開始価格ではなく下がった価格を払い、initializerのSOLを受け取る
+    const paidSolAmount =
+      takerSolAmountBefore + initializerSolAmount - takerSolAmountAfter;
+    assert.ok(paidSolAmount < startSolAmount);
+    assert.ok(paidSolAmount > endSolAmount);
+  });
+
+  it("Sealed-bid auction settles to the highest reveal and forfeits unrevealed deposits", async () => {
+    const mintAuction = await createMint(
+      provider.connection,
+      payer,
+      mintAuthority.publicKey,
+      null,
+      0
+    );
+    const sellerTokenAccount = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      payer,
+      mintAuction,
+      initializerMainAccount.publicKey
+    );
+    await mintTo(
+      provider.connection,
+      payer,
+      mintAuction,
+      sellerTokenAccount.address,
+      mintAuthority.publicKey,
+      1,
+      [mintAuthority]
+    );
+    const winnerTokenAccount = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      payer,
+      mintAuction,
+      takerMainAccount.publicKey
+    );
+
+    // revealしないbidder
+    const bidder: anchor.web3.Keypair = anchor.web3.Keypair.generate();
+    await provider.connection.confirmTransaction(
+      await provider.connection.requestAirdrop(bidder.publicKey, 2_000_000_000),
+      "confirmed"
+    );
+
+    const auctionNonce = new anchor.BN(1);
+    const [sealedAuctionAccountPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("sealed-auction"),
+        initializerMainAccount.publicKey.toBuffer(),
+        auctionNonce.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const [auctionVaultAuthorityPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("vault-authority"),
+        sealedAuctionAccountPda.toBuffer(),
+      ],
+      program.programId
+    );
+    const getSealedBidPda = async (bidderKey: PublicKey) =>
+      (
+        await PublicKey.findProgramAddress(
+          [
+            anchor.utils.bytes.utf8.encode("sealed-bid"),
+            sealedAuctionAccountPda.toBuffer(),
+            bidderKey.toBuffer(),
+          ],
+          program.programId
+        )
+      )[0];
+    const [vaultAccountPda, vaultAccountBump] =
+      await PublicKey.findProgramAddress(
+        [
+          Buffer.from(anchor.utils.bytes.utf8.encode("vault-account")),
+          sealedAuctionAccountPda.toBuffer(),
+          sellerTokenAccount.address.toBuffer(),
+        ],
+        program.programId
+      );
+
+    const commitEndAt = Math.floor(Date.now() / 1000) + 5;
+    const revealEndAt = commitEndAt + 5;
+    await program.rpc.createSealedAuction(
+      {
+        vaultAccountBumps: Buffer.from([vaultAccountBump]),
+        nftQuantities: [new anchor.BN(1)], // NFTなので1
+        reservePrice: new anchor.BN(100_000_000),
+        minDeposit: new anchor.BN(50_000_000), // 安いcommitで枠を埋められないようにする
+        commitEndAt: new anchor.BN(commitEndAt),
+        revealEndAt: new anchor.BN(revealEndAt),
+        unrevealedForfeitBasisPoints: 5000, // revealしなかったbidはdepositの半分をsellerに払う
+        nonce: auctionNonce,
+      },
+      {
+        accounts: {
+          seller: initializerMainAccount.publicKey,
+          sealedAuctionAccount: sealedAuctionAccountPda,
+          vaultAuthority: auctionVaultAuthorityPda,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+        },
+        remainingAccounts: [
+          {
+            pubkey: sellerTokenAccount.address,
+            isWritable: true,
+            isSigner: false,
+          },
+          { pubkey: vaultAccountPda, isWritable: true, isSigner: false },
+          { pubkey: mintAuction, isWritable: false, isSigner: false },
+        ],
+        signers: [initializerMainAccount],
+      }
+    );
+
+    // bidの額はamount, salt, bidderのhashだけを渡し、額を推測されないようにdepositは多めに預ける
+    const getCommitment = (amount: number, salt: Buffer, bidderKey: PublicKey) => [
+      ...createHash("sha256")
+        .update(
+          Buffer.concat([
+            new anchor.BN(amount).toArrayLike(Buffer, "le", 8),
+            salt,
+            bidderKey.toBuffer(),
+          ])
+        )
+        .digest(),
+    ];
+    const commitSealedBid = async (
+      bidderAccount: anchor.web3.Keypair,
+      commitment: number[],
+      depositAmount: number
+    ) =>
+      program.rpc.commitSealedBid(commitment, new anchor.BN(depositAmount), {
+        accounts: {
+          bidder: bidderAccount.publicKey,
+          sealedAuctionAccount: sealedAuctionAccountPda,
+          sealedBidAccount: await getSealedBidPda(bidderAccount.publicKey),
+          systemProgram: anchor.web3.SystemProgram.programId,
+        },
+        signers: [bidderAccount],
+      });
+    const revealSealedBid = async (
+      bidderAccount: anchor.web3.Keypair,
+      amount: number,
+      salt: Buffer
+    ) =>
+      program.rpc.revealSealedBid(new anchor.BN(amount), [...salt], {
+        accounts: {
+          bidder: bidderAccount.publicKey,
+          sealedAuctionAccount: sealedAuctionAccountPda,
+          sealedBidAccount: await getSealedBidPda(bidderAccount.publicKey),
+        },
+        signers: [bidderAccount],
+      });
+
+    const winningBid = 150_000_000;
+    const takerSalt = Buffer.alloc(32, 1);
+    await commitSealedBid(
+      takerMainAccount,
+      getCommitment(winningBid, takerSalt, takerMainAccount.publicKey),
+      200_000_000
+    );
+    // sellerが決めた下限とreserve priceに届かないdepositではbidの枠を取れない
+    try {
+      await commitSealedBid(
+        bidder,
+        getCommitment(0, Buffer.alloc(32, 2), bidder.publicKey),
+        50_000_000 - 1
+      );
+      assert.fail("commit below the minimum deposit should fail");
+    } catch (err) {
+      assert.equal(err.msg, "SealedBidDepositTooSmall");
+    }
+    try {
+      await commitSealedBid(
+        bidder,
+        getCommitment(0, Buffer.alloc(32, 2), bidder.publicKey),
+        100_000_000 - 1
+      );
+      assert.fail("commit below the reserve price should fail");
+    } catch (err) {
+      assert.equal(err.msg, "BidBelowReservePrice");
+    }
+    const bidderDeposit = 300_000_000;
+    const bidderSolAmountBeforeCommit = await provider.connection.getBalance(
+      bidder.publicKey
+    );
+    await commitSealedBid(
+      bidder,
+      getCommitment(250_000_000, Buffer.alloc(32, 2), bidder.publicKey),
+      bidderDeposit
+    );
+
+    // commitの期間中はrevealできない
+    try {
+      await revealSealedBid(takerMainAccount, winningBid, takerSalt);
+      assert.fail("reveal during the commit window should fail");
+    } catch (err) {
+      assert.equal(err.msg, "NotInRevealWindow");
+    }
+
+    await waitUntil(commitEndAt);
+
+    try {
+      await revealSealedBid(takerMainAccount, winningBid, Buffer.alloc(32, 3));
+      assert.fail("reveal with a wrong salt should fail");
+    } catch (err) {
+      assert.equal(err.msg, "SealedBidCommitmentMismatch");
+    }
+    await revealSealedBid(takerMainAccount, winningBid, takerSalt);
+
+    await waitUntil(revealEndAt);
+
+    const sellerSolAmountBeforeSettle = await provider.connection.getBalance(
+      initializerMainAccount.publicKey
+    );
+    await program.rpc.settleSealedAuction({
+      accounts: {
+        seller: initializerMainAccount.publicKey,
+        winner: takerMainAccount.publicKey,
+        sealedAuctionAccount: sealedAuctionAccountPda,
+        vaultAuthority: auctionVaultAuthorityPda,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        token2022Program: TOKEN_2022_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      },
+      remainingAccounts: [
+        {
+          pubkey: sellerTokenAccount.address,
+          isWritable: false,
+          isSigner: false,
+        },
+        { pubkey: vaultAccountPda, isWritable: true, isSigner: false },
+        {
+          pubkey: winnerTokenAccount.address,
+          isWritable: true,
+          isSigner: false,
+        },
+        { pubkey: mintAuction, isWritable: false, isSigner: false },
+        {
+          pubkey: await getSealedBidPda(takerMainAccount.publicKey),
+          isWritable: true,
+          isSigner: false,
+        },
+        {
+          pubkey: takerMainAccount.publicKey,
+          isWritable: true,
+          isSigner: false,
+        },
+        {
+          pubkey: await getSealedBidPda(bidder.publicKey),
+          isWritable: true,
+          isSigner: false,
+        },
+        { pubkey: bidder.publicKey, isWritable: true, isSigner: false },
+      ],
+    });
+
+    let _winnerTokenAccount = await getAccount(
+      provider.connection,
+      winnerTokenAccount.address
+    );
+    assert.ok(Number(_winnerTokenAccount.amount) === 1);
+
+    // revealしなかったbidderはdepositの半分を失い、rentは戻る
+    assert.equal(
+      bidderSolAmountBeforeCommit -
+        (await provider.connection.getBalance(bidder.publicKey)),
+      bidderDeposit / 2
+    );
+
+    // sellerは落札額と没収分に加えてauctionとvaultのrentを受け取る
+    const sellerSolAmountAfterSettle = await provider.connection.getBalance(
+      initializerMainAccount.publicKey
+    );
+    assert.ok(
+      sellerSolAmountAfterSettle - sellerSolAmountBeforeSettle >
+        winningBid + bidderDeposit / 2
+    );
+  });
+
+  it("Create pool fails without ratio or verified collection metadata", async () => {
+    const mintPool = await createMint(
+      provider.connection,
+      payer,
+      mintAuthority.publicKey,
+      null,
+      0
+    );
+    const ownerTokenAccount = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      payer,
+      mintPool,
+      initializerMainAccount.publicKey
+    );
+    await mintTo(
+      provider.connection,
+      payer,
+      mintPool,
+      ownerTokenAccount.address,
+      mintAuthority.publicKey,
+      1,
+      [mintAuthority]
+    );
+
+    const poolNonce = new anchor.BN(1);
+    const [poolAccountPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("pool"),
+        initializerMainAccount.publicKey.toBuffer(),
+        poolNonce.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const [poolVaultAuthorityPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("vault-authority"),
+        poolAccountPda.toBuffer(),
+      ],
+      program.programId
+    );
+    const [vaultAccountPda, vaultAccountBump] =
+      await PublicKey.findProgramAddress(
+        [
+          Buffer.from(anchor.utils.bytes.utf8.encode("vault-account")),
+          poolAccountPda.toBuffer(),
+          ownerTokenAccount.address.toBuffer(),
+        ],
+        program.programId
+      );
+
+    const offeredCollection = anchor.web3.Keypair.generate().publicKey;
+    const acceptedCollection = anchor.web3.Keypair.generate().publicKey;
+    const createPool = (acceptedNftRatio: number) =>
+      program.rpc.createPool(
+        Buffer.from([vaultAccountBump]),
+        offeredCollection,
+        acceptedCollection,
+        acceptedNftRatio,
+        new anchor.BN(1_000_000),
+        poolNonce,
+        {
+          accounts: {
+            owner: initializerMainAccount.publicKey,
+            poolAccount: poolAccountPda,
+            vaultAuthority: poolVaultAuthorityPda,
+            systemProgram: anchor.web3.SystemProgram.programId,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            token2022Program: TOKEN_2022_PROGRAM_ID,
+          },
+          remainingAccounts: [
+            {
+              pubkey: ownerTokenAccount.address,
+              isWritable: true,
+              isSigner: false,
+            },
+            { pubkey: vaultAccountPda, isWritable: true, isSigner: false },
+            { pubkey: mintPool, isWritable: false, isSigner: false },
+            // metadataの代わりにmintを渡す
+            { pubkey: mintPool, isWritable: false, isSigner: false },
+          ],
+          signers: [initializerMainAccount],
+        }
+      );
+
+    try {
+      await createPool(0);
+      assert.fail("create pool with ratio 0 should fail");
+    } catch (err) {
+      assert.equal(err.msg, "InvalidPoolRatio");
+    }
+
+    // collectionを検証できないNFTはpoolに預けられない
+    try {
+      await createPool(2);
+      assert.fail("create pool without metadata should fail");
+    } catch (err) {
+      assert.equal(err.msg, "IncorrectAccountInfoOwner");
+    }
+
+    const _poolAccount = await provider.connection.getAccountInfo(
+      poolAccountPda
+    );
+    assert.equal(_poolAccount, null);
+    const _ownerTokenAccount = await getAccount(
+      provider.connection,
+      ownerTokenAccount.address
+    );
+    assert.equal(Number(_ownerTokenAccount.amount), 1);
+  });
+
+  it("Swap pool NFTs until the pool is empty and close it", async () => {
+    // ownerは出すcollectionのNFTを2つ預け、swapperは受け取る側のcollectionのNFTを1つずつ渡す
+    const offeredCollection = await createCollectionNft();
+    const acceptedCollection = await createCollectionNft();
+    const poolNfts = [
+      await createCollectionMember(
+        offeredCollection,
+        initializerMainAccount.publicKey
+      ),
+      await createCollectionMember(
+        offeredCollection,
+        initializerMainAccount.publicKey
+      ),
+    ];
+    const swapperNfts = [
+      await createCollectionMember(
+        acceptedCollection,
+        takerMainAccount.publicKey
+      ),
+      await createCollectionMember(
+        acceptedCollection,
+        takerMainAccount.publicKey
+      ),
+    ];
+
+    const poolNonce = new anchor.BN(2);
+    const [poolAccountPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("pool"),
+        initializerMainAccount.publicKey.toBuffer(),
+        poolNonce.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const [poolVaultAuthorityPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("vault-authority"),
+        poolAccountPda.toBuffer(),
+      ],
+      program.programId
+    );
+    const vaults = await Promise.all(
+      poolNfts.map(({ tokenAccount }) =>
+        PublicKey.findProgramAddress(
+          [
+            Buffer.from(anchor.utils.bytes.utf8.encode("vault-account")),
+            poolAccountPda.toBuffer(),
+            tokenAccount.toBuffer(),
+          ],
+          program.programId
+        )
+      )
+    );
+
+    const depositAccounts = (index: number) => [
+      {
+        pubkey: poolNfts[index].tokenAccount,
+        isWritable: true,
+        isSigner: false,
+      },
+      { pubkey: vaults[index][0], isWritable: true, isSigner: false },
+      { pubkey: poolNfts[index].mint, isWritable: false, isSigner: false },
+    ];
+
+    const swapFee = 5_000_000;
+    await program.rpc.createPool(
+      Buffer.from(vaults.map(([, bump]) => bump)),
+      offeredCollection.mint,
+      acceptedCollection.mint,
+      1, // poolのNFT 1つにつき1つ受け取る
+      new anchor.BN(swapFee),
+      poolNonce,
+      {
+        accounts: {
+          owner: initializerMainAccount.publicKey,
+          poolAccount: poolAccountPda,
+          vaultAuthority: poolVaultAuthorityPda,
+          systemProgram: anchor.web3.SystemProgram.programId,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+        },
+        remainingAccounts: [
+          ...depositAccounts(0),
+          ...depositAccounts(1),
+          ...poolNfts.map(({ metadata }) => ({
+            pubkey: metadata,
+            isWritable: false,
+            isSigner: false,
+          })),
+        ],
+        signers: [initializerMainAccount],
+      }
+    );
+
+    // poolのNFTの[預けたtoken account, vault account, mint, swapperのtoken account]の後に
+    // 渡すNFTの[swapperのtoken account, ownerのtoken account, mint, metadata]
+    const swapPool = async (index: number) => {
+      const swapperTokenAccount = await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        payer,
+        poolNfts[index].mint,
+        takerMainAccount.publicKey
+      );
+      const ownerTokenAccount = await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        payer,
+        swapperNfts[index].mint,
+        initializerMainAccount.publicKey
+      );
+      await program.rpc.swapPool({
+        accounts: {
+          swapper: takerMainAccount.publicKey,
+          owner: initializerMainAccount.publicKey,
+          poolAccount: poolAccountPda,
+          vaultAuthority: poolVaultAuthorityPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        },
+        remainingAccounts: [
+          ...depositAccounts(index),
+          {
+            pubkey: swapperTokenAccount.address,
+            isWritable: true,
+            isSigner: false,
+          },
+          {
+            pubkey: swapperNfts[index].tokenAccount,
+            isWritable: true,
+            isSigner: false,
+          },
+          {
+            pubkey: ownerTokenAccount.address,
+            isWritable: true,
+            isSigner: false,
+          },
+          {
+            pubkey: swapperNfts[index].mint,
+            isWritable: false,
+            isSigner: false,
+          },
+          {
+            pubkey: swapperNfts[index].metadata,
+            isWritable: false,
+            isSigner: false,
+          },
+        ],
+        signers: [takerMainAccount],
+      });
+      return {
+        swapperTokenAccount: swapperTokenAccount.address,
+        ownerTokenAccount: ownerTokenAccount.address,
+      };
+    };
+
+    // swapごとにownerはswap feeとvaultのrentを受け取る
+    const vaultRent =
+      await provider.connection.getMinimumBalanceForRentExemption(165);
+    for (let index = 0; index < poolNfts.length; index++) {
+      const ownerBalanceBefore = await provider.connection.getBalance(
+        initializerMainAccount.publicKey
+      );
+      const { swapperTokenAccount, ownerTokenAccount } = await swapPool(index);
+      assert.equal(
+        (await provider.connection.getBalance(
+          initializerMainAccount.publicKey
+        )) - ownerBalanceBefore,
+        swapFee + vaultRent
+      );
+      const _swapperTokenAccount = await getAccount(
+        provider.connection,
+        swapperTokenAccount
+      );
+      assert.equal(Number(_swapperTokenAccount.amount), 1);
+      const _ownerTokenAccount = await getAccount(
+        provider.connection,
+        ownerTokenAccount
+      );
+      assert.equal(Number(_ownerTokenAccount.amount), 1);
+    }
+
+    try {
+      await swapPool(0);
+      assert.fail("swap on an empty pool should fail");
+    } catch (err) {
+      assert.equal(err.msg, "PoolEmpty");
+    }
+
+    // 空になったpoolは引き出すNFTがないのでremaining accountsなしで閉じる
+    await program.rpc.closePool({
+      accounts: {
+        owner: initializerMainAccount.publicKey,
+        poolAccount: poolAccountPda,
+        vaultAuthority: poolVaultAuthorityPda,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        token2022Program: TOKEN_2022_PROGRAM_ID,
+      },
+      signers: [initializerMainAccount],
+    });
+    assert.equal(
+      await provider.connection.getAccountInfo(poolAccountPda),
+      null
+    );
+    assert.equal(
+      await provider.connection.getAccountInfo(poolVaultAuthorityPda),
+      null
+    );
+  });
+
+  it("Standing offer holds the budget and the buyer withdraws the rest", async () => {
+    const buyer: anchor.web3.Keypair = anchor.web3.Keypair.generate();
+    await provider.connection.confirmTransaction(
+      await provider.connection.requestAirdrop(buyer.publicKey, 2_000_000_000),
+      "confirmed"
+    );
+
+    const mintOffer = await createMint(
+      provider.connection,
+      payer,
+      mintAuthority.publicKey,
+      null,
+      0
+    );
+    const sellerTokenAccount = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      payer,
+      mintOffer,
+      initializerMainAccount.publicKey
+    );
+    const buyerTokenAccount = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      payer,
+      mintOffer,
+      buyer.publicKey
+    );
+    await mintTo(
+      provider.connection,
+      payer,
+      mintOffer,
+      sellerTokenAccount.address,
+      mintAuthority.publicKey,
+      1,
+      [mintAuthority]
+    );
+
+    const standingOfferNonce = new anchor.BN(1);
+    const [standingOfferAccountPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("standing-offer"),
+        buyer.publicKey.toBuffer(),
+        standingOfferNonce.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+
+    const collection = anchor.web3.Keypair.generate().publicKey;
+    const pricePerNft = 200_000_000;
+    const createStandingOffer = (count: number) =>
+      program.rpc.createStandingOffer(
+        collection,
+        new anchor.BN(pricePerNft),
+        count,
+        standingOfferNonce,
+        {
+          accounts: {
+            buyer: buyer.publicKey,
+            standingOfferAccount: standingOfferAccountPda,
+            systemProgram: anchor.web3.SystemProgram.programId,
+          },
+          signers: [buyer],
+        }
+      );
+
+    try {
+      await createStandingOffer(0);
+      assert.fail("create standing offer with count 0 should fail");
+    } catch (err) {
+      assert.equal(err.msg, "InvalidStandingOffer");
+    }
+
+    // 1つ200_000_000 lamportで5つまで買う
+    await createStandingOffer(5);
+    const rent =
+      await provider.connection.getMinimumBalanceForRentExemption(
+        8 + 32 + 32 + 8 + 1 + 8 + 1
+      );
+    assert.equal(
+      await provider.connection.getBalance(standingOfferAccountPda),
+      rent + pricePerNft * 5
+    );
+    const _standingOfferAccount =
+      await program.account.standingOfferAccount.fetch(
+        standingOfferAccountPda
+      );
+    assert.equal(_standingOfferAccount.remainingCount, 5);
+
+    // collectionを検証できないNFTではfillできない
+    try {
+      await program.rpc.fillStandingOffer({
+        accounts: {
+          seller: initializerMainAccount.publicKey,
+          buyer: buyer.publicKey,
+          standingOfferAccount: standingOfferAccountPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+        },
+        remainingAccounts: [
+          {
+            pubkey: sellerTokenAccount.address,
+            isWritable: true,
+            isSigner: false,
+          },
+          {
+            pubkey: buyerTokenAccount.address,
+            isWritable: true,
+            isSigner: false,
+          },
+          { pubkey: mintOffer, isWritable: false, isSigner: false },
+          // metadataの代わりにmintを渡す
+          { pubkey: mintOffer, isWritable: false, isSigner: false },
+        ],
+        signers: [initializerMainAccount],
+      });
+      assert.fail("fill standing offer without metadata should fail");
+    } catch (err) {
+      assert.equal(err.msg, "IncorrectAccountInfoOwner");
+    }
+
+    const buyerBalance = await provider.connection.getBalance(buyer.publicKey);
+    await program.rpc.withdrawStandingOffer({
+      accounts: {
+        buyer: buyer.publicKey,
+        standingOfferAccount: standingOfferAccountPda,
+      },
+      signers: [buyer],
+    });
+    assert.equal(
+      await provider.connection.getAccountInfo(standingOfferAccountPda),
+      null
+    );
+    // 残っている予算とrentが戻る feeはpayerが払う
+    assert.equal(
+      await provider.connection.getBalance(buyer.publicKey),
+      buyerBalance + rent + pricePerNft * 5
+    );
+  });
+
+  it("Fill a standing offer until it is filled and withdraw the rest", async () => {
+    const buyer: anchor.web3.Keypair = anchor.web3.Keypair.generate();
+    await provider.connection.confirmTransaction(
+      await provider.connection.requestAirdrop(buyer.publicKey, 2_000_000_000),
+      "confirmed"
+    );
+
+    // sellerはcollectionのNFTを3つ持ち、buyerは2つまで買う
+    const collection = await createCollectionNft();
+    const sellerNfts = [];
+    for (let index = 0; index < 3; index++) {
+      sellerNfts.push(
+        await createCollectionMember(
+          collection,
+          initializerMainAccount.publicKey
+        )
+      );
+    }
+
+    const standingOfferNonce = new anchor.BN(2);
+    const [standingOfferAccountPda] = await PublicKey.findProgramAddress(
+      [
+        anchor.utils.bytes.utf8.encode("standing-offer"),
+        buyer.publicKey.toBuffer(),
+        standingOfferNonce.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const pricePerNft = 200_000_000;
+    await program.rpc.createStandingOffer(
+      collection.mint,
+      new anchor.BN(pricePerNft),
+      2,
+      standingOfferNonce,
+      {
+        accounts: {
+          buyer: buyer.publicKey,
+          standingOfferAccount: standingOfferAccountPda,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        },
+        signers: [buyer],
+      }
+    );
+    const rent =
+      await provider.connection.getMinimumBalanceForRentExemption(
+        8 + 32 + 32 + 8 + 1 + 8 + 1
+      );
+
+    // sellerのtoken account, buyerのtoken account, mint, metadataの順
+    const fillStandingOffer = async (nft: {
+      mint: PublicKey;
+      metadata: PublicKey;
+      tokenAccount: PublicKey;
+    }) => {
+      const buyerTokenAccount = await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        payer,
+        nft.mint,
+        buyer.publicKey
+      );
+      await program.rpc.fillStandingOffer({
+        accounts: {
+          seller: initializerMainAccount.publicKey,
+          buyer: buyer.publicKey,
+          standingOfferAccount: standingOfferAccountPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+        },
+        remainingAccounts: [
+          { pubkey: nft.tokenAccount, isWritable: true, isSigner: false },
+          {
+            pubkey: buyerTokenAccount.address,
+            isWritable: true,
+            isSigner: false,
+          },
+          { pubkey: nft.mint, isWritable: false, isSigner: false },
+          { pubkey: nft.metadata, isWritable: false, isSigner: false },
+        ],
+        signers: [initializerMainAccount],
+      });
+      return buyerTokenAccount.address;
+    };
+
+    // fillごとにsellerは1つ分のSOLを受け取り、残りの数と予算が減る
+    for (let index = 0; index < 2; index++) {
+      const sellerBalanceBefore = await provider.connection.getBalance(
+        initializerMainAccount.publicKey
+      );
+      const buyerTokenAccount = await fillStandingOffer(sellerNfts[index]);
+      assert.equal(
+        (await provider.connection.getBalance(
+          initializerMainAccount.publicKey
+        )) - sellerBalanceBefore,
+        pricePerNft
+      );
+      const _buyerTokenAccount = await getAccount(
+        provider.connection,
+        buyerTokenAccount
+      );
+      assert.equal(Number(_buyerTokenAccount.amount), 1);
+
+      const remainingCount = 2 - (index + 1);
+      const _standingOfferAccount =
+        await program.account.standingOfferAccount.fetch(
+          standingOfferAccountPda
+        );
+      assert.equal(_standingOfferAccount.remainingCount, remainingCount);
+      assert.equal(
+        await provider.connection.getBalance(standingOfferAccountPda),
+        rent + pricePerNft * remainingCount
+      );
+    }
+
+    try {
+      await fillStandingOffer(sellerNfts[2]);
+      assert.fail("fill standing offer after it is filled should fail");
+    } catch (err) {
+      assert.equal(err.msg, "StandingOfferFilled");
+    }
+    const _sellerTokenAccount = await getAccount(
+      provider.connection,
+      sellerNfts[2].tokenAccount
+    );
+    assert.equal(Number(_sellerTokenAccount.amount), 1);
+
+    // 予算は使い切ったのでrentだけが戻る
+    const buyerBalance = await provider.connection.getBalance(buyer.publicKey);
+    await program.rpc.withdrawStandingOffer({
+      accounts: {
+        buyer: buyer.publicKey,
+        standingOfferAccount: standingOfferAccountPda,
+      },
+      signers: [buyer],
+    });
+    assert.equal(
+      await provider.connection.getAccountInfo(standingOfferAccountPda),
+      null
+    );
+    assert.equal(
+      await provider.connection.getBalance(buyer.publicKey),
+      buyerBalance + rent
+    );
+  });
+
+  it("Match complementary open offers, charge the fee and pay the keeper a tip", async () => {
+    const keeper: anchor.web3.Keypair = anchor.web3.Keypair.generate();
+    await provider.connection.confirmTransaction(
+      await provider.connection.requestAirdrop(keeper.publicKey, 1_000_000_000),
+      "confirmed"
+    );
+
+    // initializerはmintXを出してmintYが欲しい takerはmintYを出してmintXが欲しい
+    const createNft = async (owner: PublicKey) => {
+      const mint = await createMint(
+        provider.connection,
+        payer,
+        mintAuthority.publicKey,
+        null,
+        0
+      );
+      const tokenAccount = await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        payer,
+        mint,
+        owner
+      );
+      await mintTo(
+        provider.connection,
+        payer,
+        mint,
+        tokenAccount.address,
+        mintAuthority.publicKey,
+        1,
+        [mintAuthority]
+      );
+      return { mint, tokenAccount };
+    };
+    const { mint: mintX, tokenAccount: initializerTokenAccountX } =
+      await createNft(initializerMainAccount.publicKey);
+    const { mint: mintY, tokenAccount: takerTokenAccountY } = await createNft(
+      takerMainAccount.publicKey
+    );
+    const takerTokenAccountX = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      payer,
+      mintX,
+      takerMainAccount.publicKey
+    );
+    const initializerTokenAccountY = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      payer,
+      mintY,
+      initializerMainAccount.publicKey
+    );
+
+    // どちらもtakerを決めないopen offer
+    const createOpenOffer = async (
+      initializer: anchor.web3.Keypair,
+      initializerSolAmount: number,
+      takerSolAmount: number,
+      tokenAccount: PublicKey,
+      mint: PublicKey,
+      wantedMint: PublicKey,
+      keeperTipBasisPoints: number
+    ) => {
+      escrowNonce += 1;
+      const [_escrowAccountPda] = await PublicKey.findProgramAddress(
+        [
+          anchor.utils.bytes.utf8.encode("escrow"),
+          initializer.publicKey.toBuffer(),
+          PublicKey.default.toBuffer(),
+          new anchor.BN(escrowNonce).toArrayLike(Buffer, "le", 8),
+        ],
+        program.programId
+      );
+      const [_vaultAuthorityPda] = await PublicKey.findProgramAddress(
+        [
+          anchor.utils.bytes.utf8.encode("vault-authority"),
+          _escrowAccountPda.toBuffer(),
+        ],
+        program.programId
+      );
+      const [_vaultAccountPda, _vaultAccountBump] =
+        await PublicKey.findProgramAddress(
+          [
+            Buffer.from(anchor.utils.bytes.utf8.encode("vault-account")),
+            _escrowAccountPda.toBuffer(),
+            tokenAccount.toBuffer(),
+          ],
+          program.programId
+        );
+
+      await program.rpc.initialize(
+        initializeArgs({
+          initializerAdditionalSolAmount: new anchor.BN(initializerSolAmount),
+          takerAdditionalSolAmount: new anchor.BN(takerSolAmount),
+          initializerNftAmount: 1,
+          takerNftAmount: 1,
+          vaultAccountBumps: Buffer.from([_vaultAccountBump]),
+          initializerNftQuantities: [new anchor.BN(1)],
+          takerNftQuantities: [new anchor.BN(1)],
+          keeperTipBasisPoints,
+        }),
+        {
+          accounts: {
+            initializer: initializer.publicKey,
+            taker: PublicKey.default,
+            escrowAccount: _escrowAccountPda,
+            systemProgram: anchor.web3.SystemProgram.programId,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            token2022Program: TOKEN_2022_PROGRAM_ID,
+            vaultAuthority: _vaultAuthorityPda,
+          },
+          remainingAccounts: [
+            { pubkey: tokenAccount, isWritable: true, isSigner: false },
+            { pubkey: _vaultAccountPda, isWritable: true, isSigner: false },
+            { pubkey: mint, isWritable: false, isSigner: false },
+            { pubkey: wantedMint, isWritable: false, isSigner: false },
+          ],
+          signers: [initializer],
+        }
+      );
+      return {
+        escrowAccountPda: _escrowAccountPda,
+        vaultAuthorityPda: _vaultAuthorityPda,
+        vaultAccountPda: _vaultAccountPda,
+      };
+    };
+
+    // escrow aはtakerに払う分より多くSOLを出しているので、差額からescrow aが決めた上限までがkeeperのtipになる
+    const initializerSolAmount = 100_000_000;
+    const takerSolAmount = 50_000_000;
+    try {
+      await createOpenOffer(
+        initializerMainAccount,
+        initializerSolAmount,
+        0,
+        initializerTokenAccountX.address,
+        mintX,
+        mintY,
+        101
+      );
+      assert.fail("keeper tip over the maximum should fail");
+    } catch (err) {
+      assert.equal(err.msg, "InvalidKeeperTipBasisPoints");
+    }
+    const offerA = await createOpenOffer(
+      initializerMainAccount,
+      initializerSolAmount,
+      0,
+      initializerTokenAccountX.address,
+      mintX,
+      mintY,
+      100
+    );
+    // escrow bはtipを払わない
+    const offerB = await createOpenOffer(
+      takerMainAccount,
+      0,
+      takerSolAmount,
+      takerTokenAccountY.address,
+      mintY,
+      mintX,
+      0
+    );
+
+    // 1%の手数料をescrow aからescrow bのinitializerに払うSOLから差し引く
+    await program.rpc.updateConfig(
+      provider.wallet.publicKey,
+      100,
+      new anchor.BN(0),
+      treasury.publicKey,
+      {
+        accounts: {
+          admin: provider.wallet.publicKey,
+          config: configPda,
+        },
+      }
+    );
+
+    const matchOffers = (
+      escrowAccountPdaB: PublicKey,
+      vaultAuthorityPdaB: PublicKey
+    ) =>
+      program.rpc.matchOffers({
+        accounts: {
+          keeper: keeper.publicKey,
+          initializerA: initializerMainAccount.publicKey,
+          initializerB: takerMainAccount.publicKey,
+          escrowAccountA: offerA.escrowAccountPda,
+          vaultAuthorityA: offerA.vaultAuthorityPda,
+          escrowAccountB: escrowAccountPdaB,
+          vaultAuthorityB: vaultAuthorityPdaB,
+          config: configPda,
+          treasury: treasury.publicKey,
+          referrerA: initializerMainAccount.publicKey, // referrerがいない場合は使われない
+          referrerB: takerMainAccount.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        },
+        remainingAccounts: [
+          {
+            pubkey: initializerTokenAccountX.address,
+            isWritable: true,
+            isSigner: false,
+          },
+          { pubkey: offerA.vaultAccountPda, isWritable: true, isSigner: false },
+          {
+            pubkey: takerTokenAccountX.address,
+            isWritable: true,
+            isSigner: false,
+          },
+          { pubkey: mintX, isWritable: false, isSigner: false },
+          {
+            pubkey: takerTokenAccountY.address,
+            isWritable: true,
+            isSigner: false,
+          },
+          { pubkey: offerB.vaultAccountPda, isWritable: true, isSigner: false },
+          {
+            pubkey: initializerTokenAccountY.address,
+            isWritable: true,
+            isSigner: false,
+          },
+          { pubkey: mintY, isWritable: false, isSigner: false },
+        ],
+        signers: [keeper],
+      });
+
+    // 同じescrow同士はmatchできない
+    try {
+      await matchOffers(offerA.escrowAccountPda, offerA.vaultAuthorityPda);
+      assert.fail("matching an escrow with itself should fail");
+    } catch (err) {
+      assert.equal(err.msg, "OffersNotCompatible");
+    }
+
+    const keeperSolAmountBefore = await provider.connection.getBalance(
+      keeper.publicKey
+    );
+    const takerSolAmountBefore = await provider.connection.getBalance(
+      takerMainAccount.publicKey
+    );
+    const treasurySolAmountBefore = await provider.connection.getBalance(
+      treasury.publicKey
+    );
+    await matchOffers(offerB.escrowAccountPda, offerB.vaultAuthorityPda);
+
+    // NFTは互いの欲しい側へ
+    const _takerTokenAccountX = await getAccount(
+      provider.connection,
+      takerTokenAccountX.address
+    );
+    assert.equal(Number(_takerTokenAccountX.amount), 1);
+    const _initializerTokenAccountY = await getAccount(
+      provider.connection,
+      initializerTokenAccountY.address
+    );
+    assert.equal(Number(_initializerTokenAccountY.amount), 1);
+
+    // escrow aの差額50_000_000のうち上限の1%がkeeperへ
+    assert.equal(
+      await provider.connection.getBalance(keeper.publicKey),
+      keeperSolAmountBefore + initializerSolAmount / 100
+    );
+    // escrow bのinitializerは手数料を引いた分とvaultとescrowのrentを受け取る
+    assert.equal(
+      await provider.connection.getBalance(treasury.publicKey),
+      treasurySolAmountBefore + takerSolAmount / 100
+    );
+    assert.ok(
+      (await provider.connection.getBalance(takerMainAccount.publicKey)) >
+        takerSolAmountBefore + takerSolAmount - takerSolAmount / 100
+    );
+    assert.equal(
+      await provider.connection.getAccountInfo(offerA.escrowAccountPda),
+      null
+    );
+    assert.equal(
+      await provider.connection.getAccountInfo(offerB.escrowAccountPda),
+      null
+    );
+
+    // SOLが動かないswapの固定の手数料はkeeperではなく両方のescrowが半分ずつ払う
+    const flatFee = 2_000_000; // escrowのrentから払える額
+    await program.rpc.updateConfig(
+      provider.wallet.publicKey,
+      0,
+      new anchor.BN(flatFee),
+      treasury.publicKey,
+      {
+        accounts: {
+          admin: provider.wallet.publicKey,
+          config: configPda,
+        },
+      }
+    );
+    // 受け取ったNFTを逆向きに出し合う
+    const offerC = await createOpenOffer(
+      initializerMainAccount,
+      0,
+      0,
+      initializerTokenAccountY.address,
+      mintY,
+      mintX,
+      0
+    );
+    const offerD = await createOpenOffer(
+      takerMainAccount,
+      0,
+      0,
+      takerTokenAccountX.address,
+      mintX,
+      mintY,
+      0
+    );
+    const keeperSolAmountBeforeSwap = await provider.connection.getBalance(
+      keeper.publicKey
+    );
+    const treasurySolAmountBeforeSwap = await provider.connection.getBalance(
+      treasury.publicKey
+    );
+    await program.rpc.matchOffers({
+      accounts: {
+        keeper: keeper.publicKey,
+        initializerA: initializerMainAccount.publicKey,
+        initializerB: takerMainAccount.publicKey,
+        escrowAccountA: offerC.escrowAccountPda,
+        vaultAuthorityA: offerC.vaultAuthorityPda,
+        escrowAccountB: offerD.escrowAccountPda,
+        vaultAuthorityB: offerD.vaultAuthorityPda,
+        config: configPda,
+        treasury: treasury.publicKey,
+        referrerA: initializerMainAccount.publicKey, // referrerがいない場合は使われない
+        referrerB: takerMainAccount.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        token2022Program: TOKEN_2022_PROGRAM_ID,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      },
+      remainingAccounts: [
+        {
+          pubkey: initializerTokenAccountY.address,
+          isWritable: true,
+          isSigner: false,
+        },
+        { pubkey: offerC.vaultAccountPda, isWritable: true, isSigner: false },
+        {
+          pubkey: takerTokenAccountY.address,
+          isWritable: true,
+          isSigner: false,
+        },
+        { pubkey: mintY, isWritable: false, isSigner: false },
+        {
+          pubkey: takerTokenAccountX.address,
+          isWritable: true,
+          isSigner: false,
+        },
+        { pubkey: offerD.vaultAccountPda, isWritable: true, isSigner: false },
+        {
+          pubkey: initializerTokenAccountX.address,
+          isWritable: true,
+          isSigner: false,
+        },
+        { pubkey: mintX, isWritable: false, isSigner: false },
+      ],
+      signers: [keeper],
+    });
+    assert.equal(
+      await provider.connection.getBalance(keeper.publicKey),
+      keeperSolAmountBeforeSwap
+    );
+    assert.equal(
+      await provider.connection.getBalance(treasury.publicKey),
+      treasurySolAmountBeforeSwap + flatFee
+    );
+    assert.equal(
+      await provider.connection.getAccountInfo(offerC.escrowAccountPda),
+      null
+    );
+    assert.equal(
+      await provider.connection.getAccountInfo(offerD.escrowAccountPda),
+      null
+    );
+    // 後続のテストの残高が変わらないように手数料を0に戻す
+    await program.rpc.updateConfig(
+      provider.wallet.publicKey,
+      0,
+      new anchor.BN(0),
+      treasury.publicKey,
+      {
+        accounts: {
+          admin: provider.wallet.publicKey,
+          config: configPda,
+        },
+      }
+    );
+  });
+
+  it("Rent an NFT and return it only after the rental ends", async () => {
+    const renter: anchor.web3.Keypair = anchor.web3.Keypair.generate();
+    await provider.connection.confirmTransaction(
+      await provider.connection.requestAirdrop(renter.publicKey, 1_000_000_000),
+      "confirmed"
+    );
+
+    const dailyPrice = 10_000_000;
+    const createRental = async (maxDays: number, nonce: anchor.BN) => {
+      // 期間中はmaster editionがfreezeするのでfreeze authorityも渡す
+      const mint = await createMint(
+        provider.connection,
+        payer,
+        mintAuthority.publicKey,
+        mintAuthority.publicKey,
+        0
+      );
+      const lenderTokenAccount = await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        payer,
+        mint,
+        initializerMainAccount.publicKey
+      );
+      await mintTo(
+        provider.connection,
+        payer,
+        mint,
+        lenderTokenAccount.address,
+        mintAuthority.publicKey,
+        1,
+        [mintAuthority]
+      );
+      const metadata = await createMetadata(mint, 0, [
+        { address: mintAuthority.publicKey, share: 100 },
+      ]);
+      const edition = await createMasterEdition(mint, metadata);
+      const [rentalAccountPda] = await PublicKey.findProgramAddress(
+        [
+          anchor.utils.bytes.utf8.encode("rental"),
+          initializerMainAccount.publicKey.toBuffer(),
+          nonce.toArrayLike(Buffer, "le", 8),
+        ],
+        program.programId
+      );
+      const [rentalVaultAuthorityPda] = await PublicKey.findProgramAddress(
+        [
+          anchor.utils.bytes.utf8.encode("vault-authority"),
+          rentalAccountPda.toBuffer(),
+        ],
+        program.programId
+      );
+      const [vaultAccountPda, vaultAccountBump] =
+        await PublicKey.findProgramAddress(
+          [
+            Buffer.from(anchor.utils.bytes.utf8.encode("vault-account")),
+            rentalAccountPda.toBuffer(),
+            lenderTokenAccount.address.toBuffer(),
+          ],
+          program.programId
+        );
+
+      await program.rpc.createRental(
+        vaultAccountBump,
+        new anchor.BN(dailyPrice),
+        maxDays,
+        nonce,
+        {
+          accounts: {
+            lender: initializerMainAccount.publicKey,
+            rentalAccount: rentalAccountPda,
+            vaultAuthority: rentalVaultAuthorityPda,
+            systemProgram: anchor.web3.SystemProgram.programId,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            token2022Program: TOKEN_2022_PROGRAM_ID,
+          },
+          remainingAccounts: [
+            {
+              pubkey: lenderTokenAccount.address,
+              isWritable: true,
+              isSigner: false,
+            },
+            { pubkey: vaultAccountPda, isWritable: true, isSigner: false },
+            { pubkey: mint, isWritable: false, isSigner: false },
+          ],
+          signers: [initializerMainAccount],
+        }
+      );
+      return {
+        mint,
+        edition,
+        lenderTokenAccount,
+        rentalAccountPda,
+        rentalVaultAuthorityPda,
+        vaultAccountPda,
+      };
+    };
+
+    const endRental = (
+      authority: anchor.web3.Keypair,
+      rental: {
+        mint: PublicKey;
+        edition: PublicKey;
+        lenderTokenAccount: Account;
+        rentalAccountPda: PublicKey;
+        rentalVaultAuthorityPda: PublicKey;
+        vaultAccountPda: PublicKey;
+      }
+    ) =>
+      program.rpc.endRental({
+        accounts: {
+          authority: authority.publicKey,
+          lender: initializerMainAccount.publicKey,
+          rentalAccount: rental.rentalAccountPda,
+          vaultAuthority: rental.rentalVaultAuthorityPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+          tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+        },
+        // 借りられていない場合はvaultから戻す
+        remainingAccounts: [
+          {
+            pubkey: rental.lenderTokenAccount.address,
+            isWritable: true,
+            isSigner: false,
+          },
+          { pubkey: rental.vaultAccountPda, isWritable: true, isSigner: false },
+          { pubkey: rental.mint, isWritable: false, isSigner: false },
+        ],
+        signers: [authority],
+      });
+
+    try {
+      await createRental(0, new anchor.BN(1));
+      assert.fail("create rental with max days 0 should fail");
+    } catch (err) {
+      assert.equal(err.msg, "InvalidRentalDays");
+    }
+
+    const rental = await createRental(7, new anchor.BN(1));
+    const renterTokenAccount = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      payer,
+      rental.mint,
+      renter.publicKey
+    );
+    const rentNft = (days: number) =>
+      program.rpc.rentNft(days, {
+        accounts: {
+          renter: renter.publicKey,
+          lender: initializerMainAccount.publicKey,
+          rentalAccount: rental.rentalAccountPda,
+          vaultAuthority: rental.rentalVaultAuthorityPda,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          token2022Program: TOKEN_2022_PROGRAM_ID,
+          tokenMetadataProgram: TOKEN_METADATA_PROGRAM_ID,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        },
+        remainingAccounts: [
+          {
+            pubkey: rental.lenderTokenAccount.address,
+            isWritable: true,
+            isSigner: false,
+          },
+          { pubkey: rental.vaultAccountPda, isWritable: true, isSigner: false },
+          {
+            pubkey: renterTokenAccount.address,
+            isWritable: true,
+            isSigner: false,
+          },
+          { pubkey: rental.mint, isWritable: false, isSigner: false },
+          { pubkey: rental.edition, isWritable: false, isSigner: false },
+        ],
+        signers: [renter],
+      });
+
+    try {
+      await rentNft(8);
+      assert.fail("rent longer than max days should fail");
+    } catch (err) {
+      assert.equal(err.msg, "InvalidRentalDays");
+    }
+
+    // renterは3日分を先にlenderへ払う vaultのrentもlenderに戻る
+    const lenderSolAmountBefore = await provider.connection.getBalance(
+      initializerMainAccount.publicKey
+    );
+    const vaultRent = await provider.connection.getBalance(
+      rental.vaultAccountPda
+    );
+    await rentNft(3);
+    assert.equal(
+      await provider.connection.getBalance(initializerMainAccount.publicKey),
+      lenderSolAmountBefore + dailyPrice * 3 + vaultRent
+    );
+    const _rentalAccount = await program.account.rentalAccount.fetch(
+      rental.rentalAccountPda
+    );
+    assert.ok(_rentalAccount.renterKey.equals(renter.publicKey));
+
+    try {
+      await rentNft(1);
+      assert.fail("renting a rented NFT should fail");
+    } catch (err) {
+      assert.equal(err.msg, "RentalAlreadyRented");
+    }
+
+    // 期間中はlenderでも取り戻せない
+    try {
+      await endRental(initializerMainAccount, rental);
+      assert.fail("end rental during the period should fail");
+    } catch (err) {
+      assert.equal(err.msg, "RentalNotEnded");
+    }
+
+    // NFTはrenterのATAにあり、vault authorityがdelegateとしてfreezeしている
+    assert.equal(
+      await provider.connection.getAccountInfo(rental.vaultAccountPda),
+      null
+    );
+    const _renterTokenAccount = await getAccount(
+      provider.connection,
+      renterTokenAccount.address
+    );
+    assert.equal(Number(_renterTokenAccount.amount), 1);
+    assert.ok(_renterTokenAccount.isFrozen);
+    assert.ok(
+      _renterTokenAccount.delegate.equals(rental.rentalVaultAuthorityPda)
+    );
+    assert.ok(
+      _rentalAccount.renterTokenAccount.equals(renterTokenAccount.address)
+    );
+
+    // 期間中はrenterも他のwalletに動かせない
+    let transferred = true;
+    try {
+      await transfer(
+        provider.connection,
+        renter,
+        renterTokenAccount.address,
+        rental.lenderTokenAccount.address,
+        renter,
+        1
+      );
+    } catch (err) {
+      transferred = false;
+    }
+    assert.ok(!transferred);
+
+    // 借りられていない場合はlenderだけが取り下げられる
+    const unrentedRental = await createRental(7, new anchor.BN(2));
+    try {
+      await endRental(renter, unrentedRental);
+      assert.fail("end unrented rental by non-lender should fail");
+    } catch (err) {
+      assert.equal(err.msg, "AssociatedAuthorityMismatch");
+    }
+    await endRental(initializerMainAccount, unrentedRental);
+    const _lenderTokenAccount = await getAccount(
+      provider.connection,
+      unrentedRental.lenderTokenAccount.address
+    );
+    assert.equal(Number(_lenderTokenAccount.amount), 1);
+    assert.equal(
+      await provider.connection.getAccountInfo(unrentedRental.rentalAccountPda),
+      null
+    );
+  });
+
   // TODO: initializerがSOL払う場合
 
   // TODO: takerがSOL払う場合